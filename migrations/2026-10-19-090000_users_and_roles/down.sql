@@ -0,0 +1,21 @@
+-- SQLite can't drop a column that references another table, so pdfs is rebuilt
+-- without it
+CREATE TABLE pdfs_without_projects (
+    id INTEGER PRIMARY KEY AUTOINCREMENT NOT NULL,
+    title TEXT NOT NULL,
+    authors TEXT NOT NULL,
+    year INTEGER NOT NULL,
+    journal TEXT NOT NULL,
+    volume TEXT NOT NULL,
+    data BLOB NOT NULL
+);
+
+INSERT INTO pdfs_without_projects (id, title, authors, year, journal, volume, data)
+SELECT id, title, authors, year, journal, volume, data FROM pdfs;
+
+DROP TABLE pdfs;
+ALTER TABLE pdfs_without_projects RENAME TO pdfs;
+
+DROP TABLE project_members;
+DROP TABLE sessions;
+DROP TABLE users;
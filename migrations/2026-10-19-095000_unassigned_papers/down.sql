@@ -0,0 +1,13 @@
+-- Every paper in the project goes back to belonging to no project
+UPDATE pdfs
+SET project_id = NULL
+WHERE project_id = (SELECT id FROM projects WHERE path = 'projects/unassigned-papers');
+
+UPDATE sessions
+SET active_project_id = NULL
+WHERE active_project_id = (SELECT id FROM projects WHERE path = 'projects/unassigned-papers');
+
+DELETE FROM project_members
+WHERE project_id = (SELECT id FROM projects WHERE path = 'projects/unassigned-papers');
+
+DELETE FROM projects WHERE path = 'projects/unassigned-papers';
@@ -0,0 +1,21 @@
+-- Papers from before projects had members belong to no project, which left them open
+-- to every signed-in user. They move to a project of their own, owned by the first
+-- account. Without any accounts yet, the first one registered takes it over.
+INSERT INTO projects (name, path, fields)
+SELECT 'Unassigned papers', 'projects/unassigned-papers', '[]'
+WHERE EXISTS (SELECT 1 FROM pdfs WHERE project_id IS NULL)
+    AND NOT EXISTS (SELECT 1 FROM projects WHERE path = 'projects/unassigned-papers');
+
+INSERT INTO project_members (project_id, user_id, role)
+SELECT projects.id, (SELECT min(id) FROM users), 'owner'
+FROM projects
+WHERE projects.path = 'projects/unassigned-papers'
+    AND EXISTS (SELECT 1 FROM users)
+    AND EXISTS (SELECT 1 FROM pdfs WHERE project_id IS NULL)
+    AND NOT EXISTS (
+        SELECT 1 FROM project_members WHERE project_members.project_id = projects.id
+    );
+
+UPDATE pdfs
+SET project_id = (SELECT id FROM projects WHERE path = 'projects/unassigned-papers')
+WHERE project_id IS NULL;
@@ -0,0 +1,2 @@
+DROP TABLE pdf_authors;
+DROP TABLE authors;
@@ -0,0 +1,105 @@
+PRAGMA foreign_keys = ON;
+
+-- Each project keeps its own authors, so editing one never changes another project
+CREATE TABLE authors (
+    id INTEGER PRIMARY KEY AUTOINCREMENT NOT NULL,
+    project_id INTEGER NOT NULL,
+    family_name TEXT NOT NULL,
+    given_name TEXT,
+    orcid TEXT,
+    UNIQUE(project_id, orcid),
+    FOREIGN KEY(project_id) REFERENCES projects(id) ON DELETE CASCADE
+);
+
+CREATE INDEX authors_name ON authors(project_id, family_name, given_name);
+
+CREATE TABLE pdf_authors (
+    pdf_id INTEGER NOT NULL,
+    author_id INTEGER NOT NULL,
+    position INTEGER NOT NULL,
+    PRIMARY KEY(pdf_id, position),
+    FOREIGN KEY(pdf_id) REFERENCES pdfs(id) ON DELETE CASCADE,
+    FOREIGN KEY(author_id) REFERENCES authors(id) ON DELETE CASCADE
+);
+
+CREATE INDEX pdf_authors_author ON pdf_authors(author_id);
+
+-- Split the existing free-text author lists using the same rules as
+-- `parse_author_list` in src/models/author.rs
+CREATE TEMPORARY TABLE author_names AS
+WITH RECURSIVE
+-- First split on "and", "&" and ";"
+pieces(pdf_id, piece, rest, ord) AS (
+    SELECT id, NULL,
+           replace(replace(replace(trim(authors), ', and ', ';'), ' and ', ';'), ' & ', ';') || ';',
+           0
+    FROM pdfs
+    UNION ALL
+    SELECT pdf_id,
+           trim(rtrim(trim(substr(rest, 1, instr(rest, ';') - 1)), ',')),
+           substr(rest, instr(rest, ';') + 1),
+           ord + 1
+    FROM pieces
+    WHERE rest <> ''
+),
+-- A piece is a comma-separated list unless it reads as a single "Family, Given"
+lists(pdf_id, ord, list) AS (
+    SELECT pdf_id, ord,
+           CASE
+               WHEN length(piece) - length(replace(piece, ',', '')) > 1
+                    OR instr(trim(substr(piece, 1, instr(piece, ',') - 1)), ' ') > 0
+               THEN replace(piece, ',', ';')
+               ELSE piece
+           END || ';'
+    FROM pieces
+    WHERE piece IS NOT NULL AND piece <> ''
+),
+split(pdf_id, ord, piece, rest) AS (
+    SELECT pdf_id, ord * 1000, NULL, list FROM lists
+    UNION ALL
+    SELECT pdf_id, ord + 1,
+           trim(substr(rest, 1, instr(rest, ';') - 1)),
+           substr(rest, instr(rest, ';') + 1)
+    FROM split
+    WHERE rest <> ''
+),
+names(pdf_id, ord, name, head, last) AS (
+    SELECT pdf_id, ord, piece,
+           trim(rtrim(piece, replace(piece, ' ', ''))),
+           substr(piece, length(rtrim(piece, replace(piece, ' ', ''))) + 1)
+    FROM split
+    WHERE piece IS NOT NULL AND piece <> ''
+)
+SELECT pdf_id,
+       row_number() OVER (PARTITION BY pdf_id ORDER BY ord) - 1 AS position,
+       CASE
+           WHEN instr(name, ',') > 0 THEN trim(substr(name, 1, instr(name, ',') - 1))
+           WHEN head = '' THEN name
+           WHEN last GLOB '[A-Z]' OR last GLOB '[A-Z][A-Z]' OR last GLOB '[A-Z][A-Z][A-Z]' THEN head
+           ELSE last
+       END AS family_name,
+       nullif(CASE
+           WHEN instr(name, ',') > 0 THEN trim(substr(name, instr(name, ',') + 1))
+           WHEN head = '' THEN ''
+           WHEN last GLOB '[A-Z]' OR last GLOB '[A-Z][A-Z]' OR last GLOB '[A-Z][A-Z][A-Z]' THEN last
+           ELSE head
+       END, '') AS given_name
+FROM names;
+
+INSERT INTO authors (project_id, family_name, given_name)
+SELECT DISTINCT pdfs.project_id, n.family_name, n.given_name
+FROM author_names n
+JOIN pdfs ON pdfs.id = n.pdf_id
+WHERE n.family_name <> '';
+
+INSERT INTO pdf_authors (pdf_id, author_id, position)
+SELECT n.pdf_id, min(a.id), n.position
+FROM author_names n
+JOIN pdfs ON pdfs.id = n.pdf_id
+JOIN authors a
+    ON a.project_id = pdfs.project_id
+    AND a.family_name = n.family_name
+    AND a.given_name IS n.given_name
+GROUP BY n.pdf_id, n.position;
+
+DROP TABLE author_names;
@@ -0,0 +1,11 @@
+DROP INDEX pdfs_arxiv_id;
+DROP INDEX pdfs_pmid;
+DROP INDEX pdfs_project_doi;
+ALTER TABLE pdfs DROP COLUMN publisher;
+ALTER TABLE pdfs DROP COLUMN issue;
+ALTER TABLE pdfs DROP COLUMN pages;
+ALTER TABLE pdfs DROP COLUMN url;
+ALTER TABLE pdfs DROP COLUMN isbn;
+ALTER TABLE pdfs DROP COLUMN arxiv_id;
+ALTER TABLE pdfs DROP COLUMN pmid;
+ALTER TABLE pdfs DROP COLUMN doi;
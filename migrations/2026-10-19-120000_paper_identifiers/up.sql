@@ -0,0 +1,13 @@
+ALTER TABLE pdfs ADD COLUMN doi TEXT;
+ALTER TABLE pdfs ADD COLUMN pmid TEXT;
+ALTER TABLE pdfs ADD COLUMN arxiv_id TEXT;
+ALTER TABLE pdfs ADD COLUMN isbn TEXT;
+ALTER TABLE pdfs ADD COLUMN url TEXT;
+ALTER TABLE pdfs ADD COLUMN pages TEXT;
+ALTER TABLE pdfs ADD COLUMN issue TEXT;
+ALTER TABLE pdfs ADD COLUMN publisher TEXT;
+
+-- The same paper may be filed in several projects, but only once per project
+CREATE UNIQUE INDEX pdfs_project_doi ON pdfs(project_id, doi) WHERE doi IS NOT NULL;
+CREATE INDEX pdfs_pmid ON pdfs(pmid) WHERE pmid IS NOT NULL;
+CREATE INDEX pdfs_arxiv_id ON pdfs(arxiv_id) WHERE arxiv_id IS NOT NULL;
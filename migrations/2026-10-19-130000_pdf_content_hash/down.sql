@@ -0,0 +1,2 @@
+DROP INDEX pdfs_project_content_hash;
+ALTER TABLE pdfs DROP COLUMN content_hash;
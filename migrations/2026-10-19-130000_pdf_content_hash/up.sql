@@ -0,0 +1,6 @@
+-- SHA-256 of the file, used to skip papers that were already ingested. Existing
+-- files are hashed by the ingester, since SQLite has no SHA-256 function.
+ALTER TABLE pdfs ADD COLUMN content_hash TEXT;
+
+CREATE INDEX pdfs_project_content_hash ON pdfs(project_id, content_hash)
+    WHERE content_hash IS NOT NULL;
@@ -0,0 +1,5 @@
+DROP TRIGGER pdfs_text_delete;
+DROP TRIGGER pdfs_text_update;
+DROP TRIGGER pdfs_text_insert;
+DROP TABLE pdf_text;
+ALTER TABLE pdfs DROP COLUMN page_count;
@@ -0,0 +1,32 @@
+-- Number of pages whose text has been extracted, absent until the file is indexed
+ALTER TABLE pdfs ADD COLUMN page_count INTEGER;
+
+-- Full-text index of the papers. Each paper has a page 0 row holding its title, authors
+-- and journal, kept in step with `pdfs` by the triggers below, and one row per page of
+-- text written when the file is indexed.
+CREATE VIRTUAL TABLE pdf_text USING fts5(
+    title,
+    authors,
+    journal,
+    text,
+    pdf_id UNINDEXED,
+    page_number UNINDEXED,
+    tokenize = 'porter unicode61 remove_diacritics 2'
+);
+
+INSERT INTO pdf_text (title, authors, journal, text, pdf_id, page_number)
+    SELECT title, authors, journal, '', id, 0 FROM pdfs;
+
+CREATE TRIGGER pdfs_text_insert AFTER INSERT ON pdfs BEGIN
+    INSERT INTO pdf_text (title, authors, journal, text, pdf_id, page_number)
+        VALUES (new.title, new.authors, new.journal, '', new.id, 0);
+END;
+
+CREATE TRIGGER pdfs_text_update AFTER UPDATE OF title, authors, journal ON pdfs BEGIN
+    UPDATE pdf_text SET title = new.title, authors = new.authors, journal = new.journal
+        WHERE pdf_id = new.id AND page_number = 0;
+END;
+
+CREATE TRIGGER pdfs_text_delete AFTER DELETE ON pdfs BEGIN
+    DELETE FROM pdf_text WHERE pdf_id = old.id;
+END;
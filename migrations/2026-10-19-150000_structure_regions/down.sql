@@ -0,0 +1 @@
+DROP TABLE structure_regions;
@@ -0,0 +1,19 @@
+PRAGMA foreign_keys = ON;
+
+-- Boxes around structure drawings on a paper's pages, in PDF points from the top left
+-- of the page. Detection proposes them as pending for the user to accept or reject.
+CREATE TABLE structure_regions (
+    id INTEGER PRIMARY KEY AUTOINCREMENT NOT NULL,
+    pdf_id INTEGER NOT NULL,
+    page_number INTEGER NOT NULL,
+    x REAL NOT NULL,
+    y REAL NOT NULL,
+    width REAL NOT NULL,
+    height REAL NOT NULL,
+    score REAL NOT NULL,
+    status TEXT CHECK(status IN ('pending', 'accepted', 'rejected')) NOT NULL DEFAULT 'pending',
+    created_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    FOREIGN KEY(pdf_id) REFERENCES pdfs(id) ON DELETE CASCADE
+);
+
+CREATE INDEX structure_regions_pdf ON structure_regions(pdf_id, page_number);
@@ -0,0 +1,27 @@
+PRAGMA foreign_keys = ON;
+
+-- Background work such as text extraction, structure detection, recognition and
+-- exports. `task` is the JSON description of the work and `kind` its type, kept apart
+-- for listing. Rows stay after the job ends so its status and result can be read.
+CREATE TABLE jobs (
+    id INTEGER PRIMARY KEY AUTOINCREMENT NOT NULL,
+    project_id INTEGER,
+    created_by INTEGER,
+    kind TEXT NOT NULL,
+    task TEXT NOT NULL,
+    status TEXT CHECK(status IN ('queued', 'running', 'succeeded', 'failed', 'cancelled')) NOT NULL DEFAULT 'queued',
+    attempts INTEGER NOT NULL DEFAULT 0,
+    max_attempts INTEGER NOT NULL DEFAULT 3,
+    cancel_requested BOOLEAN NOT NULL DEFAULT 0,
+    result TEXT,
+    error TEXT,
+    run_after TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    created_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    started_at TIMESTAMP,
+    finished_at TIMESTAMP,
+    FOREIGN KEY(project_id) REFERENCES projects(id) ON DELETE CASCADE,
+    FOREIGN KEY(created_by) REFERENCES users(id) ON DELETE SET NULL
+);
+
+CREATE INDEX jobs_queue ON jobs(status, run_after);
+CREATE INDEX jobs_project ON jobs(project_id, id);
@@ -0,0 +1,2 @@
+-- How far a running job has got, from 0 to 1, for jobs that report it
+ALTER TABLE jobs ADD COLUMN progress REAL;
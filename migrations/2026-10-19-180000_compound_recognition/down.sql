@@ -0,0 +1,4 @@
+ALTER TABLE compounds DROP COLUMN recognition_confidence;
+ALTER TABLE compounds DROP COLUMN recognized_inchi;
+ALTER TABLE compounds DROP COLUMN recognized_smiles;
+ALTER TABLE compounds DROP COLUMN recognition_backend;
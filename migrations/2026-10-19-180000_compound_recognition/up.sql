@@ -0,0 +1,6 @@
+-- What the structure recognizer made of a compound's drawing, kept after curators
+-- correct the structure so recognizers can be compared against the curated SMILES
+ALTER TABLE compounds ADD COLUMN recognition_backend TEXT;
+ALTER TABLE compounds ADD COLUMN recognized_smiles TEXT;
+ALTER TABLE compounds ADD COLUMN recognized_inchi TEXT;
+ALTER TABLE compounds ADD COLUMN recognition_confidence REAL;
@@ -0,0 +1,2 @@
+DROP INDEX compounds_label;
+ALTER TABLE compounds DROP COLUMN label;
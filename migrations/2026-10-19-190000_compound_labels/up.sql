@@ -0,0 +1,3 @@
+-- The name the paper gives the compound, such as "7a", for matching it to tables and text
+ALTER TABLE compounds ADD COLUMN label TEXT NOT NULL DEFAULT '';
+CREATE INDEX compounds_label ON compounds (pdf_id, label);
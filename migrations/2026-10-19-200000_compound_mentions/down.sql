@@ -0,0 +1 @@
+DROP TABLE compound_mentions;
@@ -0,0 +1,15 @@
+PRAGMA foreign_keys = ON;
+
+-- Sentences of a paper's extracted text that refer to one of its compounds by label
+CREATE TABLE compound_mentions (
+    id INTEGER PRIMARY KEY AUTOINCREMENT NOT NULL,
+    compound_id INTEGER NOT NULL,
+    pdf_id INTEGER NOT NULL,
+    page_number INTEGER NOT NULL,
+    sentence TEXT NOT NULL,
+    FOREIGN KEY(compound_id) REFERENCES compounds(id) ON DELETE CASCADE,
+    FOREIGN KEY(pdf_id) REFERENCES pdfs(id) ON DELETE CASCADE
+);
+
+CREATE INDEX compound_mentions_compound ON compound_mentions(compound_id);
+CREATE INDEX compound_mentions_pdf ON compound_mentions(pdf_id, page_number);
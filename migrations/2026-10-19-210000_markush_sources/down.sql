@@ -0,0 +1,4 @@
+DROP INDEX compounds_markush_source;
+ALTER TABLE compounds DROP COLUMN rgroup_assignments;
+ALTER TABLE compounds DROP COLUMN markush_source_id;
+DROP TABLE markush_sources;
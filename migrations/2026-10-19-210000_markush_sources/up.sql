@@ -0,0 +1,20 @@
+PRAGMA foreign_keys = ON;
+
+-- A generic structure drawn in a paper: a core with attachment points such as [R1] and
+-- the table of substituents that stand in for them, one row per compound
+CREATE TABLE markush_sources (
+    id INTEGER PRIMARY KEY AUTOINCREMENT NOT NULL,
+    pdf_id INTEGER NOT NULL,
+    name TEXT NOT NULL DEFAULT '',
+    core TEXT NOT NULL,
+    rgroups TEXT NOT NULL,
+    FOREIGN KEY(pdf_id) REFERENCES pdfs(id) ON DELETE CASCADE
+);
+
+CREATE INDEX markush_sources_pdf ON markush_sources(pdf_id);
+
+-- The generic structure a compound was enumerated from and the substituent chosen for
+-- each of its attachment points, as a JSON object such as {"R1": "Cl"}
+ALTER TABLE compounds ADD COLUMN markush_source_id INTEGER;
+ALTER TABLE compounds ADD COLUMN rgroup_assignments TEXT;
+CREATE INDEX compounds_markush_source ON compounds (markush_source_id);
@@ -0,0 +1,2 @@
+DROP INDEX recognitions_region;
+DROP TABLE recognitions;
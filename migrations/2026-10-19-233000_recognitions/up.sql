@@ -0,0 +1,16 @@
+-- Every structure the server's recognizer read. Compounds drawn from a recognition refer
+-- to it by ID, so the recognized structure comes from the server rather than the browser.
+CREATE TABLE recognitions (
+    id INTEGER PRIMARY KEY AUTOINCREMENT NOT NULL,
+    project_id INTEGER NOT NULL,
+    region_id INTEGER,
+    backend TEXT NOT NULL,
+    smiles TEXT NOT NULL,
+    inchi TEXT,
+    confidence REAL,
+    created_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    FOREIGN KEY(project_id) REFERENCES projects(id) ON DELETE CASCADE,
+    FOREIGN KEY(region_id) REFERENCES structure_regions(id) ON DELETE SET NULL
+);
+
+CREATE INDEX recognitions_region ON recognitions(region_id);
@@ -37,6 +37,41 @@ async function initApp() {
 // Start the app
 initApp();
 
+// Pre-fill empty BibTeX fields with metadata read from the selected PDF
+document.getElementById('pdfFile').addEventListener('change', async (e) => {
+    const file = e.target.files[0];
+    if (!file) return;
+
+    const formData = new FormData();
+    formData.append('pdf', file);
+
+    try {
+        const response = await fetch('/api/pdfs/metadata', {
+            method: 'POST',
+            body: formData
+        });
+        if (!response.ok) return;
+
+        const suggestion = await response.json();
+        const fields = {
+            titleInput: suggestion.title,
+            authorsInput: suggestion.authors,
+            yearInput: suggestion.year,
+            journalInput: suggestion.journal,
+            volumeInput: suggestion.volume,
+            doiInput: suggestion.doi
+        };
+        for (const [id, value] of Object.entries(fields)) {
+            const input = document.getElementById(id);
+            if (input && value && !input.value) {
+                input.value = value;
+            }
+        }
+    } catch (error) {
+        console.error('Error reading PDF metadata:', error);
+    }
+});
+
 document.getElementById('uploadForm').addEventListener('submit', async (e) => {
     e.preventDefault();
 
@@ -49,7 +84,8 @@ document.getElementById('uploadForm').addEventListener('submit', async (e) => {
         authors: document.getElementById('authorsInput').value,
         year: document.getElementById('yearInput').value,
         journal: document.getElementById('journalInput').value,
-        volume: document.getElementById('volumeInput').value
+        volume: document.getElementById('volumeInput').value,
+        doi: document.getElementById('doiInput')?.value || null
     };
 
     formData.append('bibtexData', JSON.stringify(bibtexData));
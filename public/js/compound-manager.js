@@ -1,4 +1,8 @@
-const RECOG_SERVER_PORT = 5000;
+function escapeHtml(text) {
+    const element = document.createElement('div');
+    element.textContent = text;
+    return element.innerHTML;
+}
 
 class CompoundManager {
     constructor() {
@@ -7,11 +11,14 @@ class CompoundManager {
         this.chemicalDataFields = [];
         this.paperTitles = {};
         this.compoundsTable = null;
+        // What the recognizer made of the captured image, kept even if the structure is edited
+        this.recognition = null;
 
         // Setup for main page (index.html) or compounds page (compounds.html)
         this.isCompoundsPage = window.location.pathname.includes('compounds.html');
         this.setupEventListeners();
         this.loadChemicalDataFields();
+        this.listenForUpdates();
 
         // Initialize DataTable if on compounds page
         if (this.isCompoundsPage) {
@@ -282,6 +289,7 @@ class CompoundManager {
     async saveCompound() {
         const smilesInput = document.getElementById('smilesInput');
         const inchiInput = document.getElementById('inchiInput');
+        const labelInput = document.getElementById('labelInput');
         const capturedImage = document.getElementById('capturedImage');
         const chemicalData = this.getChemicalDataValues();
 
@@ -289,8 +297,10 @@ class CompoundManager {
             pdf_id: this.currentPDFId,
             smiles: smilesInput.value,
             inchi: inchiInput.value,
+            label: labelInput ? labelInput.value : '',
             image: capturedImage.getAttribute('data-image'),
-            chemical_data: chemicalData
+            chemical_data: chemicalData,
+            recognition_id: this.recognition ? this.recognition.recognition_id : null
         };
 
         try {
@@ -319,6 +329,7 @@ class CompoundManager {
         const capturedImage = document.getElementById('capturedImage');
         capturedImage.innerHTML = `<img src="${imageData}" style="max-width: 100%">`;
         capturedImage.setAttribute('data-image', imageData);
+        this.recognition = null;
     }
 
     async recognizeStructure() {
@@ -337,7 +348,8 @@ class CompoundManager {
             // Show loading indicator
             structurePreview.innerHTML = '<div class="alert alert-info">Recognizing structure...</div>';
 
-            const response = await fetch('http://localhost:${RECOG_SERVER_PORT}/api/recognize-structure', {
+            // The server forwards the image to the configured recognizer
+            const response = await fetch('/api/structures/recognize', {
                 method: 'POST',
                 headers: {
                     'Content-Type': 'application/json'
@@ -347,16 +359,18 @@ class CompoundManager {
 
             const data = await response.json();
             if (response.ok) {
+                this.recognition = data;
+
                 // Update SMILES with canonical version
                 smilesInput.value = data.smiles;
 
                 // Populate InChI automatically
-                inchiInput.value = data.inchi;
+                inchiInput.value = data.inchi || '';
 
                 // Add edit button to structure preview
                 structurePreview.innerHTML = `
                     <div class="position-relative">
-                        <img src="${data.structure_image}" style="max-width: 100%">
+                        <img src="${data.structure_image || imageData}" style="max-width: 100%">
                         <button id="editStructure" class="btn btn-sm btn-primary position-absolute top-0 end-0 m-2">
                             Edit Structure
                         </button>
@@ -376,15 +390,15 @@ class CompoundManager {
             }
         } catch (error) {
             console.error('Error recognizing structure:', error);
-            structurePreview.innerHTML = '<div class="alert alert-danger">Error connecting to chemical service</div>';
+            structurePreview.innerHTML = '<div class="alert alert-danger">Error connecting to the server</div>';
             alert('Error recognizing structure');
         }
     }
 
     async validateStructure() {
         const smilesInput = document.getElementById('smilesInput');
-        const inchiInput = document.getElementById('inchiInput');
         const structurePreview = document.getElementById('structurePreview');
+        const imageData = document.getElementById('capturedImage').getAttribute('data-image');
         const smiles = smilesInput.value;
 
         if (!smiles) {
@@ -393,7 +407,7 @@ class CompoundManager {
         }
 
         try {
-            const response = await fetch('http://localhost:${RECOG_SERVER_PORT}/api/validate-smiles', {
+            const response = await fetch('/api/structures/validate', {
                 method: 'POST',
                 headers: {
                     'Content-Type': 'application/json'
@@ -406,13 +420,10 @@ class CompoundManager {
                 // Update SMILES with canonical version
                 smilesInput.value = data.smiles;
 
-                // Populate InChI automatically
-                inchiInput.value = data.inchi;
-
                 // Add edit button to structure preview
                 structurePreview.innerHTML = `
                     <div class="position-relative">
-                        <img src="${data.structure_image}" style="max-width: 100%">
+                        ${imageData ? `<img src="${imageData}" style="max-width: 100%">` : `<code>${escapeHtml(data.smiles)}</code>`}
                         <button id="editStructure" class="btn btn-sm btn-primary position-absolute top-0 end-0 m-2">
                             Edit Structure
                         </button>
@@ -441,21 +452,25 @@ class CompoundManager {
 
         // Open the editor in a new window
         const editorWindow = window.open(
-            `http://localhost:${RECOG_SERVER_PORT}/rdkit-editor.html?molblock=${encodedMolblock}`,
-            'RDKitEditor',
+            `/static/html/structure-editor.html?molblock=${encodedMolblock}`,
+            'StructureEditor',
             'width=800,height=600'
         );
 
-        // Set up event listener to receive the edited molecule
-        window.addEventListener('message', async (event) => {
+        // Only the latest editor window may send a structure back
+        if (this.editorListener) {
+            window.removeEventListener('message', this.editorListener);
+        }
+        this.editorListener = async (event) => {
             // Check if it's from our editor
-            if (event.data && event.data.type === 'rdkit-molecule') {
+            if (event.origin === window.location.origin && event.source === editorWindow
+                && event.data && event.data.type === 'rdkit-molecule') {
                 // Get the molfile from the editor
                 const molblock = event.data.molblock;
 
-                // Convert molfile to SMILES and InChI
+                // Convert molfile to SMILES
                 try {
-                    const response = await fetch('http://localhost:${RECOG_SERVER_PORT}/api/molfile-to-structure', {
+                    const response = await fetch('/api/structures/from-molfile', {
                         method: 'POST',
                         headers: {
                             'Content-Type': 'application/json'
@@ -463,18 +478,16 @@ class CompoundManager {
                         body: JSON.stringify({ molfile: molblock })
                     });
 
-                    if (response.ok) {
-                        const data = await response.json();
-
+                    const data = await response.json();
+                    if (response.ok && data.valid) {
                         // Update form values
                         document.getElementById('smilesInput').value = data.smiles;
-                        document.getElementById('inchiInput').value = data.inchi;
 
                         // Update structure preview
                         const structurePreview = document.getElementById('structurePreview');
                         structurePreview.innerHTML = `
                             <div class="position-relative">
-                                <img src="${data.structure_image}" style="max-width: 100%">
+                                <code>${escapeHtml(data.smiles)}</code>
                                 <button id="editStructure" class="btn btn-sm btn-primary position-absolute top-0 end-0 m-2">
                                     Edit Structure
                                 </button>
@@ -493,7 +506,8 @@ class CompoundManager {
                     console.error('Error converting molfile:', error);
                 }
             }
-        });
+        };
+        window.addEventListener('message', this.editorListener);
     }
 
     // Load papers for dropdown filter or selection
@@ -821,6 +835,7 @@ class CompoundManager {
                 pdf_id: originalCompound.pdf_id,
                 smiles: originalCompound.smiles,
                 inchi: originalCompound.inchi,
+                label: originalCompound.label,
                 image: originalCompound.image,
                 chemical_data: chemicalData
             };
@@ -860,6 +875,69 @@ class CompoundManager {
         }
     }
 
+    // Follow new compounds, job progress and validation errors as they happen
+    listenForUpdates() {
+        if (!window.EventSource) return;
+        const events = new EventSource('/api/events');
+
+        events.addEventListener('compound_created', (e) => {
+            const compound = JSON.parse(e.data);
+            if (this.isCompoundsPage || compound.pdf_id === this.currentPDFId) {
+                this.scheduleReload();
+            }
+        });
+        events.addEventListener('job', (e) => this.showJobProgress(JSON.parse(e.data)));
+        events.addEventListener('validation_error', (e) => {
+            const error = JSON.parse(e.data);
+            this.showAlert(`${escapeHtml(error.source)}: ${escapeHtml(error.message)}`, 'warning');
+        });
+        // Missed events can't be replayed, so reload what is shown instead
+        events.addEventListener('lagged', () => this.scheduleReload());
+    }
+
+    // Imports announce many compounds at once, so reload once they stop arriving
+    scheduleReload() {
+        clearTimeout(this.reloadTimer);
+        this.reloadTimer = setTimeout(() => {
+            if (this.isCompoundsPage) {
+                const paperFilter = document.getElementById('paperFilter');
+                if (paperFilter && paperFilter.value !== 'all') {
+                    this.loadCompoundsByPaper(parseInt(paperFilter.value));
+                } else {
+                    this.loadAllCompounds();
+                }
+            } else if (this.currentPDFId) {
+                this.loadCompounds(this.currentPDFId);
+            }
+        }, 500);
+    }
+
+    // One line per running job in the corner of the page, removed when it ends
+    showJobProgress(job) {
+        let panel = document.getElementById('jobProgress');
+        if (!panel) {
+            panel = document.createElement('div');
+            panel.id = 'jobProgress';
+            panel.className = 'position-fixed bottom-0 end-0 m-3';
+            panel.style.zIndex = 1080;
+            document.body.appendChild(panel);
+        }
+        let line = document.getElementById(`job-${job.job_id}`);
+        if (!line) {
+            line = document.createElement('div');
+            line.id = `job-${job.job_id}`;
+            line.className = 'alert alert-info py-1 px-2 mb-1 small';
+            panel.appendChild(line);
+        }
+        const percent = job.progress === null ? '' : ` ${Math.round(job.progress * 100)}%`;
+        const message = job.message ? ` (${job.message})` : '';
+        line.textContent = `${job.kind.replace(/_/g, ' ')}: ${job.status}${percent}${message}`;
+        if (['succeeded', 'failed', 'cancelled'].includes(job.status)) {
+            line.className = `alert alert-${job.status === 'succeeded' ? 'success' : 'danger'} py-1 px-2 mb-1 small`;
+            setTimeout(() => line.remove(), 5000);
+        }
+    }
+
     // Helper function to show alerts
     showAlert(message, type) {
         // Create alert element
@@ -880,16 +958,21 @@ class CompoundManager {
             setTimeout(() => {
                 alertElement.classList.remove('show');
                 setTimeout(() => alertElement.remove(), 150);
-            }, RECOG_SERVER_PORT);
+            }, 5000);
         }
     }
 
     clearForm() {
         document.getElementById('smilesInput').value = '';
         document.getElementById('inchiInput').value = '';
+        const labelInput = document.getElementById('labelInput');
+        if (labelInput) {
+            labelInput.value = '';
+        }
         document.getElementById('capturedImage').innerHTML = '';
         document.getElementById('capturedImage').removeAttribute('data-image');
         document.getElementById('structurePreview').innerHTML = '';
+        this.recognition = null;
 
         // Clear all chemical data fields
         document.querySelectorAll('.chemical-data-field').forEach(field => {
@@ -1,5 +1,3 @@
-const RECOG_SERVER_PORT = 5000;
-
 document.addEventListener('DOMContentLoaded', function () {
     // Initialize DataTable
     const papersTable = $('#papersTable').DataTable({
@@ -41,6 +39,7 @@ document.addEventListener('DOMContentLoaded', function () {
     // Event listeners
     document.getElementById('savePaperBtn').addEventListener('click', savePaper);
     document.getElementById('updatePaperBtn').addEventListener('click', updatePaper);
+    document.getElementById('paperFile').addEventListener('change', suggestMetadata);
 
     // Action buttons event delegation
     document.getElementById('papersTableBody').addEventListener('click', function (e) {
@@ -79,6 +78,46 @@ document.addEventListener('DOMContentLoaded', function () {
         }
     }
 
+    // Pre-fill empty fields with metadata read from the selected PDF
+    async function suggestMetadata() {
+        const paperFile = document.getElementById('paperFile').files[0];
+        if (!paperFile) return;
+
+        try {
+            const formData = new FormData();
+            formData.append('pdf', paperFile);
+
+            const response = await fetch('/api/pdfs/metadata', {
+                method: 'POST',
+                body: formData
+            });
+            if (!response.ok) return;
+
+            const suggestion = await response.json();
+            const fields = {
+                paperTitle: suggestion.title,
+                paperAuthors: suggestion.authors,
+                paperYear: suggestion.year,
+                paperJournal: suggestion.journal,
+                paperVolume: suggestion.volume,
+                paperDoi: suggestion.doi
+            };
+            let filled = false;
+            for (const [id, value] of Object.entries(fields)) {
+                const input = document.getElementById(id);
+                if (input && value && !input.value) {
+                    input.value = value;
+                    filled = true;
+                }
+            }
+            if (filled) {
+                showAlert('Some fields were filled in from the PDF. Please review them before saving.', 'info', 'addPaperModal');
+            }
+        } catch (error) {
+            console.error('Error reading PDF metadata:', error);
+        }
+    }
+
     // Save new paper
     async function savePaper() {
         const paperFile = document.getElementById('paperFile').files[0];
@@ -87,6 +126,7 @@ document.addEventListener('DOMContentLoaded', function () {
         const year = document.getElementById('paperYear').value;
         const journal = document.getElementById('paperJournal').value;
         const volume = document.getElementById('paperVolume').value;
+        const doi = document.getElementById('paperDoi')?.value || null;
 
         if (!paperFile || !title || !authors || !year || !journal) {
             showAlert('Please fill in all required fields.', 'warning', 'addPaperModal');
@@ -97,7 +137,7 @@ document.addEventListener('DOMContentLoaded', function () {
             const formData = new FormData();
             formData.append('pdf', paperFile);
             formData.append('bibtexData', JSON.stringify({
-                title, authors, year, journal, volume
+                title, authors, year, journal, volume, doi
             }));
 
             const response = await fetch('/api/pdfs', {
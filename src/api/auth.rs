@@ -0,0 +1,55 @@
+use axum::routing::{get, post};
+use axum::{Json, Router};
+use axum_extra::extract::cookie::CookieJar;
+use serde::Deserialize;
+
+use super::ApiResult;
+use crate::auth::{self, CurrentUser};
+use crate::db::establish;
+use crate::models::*;
+
+pub fn router<S>() -> Router<S>
+where
+    S: Clone + Send + Sync + 'static,
+{
+    Router::new()
+        .route("/api/auth/register", post(register))
+        .route("/api/auth/login", post(login))
+        .route("/api/auth/logout", post(logout))
+        .route("/api/auth/me", get(me))
+}
+
+#[derive(Deserialize)]
+pub struct Credentials {
+    pub username: String,
+    pub password: String,
+}
+
+async fn register(
+    jar: CookieJar,
+    Json(body): Json<Credentials>,
+) -> ApiResult<(CookieJar, Json<User>)> {
+    let mut conn = establish().await?;
+    let (user, token) = auth::register(&body.username, &body.password, &mut conn).await?;
+    Ok((jar.add(auth::session_cookie(token)), Json(user)))
+}
+
+async fn login(
+    jar: CookieJar,
+    Json(body): Json<Credentials>,
+) -> ApiResult<(CookieJar, Json<User>)> {
+    let mut conn = establish().await?;
+    let (user, token) = auth::login(&body.username, &body.password, &mut conn).await?;
+    Ok((jar.add(auth::session_cookie(token)), Json(user)))
+}
+
+async fn logout(jar: CookieJar, current: CurrentUser) -> ApiResult<CookieJar> {
+    let session = current.require_session()?;
+    let mut conn = establish().await?;
+    Session::delete(&session.token_hash, &mut conn).await?;
+    Ok(jar.add(auth::removal_cookie()))
+}
+
+async fn me(current: CurrentUser) -> Json<User> {
+    Json(current.user)
+}
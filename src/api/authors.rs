@@ -0,0 +1,114 @@
+use axum::extract::{Path, Query};
+use axum::routing::{get, put};
+use axum::{Json, Router};
+use serde::{Deserialize, Serialize};
+
+use super::ApiResult;
+use crate::auth::CurrentUser;
+use crate::db::establish;
+use crate::error::MolmineError;
+use crate::models::*;
+
+pub fn router<S>() -> Router<S>
+where
+    S: Clone + Send + Sync + 'static,
+{
+    Router::new()
+        .route("/api/authors", get(search_authors))
+        .route("/api/authors/:id", get(get_author))
+        .route("/api/authors/:id/orcid", put(set_orcid))
+        .route("/api/pdfs/:id/authors", get(list_pdf_authors))
+}
+
+#[derive(Deserialize)]
+pub struct SearchParams {
+    #[serde(default)]
+    pub q: String,
+}
+
+/// Authors of papers in the active project, optionally filtered by name or ORCID
+async fn search_authors(
+    current: CurrentUser,
+    Query(params): Query<SearchParams>,
+) -> ApiResult<Json<Vec<Author>>> {
+    let mut conn = establish().await?;
+    let project_id = current
+        .require_active_project(ProjectRole::Viewer, &mut conn)
+        .await?;
+    Ok(Json(
+        Author::search(project_id, &params.q, &mut conn).await?,
+    ))
+}
+
+/// An author together with their papers in the active project
+#[derive(Serialize)]
+pub struct AuthorView {
+    #[serde(flatten)]
+    pub author: Author,
+    pub pdfs: Vec<PdfInfo>,
+}
+
+async fn get_author(current: CurrentUser, Path(id): Path<AuthorId>) -> ApiResult<Json<AuthorView>> {
+    let mut conn = establish().await?;
+    let project_id = current
+        .require_active_project(ProjectRole::Viewer, &mut conn)
+        .await?;
+    let author = Author::get_by_id(id, &mut conn).await?;
+    if author.project_id != project_id {
+        return Err(MolmineError::NotFound("Author"));
+    }
+    let pdfs = PdfInfo::list_for_author(id, project_id, &mut conn).await?;
+    Ok(Json(AuthorView { author, pdfs }))
+}
+
+#[derive(Deserialize)]
+pub struct OrcidRequest {
+    pub orcid: Option<String>,
+}
+
+async fn set_orcid(
+    current: CurrentUser,
+    Path(id): Path<AuthorId>,
+    Json(body): Json<OrcidRequest>,
+) -> ApiResult<Json<Author>> {
+    let orcid = match body.orcid.as_deref().map(str::trim) {
+        None | Some("") => None,
+        Some(orcid) => Some(
+            normalize_orcid(orcid)
+                .ok_or_else(|| MolmineError::InvalidInput(format!("Invalid ORCID iD: {orcid}")))?,
+        ),
+    };
+    let mut conn = establish().await?;
+    let project_id = current
+        .require_active_project(ProjectRole::Editor, &mut conn)
+        .await?;
+    // Each project has its own authors, so this cannot change another project's
+    if Author::get_by_id(id, &mut conn).await?.project_id != project_id {
+        return Err(MolmineError::NotFound("Author"));
+    }
+    let author = Author::set_orcid(id, orcid.as_deref(), &mut conn)
+        .await
+        .map_err(map_unique_violation)?;
+    Ok(Json(author))
+}
+
+fn map_unique_violation(err: diesel::result::Error) -> MolmineError {
+    use diesel::result::{DatabaseErrorKind, Error};
+    match err {
+        Error::DatabaseError(DatabaseErrorKind::UniqueViolation, _) => MolmineError::InvalidInput(
+            "That ORCID iD already belongs to another author in this project".to_string(),
+        ),
+        err => err.into(),
+    }
+}
+
+async fn list_pdf_authors(
+    current: CurrentUser,
+    Path(pdf_id): Path<PdfId>,
+) -> ApiResult<Json<Vec<Author>>> {
+    let mut conn = establish().await?;
+    current
+        .require_pdf(pdf_id, ProjectRole::Viewer, &mut conn)
+        .await?;
+    Ok(Json(Author::list_for_pdf(pdf_id, &mut conn).await?))
+}
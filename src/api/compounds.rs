@@ -0,0 +1,501 @@
+use axum::body::Body;
+use axum::extract::{DefaultBodyLimit, Multipart, Path, Query};
+use axum::http::header;
+use axum::response::IntoResponse;
+use axum::routing::{get, post};
+use axum::{Json, Router};
+use diesel::OptionalExtension;
+use serde::Deserialize;
+use serde_json::{Value, json};
+
+use super::{ApiResult, multipart_error};
+use crate::auth::CurrentUser;
+use crate::db::{AsyncConn, establish};
+use crate::error::MolmineError;
+use crate::events::{ServerEvent, publish};
+use crate::export::{
+    CmlExport, ColumnarFormat, CsvExport, JsonLdExport, SdfExport, TableOptions, write_columnar,
+    write_xlsx,
+};
+use crate::import::{CompoundFormat, CompoundImportReport, ImportTarget, import_compounds};
+use crate::labels::{link_mentions, normalize_label};
+use crate::models::*;
+
+/// Compound lists from collaborators can hold tens of thousands of structures
+const MAX_COMPOUND_FILE_SIZE: usize = 100 * 1024 * 1024;
+
+pub fn router<S>() -> Router<S>
+where
+    S: Clone + Send + Sync + 'static,
+{
+    // GET takes a PDF id and lists its compounds; PUT and DELETE take a compound id
+    Router::new()
+        .route("/api/compounds", post(create_compound))
+        .route("/api/compounds/export", get(export_compounds))
+        .route(
+            "/api/compounds/import",
+            post(import_compound_file).layer(DefaultBodyLimit::max(MAX_COMPOUND_FILE_SIZE)),
+        )
+        .route(
+            "/api/compounds/:id",
+            get(list_compounds)
+                .put(update_compound)
+                .delete(delete_compound),
+        )
+}
+
+/// A compound as sent by the browser, with `chemical_data` as a JSON object
+#[derive(Deserialize)]
+pub struct CompoundRequest {
+    pub pdf_id: PdfId,
+    pub smiles: String,
+    /// Left out when updating to keep the stored InChI, like `label`, `image` and
+    /// `chemical_data`
+    #[serde(default)]
+    pub inchi: Option<String>,
+    #[serde(default)]
+    pub label: Option<String>,
+    #[serde(default)]
+    pub image: Option<String>,
+    #[serde(default)]
+    pub chemical_data: Value,
+    /// The recognition the compound was drawn from, if any. What was recognized is taken
+    /// from the server's record of it.
+    #[serde(default)]
+    pub recognition_id: Option<RecognitionId>,
+}
+
+/// The request's `chemical_data` encoded for storage, or `None` if it was left out
+fn encode_chemical_data(data: Value) -> Option<String> {
+    match data {
+        Value::Null => None,
+        // Older pages send the data already encoded as a string
+        Value::String(encoded) => Some(encoded),
+        data => Some(data.to_string()),
+    }
+}
+
+impl From<CompoundRequest> for NewCompound {
+    fn from(request: CompoundRequest) -> Self {
+        NewCompound {
+            pdf_id: request.pdf_id,
+            smiles: request.smiles,
+            inchi: request.inchi.unwrap_or_default(),
+            image: request.image.unwrap_or_default(),
+            chemical_data: encode_chemical_data(request.chemical_data)
+                .unwrap_or_else(|| "{}".to_string()),
+            recognition_backend: None,
+            recognized_smiles: None,
+            recognized_inchi: None,
+            recognition_confidence: None,
+            label: normalize_label(request.label.as_deref().unwrap_or_default()),
+            markush_source_id: None,
+            rgroup_assignments: None,
+        }
+    }
+}
+
+impl From<CompoundRequest> for CompoundChanges {
+    fn from(request: CompoundRequest) -> Self {
+        CompoundChanges {
+            pdf_id: request.pdf_id,
+            smiles: request.smiles,
+            inchi: request.inchi,
+            image: request.image,
+            chemical_data: encode_chemical_data(request.chemical_data),
+            recognition_backend: None,
+            recognized_smiles: None,
+            recognized_inchi: None,
+            recognition_confidence: None,
+            label: request.label.as_deref().map(normalize_label),
+        }
+    }
+}
+
+/// The server's record of the recognition a compound was drawn from. Recognitions made in
+/// another project are treated as missing.
+async fn cited_recognition(
+    recognition_id: Option<RecognitionId>,
+    pdf: &PdfInfo,
+    conn: &mut AsyncConn,
+) -> Result<Option<RecognitionRecord>, MolmineError> {
+    let Some(recognition_id) = recognition_id else {
+        return Ok(None);
+    };
+    let record = RecognitionRecord::get_by_id(recognition_id, conn)
+        .await
+        .optional()?
+        .filter(|record| Some(record.project_id) == pdf.project_id)
+        .ok_or(MolmineError::NotFound("Recognition"))?;
+    Ok(Some(record))
+}
+
+async fn create_compound(
+    current: CurrentUser,
+    Json(body): Json<CompoundRequest>,
+) -> ApiResult<Json<Compound>> {
+    let mut conn = establish().await?;
+    let pdf = current
+        .require_pdf(body.pdf_id, ProjectRole::Editor, &mut conn)
+        .await?;
+    let recognition = cited_recognition(body.recognition_id, &pdf, &mut conn).await?;
+    let mut new = NewCompound::from(body);
+    if let Some(record) = recognition {
+        new.recognition_backend = Some(record.backend);
+        new.recognized_smiles = Some(record.smiles);
+        new.recognized_inchi = record.inchi;
+        new.recognition_confidence = record.confidence;
+    }
+    let compound = new.insert(&mut conn).await?;
+    if !compound.label.is_empty() {
+        relink_mentions(compound.pdf_id, &mut conn).await;
+    }
+    publish(
+        pdf.project_id,
+        ServerEvent::CompoundCreated {
+            compound_id: compound.id,
+            pdf_id: compound.pdf_id,
+            smiles: compound.smiles.clone(),
+        },
+    );
+    Ok(Json(compound))
+}
+
+async fn list_compounds(
+    current: CurrentUser,
+    Path(pdf_id): Path<PdfId>,
+) -> ApiResult<Json<Vec<Compound>>> {
+    let mut conn = establish().await?;
+    current
+        .require_pdf(pdf_id, ProjectRole::Viewer, &mut conn)
+        .await?;
+    Ok(Json(Compound::list_for_pdf(pdf_id, &mut conn).await?))
+}
+
+async fn update_compound(
+    current: CurrentUser,
+    Path(id): Path<CompoundId>,
+    Json(body): Json<CompoundRequest>,
+) -> ApiResult<Json<Compound>> {
+    let mut conn = establish().await?;
+    let old = current
+        .require_compound(id, ProjectRole::Editor, &mut conn)
+        .await?;
+    // Moving a compound to another paper needs edit rights there too
+    let pdf = current
+        .require_pdf(body.pdf_id, ProjectRole::Editor, &mut conn)
+        .await?;
+    let recognition = cited_recognition(body.recognition_id, &pdf, &mut conn).await?;
+    let mut changes = CompoundChanges::from(body);
+    if let Some(record) = recognition {
+        changes.recognition_backend = Some(record.backend);
+        changes.recognized_smiles = Some(record.smiles);
+        changes.recognized_inchi = record.inchi;
+        changes.recognition_confidence = record.confidence;
+    }
+    let compound = Compound::update(id, &changes, &mut conn).await?;
+    if compound.label != old.label || compound.pdf_id != old.pdf_id {
+        relink_mentions(compound.pdf_id, &mut conn).await;
+    }
+    if compound.pdf_id != old.pdf_id && !old.label.is_empty() {
+        relink_mentions(old.pdf_id, &mut conn).await;
+    }
+    Ok(Json(compound))
+}
+
+/// Finds the mentions of the paper's compounds again after their labels changed. The
+/// compounds are saved either way, so a failure is only logged.
+pub(crate) async fn relink_mentions(pdf_id: PdfId, conn: &mut AsyncConn) {
+    if let Err(e) = link_mentions(pdf_id, conn).await {
+        tracing::warn!("Could not link compound mentions in PDF {}: {e}", pdf_id.0);
+    }
+}
+
+async fn delete_compound(
+    current: CurrentUser,
+    Path(id): Path<CompoundId>,
+) -> ApiResult<Json<Value>> {
+    let mut conn = establish().await?;
+    current
+        .require_compound(id, ProjectRole::Editor, &mut conn)
+        .await?;
+    Compound::delete(id, &mut conn).await?;
+    Ok(Json(json!({ "success": true })))
+}
+
+/// A structure–data file format compounds can be exported in
+#[derive(Copy, Clone, Debug, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum CompoundExportFormat {
+    Sdf,
+    Csv,
+    Xlsx,
+    Parquet,
+    Arrow,
+    Cml,
+    Jsonld,
+}
+
+#[derive(Deserialize)]
+pub struct CompoundExportParams {
+    pub format: CompoundExportFormat,
+    /// Add RDKit descriptor columns (all but SDF)
+    #[serde(default)]
+    pub descriptors: bool,
+    /// Embed the captured structure images (XLSX)
+    #[serde(default)]
+    pub images: bool,
+    /// Add RDKit fingerprints as a binary column (Parquet and Arrow)
+    #[serde(default)]
+    pub fingerprints: bool,
+}
+
+/// Downloads every compound of the active project. SDF, CSV, CML and JSON-LD are streamed.
+async fn export_compounds(
+    current: CurrentUser,
+    Query(params): Query<CompoundExportParams>,
+) -> ApiResult<impl IntoResponse> {
+    let mut conn = establish().await?;
+    let project_id = current
+        .require_active_project(ProjectRole::Viewer, &mut conn)
+        .await?;
+    let options = TableOptions {
+        descriptors: params.descriptors,
+        images: params.images,
+        fingerprints: params.fingerprints,
+    };
+    let (content_type, extension, body) = match params.format {
+        CompoundExportFormat::Sdf => {
+            let export = SdfExport::new(project_id, conn).await?;
+            (
+                "chemical/x-mdl-sdfile",
+                "sdf",
+                Body::from_stream(export.into_stream()),
+            )
+        }
+        CompoundExportFormat::Csv => {
+            let export = CsvExport::new(project_id, options, conn).await?;
+            (
+                "text/csv; charset=utf-8",
+                "csv",
+                Body::from_stream(export.into_stream()),
+            )
+        }
+        CompoundExportFormat::Cml => {
+            let export = CmlExport::new(project_id, conn).await?;
+            (
+                "chemical/x-cml",
+                "cml",
+                Body::from_stream(export.into_stream()),
+            )
+        }
+        CompoundExportFormat::Jsonld => {
+            let export = JsonLdExport::new(project_id, conn).await?;
+            (
+                "application/ld+json",
+                "jsonld",
+                Body::from_stream(export.into_stream()),
+            )
+        }
+        CompoundExportFormat::Xlsx => (
+            "application/vnd.openxmlformats-officedocument.spreadsheetml.sheet",
+            "xlsx",
+            Body::from(write_xlsx(project_id, options, conn).await?),
+        ),
+        CompoundExportFormat::Parquet | CompoundExportFormat::Arrow => {
+            let format = match params.format {
+                CompoundExportFormat::Parquet => ColumnarFormat::Parquet,
+                _ => ColumnarFormat::Arrow,
+            };
+            (
+                format.content_type(),
+                format.extension(),
+                Body::from(write_columnar(project_id, format, options, conn).await?),
+            )
+        }
+    };
+    let disposition = format!("attachment; filename=\"compounds.{extension}\"");
+    Ok((
+        [
+            (header::CONTENT_TYPE, content_type.to_string()),
+            (header::CONTENT_DISPOSITION, disposition),
+        ],
+        body,
+    ))
+}
+
+#[derive(Deserialize)]
+pub struct CompoundImportParams {
+    #[serde(default)]
+    pub dry_run: bool,
+    pub format: Option<CompoundFormat>,
+    /// The paper to attach the compounds to; without one they go to the project's
+    /// "External source" placeholder
+    pub pdf_id: Option<PdfId>,
+}
+
+/// Creates compounds in the active project from the `.sdf`, `.smi` or `.csv` file in the
+/// multipart `file` field. Records that fail to parse are reported, not fatal.
+async fn import_compound_file(
+    current: CurrentUser,
+    Query(params): Query<CompoundImportParams>,
+    mut multipart: Multipart,
+) -> ApiResult<Json<CompoundImportReport>> {
+    let mut upload = None;
+    while let Some(field) = multipart.next_field().await.map_err(multipart_error)? {
+        if field.name() == Some("file") {
+            let filename = field.file_name().map(str::to_string);
+            let text = field.text().await.map_err(multipart_error)?;
+            upload = Some((filename, text));
+        }
+    }
+    let (filename, text) =
+        upload.ok_or_else(|| MolmineError::InvalidInput("No file uploaded".to_string()))?;
+    let format = params
+        .format
+        .or_else(|| CompoundFormat::detect(filename.as_deref(), &text))
+        .ok_or_else(|| MolmineError::InvalidInput("The file is empty".to_string()))?;
+
+    let mut conn = establish().await?;
+    let project_id = current
+        .require_active_project(ProjectRole::Editor, &mut conn)
+        .await?;
+    let target = match params.pdf_id {
+        Some(pdf_id) => {
+            let pdf = current
+                .require_pdf(pdf_id, ProjectRole::Editor, &mut conn)
+                .await?;
+            if pdf.project_id.is_some_and(|id| id != project_id) {
+                return Err(MolmineError::InvalidInput(
+                    "The PDF belongs to another project".to_string(),
+                ));
+            }
+            ImportTarget::Pdf(pdf_id)
+        }
+        None => ImportTarget::ExternalSource,
+    };
+    let report =
+        import_compounds(&text, format, project_id, target, params.dry_run, &mut conn).await?;
+    if let (false, Some(pdf_id)) = (report.dry_run, report.pdf_id) {
+        relink_mentions(pdf_id, &mut conn).await;
+    }
+    publish_import(project_id, filename.as_deref(), &report);
+    Ok(Json(report))
+}
+
+/// Tells the pages open on the project about the compounds an import created and the
+/// records it skipped
+fn publish_import(project_id: ProjectId, filename: Option<&str>, report: &CompoundImportReport) {
+    if report.dry_run {
+        return;
+    }
+    if let Some(pdf_id) = report.pdf_id {
+        for imported in &report.imported {
+            if let Some(compound_id) = imported.compound_id {
+                publish(
+                    Some(project_id),
+                    ServerEvent::CompoundCreated {
+                        compound_id,
+                        pdf_id,
+                        smiles: imported.smiles.clone(),
+                    },
+                );
+            }
+        }
+    }
+    for failure in &report.failures {
+        publish(
+            Some(project_id),
+            ServerEvent::ValidationError {
+                source: format!(
+                    "{} record {} (line {})",
+                    filename.unwrap_or("Import"),
+                    failure.record,
+                    failure.line
+                ),
+                message: failure.reason.clone(),
+            },
+        );
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::db::establish_test;
+
+    #[tokio::test]
+    async fn test_update_keeps_omitted_fields() {
+        let mut conn = establish_test().await.unwrap();
+        let project = fixtures::insert_project(&mut conn).await;
+        let pdf = fixtures::insert_pdf(project.id, &mut conn).await;
+        let compound = NewCompound {
+            label: "7a".to_string(),
+            image: "data:image/png;base64,AAAA".to_string(),
+            ..fixtures::new_compound(pdf.id)
+        }
+        .insert(&mut conn)
+        .await
+        .unwrap();
+
+        let request: CompoundRequest =
+            serde_json::from_value(json!({ "pdf_id": pdf.id, "smiles": "CC(=O)O" })).unwrap();
+        let updated = Compound::update(compound.id, &CompoundChanges::from(request), &mut conn)
+            .await
+            .unwrap();
+        assert_eq!(updated.smiles, "CC(=O)O");
+        assert_eq!(updated.inchi, compound.inchi);
+        assert_eq!(updated.label, "7a");
+        assert_eq!(updated.image, compound.image);
+        assert_eq!(updated.chemical_data, compound.chemical_data);
+        fixtures::delete_project(project.id, &mut conn).await;
+    }
+
+    #[tokio::test]
+    async fn test_cited_recognition() {
+        let mut conn = establish_test().await.unwrap();
+        let project = fixtures::insert_project(&mut conn).await;
+        let other = fixtures::insert_project(&mut conn).await;
+        let pdf = fixtures::insert_pdf(project.id, &mut conn).await;
+        let pdf = PdfInfo::get_by_id(pdf.id, &mut conn).await.unwrap();
+        let record = |project_id| NewRecognitionRecord {
+            project_id,
+            region_id: None,
+            backend: "mock".to_string(),
+            smiles: "c1ccccc1".to_string(),
+            inchi: None,
+            confidence: Some(0.8),
+        };
+        let own = record(project.id).insert(&mut conn).await.unwrap();
+        let foreign = record(other.id).insert(&mut conn).await.unwrap();
+
+        // Whatever the browser claims was recognized is ignored
+        let request: CompoundRequest = serde_json::from_value(json!({
+            "pdf_id": pdf.id,
+            "smiles": "Cc1ccccc1",
+            "recognition_id": own.id,
+            "recognition": { "smiles": "CCO", "backend": "forged", "confidence": 1.0 },
+        }))
+        .unwrap();
+        let cited = cited_recognition(request.recognition_id, &pdf, &mut conn)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(cited.smiles, "c1ccccc1");
+        assert_eq!(cited.backend, "mock");
+        assert!(NewCompound::from(request).recognized_smiles.is_none());
+
+        assert!(matches!(
+            cited_recognition(Some(foreign.id), &pdf, &mut conn).await,
+            Err(MolmineError::NotFound("Recognition"))
+        ));
+        assert!(
+            cited_recognition(None, &pdf, &mut conn)
+                .await
+                .unwrap()
+                .is_none()
+        );
+        fixtures::delete_project(project.id, &mut conn).await;
+        fixtures::delete_project(other.id, &mut conn).await;
+    }
+}
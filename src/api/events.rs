@@ -0,0 +1,59 @@
+use axum::Router;
+use axum::response::sse::{Event, KeepAlive, Sse};
+use axum::routing::get;
+use futures::Stream;
+use tokio::sync::broadcast::error::RecvError;
+
+use super::ApiResult;
+use crate::auth::CurrentUser;
+use crate::db::establish;
+use crate::events::subscribe;
+use crate::models::*;
+
+pub fn router<S>() -> Router<S>
+where
+    S: Clone + Send + Sync + 'static,
+{
+    Router::new().route("/api/events", get(stream_events))
+}
+
+/// Streams the active project's events as server-sent events named after their
+/// `type`. A `lagged` event with the number of missed events is sent if the
+/// connection falls behind, after which pages should reload what they show. The
+/// user's role is checked again before each event, so the stream ends once they are
+/// removed from the project.
+async fn stream_events(
+    current: CurrentUser,
+) -> ApiResult<Sse<impl Stream<Item = Result<Event, axum::Error>>>> {
+    let mut conn = establish().await?;
+    let project_id = current
+        .require_active_project(ProjectRole::Viewer, &mut conn)
+        .await?;
+    let state = (subscribe(), current, conn);
+    let events =
+        futures::stream::unfold(state, move |(mut events, current, mut conn)| async move {
+            loop {
+                let event = match events.recv().await {
+                    Ok(event) if event.project_id == Some(project_id) => {
+                        if current
+                            .require_project(project_id, ProjectRole::Viewer, &mut conn)
+                            .await
+                            .is_err()
+                        {
+                            return None;
+                        }
+                        Event::default()
+                            .event(event.event.name())
+                            .json_data(&event.event)
+                    }
+                    Ok(_) => continue,
+                    Err(RecvError::Lagged(missed)) => {
+                        Ok(Event::default().event("lagged").data(missed.to_string()))
+                    }
+                    Err(RecvError::Closed) => return None,
+                };
+                return Some((event, (events, current, conn)));
+            }
+        });
+    Ok(Sse::new(events).keep_alive(KeepAlive::default()))
+}
@@ -0,0 +1,195 @@
+use axum::body::Body;
+use axum::extract::{Path, Query};
+use axum::http::header;
+use axum::response::IntoResponse;
+use axum::routing::{get, post};
+use axum::{Json, Router};
+use chrono::NaiveDateTime;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use super::ApiResult;
+use crate::auth::CurrentUser;
+use crate::db::{AsyncConn, establish};
+use crate::error::MolmineError;
+use crate::jobs::{JobTask, enqueue, export_path};
+use crate::models::*;
+
+const DEFAULT_JOB_LIMIT: i64 = 50;
+const MAX_JOB_LIMIT: i64 = 500;
+
+pub fn router<S>() -> Router<S>
+where
+    S: Clone + Send + Sync + 'static,
+{
+    Router::new()
+        .route("/api/jobs", get(list_jobs).post(create_job))
+        .route("/api/jobs/:id", get(get_job))
+        .route("/api/jobs/:id/cancel", post(cancel_job))
+        .route("/api/jobs/:id/download", get(download_export))
+}
+
+/// A job as sent to the browser, with its task and result as JSON rather than text
+#[derive(Serialize)]
+pub struct JobResponse {
+    pub id: JobId,
+    pub project_id: Option<ProjectId>,
+    pub kind: String,
+    pub task: Value,
+    pub status: JobStatus,
+    pub attempts: i32,
+    pub max_attempts: i32,
+    pub cancel_requested: bool,
+    pub result: Option<Value>,
+    pub error: Option<String>,
+    pub run_after: NaiveDateTime,
+    pub created_at: NaiveDateTime,
+    pub started_at: Option<NaiveDateTime>,
+    pub finished_at: Option<NaiveDateTime>,
+    pub progress: Option<f64>,
+}
+
+impl From<Job> for JobResponse {
+    fn from(job: Job) -> Self {
+        JobResponse {
+            id: job.id,
+            project_id: job.project_id,
+            kind: job.kind,
+            task: serde_json::from_str(&job.task).unwrap_or(Value::Null),
+            status: job.status,
+            attempts: job.attempts,
+            max_attempts: job.max_attempts,
+            cancel_requested: job.cancel_requested,
+            result: job
+                .result
+                .and_then(|result| serde_json::from_str(&result).ok()),
+            error: job.error,
+            run_after: job.run_after,
+            created_at: job.created_at,
+            started_at: job.started_at,
+            finished_at: job.finished_at,
+            progress: job.progress,
+        }
+    }
+}
+
+/// Loads a job the user may see, with `required` on its project
+async fn require_job(
+    current: &CurrentUser,
+    id: JobId,
+    required: ProjectRole,
+    conn: &mut AsyncConn,
+) -> Result<Job, MolmineError> {
+    let job = Job::get_by_id(id, conn)
+        .await
+        .map_err(|_| MolmineError::NotFound("Job"))?;
+    let project_id = job.project_id.ok_or(MolmineError::NotFound("Job"))?;
+    current.require_project(project_id, required, conn).await?;
+    Ok(job)
+}
+
+/// Queues a job described like `{"kind": "detect_regions", "pdf_id": 3}`. Indexing,
+/// detection and recognition write to the project and take the editor role, while
+/// exports only read.
+async fn create_job(
+    current: CurrentUser,
+    Json(task): Json<JobTask>,
+) -> ApiResult<Json<JobResponse>> {
+    let mut conn = establish().await?;
+    let project_id = match &task {
+        JobTask::IndexText { pdf_id }
+        | JobTask::DetectRegions { pdf_id }
+        | JobTask::RecognizePdf { pdf_id } => {
+            current
+                .require_pdf(*pdf_id, ProjectRole::Editor, &mut conn)
+                .await?
+                .project_id
+        }
+        JobTask::RecognizeRegion { region_id } => {
+            let region = StructureRegion::get_by_id(*region_id, &mut conn).await?;
+            current
+                .require_pdf(region.pdf_id, ProjectRole::Editor, &mut conn)
+                .await?
+                .project_id
+        }
+        JobTask::Export { .. } => Some(
+            current
+                .require_active_project(ProjectRole::Viewer, &mut conn)
+                .await?,
+        ),
+    };
+    let job = enqueue(&task, project_id, Some(current.user.id), &mut conn).await?;
+    Ok(Json(job.into()))
+}
+
+#[derive(Deserialize)]
+pub struct JobListParams {
+    pub status: Option<JobStatus>,
+    pub limit: Option<i64>,
+}
+
+/// The active project's jobs, newest first
+async fn list_jobs(
+    current: CurrentUser,
+    Query(params): Query<JobListParams>,
+) -> ApiResult<Json<Vec<JobResponse>>> {
+    let mut conn = establish().await?;
+    let project_id = current
+        .require_active_project(ProjectRole::Viewer, &mut conn)
+        .await?;
+    let limit = params
+        .limit
+        .unwrap_or(DEFAULT_JOB_LIMIT)
+        .clamp(1, MAX_JOB_LIMIT);
+    let jobs = Job::list_for_project(project_id, params.status, limit, &mut conn).await?;
+    Ok(Json(jobs.into_iter().map(JobResponse::from).collect()))
+}
+
+async fn get_job(current: CurrentUser, Path(id): Path<JobId>) -> ApiResult<Json<JobResponse>> {
+    let mut conn = establish().await?;
+    let job = require_job(&current, id, ProjectRole::Viewer, &mut conn).await?;
+    Ok(Json(job.into()))
+}
+
+/// Cancels a job. Editors may cancel any job on the project, others only their own.
+async fn cancel_job(current: CurrentUser, Path(id): Path<JobId>) -> ApiResult<Json<JobResponse>> {
+    let mut conn = establish().await?;
+    let job = require_job(&current, id, ProjectRole::Viewer, &mut conn).await?;
+    if job.created_by != Some(current.user.id)
+        && let Some(project_id) = job.project_id
+    {
+        current
+            .require_project(project_id, ProjectRole::Editor, &mut conn)
+            .await?;
+    }
+    Ok(Json(Job::cancel(id, &mut conn).await?.into()))
+}
+
+/// Downloads the file written by a finished export job
+async fn download_export(
+    current: CurrentUser,
+    Path(id): Path<JobId>,
+) -> ApiResult<impl IntoResponse> {
+    let mut conn = establish().await?;
+    let job = require_job(&current, id, ProjectRole::Viewer, &mut conn).await?;
+    let format = match serde_json::from_str(&job.task)? {
+        JobTask::Export { format, .. } if job.status == JobStatus::Succeeded => format,
+        JobTask::Export { .. } => {
+            return Err(MolmineError::InvalidInput(
+                "The export has not finished".to_string(),
+            ));
+        }
+        _ => return Err(MolmineError::NotFound("Export")),
+    };
+    let data = tokio::fs::read(export_path(id, format))
+        .await
+        .map_err(|_| MolmineError::NotFound("Export file"))?;
+    let disposition = format!("attachment; filename=\"compounds.{}\"", format.extension());
+    Ok((
+        [
+            (header::CONTENT_TYPE, format.content_type().to_string()),
+            (header::CONTENT_DISPOSITION, disposition),
+        ],
+        Body::from(data),
+    ))
+}
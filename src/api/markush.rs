@@ -0,0 +1,106 @@
+use axum::extract::Path;
+use axum::routing::get;
+use axum::{Json, Router};
+use serde::{Deserialize, Serialize};
+
+use super::ApiResult;
+use super::compounds::relink_mentions;
+use crate::auth::CurrentUser;
+use crate::db::establish;
+use crate::events::{ServerEvent, publish};
+use crate::markush::{MarkushReport, RGroupRow, add_markush};
+use crate::models::*;
+
+pub fn router<S>() -> Router<S>
+where
+    S: Clone + Send + Sync + 'static,
+{
+    Router::new()
+        .route(
+            "/api/pdfs/:id/markush",
+            get(list_markush).post(create_markush),
+        )
+        .route("/api/markush/:id", get(get_markush))
+}
+
+/// A core with attachment points such as `[R1]` and its R-group table
+#[derive(Deserialize)]
+pub struct MarkushRequest {
+    /// Where the paper shows the structure, such as "Table 2"
+    #[serde(default)]
+    pub name: String,
+    pub core: String,
+    pub rows: Vec<RGroupRow>,
+    #[serde(default)]
+    pub dry_run: bool,
+}
+
+#[derive(Serialize)]
+pub struct MarkushResponse {
+    #[serde(flatten)]
+    pub source: MarkushSource,
+    pub compounds: Vec<Compound>,
+}
+
+/// Enumerates the paper's generic structure into compounds, or with `dry_run` only shows
+/// what they would be
+async fn create_markush(
+    current: CurrentUser,
+    Path(pdf_id): Path<PdfId>,
+    Json(body): Json<MarkushRequest>,
+) -> ApiResult<Json<MarkushReport>> {
+    let mut conn = establish().await?;
+    let pdf = current
+        .require_pdf(pdf_id, ProjectRole::Editor, &mut conn)
+        .await?;
+    let report = add_markush(
+        pdf_id,
+        &body.name,
+        &body.core,
+        body.rows,
+        body.dry_run,
+        &mut conn,
+    )
+    .await?;
+    if report.source.is_some() {
+        relink_mentions(pdf_id, &mut conn).await;
+        for compound in &report.enumerated {
+            if let Some(compound_id) = compound.compound_id {
+                publish(
+                    pdf.project_id,
+                    ServerEvent::CompoundCreated {
+                        compound_id,
+                        pdf_id,
+                        smiles: compound.smiles.clone(),
+                    },
+                );
+            }
+        }
+    }
+    Ok(Json(report))
+}
+
+async fn list_markush(
+    current: CurrentUser,
+    Path(pdf_id): Path<PdfId>,
+) -> ApiResult<Json<Vec<MarkushSource>>> {
+    let mut conn = establish().await?;
+    current
+        .require_pdf(pdf_id, ProjectRole::Viewer, &mut conn)
+        .await?;
+    Ok(Json(MarkushSource::list_for_pdf(pdf_id, &mut conn).await?))
+}
+
+/// The generic structure with the compounds enumerated from it
+async fn get_markush(
+    current: CurrentUser,
+    Path(id): Path<MarkushSourceId>,
+) -> ApiResult<Json<MarkushResponse>> {
+    let mut conn = establish().await?;
+    let source = MarkushSource::get_by_id(id, &mut conn).await?;
+    current
+        .require_pdf(source.pdf_id, ProjectRole::Viewer, &mut conn)
+        .await?;
+    let compounds = MarkushSource::compounds(id, &mut conn).await?;
+    Ok(Json(MarkushResponse { source, compounds }))
+}
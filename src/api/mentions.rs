@@ -0,0 +1,60 @@
+use axum::extract::Path;
+use axum::routing::{get, post};
+use axum::{Json, Router};
+use serde_json::{Value, json};
+
+use super::ApiResult;
+use crate::auth::CurrentUser;
+use crate::db::establish;
+use crate::labels::link_mentions;
+use crate::models::*;
+
+pub fn router<S>() -> Router<S>
+where
+    S: Clone + Send + Sync + 'static,
+{
+    Router::new()
+        .route("/api/compounds/:id/mentions", get(compound_mentions))
+        .route("/api/pdfs/:id/mentions", get(pdf_mentions))
+        .route("/api/pdfs/:id/mentions/link", post(link_pdf_mentions))
+}
+
+/// The pages and sentences of its paper that refer to the compound by its label
+async fn compound_mentions(
+    current: CurrentUser,
+    Path(id): Path<CompoundId>,
+) -> ApiResult<Json<Vec<CompoundMention>>> {
+    let mut conn = establish().await?;
+    current
+        .require_compound(id, ProjectRole::Viewer, &mut conn)
+        .await?;
+    Ok(Json(
+        CompoundMention::list_for_compound(id, &mut conn).await?,
+    ))
+}
+
+async fn pdf_mentions(
+    current: CurrentUser,
+    Path(pdf_id): Path<PdfId>,
+) -> ApiResult<Json<Vec<CompoundMention>>> {
+    let mut conn = establish().await?;
+    current
+        .require_pdf(pdf_id, ProjectRole::Viewer, &mut conn)
+        .await?;
+    Ok(Json(
+        CompoundMention::list_for_pdf(pdf_id, &mut conn).await?,
+    ))
+}
+
+/// Finds the mentions of the paper's compounds again, such as after labelling many of them
+async fn link_pdf_mentions(
+    current: CurrentUser,
+    Path(pdf_id): Path<PdfId>,
+) -> ApiResult<Json<Value>> {
+    let mut conn = establish().await?;
+    current
+        .require_pdf(pdf_id, ProjectRole::Editor, &mut conn)
+        .await?;
+    let mentions = link_mentions(pdf_id, &mut conn).await?;
+    Ok(Json(json!({ "mentions": mentions })))
+}
@@ -0,0 +1,51 @@
+//! JSON endpoints under `/api` used by the pages in `public/`.
+//!
+//! Every handler takes a [`CurrentUser`](crate::auth::CurrentUser) and checks the
+//! caller's role on the project it touches before reading or writing anything.
+//! Requests authenticate with the session cookie or an `Authorization: Bearer`
+//! API token; token requests pick their project with the `X-Molmine-Project` header.
+
+pub mod auth;
+pub mod authors;
+pub mod compounds;
+pub mod events;
+pub mod jobs;
+pub mod markush;
+pub mod mentions;
+pub mod pdfs;
+pub mod projects;
+pub mod recognition;
+pub mod regions;
+pub mod tables;
+pub mod tokens;
+
+use axum::Router;
+
+use crate::error::MolmineError;
+
+pub type ApiResult<T> = Result<T, MolmineError>;
+
+/// Malformed multipart bodies are the client's fault
+fn multipart_error(err: axum::extract::multipart::MultipartError) -> MolmineError {
+    MolmineError::InvalidInput(err.body_text())
+}
+
+pub fn router<S>() -> Router<S>
+where
+    S: Clone + Send + Sync + 'static,
+{
+    Router::new()
+        .merge(auth::router())
+        .merge(projects::router())
+        .merge(pdfs::router())
+        .merge(compounds::router())
+        .merge(tokens::router())
+        .merge(authors::router())
+        .merge(regions::router())
+        .merge(tables::router())
+        .merge(mentions::router())
+        .merge(markush::router())
+        .merge(recognition::router())
+        .merge(jobs::router())
+        .merge(events::router())
+}
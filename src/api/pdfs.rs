@@ -0,0 +1,531 @@
+use std::collections::HashMap;
+
+use axum::extract::{DefaultBodyLimit, Multipart, Path, Query};
+use axum::http::{HeaderMap, StatusCode, header};
+use axum::response::IntoResponse;
+use axum::routing::{get, post, put};
+use axum::{Json, Router};
+use serde::Deserialize;
+use serde_json::{Value, json};
+
+use super::{ApiResult, multipart_error};
+use crate::auth::CurrentUser;
+use crate::bibliography::{
+    BibFormat, ExportFormat, ImportOptions, ImportReport, import_bibliography, load_citations,
+    write_bibliography,
+};
+use crate::db::establish;
+use crate::error::MolmineError;
+use crate::jobs::{JobTask, enqueue};
+use crate::models::*;
+use crate::pdf::{self, PaperSuggestion, Region, RenderRequest};
+
+/// Uploads are far larger than axum's default 2 MB body limit
+const MAX_PDF_SIZE: usize = 200 * 1024 * 1024;
+/// Exported reference libraries are text, but can still run to thousands of entries
+const MAX_BIBLIOGRAPHY_SIZE: usize = 20 * 1024 * 1024;
+
+pub fn router<S>() -> Router<S>
+where
+    S: Clone + Send + Sync + 'static,
+{
+    Router::new()
+        .route(
+            "/api/pdfs",
+            get(list_pdfs)
+                .post(upload_pdf)
+                .layer(DefaultBodyLimit::max(MAX_PDF_SIZE)),
+        )
+        .route(
+            "/api/pdfs/metadata",
+            post(suggest_metadata).layer(DefaultBodyLimit::max(MAX_PDF_SIZE)),
+        )
+        .route("/api/pdfs/lookup", get(lookup_pdf))
+        .route("/api/pdfs/search", get(search_pdfs))
+        .route("/api/pdfs/export", get(export_pdfs))
+        .route(
+            "/api/pdfs/import",
+            post(import_pdfs).layer(DefaultBodyLimit::max(MAX_BIBLIOGRAPHY_SIZE)),
+        )
+        .route(
+            "/api/pdfs/:id/file",
+            put(attach_file).layer(DefaultBodyLimit::max(MAX_PDF_SIZE)),
+        )
+        .route("/api/pdfs/:id/pages/:page", get(render_page))
+        .route(
+            "/api/pdfs/:id",
+            get(download_pdf).put(update_pdf).delete(delete_pdf),
+        )
+}
+
+/// Bibliographic fields as sent by the upload and edit forms
+#[derive(Deserialize)]
+pub struct PaperRequest {
+    pub title: String,
+    pub authors: String,
+    pub year: YearValue,
+    pub journal: String,
+    #[serde(default)]
+    pub volume: String,
+    #[serde(default)]
+    pub doi: Option<String>,
+    #[serde(default)]
+    pub pmid: Option<String>,
+    #[serde(default)]
+    pub arxiv_id: Option<String>,
+    #[serde(default)]
+    pub isbn: Option<String>,
+    #[serde(default)]
+    pub url: Option<String>,
+    #[serde(default)]
+    pub pages: Option<String>,
+    #[serde(default)]
+    pub issue: Option<String>,
+    #[serde(default)]
+    pub publisher: Option<String>,
+}
+
+/// Trims an optional field, treating blank values as absent
+fn optional_text(value: Option<String>) -> Option<String> {
+    value
+        .map(|value| value.trim().to_string())
+        .filter(|value| !value.is_empty())
+}
+
+/// Normalizes an optional identifier, rejecting values that don't parse
+fn optional_identifier(
+    value: Option<String>,
+    label: &str,
+    normalize: fn(&str) -> Option<String>,
+) -> Result<Option<String>, MolmineError> {
+    optional_text(value)
+        .map(|value| {
+            normalize(&value)
+                .ok_or_else(|| MolmineError::InvalidInput(format!("Invalid {label}: {value}")))
+        })
+        .transpose()
+}
+
+/// Form inputs send the year as a string, scripts usually as a number
+#[derive(Deserialize)]
+#[serde(untagged)]
+pub enum YearValue {
+    Number(i32),
+    Text(String),
+}
+
+impl TryFrom<PaperRequest> for PdfMetadata {
+    type Error = MolmineError;
+
+    fn try_from(request: PaperRequest) -> Result<Self, Self::Error> {
+        let year = match request.year {
+            YearValue::Number(year) => year,
+            YearValue::Text(text) => text
+                .trim()
+                .parse()
+                .map_err(|_| MolmineError::InvalidInput(format!("Invalid year: {text}")))?,
+        };
+        if request.title.trim().is_empty() {
+            return Err(MolmineError::InvalidInput("Title is required".to_string()));
+        }
+        Ok(PdfMetadata {
+            title: request.title.trim().to_string(),
+            authors: request.authors.trim().to_string(),
+            year,
+            journal: request.journal.trim().to_string(),
+            volume: request.volume.trim().to_string(),
+            doi: optional_identifier(request.doi, "DOI", normalize_doi)?,
+            pmid: optional_identifier(request.pmid, "PMID", normalize_pmid)?,
+            arxiv_id: optional_identifier(request.arxiv_id, "arXiv ID", normalize_arxiv_id)?,
+            isbn: optional_identifier(request.isbn, "ISBN", normalize_isbn)?,
+            url: optional_identifier(request.url, "URL", normalize_url)?,
+            pages: optional_text(request.pages),
+            issue: optional_text(request.issue),
+            publisher: optional_text(request.publisher),
+        })
+    }
+}
+
+async fn list_pdfs(current: CurrentUser) -> ApiResult<Json<Vec<PdfInfo>>> {
+    let mut conn = establish().await?;
+    let project_id = current
+        .require_active_project(ProjectRole::Viewer, &mut conn)
+        .await?;
+    Ok(Json(
+        PdfInfo::list_for_project(project_id, &mut conn).await?,
+    ))
+}
+
+async fn upload_pdf(current: CurrentUser, mut multipart: Multipart) -> ApiResult<Json<PdfInfo>> {
+    let PdfUpload { data, fields } = read_pdf_upload(&mut multipart).await?;
+    let details = fields
+        .get("bibtexData")
+        .ok_or_else(|| MolmineError::InvalidInput("Paper details are required".to_string()))?;
+    let metadata = PdfMetadata::try_from(
+        serde_json::from_str::<PaperRequest>(details)
+            .map_err(|e| MolmineError::InvalidInput(format!("Invalid paper details: {e}")))?,
+    )?;
+
+    let mut conn = establish().await?;
+    let project_id = current
+        .require_active_project(ProjectRole::Editor, &mut conn)
+        .await?;
+    let pdf = metadata
+        .into_new_pdf(data, Some(project_id))
+        .insert(&mut conn)
+        .await?;
+    queue_text_index(&current, pdf.id, Some(project_id), &mut conn).await;
+    Ok(Json(PdfInfo::get_by_id(pdf.id, &mut conn).await?))
+}
+
+/// Queues a job adding the file's text to the full-text index. The upload succeeds
+/// even if it can't be queued, as `molmine pdf index` can fill the text in later.
+async fn queue_text_index(
+    current: &CurrentUser,
+    pdf_id: PdfId,
+    project_id: Option<ProjectId>,
+    conn: &mut crate::db::AsyncConn,
+) {
+    let task = JobTask::IndexText { pdf_id };
+    if let Err(e) = enqueue(&task, project_id, Some(current.user.id), conn).await {
+        tracing::warn!("Could not queue indexing the text of PDF {}: {e}", pdf_id.0);
+    }
+}
+
+/// Reads bibliographic details out of a PDF without storing it, so the upload form
+/// can be pre-filled for the user to review
+async fn suggest_metadata(
+    _current: CurrentUser,
+    mut multipart: Multipart,
+) -> ApiResult<Json<PaperSuggestion>> {
+    let PdfUpload { data, .. } = read_pdf_upload(&mut multipart).await?;
+    let suggestion = tokio::task::spawn_blocking(move || pdf::suggest_metadata(&data))
+        .await
+        .map_err(|e| MolmineError::InvalidInput(format!("Failed to read the PDF: {e}")))??;
+    Ok(Json(suggestion))
+}
+
+/// A PDF from the multipart `pdf` field and the form's other fields as text
+struct PdfUpload {
+    data: Vec<u8>,
+    fields: HashMap<String, String>,
+}
+
+/// Reads a PDF upload and checks that the file is a PDF
+async fn read_pdf_upload(multipart: &mut Multipart) -> ApiResult<PdfUpload> {
+    let mut data = None;
+    let mut fields = HashMap::new();
+    while let Some(field) = multipart.next_field().await.map_err(multipart_error)? {
+        match field.name().map(str::to_string) {
+            Some(name) if name == "pdf" => {
+                data = Some(field.bytes().await.map_err(multipart_error)?.to_vec());
+            }
+            Some(name) => {
+                fields.insert(name, field.text().await.map_err(multipart_error)?);
+            }
+            None => {}
+        }
+    }
+    let data =
+        data.ok_or_else(|| MolmineError::InvalidInput("No PDF file uploaded".to_string()))?;
+    if !data.starts_with(b"%PDF-") {
+        return Err(MolmineError::InvalidInput(
+            "The uploaded file is not a PDF".to_string(),
+        ));
+    }
+    Ok(PdfUpload { data, fields })
+}
+
+/// Attaches the file to a paper created without one, e.g. by a bibliography import
+async fn attach_file(
+    current: CurrentUser,
+    Path(id): Path<PdfId>,
+    mut multipart: Multipart,
+) -> ApiResult<Json<PdfInfo>> {
+    let PdfUpload { data, .. } = read_pdf_upload(&mut multipart).await?;
+    let mut conn = establish().await?;
+    let info = current
+        .require_pdf(id, ProjectRole::Editor, &mut conn)
+        .await?;
+    let pdf = Pdf::attach_file(id, data, &mut conn).await?;
+    queue_text_index(&current, id, info.project_id, &mut conn).await;
+    Ok(Json(pdf))
+}
+
+#[derive(Deserialize)]
+pub struct ImportParams {
+    #[serde(default)]
+    pub dry_run: bool,
+    pub format: Option<BibFormat>,
+}
+
+/// Creates papers from the `.bib` or `.ris` file in the multipart `file` field.
+/// With `?dry_run=true` nothing is stored and the report says what would happen.
+async fn import_pdfs(
+    current: CurrentUser,
+    Query(params): Query<ImportParams>,
+    mut multipart: Multipart,
+) -> ApiResult<Json<ImportReport>> {
+    let mut upload = None;
+    while let Some(field) = multipart.next_field().await.map_err(multipart_error)? {
+        if field.name() == Some("file") {
+            let filename = field.file_name().map(str::to_string);
+            let text = field.text().await.map_err(multipart_error)?;
+            upload = Some((filename, text));
+        }
+    }
+    let (filename, text) =
+        upload.ok_or_else(|| MolmineError::InvalidInput("No file uploaded".to_string()))?;
+    let format = params
+        .format
+        .or_else(|| BibFormat::detect(filename.as_deref(), &text))
+        .ok_or_else(|| {
+            MolmineError::InvalidInput("Expected a BibTeX (.bib) or RIS (.ris) file".to_string())
+        })?;
+
+    let mut conn = establish().await?;
+    let project_id = current
+        .require_active_project(ProjectRole::Editor, &mut conn)
+        .await?;
+    let options = ImportOptions {
+        dry_run: params.dry_run,
+        pdf_dir: None,
+    };
+    let report = import_bibliography(&text, format, project_id, &options, &mut conn).await?;
+    Ok(Json(report))
+}
+
+#[derive(Deserialize)]
+pub struct ExportParams {
+    pub format: ExportFormat,
+    /// Comma-separated compound IDs; only the papers they came from are exported
+    pub compounds: Option<String>,
+}
+
+/// Downloads the bibliography of the active project, or of the papers behind a
+/// selection of compounds
+async fn export_pdfs(
+    current: CurrentUser,
+    Query(params): Query<ExportParams>,
+) -> ApiResult<impl IntoResponse> {
+    let compounds = params
+        .compounds
+        .as_deref()
+        .map(|list| {
+            list.split(',')
+                .filter(|id| !id.trim().is_empty())
+                .map(|id| {
+                    id.trim().parse().map(CompoundId).map_err(|_| {
+                        MolmineError::InvalidInput(format!("Invalid compound ID: {id}"))
+                    })
+                })
+                .collect::<Result<Vec<_>, _>>()
+        })
+        .transpose()?;
+    let mut conn = establish().await?;
+    let project_id = current
+        .require_active_project(ProjectRole::Viewer, &mut conn)
+        .await?;
+    let citations = load_citations(project_id, compounds.as_deref(), &mut conn).await?;
+    let body = write_bibliography(&citations, params.format)?;
+    let disposition = format!(
+        "attachment; filename=\"papers.{}\"",
+        params.format.extension()
+    );
+    Ok((
+        [
+            (
+                header::CONTENT_TYPE,
+                params.format.content_type().to_string(),
+            ),
+            (header::CONTENT_DISPOSITION, disposition),
+        ],
+        body,
+    ))
+}
+
+#[derive(Deserialize)]
+pub struct LookupParams {
+    pub doi: Option<String>,
+    pub pmid: Option<String>,
+    pub arxiv_id: Option<String>,
+    pub isbn: Option<String>,
+}
+
+/// Finds the paper in the active project with the given DOI, PMID, arXiv ID or ISBN
+async fn lookup_pdf(
+    current: CurrentUser,
+    Query(params): Query<LookupParams>,
+) -> ApiResult<Json<PdfInfo>> {
+    let (kind, value) = [
+        ("doi", params.doi),
+        ("pmid", params.pmid),
+        ("arxiv_id", params.arxiv_id),
+        ("isbn", params.isbn),
+    ]
+    .into_iter()
+    .find_map(|(kind, value)| value.map(|value| (kind, value)))
+    .ok_or_else(|| {
+        MolmineError::InvalidInput("Give one of doi, pmid, arxiv_id or isbn".to_string())
+    })?;
+    let identifier = PaperIdentifier::parse(kind, &value)
+        .ok_or_else(|| MolmineError::InvalidInput(format!("Invalid {kind}: {value}")))?;
+    let mut conn = establish().await?;
+    let project_id = current
+        .require_active_project(ProjectRole::Viewer, &mut conn)
+        .await?;
+    let pdf = PdfInfo::find_by_identifier(project_id, &identifier, &mut conn)
+        .await?
+        .ok_or(MolmineError::NotFound("PDF"))?;
+    Ok(Json(pdf))
+}
+
+/// Most matching pages returned by a full-text search
+const MAX_SEARCH_RESULTS: i64 = 500;
+
+#[derive(Deserialize)]
+pub struct TextSearchParams {
+    pub q: String,
+    pub limit: Option<i64>,
+}
+
+/// Searches the text, titles, authors and journals of the active project's papers,
+/// returning the matching papers with highlighted snippets of their matching pages
+async fn search_pdfs(
+    current: CurrentUser,
+    Query(params): Query<TextSearchParams>,
+) -> ApiResult<Json<Vec<TextSearchResult>>> {
+    let limit = params.limit.unwrap_or(100).clamp(1, MAX_SEARCH_RESULTS);
+    let mut conn = establish().await?;
+    let project_id = current
+        .require_active_project(ProjectRole::Viewer, &mut conn)
+        .await?;
+    Ok(Json(
+        search_text(project_id, &params.q, limit, &mut conn).await?,
+    ))
+}
+
+#[derive(Deserialize)]
+pub struct DownloadParams {
+    #[serde(default)]
+    pub inline: bool,
+}
+
+async fn download_pdf(
+    current: CurrentUser,
+    Path(id): Path<PdfId>,
+    Query(params): Query<DownloadParams>,
+) -> ApiResult<impl IntoResponse> {
+    let mut conn = establish().await?;
+    current
+        .require_pdf(id, ProjectRole::Viewer, &mut conn)
+        .await?;
+    let pdf = Pdf::get_by_id(id, &mut conn).await?;
+    // Papers imported from a bibliography have no file until one is attached
+    if pdf.data.is_empty() {
+        return Err(MolmineError::NotFound("PDF file"));
+    }
+    let disposition = if params.inline {
+        "inline".to_string()
+    } else {
+        format!("attachment; filename=\"paper-{}.pdf\"", pdf.id.0)
+    };
+    Ok((
+        [
+            (header::CONTENT_TYPE, "application/pdf".to_string()),
+            (header::CONTENT_DISPOSITION, disposition),
+        ],
+        pdf.data,
+    ))
+}
+
+#[derive(Deserialize)]
+pub struct RenderParams {
+    pub dpi: Option<u32>,
+    /// The region to crop to, in PDF points from the top left of the page; give all
+    /// four or none
+    pub x: Option<f32>,
+    pub y: Option<f32>,
+    pub width: Option<f32>,
+    pub height: Option<f32>,
+}
+
+/// Renders a page of the paper, or a region of it, to PNG. Images are cached on the
+/// server and tagged with an ETag, so browsers only download each one once.
+async fn render_page(
+    current: CurrentUser,
+    Path((id, page_number)): Path<(PdfId, u16)>,
+    Query(params): Query<RenderParams>,
+    headers: HeaderMap,
+) -> ApiResult<impl IntoResponse> {
+    let region = match (params.x, params.y, params.width, params.height) {
+        (Some(x), Some(y), Some(width), Some(height)) => Some(Region {
+            x,
+            y,
+            width,
+            height,
+        }),
+        (None, None, None, None) => None,
+        _ => {
+            return Err(MolmineError::InvalidInput(
+                "Give all of x, y, width and height to render a region".to_string(),
+            ));
+        }
+    };
+    let request = RenderRequest {
+        page_number,
+        dpi: params.dpi.unwrap_or(pdf::DEFAULT_DPI),
+        region,
+    };
+    request.validate()?;
+    let mut conn = establish().await?;
+    current
+        .require_pdf(id, ProjectRole::Viewer, &mut conn)
+        .await?;
+    let pdf = Pdf::get_by_id(id, &mut conn).await?;
+    if pdf.data.is_empty() {
+        return Err(MolmineError::NotFound("PDF file"));
+    }
+    // The key names the file's content, so a matching tag is still current
+    if let Some(hash) = &pdf.content_hash {
+        let etag = format!("\"{}\"", request.cache_key(hash));
+        if headers
+            .get(header::IF_NONE_MATCH)
+            .is_some_and(|value| value.as_bytes() == etag.as_bytes())
+        {
+            return Ok((StatusCode::NOT_MODIFIED, [(header::ETAG, etag)]).into_response());
+        }
+    }
+    let (key, png) = pdf::render_cached(pdf, request).await?;
+    Ok((
+        [
+            (header::CONTENT_TYPE, "image/png".to_string()),
+            (header::CACHE_CONTROL, "private, max-age=86400".to_string()),
+            (header::ETAG, format!("\"{key}\"")),
+        ],
+        png,
+    )
+        .into_response())
+}
+
+async fn update_pdf(
+    current: CurrentUser,
+    Path(id): Path<PdfId>,
+    Json(body): Json<PaperRequest>,
+) -> ApiResult<Json<PdfInfo>> {
+    let metadata = PdfMetadata::try_from(body)?;
+    let mut conn = establish().await?;
+    current
+        .require_pdf(id, ProjectRole::Editor, &mut conn)
+        .await?;
+    Ok(Json(Pdf::update_metadata(id, &metadata, &mut conn).await?))
+}
+
+async fn delete_pdf(current: CurrentUser, Path(id): Path<PdfId>) -> ApiResult<Json<Value>> {
+    let mut conn = establish().await?;
+    current
+        .require_pdf(id, ProjectRole::Editor, &mut conn)
+        .await?;
+    Pdf::delete(id, &mut conn).await?;
+    Ok(Json(json!({ "success": true })))
+}
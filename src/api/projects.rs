@@ -0,0 +1,376 @@
+use std::io::Cursor;
+
+use axum::extract::{DefaultBodyLimit, Multipart, Path};
+use axum::http::header;
+use axum::response::IntoResponse;
+use axum::routing::{delete, get, post};
+use axum::{Json, Router};
+use chrono::NaiveDateTime;
+use serde::{Deserialize, Serialize};
+use serde_json::{Value, json};
+
+use super::{ApiResult, multipart_error};
+use crate::archive::{ArchiveImportReport, export_project, import_project};
+use crate::auth::CurrentUser;
+use crate::db::establish;
+use crate::error::MolmineError;
+use crate::models::*;
+
+/// Archives hold every paper of a project
+const MAX_ARCHIVE_SIZE: usize = 1024 * 1024 * 1024;
+
+pub fn router<S>() -> Router<S>
+where
+    S: Clone + Send + Sync + 'static,
+{
+    Router::new()
+        .route("/api/projects", get(list_projects).post(create_project))
+        .route(
+            "/api/projects/import",
+            post(import_archive).layer(DefaultBodyLimit::max(MAX_ARCHIVE_SIZE)),
+        )
+        .route("/api/projects/active", get(active_project))
+        .route("/api/projects/active/fields", get(active_fields))
+        .route(
+            "/api/projects/:id",
+            get(get_project).put(update_project).delete(delete_project),
+        )
+        .route("/api/projects/:id/activate", post(activate_project))
+        .route("/api/projects/:id/archive", get(download_archive))
+        .route(
+            "/api/projects/:id/members",
+            get(list_members).put(set_member),
+        )
+        .route("/api/projects/:id/members/:user_id", delete(remove_member))
+}
+
+/// A project as returned to the browser, with its fields decoded and the caller's role
+#[derive(Serialize)]
+pub struct ProjectView {
+    pub id: ProjectId,
+    pub name: String,
+    pub path: String,
+    pub created_at: Option<NaiveDateTime>,
+    pub fields: Vec<ProjectField>,
+    pub role: ProjectRole,
+}
+
+impl ProjectView {
+    fn new(project: Project, role: ProjectRole) -> Result<Self, MolmineError> {
+        let fields = project.field_defs()?;
+        Ok(ProjectView {
+            id: project.id,
+            name: project.name,
+            path: project.path,
+            created_at: project.created_at,
+            fields,
+            role,
+        })
+    }
+}
+
+#[derive(Deserialize)]
+pub struct ProjectRequest {
+    pub name: String,
+    pub fields: Vec<ProjectField>,
+}
+
+impl ProjectRequest {
+    fn validate(&self) -> Result<(), MolmineError> {
+        if self.name.trim().is_empty() {
+            return Err(MolmineError::InvalidInput(
+                "Project name is required".to_string(),
+            ));
+        }
+        if self.fields.iter().any(|field| field.name.trim().is_empty()) {
+            return Err(MolmineError::InvalidInput(
+                "All field names are required".to_string(),
+            ));
+        }
+        Ok(())
+    }
+}
+
+fn map_unique_violation(err: diesel::result::Error) -> MolmineError {
+    use diesel::result::{DatabaseErrorKind, Error};
+    match err {
+        Error::DatabaseError(DatabaseErrorKind::UniqueViolation, _) => {
+            MolmineError::InvalidInput("A project with this name already exists".to_string())
+        }
+        err => err.into(),
+    }
+}
+
+async fn list_projects(current: CurrentUser) -> ApiResult<Json<Value>> {
+    let mut conn = establish().await?;
+    let projects = Project::list_for_user(current.user.id, &mut conn)
+        .await?
+        .into_iter()
+        .filter_map(|(project, role)| {
+            let role = current.effective_role(project.id, role)?;
+            Some(ProjectView::new(project, role))
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+    Ok(Json(json!({
+        "projects": projects,
+        "activeProject": current.active_project_id,
+    })))
+}
+
+async fn create_project(
+    current: CurrentUser,
+    Json(body): Json<ProjectRequest>,
+) -> ApiResult<Json<ProjectView>> {
+    body.validate()?;
+    let session = current.require_session()?;
+    let mut conn = establish().await?;
+    let project = NewProject {
+        name: body.name.trim().to_string(),
+        path: project_path(&body.name)?,
+        created_at: chrono::Utc::now().naive_utc(),
+        fields: serde_json::to_string(&body.fields)?,
+    }
+    .insert(current.user.id, &mut conn)
+    .await
+    .map_err(map_unique_violation)?;
+    session
+        .set_active_project(Some(project.id), &mut conn)
+        .await?;
+    Ok(Json(ProjectView::new(project, ProjectRole::Owner)?))
+}
+
+async fn active_project(current: CurrentUser) -> ApiResult<Json<Value>> {
+    let mut conn = establish().await?;
+    let role = match current.active_project_id {
+        Some(project_id) => ProjectMember::role_of(project_id, current.user.id, &mut conn)
+            .await?
+            .and_then(|role| current.effective_role(project_id, role))
+            .map(|role| (project_id, role)),
+        None => None,
+    };
+    let project = match role {
+        Some((project_id, role)) => Some(ProjectView::new(
+            Project::get_by_id(project_id, &mut conn).await?,
+            role,
+        )?),
+        None => None,
+    };
+    Ok(Json(json!({ "project": project })))
+}
+
+async fn active_fields(current: CurrentUser) -> ApiResult<Json<Vec<ProjectField>>> {
+    let mut conn = establish().await?;
+    let project_id = current
+        .require_active_project(ProjectRole::Viewer, &mut conn)
+        .await?;
+    let project = Project::get_by_id(project_id, &mut conn).await?;
+    Ok(Json(project.field_defs()?))
+}
+
+async fn get_project(
+    current: CurrentUser,
+    Path(id): Path<ProjectId>,
+) -> ApiResult<Json<ProjectView>> {
+    let mut conn = establish().await?;
+    let role = current
+        .require_project(id, ProjectRole::Viewer, &mut conn)
+        .await?;
+    let project = Project::get_by_id(id, &mut conn).await?;
+    Ok(Json(ProjectView::new(project, role)?))
+}
+
+async fn update_project(
+    current: CurrentUser,
+    Path(id): Path<ProjectId>,
+    Json(body): Json<ProjectRequest>,
+) -> ApiResult<Json<ProjectView>> {
+    body.validate()?;
+    let mut conn = establish().await?;
+    let role = current
+        .require_project(id, ProjectRole::Owner, &mut conn)
+        .await?;
+    let project = Project::update(
+        id,
+        body.name.trim(),
+        &serde_json::to_string(&body.fields)?,
+        &mut conn,
+    )
+    .await?;
+    Ok(Json(ProjectView::new(project, role)?))
+}
+
+async fn delete_project(current: CurrentUser, Path(id): Path<ProjectId>) -> ApiResult<Json<Value>> {
+    let mut conn = establish().await?;
+    current
+        .require_project(id, ProjectRole::Owner, &mut conn)
+        .await?;
+    Project::delete(id, &mut conn).await?;
+    Ok(Json(json!({ "success": true })))
+}
+
+async fn activate_project(
+    current: CurrentUser,
+    Path(id): Path<ProjectId>,
+) -> ApiResult<Json<Value>> {
+    let session = current.require_session()?;
+    let mut conn = establish().await?;
+    current
+        .require_project(id, ProjectRole::Viewer, &mut conn)
+        .await?;
+    session.set_active_project(Some(id), &mut conn).await?;
+    Ok(Json(json!({ "success": true })))
+}
+
+/// Downloads the project with its papers and compounds as a zip archive
+async fn download_archive(
+    current: CurrentUser,
+    Path(id): Path<ProjectId>,
+) -> ApiResult<impl IntoResponse> {
+    let mut conn = establish().await?;
+    current
+        .require_project(id, ProjectRole::Viewer, &mut conn)
+        .await?;
+    let project = Project::get_by_id(id, &mut conn).await?;
+    let archive = export_project(id, Cursor::new(Vec::new()), &mut conn)
+        .await?
+        .into_inner();
+    let disposition = format!(
+        "attachment; filename=\"{}.molmine.zip\"",
+        slugify(&project.name)
+    );
+    Ok((
+        [
+            (header::CONTENT_TYPE, "application/zip".to_string()),
+            (header::CONTENT_DISPOSITION, disposition),
+        ],
+        archive,
+    ))
+}
+
+/// Creates a project owned by the caller from the archive in the multipart `archive`
+/// field, named after the optional `name` field or else the archived project, and
+/// makes it the active project
+async fn import_archive(
+    current: CurrentUser,
+    mut multipart: Multipart,
+) -> ApiResult<Json<ArchiveImportReport>> {
+    let session = current.require_session()?;
+    let mut archive = None;
+    let mut name = None;
+    while let Some(field) = multipart.next_field().await.map_err(multipart_error)? {
+        match field.name() {
+            Some("archive") => archive = Some(field.bytes().await.map_err(multipart_error)?),
+            Some("name") => name = Some(field.text().await.map_err(multipart_error)?),
+            _ => {}
+        }
+    }
+    let archive =
+        archive.ok_or_else(|| MolmineError::InvalidInput("No archive uploaded".to_string()))?;
+    let name = name.filter(|name| !name.trim().is_empty());
+    let mut conn = establish().await?;
+    let report = import_project(
+        Cursor::new(archive),
+        name.as_deref(),
+        current.user.id,
+        &mut conn,
+    )
+    .await?;
+    session
+        .set_active_project(Some(report.project_id), &mut conn)
+        .await?;
+    Ok(Json(report))
+}
+
+#[derive(Serialize)]
+pub struct MemberView {
+    pub user_id: UserId,
+    pub username: String,
+    pub role: ProjectRole,
+}
+
+async fn list_members(
+    current: CurrentUser,
+    Path(id): Path<ProjectId>,
+) -> ApiResult<Json<Vec<MemberView>>> {
+    let mut conn = establish().await?;
+    current
+        .require_project(id, ProjectRole::Viewer, &mut conn)
+        .await?;
+    let members = ProjectMember::list_for_project(id, &mut conn)
+        .await?
+        .into_iter()
+        .map(|(member, user)| MemberView {
+            user_id: user.id,
+            username: user.username,
+            role: member.role,
+        })
+        .collect();
+    Ok(Json(members))
+}
+
+#[derive(Deserialize)]
+pub struct MemberRequest {
+    pub username: String,
+    pub role: ProjectRole,
+}
+
+async fn set_member(
+    current: CurrentUser,
+    Path(id): Path<ProjectId>,
+    Json(body): Json<MemberRequest>,
+) -> ApiResult<Json<MemberView>> {
+    let mut conn = establish().await?;
+    current
+        .require_project(id, ProjectRole::Owner, &mut conn)
+        .await?;
+    let user = User::get_by_username(body.username.trim(), &mut conn)
+        .await?
+        .ok_or(MolmineError::NotFound("User"))?;
+    if user.id == current.user.id && body.role != ProjectRole::Owner {
+        ensure_another_owner(id, &mut conn).await?;
+    }
+    ProjectMember {
+        project_id: id,
+        user_id: user.id,
+        role: body.role,
+    }
+    .upsert(&mut conn)
+    .await?;
+    Ok(Json(MemberView {
+        user_id: user.id,
+        username: user.username,
+        role: body.role,
+    }))
+}
+
+async fn remove_member(
+    current: CurrentUser,
+    Path((id, user_id)): Path<(ProjectId, UserId)>,
+) -> ApiResult<Json<Value>> {
+    let mut conn = establish().await?;
+    // Members may always leave a project; removing others is for owners
+    let required = if user_id == current.user.id {
+        ProjectRole::Viewer
+    } else {
+        ProjectRole::Owner
+    };
+    current.require_project(id, required, &mut conn).await?;
+    if ProjectMember::role_of(id, user_id, &mut conn).await? == Some(ProjectRole::Owner) {
+        ensure_another_owner(id, &mut conn).await?;
+    }
+    ProjectMember::remove(id, user_id, &mut conn).await?;
+    Ok(Json(json!({ "success": true })))
+}
+
+/// Refuses changes that would leave a project without an owner
+async fn ensure_another_owner(
+    id: ProjectId,
+    conn: &mut crate::db::AsyncConn,
+) -> Result<(), MolmineError> {
+    if ProjectMember::count_owners(id, conn).await? <= 1 {
+        return Err(MolmineError::InvalidInput(
+            "A project must keep at least one owner".to_string(),
+        ));
+    }
+    Ok(())
+}
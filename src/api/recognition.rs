@@ -0,0 +1,139 @@
+use axum::extract::{DefaultBodyLimit, Path};
+use axum::routing::{get, post};
+use axum::{Json, Router};
+use base64::Engine;
+use serde::{Deserialize, Serialize};
+
+use super::ApiResult;
+use crate::auth::CurrentUser;
+use crate::db::establish;
+use crate::error::MolmineError;
+use crate::models::*;
+use crate::ocsr::{
+    BackendAccuracy, RecognitionReport, backend_accuracy, recognize_with_server_backend,
+    render_region,
+};
+use crate::rdkit::{canonical_smiles, molblock_to_smiles, smiles_to_molblock};
+
+/// Selections captured at high zoom make large images, and base64 adds a third
+const MAX_IMAGE_REQUEST_SIZE: usize = 20 * 1024 * 1024;
+
+pub fn router<S>() -> Router<S>
+where
+    S: Clone + Send + Sync + 'static,
+{
+    Router::new()
+        .route(
+            "/api/structures/recognize",
+            post(recognize_image).layer(DefaultBodyLimit::max(MAX_IMAGE_REQUEST_SIZE)),
+        )
+        .route("/api/structures/validate", post(validate_smiles))
+        .route("/api/structures/from-molfile", post(molfile_to_structure))
+        .route("/api/regions/:id/recognize", post(recognize_region))
+        .route("/api/recognition/accuracy", get(recognition_accuracy))
+}
+
+#[derive(Deserialize)]
+pub struct RecognizeRequest {
+    /// A PNG as a `data:image/png;base64,...` URL or plain base64
+    pub image: String,
+}
+
+/// Recognizes the structure in an image captured in the browser. The recognition is
+/// recorded in the project, so this takes the editor role.
+async fn recognize_image(
+    current: CurrentUser,
+    Json(body): Json<RecognizeRequest>,
+) -> ApiResult<Json<RecognitionReport>> {
+    let mut conn = establish().await?;
+    let project_id = current
+        .require_active_project(ProjectRole::Editor, &mut conn)
+        .await?;
+    let encoded = body
+        .image
+        .split_once(";base64,")
+        .map_or(body.image.as_str(), |(_, encoded)| encoded);
+    let png = base64::engine::general_purpose::STANDARD
+        .decode(encoded.trim())
+        .map_err(|_| MolmineError::InvalidInput("The image is not valid base64".to_string()))?;
+    Ok(Json(
+        recognize_with_server_backend(&png, project_id, None, &mut conn).await?,
+    ))
+}
+
+/// Renders a structure region of a paper and recognizes the structure in it, recording
+/// the recognition like [`recognize_image`]. Whole papers are better recognized with a
+/// background job.
+async fn recognize_region(
+    current: CurrentUser,
+    Path(id): Path<RegionId>,
+) -> ApiResult<Json<RecognitionReport>> {
+    let mut conn = establish().await?;
+    let region = StructureRegion::get_by_id(id, &mut conn).await?;
+    let pdf = current
+        .require_pdf(region.pdf_id, ProjectRole::Editor, &mut conn)
+        .await?;
+    let project_id = pdf.project_id.ok_or(MolmineError::NotFound("PDF"))?;
+    let png = render_region(&region, &mut conn).await?;
+    Ok(Json(
+        recognize_with_server_backend(&png, project_id, Some(region.id), &mut conn).await?,
+    ))
+}
+
+/// How often each recognizer's structures survived curation in the active project, for
+/// choosing between them
+async fn recognition_accuracy(current: CurrentUser) -> ApiResult<Json<Vec<BackendAccuracy>>> {
+    let mut conn = establish().await?;
+    let project_id = current
+        .require_active_project(ProjectRole::Viewer, &mut conn)
+        .await?;
+    let compounds = Compound::list_recognized_for_project(project_id, &mut conn).await?;
+    Ok(Json(backend_accuracy(&compounds)))
+}
+
+/// A structure as RDKit reads it, for the compound form and its structure editor
+#[derive(Serialize)]
+pub struct CheckedStructure {
+    pub valid: bool,
+    /// Canonical SMILES
+    pub smiles: Option<String>,
+    pub molblock: Option<String>,
+}
+
+#[derive(Deserialize)]
+pub struct SmilesRequest {
+    pub smiles: String,
+}
+
+/// Checks typed SMILES, answering with the canonical form and a molblock to edit
+async fn validate_smiles(
+    _current: CurrentUser,
+    Json(body): Json<SmilesRequest>,
+) -> ApiResult<Json<CheckedStructure>> {
+    let smiles = canonical_smiles(&body.smiles);
+    let molblock = smiles.as_deref().and_then(smiles_to_molblock);
+    Ok(Json(CheckedStructure {
+        valid: smiles.is_some(),
+        smiles,
+        molblock,
+    }))
+}
+
+#[derive(Deserialize)]
+pub struct MolfileRequest {
+    pub molfile: String,
+}
+
+/// Reads a structure drawn in the editor. The molblock is returned as drawn so that the
+/// next edit starts from the same layout.
+async fn molfile_to_structure(
+    _current: CurrentUser,
+    Json(body): Json<MolfileRequest>,
+) -> ApiResult<Json<CheckedStructure>> {
+    let smiles = molblock_to_smiles(&body.molfile);
+    Ok(Json(CheckedStructure {
+        valid: smiles.is_some(),
+        molblock: smiles.is_some().then_some(body.molfile),
+        smiles,
+    }))
+}
@@ -0,0 +1,79 @@
+use axum::extract::{Path, Query};
+use axum::routing::{get, post, put};
+use axum::{Json, Router};
+use serde::Deserialize;
+
+use super::ApiResult;
+use crate::auth::CurrentUser;
+use crate::db::establish;
+use crate::error::MolmineError;
+use crate::models::*;
+use crate::segment::propose_regions;
+
+pub fn router<S>() -> Router<S>
+where
+    S: Clone + Send + Sync + 'static,
+{
+    Router::new()
+        .route("/api/pdfs/:id/regions", get(list_regions))
+        .route("/api/pdfs/:id/regions/detect", post(detect_regions))
+        .route("/api/regions/:id", put(review_region))
+}
+
+#[derive(Deserialize)]
+pub struct RegionParams {
+    pub status: Option<RegionStatus>,
+}
+
+/// The paper's structure regions in reading order, optionally only those with a status
+async fn list_regions(
+    current: CurrentUser,
+    Path(id): Path<PdfId>,
+    Query(params): Query<RegionParams>,
+) -> ApiResult<Json<Vec<StructureRegion>>> {
+    let mut conn = establish().await?;
+    current
+        .require_pdf(id, ProjectRole::Viewer, &mut conn)
+        .await?;
+    Ok(Json(
+        StructureRegion::list_for_pdf(id, params.status, &mut conn).await?,
+    ))
+}
+
+/// Scans the paper's pages for structure drawings, replacing its pending regions with
+/// the new proposals
+async fn detect_regions(
+    current: CurrentUser,
+    Path(id): Path<PdfId>,
+) -> ApiResult<Json<Vec<StructureRegion>>> {
+    let mut conn = establish().await?;
+    current
+        .require_pdf(id, ProjectRole::Editor, &mut conn)
+        .await?;
+    let pdf = Pdf::get_by_id(id, &mut conn).await?;
+    if pdf.data.is_empty() {
+        return Err(MolmineError::NotFound("PDF file"));
+    }
+    Ok(Json(propose_regions(id, pdf.data, &mut conn).await?))
+}
+
+#[derive(Deserialize)]
+pub struct ReviewRequest {
+    pub status: RegionStatus,
+}
+
+/// Accepts or rejects a proposed region, or returns it to pending
+async fn review_region(
+    current: CurrentUser,
+    Path(id): Path<RegionId>,
+    Json(body): Json<ReviewRequest>,
+) -> ApiResult<Json<StructureRegion>> {
+    let mut conn = establish().await?;
+    let region = StructureRegion::get_by_id(id, &mut conn).await?;
+    current
+        .require_pdf(region.pdf_id, ProjectRole::Editor, &mut conn)
+        .await?;
+    Ok(Json(
+        StructureRegion::set_status(id, body.status, &mut conn).await?,
+    ))
+}
@@ -0,0 +1,103 @@
+use axum::extract::Path;
+use axum::routing::post;
+use axum::{Json, Router};
+use serde::{Deserialize, Serialize};
+
+use super::ApiResult;
+use crate::auth::CurrentUser;
+use crate::db::establish;
+use crate::error::MolmineError;
+use crate::models::*;
+use crate::pdf::{
+    ExtractedTable, Region, TableFillReport, extract_table, fill_from_table, suggest_mapping,
+};
+
+pub fn router<S>() -> Router<S>
+where
+    S: Clone + Send + Sync + 'static,
+{
+    Router::new()
+        .route("/api/pdfs/:id/tables/extract", post(extract_page_table))
+        .route("/api/pdfs/:id/tables/fill", post(fill_table))
+}
+
+/// A region of a page, in PDF points from the top left like a structure region
+#[derive(Deserialize)]
+pub struct TableRegionRequest {
+    pub page_number: u16,
+    #[serde(flatten)]
+    pub region: Region,
+}
+
+#[derive(Serialize)]
+pub struct TableResponse {
+    #[serde(flatten)]
+    pub table: ExtractedTable,
+    /// The project field each column seems to hold, to be checked before filling
+    pub mapping: Vec<Option<String>>,
+}
+
+/// Reads the table in a region of a page of the paper, suggesting which project field
+/// each column holds
+async fn extract_page_table(
+    current: CurrentUser,
+    Path(id): Path<PdfId>,
+    Json(body): Json<TableRegionRequest>,
+) -> ApiResult<Json<TableResponse>> {
+    let mut conn = establish().await?;
+    let info = current
+        .require_pdf(id, ProjectRole::Viewer, &mut conn)
+        .await?;
+    let pdf = Pdf::get_by_id(id, &mut conn).await?;
+    if pdf.data.is_empty() {
+        return Err(MolmineError::NotFound("PDF file"));
+    }
+    // Text layout is CPU-bound, so keep it off the async workers
+    let table = tokio::task::spawn_blocking(move || {
+        extract_table(&pdf.data, body.page_number, body.region)
+    })
+    .await??;
+    let fields = match info.project_id {
+        Some(project_id) => Project::get_by_id(project_id, &mut conn)
+            .await?
+            .field_defs()?,
+        None => Vec::new(),
+    };
+    let mapping = suggest_mapping(&table.columns, &fields);
+    Ok(Json(TableResponse { table, mapping }))
+}
+
+#[derive(Deserialize)]
+pub struct FillRequest {
+    pub table: ExtractedTable,
+    /// The project field of each column, `null` for columns to leave out
+    pub mapping: Vec<Option<String>>,
+    /// Replace values compounds already have
+    #[serde(default)]
+    pub overwrite: bool,
+    #[serde(default)]
+    pub dry_run: bool,
+}
+
+/// Fills a table's values into the chemical data of the paper's compounds whose labels
+/// match its rows
+async fn fill_table(
+    current: CurrentUser,
+    Path(id): Path<PdfId>,
+    Json(body): Json<FillRequest>,
+) -> ApiResult<Json<TableFillReport>> {
+    let mut conn = establish().await?;
+    current
+        .require_pdf(id, ProjectRole::Editor, &mut conn)
+        .await?;
+    let report = fill_from_table(
+        id,
+        &body.table,
+        &body.mapping,
+        body.overwrite,
+        body.dry_run,
+        &mut conn,
+    )
+    .await?;
+    Ok(Json(report))
+}
@@ -0,0 +1,178 @@
+use axum::extract::Path;
+use axum::routing::{delete, get};
+use axum::{Json, Router};
+use serde::{Deserialize, Serialize};
+
+use super::ApiResult;
+use crate::auth::{self, CurrentUser};
+use crate::db::establish;
+use crate::error::MolmineError;
+use crate::models::*;
+
+/// Prefix that makes molmine tokens recognisable in scripts and secret scanners
+const TOKEN_PREFIX: &str = "mm_";
+
+pub fn router<S>() -> Router<S>
+where
+    S: Clone + Send + Sync + 'static,
+{
+    // Token management needs a browser session; a token cannot mint or revoke tokens
+    Router::new()
+        .route("/api/tokens", get(list_tokens).post(create_token))
+        .route("/api/tokens/:id", delete(revoke_token))
+}
+
+#[derive(Serialize)]
+pub struct TokenView {
+    #[serde(flatten)]
+    pub token: ApiToken,
+    pub projects: Vec<ProjectId>,
+}
+
+#[derive(Deserialize)]
+pub struct TokenRequest {
+    pub name: String,
+    pub projects: Vec<ProjectId>,
+    #[serde(default)]
+    pub write: bool,
+    /// Days until the token expires; tokens without one never expire
+    pub expires_in_days: Option<u32>,
+}
+
+#[derive(Serialize)]
+pub struct CreatedToken {
+    /// The secret is only ever returned here
+    pub secret: String,
+    #[serde(flatten)]
+    pub token: TokenView,
+}
+
+async fn list_tokens(current: CurrentUser) -> ApiResult<Json<Vec<TokenView>>> {
+    current.require_session()?;
+    let mut conn = establish().await?;
+    let mut views = Vec::new();
+    for token in ApiToken::list_for_user(current.user.id, &mut conn).await? {
+        let projects = token.projects(&mut conn).await?;
+        views.push(TokenView { token, projects });
+    }
+    Ok(Json(views))
+}
+
+async fn create_token(
+    current: CurrentUser,
+    Json(body): Json<TokenRequest>,
+) -> ApiResult<Json<CreatedToken>> {
+    current.require_session()?;
+    if body.name.trim().is_empty() {
+        return Err(MolmineError::InvalidInput(
+            "Token name is required".to_string(),
+        ));
+    }
+    if body.projects.is_empty() {
+        return Err(MolmineError::InvalidInput(
+            "A token must be scoped to at least one project".to_string(),
+        ));
+    }
+    // A project listed twice is scoped once
+    let mut projects: Vec<ProjectId> = Vec::with_capacity(body.projects.len());
+    for project_id in body.projects {
+        if !projects.contains(&project_id) {
+            projects.push(project_id);
+        }
+    }
+    let mut conn = establish().await?;
+    let required = if body.write {
+        ProjectRole::Editor
+    } else {
+        ProjectRole::Viewer
+    };
+    for project_id in &projects {
+        current
+            .require_project(*project_id, required, &mut conn)
+            .await?;
+    }
+
+    let secret = format!("{TOKEN_PREFIX}{}", auth::generate_token());
+    let expires_at = body
+        .expires_in_days
+        .map(|days| chrono::Utc::now().naive_utc() + chrono::Duration::days(i64::from(days)));
+    let token = NewApiToken {
+        user_id: current.user.id,
+        name: body.name.trim().to_string(),
+        token_hash: auth::hash_token(&secret),
+        can_write: body.write,
+        expires_at,
+    }
+    .insert(&projects, &mut conn)
+    .await?;
+    Ok(Json(CreatedToken {
+        secret,
+        token: TokenView { token, projects },
+    }))
+}
+
+async fn revoke_token(
+    current: CurrentUser,
+    Path(id): Path<ApiTokenId>,
+) -> ApiResult<Json<TokenView>> {
+    current.require_session()?;
+    let mut conn = establish().await?;
+    let token = ApiToken::get_by_id(id, &mut conn).await?;
+    if token.user_id != current.user.id {
+        return Err(MolmineError::NotFound("Token"));
+    }
+    let token = ApiToken::revoke(id, &mut conn).await?;
+    let projects = token.projects(&mut conn).await?;
+    Ok(Json(TokenView { token, projects }))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::db::establish_test;
+
+    #[tokio::test]
+    async fn test_create_token_scopes_projects_once() {
+        let mut conn = establish_test().await.unwrap();
+        let project = fixtures::insert_project(&mut conn).await;
+        let username = format!(
+            "tokens-{}",
+            chrono::Utc::now().timestamp_nanos_opt().unwrap()
+        );
+        let (user, secret) = auth::register(&username, "correct horse", &mut conn)
+            .await
+            .unwrap();
+        ProjectMember {
+            project_id: project.id,
+            user_id: user.id,
+            role: ProjectRole::Editor,
+        }
+        .upsert(&mut conn)
+        .await
+        .unwrap();
+        let (session, user) = Session::find_valid(&auth::hash_token(&secret), &mut conn)
+            .await
+            .unwrap()
+            .unwrap();
+        let current = CurrentUser {
+            user,
+            session: Some(session),
+            token: None,
+            active_project_id: None,
+        };
+
+        let request = TokenRequest {
+            name: "script".to_string(),
+            projects: vec![project.id, project.id],
+            write: true,
+            expires_in_days: None,
+        };
+        let Json(created) = create_token(current, Json(request)).await.unwrap();
+        assert_eq!(created.token.projects, vec![project.id]);
+        assert_eq!(
+            created.token.token.projects(&mut conn).await.unwrap(),
+            vec![project.id]
+        );
+        fixtures::delete_project(project.id, &mut conn).await;
+    }
+}
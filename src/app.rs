@@ -78,6 +78,7 @@ pub fn App() -> impl IntoView {
             <main>
                 <Routes fallback=|| "Page not found.".into_view()>
                     <Route path=StaticSegment("") view=HomePage/>
+                    <Route path=StaticSegment("login") view=LoginPage/>
                     // <Route path=StaticSegment("about") view=AboutPage/>
                 </Routes>
             </main>
@@ -0,0 +1,528 @@
+//! Whole-project archives for moving a project between machines.
+//!
+//! An archive is a zip file holding:
+//!
+//! - `manifest.json`: the format name, [`ARCHIVE_SCHEMA_VERSION`] and counts
+//! - `project.json`: the project's name and field definitions
+//! - `pdfs.json` and `compounds.json`: the rows, keyed by their IDs on the exporting machine
+//! - `markush.json`: the generic structures compounds were enumerated from, if any
+//! - `pdfs/<id>.pdf` and `images/<id>.png`: the paper files and captured structure images
+//!
+//! Importing creates a new project and gives every paper and compound a new ID.
+
+use std::collections::HashMap;
+use std::io::{Read, Seek, Write};
+
+use base64::Engine;
+use chrono::NaiveDateTime;
+use diesel_async::AsyncConnection;
+use serde::{Deserialize, Serialize, de::DeserializeOwned};
+use zip::write::SimpleFileOptions;
+use zip::{CompressionMethod, ZipArchive, ZipWriter};
+
+use crate::db::AsyncConn;
+use crate::error::MolmineError;
+use crate::export::image_bytes;
+use crate::models::*;
+
+const ARCHIVE_FORMAT: &str = "molmine-project";
+/// Bump when the layout or the meaning of a file changes. Importers read every version
+/// up to their own.
+pub const ARCHIVE_SCHEMA_VERSION: u32 = 1;
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Manifest {
+    pub format: String,
+    pub schema_version: u32,
+    /// The molmine version that wrote the archive, for troubleshooting
+    pub molmine_version: String,
+    pub exported_at: NaiveDateTime,
+    pub pdfs: usize,
+    pub compounds: usize,
+}
+
+impl Manifest {
+    fn check(&self) -> Result<(), MolmineError> {
+        if self.format != ARCHIVE_FORMAT {
+            return Err(MolmineError::InvalidInput(
+                "Not a molmine project archive".to_string(),
+            ));
+        }
+        if self.schema_version > ARCHIVE_SCHEMA_VERSION {
+            return Err(MolmineError::InvalidInput(format!(
+                "The archive was written by molmine {} (archive version {}); \
+                 this version reads up to archive version {ARCHIVE_SCHEMA_VERSION}",
+                self.molmine_version, self.schema_version
+            )));
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct ArchivedProject {
+    name: String,
+    created_at: Option<NaiveDateTime>,
+    fields: Vec<ProjectField>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct ArchivedAuthor {
+    family_name: String,
+    given_name: Option<String>,
+    orcid: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct ArchivedPdf {
+    #[serde(flatten)]
+    info: PdfInfo,
+    /// Path of the paper's file in the archive; papers imported from a bibliography
+    /// may have none
+    file: Option<String>,
+    /// The structured author list, which the free-text `authors` can't fully restore
+    author_list: Vec<ArchivedAuthor>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct ArchivedCompound {
+    id: CompoundId,
+    pdf_id: PdfId,
+    smiles: String,
+    inchi: String,
+    /// Path of the captured structure image in the archive
+    image_file: Option<String>,
+    chemical_data: String,
+    #[serde(default)]
+    recognition_backend: Option<String>,
+    #[serde(default)]
+    recognized_smiles: Option<String>,
+    #[serde(default)]
+    recognized_inchi: Option<String>,
+    #[serde(default)]
+    recognition_confidence: Option<f64>,
+    #[serde(default)]
+    label: String,
+    #[serde(default)]
+    markush_source_id: Option<MarkushSourceId>,
+    #[serde(default)]
+    rgroup_assignments: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct ArchivedMarkushSource {
+    id: MarkushSourceId,
+    pdf_id: PdfId,
+    name: String,
+    core: String,
+    rgroups: String,
+}
+
+fn write_json<W: Write + Seek, T: Serialize>(
+    zip: &mut ZipWriter<W>,
+    name: &str,
+    value: &T,
+) -> Result<(), MolmineError> {
+    let options = SimpleFileOptions::default().compression_method(CompressionMethod::Deflated);
+    zip.start_file(name, options)?;
+    serde_json::to_writer_pretty(&mut *zip, value)?;
+    Ok(())
+}
+
+/// Stores an already compressed file as is
+fn write_file<W: Write + Seek>(
+    zip: &mut ZipWriter<W>,
+    name: &str,
+    data: &[u8],
+) -> Result<(), MolmineError> {
+    let options = SimpleFileOptions::default()
+        .compression_method(CompressionMethod::Stored)
+        .large_file(data.len() as u64 >= u32::MAX as u64);
+    zip.start_file(name, options)?;
+    zip.write_all(data)?;
+    Ok(())
+}
+
+/// Writes the project, its papers with their files, and its compounds to a zip archive
+pub async fn export_project<W: Write + Seek + Send>(
+    project_id: ProjectId,
+    writer: W,
+    conn: &mut AsyncConn,
+) -> Result<W, MolmineError> {
+    let project = Project::get_by_id(project_id, conn).await?;
+    let mut zip = ZipWriter::new(writer);
+    let mut pdfs = Vec::new();
+    let mut compounds = Vec::new();
+    let mut markush = Vec::new();
+    // Papers are read one at a time so only one file is in memory
+    for info in PdfInfo::list_for_project(project_id, conn).await? {
+        let pdf = Pdf::get_by_id(info.id, conn).await?;
+        let file = (!pdf.data.is_empty()).then(|| format!("pdfs/{}.pdf", info.id.0));
+        if let Some(file) = &file {
+            write_file(&mut zip, file, &pdf.data)?;
+        }
+        for compound in Compound::list_for_pdf(info.id, conn).await? {
+            let image_file = match image_bytes(&compound) {
+                Some(png) => {
+                    let image_file = format!("images/{}.png", compound.id.0);
+                    write_file(&mut zip, &image_file, &png)?;
+                    Some(image_file)
+                }
+                None => None,
+            };
+            compounds.push(ArchivedCompound {
+                id: compound.id,
+                pdf_id: compound.pdf_id,
+                smiles: compound.smiles,
+                inchi: compound.inchi,
+                image_file,
+                chemical_data: compound.chemical_data,
+                recognition_backend: compound.recognition_backend,
+                recognized_smiles: compound.recognized_smiles,
+                recognized_inchi: compound.recognized_inchi,
+                recognition_confidence: compound.recognition_confidence,
+                label: compound.label,
+                markush_source_id: compound.markush_source_id,
+                rgroup_assignments: compound.rgroup_assignments,
+            });
+        }
+        for source in MarkushSource::list_for_pdf(info.id, conn).await? {
+            markush.push(ArchivedMarkushSource {
+                id: source.id,
+                pdf_id: source.pdf_id,
+                name: source.name,
+                core: source.core,
+                rgroups: source.rgroups,
+            });
+        }
+        let author_list = Author::list_for_pdf(info.id, conn)
+            .await?
+            .into_iter()
+            .map(|author| ArchivedAuthor {
+                family_name: author.family_name,
+                given_name: author.given_name,
+                orcid: author.orcid,
+            })
+            .collect();
+        pdfs.push(ArchivedPdf {
+            info,
+            file,
+            author_list,
+        });
+    }
+
+    write_json(
+        &mut zip,
+        "manifest.json",
+        &Manifest {
+            format: ARCHIVE_FORMAT.to_string(),
+            schema_version: ARCHIVE_SCHEMA_VERSION,
+            molmine_version: env!("CARGO_PKG_VERSION").to_string(),
+            exported_at: chrono::Utc::now().naive_utc(),
+            pdfs: pdfs.len(),
+            compounds: compounds.len(),
+        },
+    )?;
+    write_json(
+        &mut zip,
+        "project.json",
+        &ArchivedProject {
+            name: project.name.clone(),
+            created_at: project.created_at,
+            fields: project.field_defs()?,
+        },
+    )?;
+    write_json(&mut zip, "pdfs.json", &pdfs)?;
+    write_json(&mut zip, "compounds.json", &compounds)?;
+    write_json(&mut zip, "markush.json", &markush)?;
+    Ok(zip.finish()?)
+}
+
+/// What an import created, with the new ID of every paper and compound by its old one
+#[derive(Debug, Serialize)]
+pub struct ArchiveImportReport {
+    pub project_id: ProjectId,
+    pub name: String,
+    pub schema_version: u32,
+    pub pdf_ids: HashMap<PdfId, PdfId>,
+    pub compound_ids: HashMap<CompoundId, CompoundId>,
+}
+
+/// Largest file an archive may hold, once uncompressed
+const MAX_ENTRY_SIZE: u64 = 1 << 30;
+/// Largest total of the files read from one archive, once uncompressed
+const MAX_ARCHIVE_SIZE: u64 = 8 << 30;
+
+/// Reads files from an archive without trusting the sizes its headers claim
+struct ArchiveReader<R> {
+    zip: ZipArchive<R>,
+    max_entry_size: u64,
+    remaining: u64,
+}
+
+impl<R: Read + Seek> ArchiveReader<R> {
+    fn new(reader: R) -> Result<Self, MolmineError> {
+        Self::with_limits(reader, MAX_ENTRY_SIZE, MAX_ARCHIVE_SIZE)
+    }
+
+    fn with_limits(
+        reader: R,
+        max_entry_size: u64,
+        max_total_size: u64,
+    ) -> Result<Self, MolmineError> {
+        Ok(ArchiveReader {
+            zip: ZipArchive::new(reader)?,
+            max_entry_size,
+            remaining: max_total_size,
+        })
+    }
+
+    fn has_file(&self, name: &str) -> bool {
+        self.zip.index_for_name(name).is_some()
+    }
+
+    fn read_file(&mut self, name: &str) -> Result<Vec<u8>, MolmineError> {
+        let file = self
+            .zip
+            .by_name(name)
+            .map_err(|_| MolmineError::InvalidInput(format!("The archive has no {name}")))?;
+        let limit = self.max_entry_size.min(self.remaining);
+        let mut data = Vec::new();
+        // One byte past the limit tells a file that is too large from one that just fits
+        file.take(limit + 1).read_to_end(&mut data)?;
+        if data.len() as u64 > limit {
+            return Err(MolmineError::InvalidInput(format!(
+                "{name} is too large to import"
+            )));
+        }
+        self.remaining -= data.len() as u64;
+        Ok(data)
+    }
+
+    fn read_json<T: DeserializeOwned>(&mut self, name: &str) -> Result<T, MolmineError> {
+        serde_json::from_slice(&self.read_file(name)?)
+            .map_err(|e| MolmineError::InvalidInput(format!("Invalid {name}: {e}")))
+    }
+}
+
+/// Creates a new project owned by `owner` from an archive. `name` replaces the archived
+/// project name, which must not clash with an existing project.
+pub async fn import_project<R: Read + Seek + Send>(
+    reader: R,
+    name: Option<&str>,
+    owner: UserId,
+    conn: &mut AsyncConn,
+) -> Result<ArchiveImportReport, MolmineError> {
+    let mut archive = ArchiveReader::new(reader)?;
+    let manifest: Manifest = archive.read_json("manifest.json")?;
+    manifest.check()?;
+    let project: ArchivedProject = archive.read_json("project.json")?;
+    let pdfs: Vec<ArchivedPdf> = archive.read_json("pdfs.json")?;
+    let compounds: Vec<ArchivedCompound> = archive.read_json("compounds.json")?;
+    // Archives written before generic structures were kept don't have the file
+    let markush: Vec<ArchivedMarkushSource> = if archive.has_file("markush.json") {
+        archive.read_json("markush.json")?
+    } else {
+        Vec::new()
+    };
+
+    let name = name.unwrap_or(&project.name).trim().to_string();
+    if name.is_empty() {
+        return Err(MolmineError::InvalidInput(
+            "Project name is required".to_string(),
+        ));
+    }
+    let path = project_path(&name)?;
+    if Project::get_by_path(&path, conn).await?.is_some() {
+        return Err(MolmineError::InvalidInput(format!(
+            "A project named {name} already exists"
+        )));
+    }
+
+    conn.transaction::<_, MolmineError, _>(|conn| {
+        Box::pin(async move {
+            let project_id = NewProject {
+                name: name.clone(),
+                path,
+                created_at: project
+                    .created_at
+                    .unwrap_or_else(|| chrono::Utc::now().naive_utc()),
+                fields: serde_json::to_string(&project.fields)?,
+            }
+            .insert(owner, conn)
+            .await?
+            .id;
+
+            let mut pdf_ids = HashMap::new();
+            for archived in pdfs {
+                let data = match &archived.file {
+                    Some(file) => archive.read_file(file)?,
+                    None => Vec::new(),
+                };
+                let info = archived.info;
+                let inserted = NewPdf {
+                    title: info.title,
+                    authors: info.authors,
+                    year: info.year,
+                    journal: info.journal,
+                    volume: info.volume,
+                    data,
+                    project_id: Some(project_id),
+                    doi: info.doi,
+                    pmid: info.pmid,
+                    arxiv_id: info.arxiv_id,
+                    isbn: info.isbn,
+                    url: info.url,
+                    pages: info.pages,
+                    issue: info.issue,
+                    publisher: info.publisher,
+                }
+                .insert(conn)
+                .await?;
+                restore_authors(inserted.id, &archived.author_list, conn).await?;
+                pdf_ids.insert(info.id, inserted.id);
+            }
+
+            let mut markush_ids = HashMap::new();
+            for archived in markush {
+                let pdf_id = *pdf_ids.get(&archived.pdf_id).ok_or_else(|| {
+                    MolmineError::InvalidInput(format!(
+                        "Generic structure {} refers to PDF {}, which is not in the archive",
+                        archived.id.0, archived.pdf_id.0
+                    ))
+                })?;
+                let inserted = NewMarkushSource {
+                    pdf_id,
+                    name: archived.name,
+                    core: archived.core,
+                    rgroups: archived.rgroups,
+                }
+                .insert(conn)
+                .await?;
+                markush_ids.insert(archived.id, inserted.id);
+            }
+
+            let mut compound_ids = HashMap::new();
+            for archived in compounds {
+                let pdf_id = *pdf_ids.get(&archived.pdf_id).ok_or_else(|| {
+                    MolmineError::InvalidInput(format!(
+                        "Compound {} refers to PDF {}, which is not in the archive",
+                        archived.id.0, archived.pdf_id.0
+                    ))
+                })?;
+                let image = match &archived.image_file {
+                    Some(file) => format!(
+                        "data:image/png;base64,{}",
+                        base64::engine::general_purpose::STANDARD.encode(archive.read_file(file)?)
+                    ),
+                    None => String::new(),
+                };
+                let inserted = NewCompound {
+                    pdf_id,
+                    smiles: archived.smiles,
+                    inchi: archived.inchi,
+                    image,
+                    chemical_data: archived.chemical_data,
+                    recognition_backend: archived.recognition_backend,
+                    recognized_smiles: archived.recognized_smiles,
+                    recognized_inchi: archived.recognized_inchi,
+                    recognition_confidence: archived.recognition_confidence,
+                    label: archived.label,
+                    markush_source_id: archived
+                        .markush_source_id
+                        .and_then(|source_id| markush_ids.get(&source_id).copied()),
+                    rgroup_assignments: archived.rgroup_assignments,
+                }
+                .insert(conn)
+                .await?;
+                compound_ids.insert(archived.id, inserted.id);
+            }
+
+            Ok(ArchiveImportReport {
+                project_id,
+                name,
+                schema_version: manifest.schema_version,
+                pdf_ids,
+                compound_ids,
+            })
+        })
+    })
+    .await
+}
+
+/// Re-links the paper's authors as archived. ORCIDs are restored unless another author
+/// in the project already has them.
+async fn restore_authors(
+    pdf_id: PdfId,
+    archived: &[ArchivedAuthor],
+    conn: &mut AsyncConn,
+) -> Result<(), MolmineError> {
+    if archived.is_empty() {
+        return Ok(());
+    }
+    let names: Vec<NewAuthor> = archived
+        .iter()
+        .map(|author| NewAuthor {
+            family_name: author.family_name.clone(),
+            given_name: author.given_name.clone(),
+        })
+        .collect();
+    Pdf::set_authors(pdf_id, &names, conn).await?;
+    let linked = Author::list_for_pdf(pdf_id, conn).await?;
+    for (author, archived) in linked.iter().zip(archived) {
+        if author.orcid.is_none()
+            && let Some(orcid) = &archived.orcid
+            && Author::get_by_orcid(author.project_id, orcid, conn)
+                .await?
+                .is_none()
+        {
+            Author::set_orcid(author.id, Some(orcid), conn).await?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_manifest_check() {
+        let mut manifest = Manifest {
+            format: ARCHIVE_FORMAT.to_string(),
+            schema_version: ARCHIVE_SCHEMA_VERSION,
+            molmine_version: "0.1.0".to_string(),
+            exported_at: NaiveDateTime::default(),
+            pdfs: 0,
+            compounds: 0,
+        };
+        assert!(manifest.check().is_ok());
+        manifest.schema_version += 1;
+        assert!(manifest.check().is_err());
+        manifest.schema_version = ARCHIVE_SCHEMA_VERSION;
+        manifest.format = "zip".to_string();
+        assert!(manifest.check().is_err());
+    }
+
+    #[test]
+    fn test_read_file_limits() {
+        let mut zip = ZipWriter::new(std::io::Cursor::new(Vec::new()));
+        write_json(&mut zip, "small.json", &[1, 2, 3]).unwrap();
+        write_json(&mut zip, "large.json", &vec![0; 1000]).unwrap();
+        let data = zip.finish().unwrap().into_inner();
+
+        let mut archive =
+            ArchiveReader::with_limits(std::io::Cursor::new(&data), 100, 1000).unwrap();
+        assert_eq!(
+            archive.read_json::<Vec<i32>>("small.json").unwrap(),
+            [1, 2, 3]
+        );
+        assert!(archive.read_file("large.json").is_err());
+        assert!(archive.read_file("missing.json").is_err());
+
+        // Every file counts towards the archive's total
+        let mut archive = ArchiveReader::with_limits(std::io::Cursor::new(&data), 100, 30).unwrap();
+        assert!(archive.read_file("small.json").is_ok());
+        assert!(archive.read_file("small.json").is_err());
+    }
+}
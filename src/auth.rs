@@ -0,0 +1,556 @@
+use argon2::Argon2;
+use argon2::password_hash::{PasswordHash, PasswordHasher, PasswordVerifier, SaltString};
+use axum::async_trait;
+use axum::extract::FromRequestParts;
+use axum::http::header::AUTHORIZATION;
+use axum::http::request::Parts;
+use axum_extra::extract::cookie::{Cookie, CookieJar, SameSite};
+use diesel::OptionalExtension;
+use rand::RngCore;
+use rand::rngs::OsRng;
+use sha2::{Digest, Sha256};
+
+use crate::db::{AsyncConn, establish};
+use crate::error::MolmineError;
+use crate::models::*;
+
+pub const SESSION_COOKIE: &str = "molmine_session";
+pub const SESSION_LIFETIME_DAYS: i64 = 30;
+pub const MIN_PASSWORD_LENGTH: usize = 8;
+
+pub fn hash_password(password: &str) -> Result<String, MolmineError> {
+    let salt = SaltString::generate(&mut OsRng);
+    Argon2::default()
+        .hash_password(password.as_bytes(), &salt)
+        .map(|hash| hash.to_string())
+        .map_err(|e| MolmineError::PasswordHashError(e.to_string()))
+}
+
+pub fn verify_password(password: &str, password_hash: &str) -> bool {
+    PasswordHash::new(password_hash).is_ok_and(|parsed| {
+        Argon2::default()
+            .verify_password(password.as_bytes(), &parsed)
+            .is_ok()
+    })
+}
+
+/// Generates a random hex-encoded secret suitable for cookies and bearer tokens
+pub fn generate_token() -> String {
+    let mut bytes = [0u8; 32];
+    OsRng.fill_bytes(&mut bytes);
+    hex::encode(bytes)
+}
+
+/// Tokens are stored hashed so that a leaked database does not leak live sessions
+pub fn hash_token(token: &str) -> String {
+    hex::encode(Sha256::digest(token.as_bytes()))
+}
+
+/// Creates a session for the user and returns the raw token to put in the cookie
+pub async fn start_session(user_id: UserId, conn: &mut AsyncConn) -> Result<String, MolmineError> {
+    let token = generate_token();
+    let expires_at = chrono::Utc::now().naive_utc() + chrono::Duration::days(SESSION_LIFETIME_DAYS);
+    NewSession {
+        token_hash: hash_token(&token),
+        user_id,
+        expires_at,
+    }
+    .insert(conn)
+    .await?;
+    Ok(token)
+}
+
+/// Creates a local account and signs in as it, returning the user and the raw session
+/// token. The first account becomes the owner of every project without members.
+pub async fn register(
+    username: &str,
+    password: &str,
+    conn: &mut AsyncConn,
+) -> Result<(User, String), MolmineError> {
+    let username = username.trim();
+    if username.is_empty() {
+        return Err(MolmineError::InvalidInput(
+            "Username is required".to_string(),
+        ));
+    }
+    if password.len() < MIN_PASSWORD_LENGTH {
+        return Err(MolmineError::InvalidInput(format!(
+            "Password must be at least {MIN_PASSWORD_LENGTH} characters"
+        )));
+    }
+    if User::get_by_username(username, conn).await?.is_some() {
+        return Err(MolmineError::InvalidInput(
+            "Username is already taken".to_string(),
+        ));
+    }
+    let user = NewUser {
+        username: username.to_string(),
+        password_hash: hash_password(password)?,
+    }
+    .insert(conn)
+    .await?;
+    if User::count(conn).await? == 1 {
+        ProjectMember::adopt_memberless(user.id, conn).await?;
+    }
+    let token = start_session(user.id, conn).await?;
+    Ok((user, token))
+}
+
+/// Checks the password and signs in, returning the user and the raw session token.
+/// Expired sessions are cleared out on the way.
+pub async fn login(
+    username: &str,
+    password: &str,
+    conn: &mut AsyncConn,
+) -> Result<(User, String), MolmineError> {
+    let user = User::get_by_username(username.trim(), conn)
+        .await?
+        .filter(|user| verify_password(password, &user.password_hash))
+        .ok_or(MolmineError::InvalidCredentials)?;
+    Session::delete_expired(conn).await?;
+    let token = start_session(user.id, conn).await?;
+    Ok((user, token))
+}
+
+pub fn session_cookie(token: String) -> Cookie<'static> {
+    Cookie::build((SESSION_COOKIE, token))
+        .path("/")
+        .http_only(true)
+        .same_site(SameSite::Lax)
+        .max_age(time::Duration::days(SESSION_LIFETIME_DAYS))
+        .build()
+}
+
+pub fn removal_cookie() -> Cookie<'static> {
+    let mut cookie = Cookie::from(SESSION_COOKIE);
+    cookie.set_path("/");
+    cookie.make_removal();
+    cookie
+}
+
+/// Header naming the project a bearer-token request operates on
+pub const PROJECT_HEADER: &str = "x-molmine-project";
+
+/// What an API token allows on top of its owner's project roles
+#[derive(Debug)]
+pub struct TokenGrant {
+    pub token: ApiToken,
+    pub projects: Vec<ProjectId>,
+}
+
+impl TokenGrant {
+    /// Read-only tokens act as viewers and write tokens as editors; tokens never act as owners
+    pub fn max_role(&self) -> ProjectRole {
+        if self.token.can_write {
+            ProjectRole::Editor
+        } else {
+            ProjectRole::Viewer
+        }
+    }
+}
+
+/// The caller of a request, resolved from either the session cookie or an
+/// `Authorization: Bearer` API token
+#[derive(Debug)]
+pub struct CurrentUser {
+    pub user: User,
+    pub session: Option<Session>,
+    pub token: Option<TokenGrant>,
+    pub active_project_id: Option<ProjectId>,
+}
+
+#[async_trait]
+impl<S> FromRequestParts<S> for CurrentUser
+where
+    S: Send + Sync,
+{
+    type Rejection = MolmineError;
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+        let mut conn = establish().await?;
+        if let Some(authorization) = parts.headers.get(AUTHORIZATION) {
+            let secret = authorization
+                .to_str()
+                .ok()
+                .and_then(|value| value.strip_prefix("Bearer "))
+                .ok_or(MolmineError::NotAuthenticated)?;
+            let (token, user) = ApiToken::find_valid(&hash_token(secret.trim()), &mut conn)
+                .await?
+                .ok_or(MolmineError::NotAuthenticated)?;
+            token.touch(&mut conn).await?;
+            let projects = token.projects(&mut conn).await?;
+            // Tokens have no session, so the project comes from a header unless the
+            // token is scoped to a single project
+            let active_project_id = match parts.headers.get(PROJECT_HEADER) {
+                Some(value) => Some(ProjectId(
+                    value
+                        .to_str()
+                        .ok()
+                        .and_then(|value| value.trim().parse().ok())
+                        .ok_or_else(|| {
+                            MolmineError::InvalidInput(format!("Invalid {PROJECT_HEADER} header"))
+                        })?,
+                )),
+                None if projects.len() == 1 => projects.first().copied(),
+                None => None,
+            };
+            return Ok(CurrentUser {
+                user,
+                session: None,
+                token: Some(TokenGrant { token, projects }),
+                active_project_id,
+            });
+        }
+
+        let jar = CookieJar::from_headers(&parts.headers);
+        let token = jar
+            .get(SESSION_COOKIE)
+            .ok_or(MolmineError::NotAuthenticated)?;
+        let (session, user) = Session::find_valid(&hash_token(token.value()), &mut conn)
+            .await?
+            .ok_or(MolmineError::NotAuthenticated)?;
+        Ok(CurrentUser {
+            user,
+            active_project_id: session.active_project_id,
+            session: Some(session),
+            token: None,
+        })
+    }
+}
+
+impl CurrentUser {
+    /// The browser session, for actions that API tokens may not perform
+    pub fn require_session(&self) -> Result<&Session, MolmineError> {
+        self.session.as_ref().ok_or(MolmineError::PermissionDenied)
+    }
+
+    /// The user's role on a project, narrowed by the API token's scope if there is one
+    pub fn effective_role(&self, project_id: ProjectId, role: ProjectRole) -> Option<ProjectRole> {
+        match &self.token {
+            Some(grant) if !grant.projects.contains(&project_id) => None,
+            Some(grant) => Some(role.min(grant.max_role())),
+            None => Some(role),
+        }
+    }
+
+    /// Fails unless the user holds at least `required` on the project
+    pub async fn require_project(
+        &self,
+        project_id: ProjectId,
+        required: ProjectRole,
+        conn: &mut AsyncConn,
+    ) -> Result<ProjectRole, MolmineError> {
+        let role = ProjectMember::role_of(project_id, self.user.id, conn)
+            .await?
+            .and_then(|role| self.effective_role(project_id, role));
+        match role {
+            Some(role) if role.allows(required) => Ok(role),
+            Some(_) => Err(MolmineError::PermissionDenied),
+            // Don't reveal the existence of projects the user isn't a member of
+            None => Err(MolmineError::NotFound("Project")),
+        }
+    }
+
+    /// Fails unless the session has an active project on which the user holds `required`
+    pub async fn require_active_project(
+        &self,
+        required: ProjectRole,
+        conn: &mut AsyncConn,
+    ) -> Result<ProjectId, MolmineError> {
+        let project_id = self
+            .active_project_id
+            .ok_or_else(|| MolmineError::InvalidInput("No active project".to_string()))?;
+        self.require_project(project_id, required, conn).await?;
+        Ok(project_id)
+    }
+
+    /// Checks access to a PDF through the project that owns it. Papers that belong to no
+    /// project are not found by anyone.
+    pub async fn require_pdf(
+        &self,
+        pdf_id: PdfId,
+        required: ProjectRole,
+        conn: &mut AsyncConn,
+    ) -> Result<PdfInfo, MolmineError> {
+        let pdf = PdfInfo::get_by_id(pdf_id, conn)
+            .await
+            .optional()?
+            .ok_or(MolmineError::NotFound("PDF"))?;
+        // A migration moved the papers from before projects had members into a project
+        let project_id = pdf.project_id.ok_or(MolmineError::NotFound("PDF"))?;
+        self.require_project(project_id, required, conn).await?;
+        Ok(pdf)
+    }
+
+    /// Checks access to a compound through the PDF it was extracted from
+    pub async fn require_compound(
+        &self,
+        compound_id: CompoundId,
+        required: ProjectRole,
+        conn: &mut AsyncConn,
+    ) -> Result<Compound, MolmineError> {
+        let compound = Compound::get_by_id(compound_id, conn)
+            .await
+            .optional()?
+            .ok_or(MolmineError::NotFound("Compound"))?;
+        self.require_pdf(compound.pdf_id, required, conn).await?;
+        Ok(compound)
+    }
+}
+
+/// Resolves the signed-in user from inside a Leptos server function
+pub async fn require_user() -> Result<CurrentUser, leptos::prelude::ServerFnError> {
+    leptos_axum::extract::<CurrentUser>().await
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::db::establish_test;
+    use axum::http::header::COOKIE;
+    use axum::http::{HeaderName, Request};
+
+    /// Resolves the caller of a request with the headers, as a handler would
+    async fn current_user(headers: &[(HeaderName, String)]) -> Result<CurrentUser, MolmineError> {
+        let mut request = Request::builder().uri("/api/auth/me");
+        for (name, value) in headers {
+            request = request.header(name, value);
+        }
+        let (mut parts, ()) = request.body(()).unwrap().into_parts();
+        CurrentUser::from_request_parts(&mut parts, &()).await
+    }
+
+    fn unique_username(prefix: &str) -> String {
+        format!(
+            "{prefix}-{}",
+            chrono::Utc::now().timestamp_nanos_opt().unwrap()
+        )
+    }
+
+    #[test]
+    fn test_password_hash() {
+        let hash = hash_password("correct horse").unwrap();
+        assert!(verify_password("correct horse", &hash));
+        assert!(!verify_password("battery staple", &hash));
+    }
+
+    #[test]
+    fn test_role_ordering() {
+        assert!(ProjectRole::Owner.allows(ProjectRole::Editor));
+        assert!(ProjectRole::Editor.allows(ProjectRole::Editor));
+        assert!(!ProjectRole::Viewer.allows(ProjectRole::Editor));
+    }
+
+    #[tokio::test]
+    async fn test_session_cookie() {
+        let mut conn = establish_test().await.unwrap();
+        let project = fixtures::insert_project(&mut conn).await;
+        let username = unique_username("cookie");
+        let (user, token) = register(&username, "correct horse", &mut conn)
+            .await
+            .unwrap();
+        ProjectMember {
+            project_id: project.id,
+            user_id: user.id,
+            role: ProjectRole::Viewer,
+        }
+        .upsert(&mut conn)
+        .await
+        .unwrap();
+
+        let cookie = |token: &str| (COOKIE, format!("{SESSION_COOKIE}={token}"));
+        let current = current_user(&[cookie(&token)]).await.unwrap();
+        assert_eq!(current.user.id, user.id);
+        assert!(current.session.is_some() && current.token.is_none());
+        assert!(matches!(
+            current_user(&[cookie("not-a-session")]).await,
+            Err(MolmineError::NotAuthenticated)
+        ));
+        assert!(matches!(
+            current_user(&[]).await,
+            Err(MolmineError::NotAuthenticated)
+        ));
+        // Signing in again starts another session rather than reusing the cookie's
+        let (_, again) = login(&username, "correct horse", &mut conn).await.unwrap();
+        assert_ne!(again, token);
+        assert!(matches!(
+            login(&username, "battery staple", &mut conn).await,
+            Err(MolmineError::InvalidCredentials)
+        ));
+        fixtures::delete_project(project.id, &mut conn).await;
+    }
+
+    #[tokio::test]
+    async fn test_project_roles() {
+        let mut conn = establish_test().await.unwrap();
+        let viewing = fixtures::insert_project(&mut conn).await;
+        let editing = fixtures::insert_project(&mut conn).await;
+        let other = fixtures::insert_project(&mut conn).await;
+        let (user, token) = register(&unique_username("member"), "correct horse", &mut conn)
+            .await
+            .unwrap();
+        for (project_id, role) in [
+            (viewing.id, ProjectRole::Viewer),
+            (editing.id, ProjectRole::Editor),
+        ] {
+            ProjectMember {
+                project_id,
+                user_id: user.id,
+                role,
+            }
+            .upsert(&mut conn)
+            .await
+            .unwrap();
+        }
+        let current = current_user(&[(COOKIE, format!("{SESSION_COOKIE}={token}"))])
+            .await
+            .unwrap();
+
+        // Roles hold per project
+        let viewed = fixtures::insert_pdf(viewing.id, &mut conn).await;
+        let edited = fixtures::insert_pdf(editing.id, &mut conn).await;
+        assert!(
+            current
+                .require_pdf(viewed.id, ProjectRole::Viewer, &mut conn)
+                .await
+                .is_ok()
+        );
+        assert!(matches!(
+            current
+                .require_pdf(viewed.id, ProjectRole::Editor, &mut conn)
+                .await,
+            Err(MolmineError::PermissionDenied)
+        ));
+        assert!(
+            current
+                .require_pdf(edited.id, ProjectRole::Editor, &mut conn)
+                .await
+                .is_ok()
+        );
+        assert!(matches!(
+            current
+                .require_project(editing.id, ProjectRole::Owner, &mut conn)
+                .await,
+            Err(MolmineError::PermissionDenied)
+        ));
+
+        // Papers in projects the user doesn't belong to are hidden, not just forbidden
+        let hidden = fixtures::insert_pdf(other.id, &mut conn).await;
+        assert!(matches!(
+            current
+                .require_pdf(hidden.id, ProjectRole::Viewer, &mut conn)
+                .await,
+            Err(MolmineError::NotFound(_))
+        ));
+
+        // Removing the member takes effect on the next check
+        ProjectMember::remove(editing.id, user.id, &mut conn)
+            .await
+            .unwrap();
+        assert!(matches!(
+            current
+                .require_pdf(edited.id, ProjectRole::Viewer, &mut conn)
+                .await,
+            Err(MolmineError::NotFound(_))
+        ));
+
+        fixtures::delete_project(viewing.id, &mut conn).await;
+        fixtures::delete_project(editing.id, &mut conn).await;
+        fixtures::delete_project(other.id, &mut conn).await;
+    }
+
+    #[tokio::test]
+    async fn test_bearer_tokens() {
+        let mut conn = establish_test().await.unwrap();
+        let first = fixtures::insert_project(&mut conn).await;
+        let second = fixtures::insert_project(&mut conn).await;
+        let (user, _) = register(&unique_username("bearer"), "correct horse", &mut conn)
+            .await
+            .unwrap();
+        for project_id in [first.id, second.id] {
+            ProjectMember {
+                project_id,
+                user_id: user.id,
+                role: ProjectRole::Owner,
+            }
+            .upsert(&mut conn)
+            .await
+            .unwrap();
+        }
+        let mut issue = async |can_write: bool, scope: &[ProjectId]| {
+            let secret = generate_token();
+            NewApiToken {
+                user_id: user.id,
+                name: "test".to_string(),
+                token_hash: hash_token(&secret),
+                can_write,
+                expires_at: None,
+            }
+            .insert(scope, &mut conn)
+            .await
+            .unwrap();
+            (AUTHORIZATION, format!("Bearer {secret}"))
+        };
+        let read_only = issue(false, &[first.id]).await;
+        let writing = issue(true, &[first.id, second.id]).await;
+
+        // A token scoped to one project acts on it without the header
+        let current = current_user(&[read_only]).await.unwrap();
+        assert_eq!(current.user.id, user.id);
+        assert!(current.session.is_none() && current.token.is_some());
+        assert_eq!(current.active_project_id, Some(first.id));
+
+        // Tokens narrow the owner's role and hide projects outside their scope
+        assert_eq!(
+            current.effective_role(first.id, ProjectRole::Owner),
+            Some(ProjectRole::Viewer)
+        );
+        assert_eq!(current.effective_role(second.id, ProjectRole::Owner), None);
+        assert!(matches!(
+            current
+                .require_project(first.id, ProjectRole::Editor, &mut conn)
+                .await,
+            Err(MolmineError::PermissionDenied)
+        ));
+        assert!(matches!(
+            current
+                .require_project(second.id, ProjectRole::Viewer, &mut conn)
+                .await,
+            Err(MolmineError::NotFound(_))
+        ));
+        assert!(current.require_session().is_err());
+
+        // Tokens over several projects need the header, and write tokens stop at editor
+        let current = current_user(std::slice::from_ref(&writing)).await.unwrap();
+        assert_eq!(current.active_project_id, None);
+        let project = HeaderName::from_static(PROJECT_HEADER);
+        let current = current_user(&[writing.clone(), (project.clone(), second.id.0.to_string())])
+            .await
+            .unwrap();
+        assert_eq!(current.active_project_id, Some(second.id));
+        assert_eq!(
+            current
+                .require_active_project(ProjectRole::Editor, &mut conn)
+                .await
+                .unwrap(),
+            second.id
+        );
+        assert_eq!(
+            current.effective_role(second.id, ProjectRole::Owner),
+            Some(ProjectRole::Editor)
+        );
+        assert!(matches!(
+            current_user(&[writing, (project, "second".to_string())]).await,
+            Err(MolmineError::InvalidInput(_))
+        ));
+
+        for unknown in ["Bearer mm_unknown", "Basic dXNlcg=="] {
+            assert!(matches!(
+                current_user(&[(AUTHORIZATION, unknown.to_string())]).await,
+                Err(MolmineError::NotAuthenticated)
+            ));
+        }
+        fixtures::delete_project(first.id, &mut conn).await;
+        fixtures::delete_project(second.id, &mut conn).await;
+    }
+}
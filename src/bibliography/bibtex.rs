@@ -0,0 +1,531 @@
+//! A forgiving BibTeX reader covering what reference managers export: braced and quoted
+//! values, `#` concatenation, `@string` macros and the usual LaTeX accents. The writer
+//! emits UTF-8, which biber and modern BibTeX setups accept as is.
+
+use std::collections::HashMap;
+use std::fmt::Write;
+
+use super::export::{Citation, WorkType};
+use super::{MalformedEntry, RawEntry};
+
+const MONTHS: [&str; 12] = [
+    "jan", "feb", "mar", "apr", "may", "jun", "jul", "aug", "sep", "oct", "nov", "dec",
+];
+
+struct Parser {
+    chars: Vec<char>,
+    pos: usize,
+    macros: HashMap<String, String>,
+}
+
+/// Splits a `.bib` file into entries. Entries that can't be read are reported and
+/// skipped without stopping the rest of the file.
+pub fn parse(text: &str) -> Vec<Result<RawEntry, MalformedEntry>> {
+    let mut parser = Parser {
+        chars: text.chars().collect(),
+        pos: 0,
+        macros: MONTHS
+            .iter()
+            .enumerate()
+            .map(|(i, month)| (month.to_string(), (i + 1).to_string()))
+            .collect(),
+    };
+    let mut entries = Vec::new();
+    while parser.skip_to('@') {
+        let start = parser.pos;
+        let line = parser.line();
+        match parser.entry() {
+            Ok(Some(entry)) => entries.push(Ok(RawEntry { line, ..entry })),
+            Ok(None) => {}
+            Err((key, reason)) => {
+                entries.push(Err(MalformedEntry { line, key, reason }));
+                // An unbalanced brace may have consumed the rest of the file
+                parser.pos = start;
+                parser.skip_to_next_entry();
+            }
+        }
+    }
+    entries
+}
+
+type ParseError = (Option<String>, String);
+
+impl Parser {
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.pos).copied()
+    }
+
+    fn line(&self) -> usize {
+        self.chars[..self.pos]
+            .iter()
+            .filter(|c| **c == '\n')
+            .count()
+            + 1
+    }
+
+    fn skip_whitespace(&mut self) {
+        while self.peek().is_some_and(char::is_whitespace) {
+            self.pos += 1;
+        }
+    }
+
+    /// Moves past the next `target`, returning false at the end of input
+    fn skip_to(&mut self, target: char) -> bool {
+        while let Some(c) = self.peek() {
+            self.pos += 1;
+            if c == target {
+                return true;
+            }
+        }
+        false
+    }
+
+    /// Recovers from a broken entry by jumping to the next `@` that starts a line
+    fn skip_to_next_entry(&mut self) {
+        while let Some(c) = self.peek() {
+            if c == '@' && (self.pos == 0 || self.chars[self.pos - 1] == '\n') {
+                return;
+            }
+            self.pos += 1;
+        }
+    }
+
+    fn identifier(&mut self) -> String {
+        let start = self.pos;
+        while self
+            .peek()
+            .is_some_and(|c| !c.is_whitespace() && !"{}()=,#\"".contains(c))
+        {
+            self.pos += 1;
+        }
+        self.chars[start..self.pos].iter().collect()
+    }
+
+    /// Reads the text up to the brace matching the one just consumed
+    fn braced(&mut self) -> Result<String, String> {
+        let start = self.pos;
+        let mut depth = 1;
+        while let Some(c) = self.peek() {
+            self.pos += 1;
+            match c {
+                '\\' => self.pos += 1,
+                '{' => depth += 1,
+                '}' => {
+                    depth -= 1;
+                    if depth == 0 {
+                        return Ok(self.chars[start..self.pos - 1].iter().collect());
+                    }
+                }
+                _ => {}
+            }
+        }
+        Err("Unbalanced braces".to_string())
+    }
+
+    fn quoted(&mut self) -> Result<String, String> {
+        let start = self.pos;
+        let mut depth = 0;
+        while let Some(c) = self.peek() {
+            self.pos += 1;
+            match c {
+                '\\' => self.pos += 1,
+                '{' => depth += 1,
+                '}' => depth -= 1,
+                '"' if depth == 0 => return Ok(self.chars[start..self.pos - 1].iter().collect()),
+                _ => {}
+            }
+        }
+        Err("Unterminated quoted value".to_string())
+    }
+
+    /// A field value: braced or quoted strings, numbers and macros joined with `#`
+    fn value(&mut self) -> Result<String, String> {
+        let mut value = String::new();
+        loop {
+            self.skip_whitespace();
+            match self.peek() {
+                Some('{') => {
+                    self.pos += 1;
+                    value.push_str(&self.braced()?);
+                }
+                Some('"') => {
+                    self.pos += 1;
+                    value.push_str(&self.quoted()?);
+                }
+                Some(_) => {
+                    let name = self.identifier();
+                    if name.is_empty() {
+                        return Err("Expected a field value".to_string());
+                    }
+                    match self.macros.get(&name.to_lowercase()) {
+                        Some(expansion) => value.push_str(expansion),
+                        None if name.chars().all(|c| c.is_ascii_digit()) => value.push_str(&name),
+                        None => return Err(format!("Undefined string macro: {name}")),
+                    }
+                }
+                None => return Err("Unexpected end of file".to_string()),
+            }
+            self.skip_whitespace();
+            if self.peek() == Some('#') {
+                self.pos += 1;
+            } else {
+                return Ok(value);
+            }
+        }
+    }
+
+    /// `name = value` pairs up to the closing delimiter
+    fn fields(&mut self, close: char) -> Result<Vec<(String, String)>, String> {
+        let mut fields = Vec::new();
+        loop {
+            self.skip_whitespace();
+            match self.peek() {
+                Some(',') => self.pos += 1,
+                Some(c) if c == close => {
+                    self.pos += 1;
+                    return Ok(fields);
+                }
+                Some(_) => {
+                    let name = self.identifier().to_lowercase();
+                    self.skip_whitespace();
+                    if name.is_empty() || self.peek() != Some('=') {
+                        return Err(format!("Expected `=` after field name {name:?}"));
+                    }
+                    self.pos += 1;
+                    fields.push((name, self.value()?));
+                }
+                None => return Err("Unexpected end of file".to_string()),
+            }
+        }
+    }
+
+    /// Reads one `@type{...}` block. Comments, preambles and string definitions yield `None`.
+    fn entry(&mut self) -> Result<Option<RawEntry>, ParseError> {
+        let entry_type = self.identifier().to_lowercase();
+        self.skip_whitespace();
+        let close = match self.peek() {
+            Some('{') => '}',
+            Some('(') => ')',
+            _ => return Err((None, format!("Expected `{{` after @{entry_type}"))),
+        };
+        self.pos += 1;
+        match entry_type.as_str() {
+            "comment" | "preamble" => {
+                self.braced().map_err(|reason| (None, reason))?;
+                return Ok(None);
+            }
+            "string" => {
+                let fields = self.fields(close).map_err(|reason| (None, reason))?;
+                self.macros.extend(fields);
+                return Ok(None);
+            }
+            _ => {}
+        }
+
+        self.skip_whitespace();
+        let start = self.pos;
+        while self.peek().is_some_and(|c| c != ',' && c != close) {
+            self.pos += 1;
+        }
+        let key: String = self.chars[start..self.pos].iter().collect();
+        let key = Some(key.trim().to_string()).filter(|key| !key.is_empty());
+        let fields = self.fields(close).map_err(|reason| (key.clone(), reason))?;
+        Ok(Some(RawEntry {
+            line: 0,
+            key,
+            fields: convert_fields(&entry_type, fields),
+        }))
+    }
+}
+
+/// Maps BibTeX field names onto `pdfs` columns and strips LaTeX markup from the values
+fn convert_fields(entry_type: &str, fields: Vec<(String, String)>) -> Vec<(&'static str, String)> {
+    let is_arxiv = fields.iter().any(|(name, value)| {
+        name == "archiveprefix" && value.eq_ignore_ascii_case("arxiv")
+            || name == "eprinttype" && value.eq_ignore_ascii_case("arxiv")
+    });
+    let mut converted = Vec::new();
+    for (name, value) in fields {
+        let column = match name.as_str() {
+            "title" => "title",
+            "author" => "authors",
+            "year" | "date" => "year",
+            "journal" | "journaltitle" => "journal",
+            "booktitle" if entry_type == "inproceedings" || entry_type == "incollection" => {
+                "journal"
+            }
+            "volume" => "volume",
+            "number" | "issue" => "issue",
+            "pages" => "pages",
+            "publisher" => "publisher",
+            "doi" => "doi",
+            "url" => "url",
+            "isbn" => "isbn",
+            "pmid" => "pmid",
+            "eprint" if is_arxiv => "arxiv_id",
+            "file" => "file",
+            _ => continue,
+        };
+        let value = match column {
+            // Identifiers and paths are taken verbatim
+            "doi" | "url" | "isbn" | "pmid" | "arxiv_id" => value.trim().to_string(),
+            "file" => jabref_file_path(&value),
+            "pages" => clean_latex(&value).replace("--", "-").replace('–', "-"),
+            _ => clean_latex(&value),
+        };
+        converted.push((column, value));
+    }
+    converted
+}
+
+/// JabRef and Zotero write `file = {Description:path/to/file.pdf:PDF}`; take the path of
+/// the first PDF in the list
+fn jabref_file_path(value: &str) -> String {
+    value
+        .split(';')
+        .map(|link| {
+            let parts: Vec<&str> = link.split(':').collect();
+            match parts.as_slice() {
+                [_, path @ .., kind] if parts.len() >= 3 && kind.eq_ignore_ascii_case("pdf") => {
+                    path.join(":")
+                }
+                _ => link.to_string(),
+            }
+        })
+        .find(|path| path.to_lowercase().ends_with(".pdf"))
+        .unwrap_or_else(|| value.to_string())
+        .replace("\\:", ":")
+        .trim()
+        .to_string()
+}
+
+/// Pairs of base letter and accented letter for each accent command
+const ACCENTS: [(char, &str); 9] = [
+    ('\'', "aáeéiíoóuúyýAÁEÉIÍOÓUÚYÝcćnńsśzźCĆNŃSŚZŹ"),
+    ('`', "aàeèiìoòuùAÀEÈIÌOÒUÙ"),
+    ('^', "aâeêiîoôuûAÂEÊIÎOÔUÛ"),
+    ('"', "aäeëiïoöuüyÿAÄEËIÏOÖUÜ"),
+    ('~', "aãnñoõAÃNÑOÕ"),
+    ('c', "cçCÇsşSŞ"),
+    ('v', "cčsšzžrřeěnňCČSŠZŽRŘEĚNŇ"),
+    ('r', "aåAÅuůUŮ"),
+    ('=', "aāeēiīoōuūAĀEĒIĪOŌUŪ"),
+];
+
+fn accented(accent: char, letter: char) -> Option<char> {
+    let (_, pairs) = ACCENTS.iter().find(|(command, _)| *command == accent)?;
+    let pairs: Vec<char> = pairs.chars().collect();
+    pairs
+        .chunks(2)
+        .find(|pair| pair[0] == letter)
+        .map(|pair| pair[1])
+}
+
+/// Converts common LaTeX escapes to Unicode and drops grouping braces and formatting commands
+pub fn clean_latex(value: &str) -> String {
+    let chars: Vec<char> = value.chars().collect();
+    let mut out = String::new();
+    let mut i = 0;
+    while i < chars.len() {
+        match chars[i] {
+            '\\' if i + 1 < chars.len() => {
+                let next = chars[i + 1];
+                if "'`^\"~=.".contains(next)
+                    || (matches!(next, 'c' | 'v' | 'r')
+                        && !chars.get(i + 2).is_some_and(char::is_ascii_alphabetic))
+                {
+                    // Accent: `\'e`, `\'{e}` or `\c c`
+                    i += 2;
+                    while chars.get(i).is_some_and(|c| *c == '{' || *c == ' ') {
+                        i += 1;
+                    }
+                    if let Some(&letter) = chars.get(i) {
+                        let letter = if letter == '\\' && chars.get(i + 1) == Some(&'i') {
+                            i += 1;
+                            'i'
+                        } else {
+                            letter
+                        };
+                        out.push(accented(next, letter).unwrap_or(letter));
+                        i += 1;
+                    }
+                    if chars.get(i) == Some(&'}') {
+                        i += 1;
+                    }
+                } else if next.is_ascii_alphabetic() {
+                    let start = i + 1;
+                    i = start;
+                    while chars.get(i).is_some_and(char::is_ascii_alphabetic) {
+                        i += 1;
+                    }
+                    let command: String = chars[start..i].iter().collect();
+                    let replacement = match command.as_str() {
+                        "ss" => "ß",
+                        "o" => "ø",
+                        "O" => "Ø",
+                        "aa" => "å",
+                        "AA" => "Å",
+                        "ae" => "æ",
+                        "AE" => "Æ",
+                        "l" => "ł",
+                        "L" => "Ł",
+                        "alpha" => "α",
+                        "beta" => "β",
+                        "gamma" => "γ",
+                        "delta" => "δ",
+                        "mu" => "μ",
+                        "textendash" => "–",
+                        "textemdash" => "—",
+                        // Formatting commands such as \emph or \textit keep their argument
+                        _ => "",
+                    };
+                    out.push_str(replacement);
+                    if !replacement.is_empty() && chars.get(i) == Some(&' ') {
+                        i += 1;
+                    }
+                } else {
+                    // Escaped special characters: \& \% \$ \_ \{ \}
+                    out.push(next);
+                    i += 2;
+                }
+            }
+            '{' | '}' | '$' => i += 1,
+            '~' => {
+                out.push(' ');
+                i += 1;
+            }
+            c => {
+                out.push(c);
+                i += 1;
+            }
+        }
+    }
+    out.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+/// Escapes the characters BibTeX treats specially
+fn escape(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for c in value.chars() {
+        match c {
+            '&' | '%' | '$' | '#' | '_' | '{' | '}' => {
+                escaped.push('\\');
+                escaped.push(c);
+            }
+            '\\' => escaped.push_str("\\textbackslash{}"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+pub fn write(citations: &[Citation]) -> String {
+    let mut out = String::new();
+    for citation in citations {
+        let pdf = &citation.pdf;
+        let entry_type = match citation.work_type() {
+            WorkType::Article => "article",
+            WorkType::Book => "book",
+            WorkType::Preprint | WorkType::Other => "misc",
+        };
+        let authors: Vec<String> = citation
+            .author_names()
+            .into_iter()
+            .map(|name| match name.given_name {
+                Some(given) => format!("{}, {given}", name.family_name),
+                None => name.family_name,
+            })
+            .collect();
+        let mut fields = vec![("title", escape(&pdf.title))];
+        if !authors.is_empty() {
+            fields.push(("author", escape(&authors.join(" and "))));
+        }
+        fields.push(("year", pdf.year.to_string()));
+        let text_fields = [
+            ("journal", Some(&pdf.journal)),
+            ("volume", Some(&pdf.volume)),
+            ("number", pdf.issue.as_ref()),
+            ("publisher", pdf.publisher.as_ref()),
+        ];
+        for (name, value) in text_fields {
+            if let Some(value) = value.filter(|value| !value.is_empty()) {
+                fields.push((name, escape(value)));
+            }
+        }
+        if let Some(pages) = &pdf.pages {
+            fields.push(("pages", escape(&pages.replace('-', "--"))));
+        }
+        // Identifiers are verbatim fields in biblatex and are read back unescaped
+        let verbatim_fields = [
+            ("doi", &pdf.doi),
+            ("url", &pdf.url),
+            ("isbn", &pdf.isbn),
+            ("pmid", &pdf.pmid),
+        ];
+        for (name, value) in verbatim_fields {
+            if let Some(value) = value {
+                fields.push((name, value.clone()));
+            }
+        }
+        if let Some(arxiv_id) = &pdf.arxiv_id {
+            fields.push(("eprint", arxiv_id.clone()));
+            fields.push(("archiveprefix", "arXiv".to_string()));
+        }
+
+        let _ = writeln!(out, "@{entry_type}{{{},", citation.key);
+        for (name, value) in fields {
+            let _ = writeln!(out, "  {name} = {{{value}}},");
+        }
+        out.push_str("}\n\n");
+    }
+    out
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_parse_bibtex() {
+        let text = r#"
+@string{jmc = "J. Med. Chem."}
+@article{smith2019,
+  title = {Synthesis of {{\"O}}xazoles via {C--H} Activation},
+  author = "Smith, John and M{\"u}ller, J{\"o}rg",
+  journal = jmc,
+  year = 2019,
+  volume = {62}, number = {4},
+  pages = {123--130},
+  doi = {10.1021/acs.jmedchem.9b01234},
+}
+@article{broken, title = {No closing brace
+@misc{arxiv1, title = "Deep Learning", year = "2021", eprint = {2101.01234}, archivePrefix = {arXiv}}
+"#;
+        let entries = parse(text);
+        assert_eq!(entries.len(), 3);
+        let first = entries[0].as_ref().unwrap();
+        assert_eq!(first.key.as_deref(), Some("smith2019"));
+        assert_eq!(first.line, 3);
+        let field = |entry: &RawEntry, name: &str| {
+            entry
+                .fields
+                .iter()
+                .find(|(field, _)| *field == name)
+                .map(|(_, value)| value.clone())
+        };
+        assert_eq!(
+            field(first, "title").as_deref(),
+            Some("Synthesis of Öxazoles via C--H Activation")
+        );
+        assert_eq!(
+            field(first, "authors").as_deref(),
+            Some("Smith, John and Müller, Jörg")
+        );
+        assert_eq!(field(first, "journal").as_deref(), Some("J. Med. Chem."));
+        assert_eq!(field(first, "pages").as_deref(), Some("123-130"));
+        assert_eq!(field(first, "issue").as_deref(), Some("4"));
+        assert!(entries[1].is_err());
+        let arxiv = entries[2].as_ref().unwrap();
+        assert_eq!(field(arxiv, "arxiv_id").as_deref(), Some("2101.01234"));
+    }
+}
@@ -0,0 +1,96 @@
+//! CSL-JSON, the citation format read by Zotero, pandoc and citeproc processors.
+
+use serde::Serialize;
+
+use super::export::{Citation, WorkType};
+
+#[derive(Debug, Serialize)]
+struct CslItem<'a> {
+    id: &'a str,
+    #[serde(rename = "type")]
+    item_type: &'static str,
+    title: &'a str,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    author: Vec<CslName>,
+    issued: CslDate,
+    #[serde(rename = "container-title", skip_serializing_if = "str::is_empty")]
+    container_title: &'a str,
+    #[serde(skip_serializing_if = "str::is_empty")]
+    volume: &'a str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    issue: Option<&'a str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    page: Option<&'a str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    publisher: Option<&'a str>,
+    #[serde(rename = "DOI", skip_serializing_if = "Option::is_none")]
+    doi: Option<&'a str>,
+    #[serde(rename = "PMID", skip_serializing_if = "Option::is_none")]
+    pmid: Option<&'a str>,
+    #[serde(rename = "ISBN", skip_serializing_if = "Option::is_none")]
+    isbn: Option<&'a str>,
+    #[serde(rename = "URL", skip_serializing_if = "Option::is_none")]
+    url: Option<String>,
+    /// CSL has no arXiv variable, so the ID goes in the note as Zotero does
+    #[serde(skip_serializing_if = "Option::is_none")]
+    note: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+struct CslName {
+    family: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    given: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+struct CslDate {
+    #[serde(rename = "date-parts")]
+    date_parts: [[i32; 1]; 1],
+}
+
+pub fn write(citations: &[Citation]) -> Result<String, serde_json::Error> {
+    let items: Vec<CslItem> = citations
+        .iter()
+        .map(|citation| {
+            let pdf = &citation.pdf;
+            CslItem {
+                id: &citation.key,
+                item_type: match citation.work_type() {
+                    WorkType::Article => "article-journal",
+                    WorkType::Book => "book",
+                    WorkType::Preprint => "article",
+                    WorkType::Other => "document",
+                },
+                title: &pdf.title,
+                author: citation
+                    .author_names()
+                    .into_iter()
+                    .map(|name| CslName {
+                        family: name.family_name,
+                        given: name.given_name,
+                    })
+                    .collect(),
+                issued: CslDate {
+                    date_parts: [[pdf.year]],
+                },
+                container_title: &pdf.journal,
+                volume: &pdf.volume,
+                issue: pdf.issue.as_deref(),
+                page: pdf.pages.as_deref(),
+                publisher: pdf.publisher.as_deref(),
+                doi: pdf.doi.as_deref(),
+                pmid: pdf.pmid.as_deref(),
+                isbn: pdf.isbn.as_deref(),
+                // arXiv preprints without a stored link still get one
+                url: pdf.url.clone().or_else(|| {
+                    pdf.arxiv_id
+                        .as_ref()
+                        .map(|id| format!("https://arxiv.org/abs/{id}"))
+                }),
+                note: pdf.arxiv_id.as_ref().map(|id| format!("arXiv: {id}")),
+            }
+        })
+        .collect();
+    serde_json::to_string_pretty(&items)
+}
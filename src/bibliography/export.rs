@@ -0,0 +1,252 @@
+use std::collections::HashSet;
+
+use serde::{Deserialize, Serialize};
+
+use super::{bibtex, csl, ris};
+use crate::db::AsyncConn;
+use crate::models::*;
+
+/// A bibliography format papers can be exported in
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ExportFormat {
+    #[serde(rename = "bibtex")]
+    Bibtex,
+    #[serde(rename = "ris")]
+    Ris,
+    #[serde(rename = "csl-json")]
+    CslJson,
+}
+
+impl ExportFormat {
+    pub fn content_type(self) -> &'static str {
+        match self {
+            ExportFormat::Bibtex => "application/x-bibtex; charset=utf-8",
+            ExportFormat::Ris => "application/x-research-info-systems; charset=utf-8",
+            ExportFormat::CslJson => "application/vnd.citationstyles.csl+json",
+        }
+    }
+
+    pub fn extension(self) -> &'static str {
+        match self {
+            ExportFormat::Bibtex => "bib",
+            ExportFormat::Ris => "ris",
+            ExportFormat::CslJson => "json",
+        }
+    }
+}
+
+/// What kind of work a paper is, as far as the stored fields tell
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum WorkType {
+    Article,
+    Book,
+    Preprint,
+    Other,
+}
+
+/// A paper with its structured author list, ready to be written out
+#[derive(Debug)]
+pub struct Citation {
+    pub key: String,
+    pub pdf: PdfInfo,
+    pub authors: Vec<Author>,
+}
+
+impl Citation {
+    pub fn work_type(&self) -> WorkType {
+        if !self.pdf.journal.is_empty() {
+            WorkType::Article
+        } else if self.pdf.isbn.is_some() {
+            WorkType::Book
+        } else if self.pdf.arxiv_id.is_some() {
+            WorkType::Preprint
+        } else {
+            WorkType::Other
+        }
+    }
+
+    /// Authors as parsed names, falling back to the free-text list for papers that
+    /// have no author links
+    pub fn author_names(&self) -> Vec<NewAuthor> {
+        if self.authors.is_empty() {
+            return parse_author_list(&self.pdf.authors);
+        }
+        self.authors
+            .iter()
+            .map(|author| NewAuthor {
+                family_name: author.family_name.clone(),
+                given_name: author.given_name.clone(),
+            })
+            .collect()
+    }
+}
+
+/// A one-line reference in the style of ACS journals, e.g.
+/// `Curie, P.; Curie, M. Sur une substance nouvelle. Comptes rendus 1898, 127, 175-178. doi:10.1234/x`
+pub fn format_reference(pdf: &PdfInfo) -> String {
+    let authors: Vec<String> = parse_author_list(&pdf.authors)
+        .into_iter()
+        .map(|name| match name.given_name {
+            Some(given) => {
+                let initials: Vec<String> = given
+                    .split(|c: char| c.is_whitespace() || c == '.')
+                    .filter_map(|part| part.chars().next())
+                    .map(|initial| format!("{initial}."))
+                    .collect();
+                format!("{}, {}", name.family_name, initials.join(" "))
+            }
+            None => name.family_name,
+        })
+        .collect();
+    let mut reference = String::new();
+    if !authors.is_empty() {
+        reference.push_str(&authors.join("; "));
+        reference.push(' ');
+    }
+    reference.push_str(pdf.title.trim_end_matches('.'));
+    reference.push_str(". ");
+    let mut source = vec![if pdf.journal.is_empty() {
+        pdf.year.to_string()
+    } else {
+        format!("{} {}", pdf.journal, pdf.year)
+    }];
+    source.extend(
+        [Some(&pdf.volume), pdf.pages.as_ref()]
+            .into_iter()
+            .flatten()
+            .filter(|part| !part.is_empty())
+            .cloned(),
+    );
+    reference.push_str(&source.join(", "));
+    reference.push('.');
+    if let Some(doi) = &pdf.doi {
+        reference.push_str(&format!(" doi:{doi}"));
+    }
+    reference
+}
+
+/// Builds a citation key such as `curie1898radioactivity`, adding a letter when the
+/// same key was already used
+fn citation_key(pdf: &PdfInfo, authors: &[NewAuthor], used: &mut HashSet<String>) -> String {
+    const STOP_WORDS: [&str; 8] = ["a", "an", "the", "on", "of", "and", "in", "for"];
+    let ascii = |text: &str| -> String {
+        text.chars()
+            .filter(char::is_ascii_alphanumeric)
+            .collect::<String>()
+            .to_lowercase()
+    };
+    let author = authors
+        .first()
+        .map(|author| ascii(&author.family_name))
+        .filter(|name| !name.is_empty())
+        .unwrap_or_else(|| "anonymous".to_string());
+    let word = pdf
+        .title
+        .split_whitespace()
+        .map(ascii)
+        .find(|word| !word.is_empty() && !STOP_WORDS.contains(&word.as_str()))
+        .unwrap_or_default();
+    let base = format!("{author}{}{word}", pdf.year);
+    let mut key = base.clone();
+    for suffix in 'a'..='z' {
+        if !used.contains(&key) {
+            break;
+        }
+        key = format!("{base}{suffix}");
+    }
+    used.insert(key.clone());
+    key
+}
+
+/// Loads the papers to cite: the whole project, or only those that the given compounds
+/// were extracted from
+pub async fn load_citations(
+    project_id: ProjectId,
+    compounds: Option<&[CompoundId]>,
+    conn: &mut AsyncConn,
+) -> Result<Vec<Citation>, diesel::result::Error> {
+    let pdfs = match compounds {
+        Some(compounds) => PdfInfo::list_for_compounds(project_id, compounds, conn).await?,
+        None => PdfInfo::list_for_project(project_id, conn).await?,
+    };
+    let mut used = HashSet::new();
+    let mut citations = Vec::new();
+    for pdf in pdfs {
+        let authors = Author::list_for_pdf(pdf.id, conn).await?;
+        let mut citation = Citation {
+            key: String::new(),
+            pdf,
+            authors,
+        };
+        citation.key = citation_key(&citation.pdf, &citation.author_names(), &mut used);
+        citations.push(citation);
+    }
+    Ok(citations)
+}
+
+pub fn write_bibliography(
+    citations: &[Citation],
+    format: ExportFormat,
+) -> Result<String, serde_json::Error> {
+    Ok(match format {
+        ExportFormat::Bibtex => bibtex::write(citations),
+        ExportFormat::Ris => ris::write(citations),
+        ExportFormat::CslJson => csl::write(citations)?,
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::bibliography::BibFormat;
+
+    #[test]
+    fn test_bibtex_round_trip() {
+        let pdf = PdfInfo {
+            id: PdfId(1),
+            title: "Radioactivity & the {new} 100% element".to_string(),
+            authors: "Marie Skłodowska Curie and Pierre Curie".to_string(),
+            year: 1898,
+            journal: "Comptes rendus".to_string(),
+            volume: "127".to_string(),
+            doi: Some("10.1234/cr.1898".to_string()),
+            pages: Some("175-178".to_string()),
+            ..fixtures::pdf_info()
+        };
+        let citations = vec![Citation {
+            key: "curie1898radioactivity".to_string(),
+            pdf,
+            authors: Vec::new(),
+        }];
+        let bibtex = write_bibliography(&citations, ExportFormat::Bibtex).unwrap();
+        let entry = BibFormat::Bibtex.parse(&bibtex)[0]
+            .clone()
+            .unwrap()
+            .validate()
+            .unwrap();
+        assert_eq!(entry.key.as_deref(), Some("curie1898radioactivity"));
+        assert_eq!(entry.metadata.title, citations[0].pdf.title);
+        assert_eq!(
+            parse_author_list(&entry.metadata.authors),
+            citations[0].author_names()
+        );
+        assert_eq!(entry.metadata.doi, citations[0].pdf.doi);
+        assert_eq!(entry.metadata.pages, citations[0].pdf.pages);
+        let ris = write_bibliography(&citations, ExportFormat::Ris).unwrap();
+        let entry = BibFormat::Ris.parse(&ris)[0]
+            .clone()
+            .unwrap()
+            .validate()
+            .unwrap();
+        assert_eq!(entry.metadata.title, citations[0].pdf.title);
+        assert_eq!(
+            parse_author_list(&entry.metadata.authors),
+            citations[0].author_names()
+        );
+        assert_eq!(
+            format_reference(&citations[0].pdf),
+            "Curie, M. S.; Curie, P. Radioactivity & the {new} 100% element. \
+             Comptes rendus 1898, 127, 175-178. doi:10.1234/cr.1898"
+        );
+    }
+}
@@ -0,0 +1,266 @@
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+
+use diesel_async::AsyncConnection;
+use serde::Serialize;
+
+use super::{BibEntry, BibFormat, MalformedEntry};
+use crate::db::AsyncConn;
+use crate::error::MolmineError;
+use crate::models::*;
+
+#[derive(Clone, Debug, Default)]
+pub struct ImportOptions {
+    /// Report what would happen without creating anything
+    pub dry_run: bool,
+    /// Folder to look for each entry's PDF in, by linked file name, citation key or DOI
+    pub pdf_dir: Option<PathBuf>,
+}
+
+/// What an import created, or would create on a dry run
+#[derive(Debug, Default, Serialize)]
+pub struct ImportReport {
+    pub dry_run: bool,
+    pub created: Vec<ImportedEntry>,
+    pub duplicates: Vec<DuplicateEntry>,
+    pub malformed: Vec<MalformedEntry>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ImportedEntry {
+    pub line: usize,
+    pub key: Option<String>,
+    pub title: String,
+    /// The new paper, absent on a dry run
+    pub pdf_id: Option<PdfId>,
+    /// The PDF found for the entry; papers without one can have it attached later
+    pub pdf_file: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct DuplicateEntry {
+    pub line: usize,
+    pub key: Option<String>,
+    pub title: String,
+    /// The identifier that matched, e.g. `doi:10.1021/...`, or the title and year for
+    /// entries without one
+    pub identifier: String,
+    /// The paper already in the project, or none if the match is an earlier entry of the file
+    pub existing_pdf_id: Option<PdfId>,
+}
+
+fn identifiers(metadata: &PdfMetadata) -> Vec<PaperIdentifier> {
+    let mut identifiers = Vec::new();
+    if let Some(doi) = &metadata.doi {
+        identifiers.push(PaperIdentifier::Doi(doi.clone()));
+    }
+    if let Some(pmid) = &metadata.pmid {
+        identifiers.push(PaperIdentifier::Pmid(pmid.clone()));
+    }
+    if let Some(arxiv_id) = &metadata.arxiv_id {
+        identifiers.push(PaperIdentifier::ArxivId(arxiv_id.clone()));
+    }
+    identifiers
+}
+
+fn describe(identifier: &PaperIdentifier) -> String {
+    match identifier {
+        PaperIdentifier::Doi(value) => format!("doi:{value}"),
+        PaperIdentifier::Pmid(value) => format!("pmid:{value}"),
+        PaperIdentifier::ArxivId(value) => format!("arxiv:{value}"),
+        PaperIdentifier::Isbn(value) => format!("isbn:{value}"),
+    }
+}
+
+/// Looks for the entry's PDF in `dir`, trying the linked file, then `<key>.pdf`, then the
+/// DOI with slashes replaced by underscores. Only file names are used, so an entry cannot
+/// point outside `dir`.
+async fn find_pdf(dir: &Path, entry: &BibEntry) -> Option<PathBuf> {
+    let mut names = Vec::new();
+    if let Some(file) = &entry.file {
+        names.push(file.clone());
+    }
+    if let Some(key) = &entry.key {
+        names.push(format!("{key}.pdf"));
+    }
+    if let Some(doi) = &entry.metadata.doi {
+        names.push(format!("{}.pdf", doi.replace('/', "_")));
+    }
+    for name in &names {
+        let Some(name) = Path::new(name).file_name() else {
+            continue;
+        };
+        let candidate = dir.join(name);
+        if is_pdf(&candidate).await {
+            return Some(candidate);
+        }
+    }
+    None
+}
+
+async fn is_pdf(path: &Path) -> bool {
+    use tokio::io::AsyncReadExt;
+    let mut header = [0; 5];
+    match tokio::fs::File::open(path).await {
+        Ok(mut file) => file.read_exact(&mut header).await.is_ok() && &header == b"%PDF-",
+        Err(_) => false,
+    }
+}
+
+fn title_key(metadata: &PdfMetadata) -> (String, i32) {
+    (metadata.title.to_lowercase(), metadata.year)
+}
+
+/// Creates a paper in the project for every new entry of a BibTeX or RIS file.
+///
+/// Entries whose DOI, PMID or arXiv ID is already in the project, or appeared earlier in
+/// the file, are reported as duplicates. Entries without any of those are matched on
+/// title and year instead. Everything is inserted in one transaction.
+pub async fn import_bibliography(
+    text: &str,
+    format: BibFormat,
+    project_id: ProjectId,
+    options: &ImportOptions,
+    conn: &mut AsyncConn,
+) -> Result<ImportReport, MolmineError> {
+    let mut report = ImportReport {
+        dry_run: options.dry_run,
+        ..Default::default()
+    };
+    let mut seen = HashSet::new();
+    let mut titles: HashMap<(String, i32), Option<PdfId>> =
+        PdfInfo::list_for_project(project_id, conn)
+            .await?
+            .into_iter()
+            .map(|pdf| ((pdf.title.to_lowercase(), pdf.year), Some(pdf.id)))
+            .collect();
+    let mut planned = Vec::new();
+    'entries: for parsed in format.parse(text) {
+        let entry = match parsed.and_then(|raw| raw.validate()) {
+            Ok(entry) => entry,
+            Err(malformed) => {
+                report.malformed.push(malformed);
+                continue;
+            }
+        };
+        let entry_identifiers = identifiers(&entry.metadata);
+        for identifier in &entry_identifiers {
+            let existing = if seen.contains(identifier) {
+                Some(None)
+            } else {
+                PdfInfo::find_by_identifier(project_id, identifier, conn)
+                    .await?
+                    .map(|pdf| Some(pdf.id))
+            };
+            if let Some(existing_pdf_id) = existing {
+                report.duplicates.push(DuplicateEntry {
+                    line: entry.line,
+                    key: entry.key,
+                    title: entry.metadata.title,
+                    identifier: describe(identifier),
+                    existing_pdf_id,
+                });
+                continue 'entries;
+            }
+        }
+        let title_key = title_key(&entry.metadata);
+        if entry_identifiers.is_empty()
+            && let Some(existing_pdf_id) = titles.get(&title_key)
+        {
+            report.duplicates.push(DuplicateEntry {
+                line: entry.line,
+                key: entry.key,
+                identifier: format!("title:{} ({})", entry.metadata.title, entry.metadata.year),
+                title: entry.metadata.title,
+                existing_pdf_id: *existing_pdf_id,
+            });
+            continue;
+        }
+        seen.extend(entry_identifiers);
+        titles.entry(title_key).or_insert(None);
+        let pdf = match &options.pdf_dir {
+            Some(dir) => find_pdf(dir, &entry).await,
+            None => None,
+        };
+        planned.push((entry, pdf));
+    }
+
+    if options.dry_run {
+        report.created = planned
+            .into_iter()
+            .map(|(entry, pdf)| ImportedEntry {
+                line: entry.line,
+                key: entry.key,
+                title: entry.metadata.title,
+                pdf_id: None,
+                pdf_file: pdf.map(|path| path.display().to_string()),
+            })
+            .collect();
+        return Ok(report);
+    }
+
+    report.created = conn
+        .transaction::<_, MolmineError, _>(|conn| {
+            Box::pin(async move {
+                let mut created = Vec::new();
+                for (entry, pdf) in planned {
+                    // Files are only read now, so a dry run never holds them in memory
+                    let (pdf_file, data) = match pdf {
+                        Some(path) => {
+                            let data = tokio::fs::read(&path).await?;
+                            (Some(path.display().to_string()), data)
+                        }
+                        None => (None, Vec::new()),
+                    };
+                    let title = entry.metadata.title.clone();
+                    let inserted = entry
+                        .metadata
+                        .into_new_pdf(data, Some(project_id))
+                        .insert(conn)
+                        .await?;
+                    created.push(ImportedEntry {
+                        line: entry.line,
+                        key: entry.key,
+                        title,
+                        pdf_id: Some(inserted.id),
+                        pdf_file,
+                    });
+                }
+                Ok(created)
+            })
+        })
+        .await?;
+    Ok(report)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_find_pdf() {
+        let root = std::env::temp_dir().join(format!("find-pdf-{}", std::process::id()));
+        let dir = root.join("pdfs");
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(root.join("outside.pdf"), b"%PDF-1.7").unwrap();
+        std::fs::write(dir.join("linked.pdf"), b"%PDF-1.7").unwrap();
+        std::fs::write(dir.join("smith2020.pdf"), b"not a PDF").unwrap();
+
+        let entry = |file: &str, key: &str| BibEntry {
+            line: 1,
+            key: Some(key.to_string()),
+            metadata: PdfMetadata::default(),
+            file: Some(file.to_string()),
+        };
+        assert_eq!(
+            find_pdf(&dir, &entry("papers/linked.pdf", "smith2020")).await,
+            Some(dir.join("linked.pdf"))
+        );
+        assert_eq!(
+            find_pdf(&dir, &entry("../outside.pdf", "../outside")).await,
+            None
+        );
+        assert_eq!(find_pdf(&dir, &entry("", "smith2020")).await, None);
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+}
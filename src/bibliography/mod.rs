@@ -0,0 +1,148 @@
+//! Reading and writing reference manager formats.
+//!
+//! The BibTeX and RIS parsers both produce [`RawEntry`] values whose field names are
+//! our own column names, so validation into [`PdfMetadata`] happens in one place.
+
+pub mod bibtex;
+pub mod csl;
+pub mod export;
+pub mod import;
+pub mod ris;
+
+pub use export::*;
+pub use import::*;
+
+use serde::{Deserialize, Serialize};
+
+use crate::models::*;
+
+/// A reference manager file format
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum BibFormat {
+    Bibtex,
+    Ris,
+}
+
+impl BibFormat {
+    /// Picks the format from the file extension, falling back to sniffing the contents
+    pub fn detect(filename: Option<&str>, text: &str) -> Option<BibFormat> {
+        let extension = filename
+            .and_then(|name| name.rsplit_once('.'))
+            .map(|(_, extension)| extension.to_lowercase());
+        match extension.as_deref() {
+            Some("bib" | "bibtex") => Some(BibFormat::Bibtex),
+            Some("ris") => Some(BibFormat::Ris),
+            _ if text.lines().any(ris::is_tag_line) => Some(BibFormat::Ris),
+            _ if text.trim_start().starts_with('@') || text.contains("\n@") => {
+                Some(BibFormat::Bibtex)
+            }
+            _ => None,
+        }
+    }
+
+    pub fn parse(self, text: &str) -> Vec<Result<RawEntry, MalformedEntry>> {
+        match self {
+            BibFormat::Bibtex => bibtex::parse(text),
+            BibFormat::Ris => ris::parse(text),
+        }
+    }
+}
+
+/// An entry as read from the file, with fields renamed to `pdfs` column names
+/// (plus `file` for a linked PDF)
+#[derive(Clone, Debug, Default)]
+pub struct RawEntry {
+    /// Line of the file the entry starts on, counting from 1
+    pub line: usize,
+    /// The BibTeX citation key or RIS `ID`
+    pub key: Option<String>,
+    pub fields: Vec<(&'static str, String)>,
+}
+
+/// An entry that could not be turned into a paper
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct MalformedEntry {
+    pub line: usize,
+    pub key: Option<String>,
+    pub reason: String,
+}
+
+/// A validated entry ready to become a paper
+#[derive(Clone, Debug)]
+pub struct BibEntry {
+    pub line: usize,
+    pub key: Option<String>,
+    pub metadata: PdfMetadata,
+    /// Path of the linked PDF as written in the file
+    pub file: Option<String>,
+}
+
+impl RawEntry {
+    fn get(&self, name: &str) -> Option<&str> {
+        self.fields
+            .iter()
+            .find(|(field, value)| *field == name && !value.trim().is_empty())
+            .map(|(_, value)| value.trim())
+    }
+
+    fn malformed(&self, reason: impl Into<String>) -> MalformedEntry {
+        MalformedEntry {
+            line: self.line,
+            key: self.key.clone(),
+            reason: reason.into(),
+        }
+    }
+
+    /// An identifier field, which must be valid if present
+    fn identifier(
+        &self,
+        name: &str,
+        label: &str,
+        normalize: fn(&str) -> Option<String>,
+    ) -> Result<Option<String>, MalformedEntry> {
+        self.get(name)
+            .map(|value| {
+                normalize(value).ok_or_else(|| self.malformed(format!("Invalid {label}: {value}")))
+            })
+            .transpose()
+    }
+
+    pub fn validate(self) -> Result<BibEntry, MalformedEntry> {
+        let title = self
+            .get("title")
+            .ok_or_else(|| self.malformed("Missing title"))?;
+        let year = self
+            .get("year")
+            .ok_or_else(|| self.malformed("Missing year"))?;
+        // Dates such as "2019-05-01" or RIS's "2019/05/01/" start with the year
+        let year = year
+            .get(..4)
+            .filter(|digits| digits.chars().all(|c| c.is_ascii_digit()))
+            .and_then(|digits| digits.parse().ok())
+            .ok_or_else(|| self.malformed(format!("Invalid year: {year}")))?;
+        let text = |name: &str| self.get(name).map(str::to_string);
+        let metadata = PdfMetadata {
+            title: title.to_string(),
+            authors: text("authors").unwrap_or_default(),
+            year,
+            journal: text("journal").unwrap_or_default(),
+            volume: text("volume").unwrap_or_default(),
+            doi: self.identifier("doi", "DOI", normalize_doi)?,
+            pmid: self.identifier("pmid", "PMID", normalize_pmid)?,
+            arxiv_id: self.identifier("arxiv_id", "arXiv ID", normalize_arxiv_id)?,
+            isbn: self.identifier("isbn", "ISBN", normalize_isbn)?,
+            // Links are informational, so a relative or odd URL is dropped rather than rejected
+            url: self.get("url").and_then(normalize_url),
+            pages: text("pages"),
+            issue: text("issue"),
+            publisher: text("publisher"),
+        };
+        Ok(BibEntry {
+            file: text("file"),
+            line: self.line,
+            key: self.key,
+            metadata,
+        })
+    }
+}
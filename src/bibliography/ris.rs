@@ -0,0 +1,210 @@
+//! RIS reader and writer. Each line is `XX  - value`; entries start with `TY` and end
+//! with `ER`.
+
+use super::export::{Citation, WorkType};
+use super::{MalformedEntry, RawEntry};
+
+/// Whether the line has the `XX  - ` shape of an RIS tag
+pub fn is_tag_line(line: &str) -> bool {
+    split_tag(line).is_some()
+}
+
+fn split_tag(line: &str) -> Option<(&str, &str)> {
+    let line = line.trim_start_matches('\u{feff}');
+    let tag = line.get(..2)?;
+    let valid_tag = tag.chars().next()?.is_ascii_uppercase()
+        && tag
+            .chars()
+            .all(|c| c.is_ascii_uppercase() || c.is_ascii_digit());
+    let rest = line[2..].strip_prefix("  -")?;
+    valid_tag.then(|| (tag, rest.trim()))
+}
+
+/// Splits an `.ris` file into entries
+pub fn parse(text: &str) -> Vec<Result<RawEntry, MalformedEntry>> {
+    let mut entries = Vec::new();
+    let mut current: Option<RisEntry> = None;
+    for (index, line) in text.lines().enumerate() {
+        let line_number = index + 1;
+        let Some((tag, value)) = split_tag(line) else {
+            // Continuation lines extend the previous value
+            if let Some(entry) = &mut current
+                && let Some((_, last)) = entry.tags.last_mut()
+                && !line.trim().is_empty()
+            {
+                last.push(' ');
+                last.push_str(line.trim());
+            }
+            continue;
+        };
+        match tag {
+            "TY" => {
+                if let Some(entry) = current.take() {
+                    entries.push(Err(entry.malformed("Missing ER before the next TY")));
+                }
+                current = Some(RisEntry {
+                    line: line_number,
+                    tags: Vec::new(),
+                });
+            }
+            "ER" => match current.take() {
+                Some(entry) => entries.push(Ok(entry.into_raw())),
+                None => entries.push(Err(MalformedEntry {
+                    line: line_number,
+                    key: None,
+                    reason: "ER without a matching TY".to_string(),
+                })),
+            },
+            _ => match &mut current {
+                Some(entry) => entry.tags.push((tag.to_string(), value.to_string())),
+                None => entries.push(Err(MalformedEntry {
+                    line: line_number,
+                    key: None,
+                    reason: format!("{tag} outside of an entry"),
+                })),
+            },
+        }
+    }
+    if let Some(entry) = current {
+        entries.push(Err(entry.malformed("Missing ER at the end of the file")));
+    }
+    entries
+}
+
+struct RisEntry {
+    line: usize,
+    tags: Vec<(String, String)>,
+}
+
+impl RisEntry {
+    fn first(&self, tags: &[&str]) -> Option<String> {
+        tags.iter().find_map(|wanted| {
+            self.tags
+                .iter()
+                .find(|(tag, value)| tag == wanted && !value.is_empty())
+                .map(|(_, value)| value.clone())
+        })
+    }
+
+    fn all(&self, tags: &[&str]) -> Vec<String> {
+        self.tags
+            .iter()
+            .filter(|(tag, value)| tags.contains(&tag.as_str()) && !value.is_empty())
+            .map(|(_, value)| value.clone())
+            .collect()
+    }
+
+    fn malformed(&self, reason: &str) -> MalformedEntry {
+        MalformedEntry {
+            line: self.line,
+            key: self.first(&["ID"]),
+            reason: reason.to_string(),
+        }
+    }
+
+    fn into_raw(self) -> RawEntry {
+        let mut fields = Vec::new();
+        let mut push = |column: &'static str, value: Option<String>| {
+            if let Some(value) = value {
+                fields.push((column, value));
+            }
+        };
+        push("title", self.first(&["TI", "T1"]));
+        let authors = self.all(&["AU", "A1"]);
+        push("authors", (!authors.is_empty()).then(|| authors.join("; ")));
+        push("year", self.first(&["PY", "Y1", "DA"]));
+        push("journal", self.first(&["JF", "JO", "T2", "JA", "J2"]));
+        push("volume", self.first(&["VL"]));
+        push("issue", self.first(&["IS"]));
+        let pages = match (self.first(&["SP"]), self.first(&["EP"])) {
+            (Some(start), Some(end)) if start != end => Some(format!("{start}-{end}")),
+            (start, _) => start,
+        };
+        push("pages", pages);
+        push("publisher", self.first(&["PB"]));
+        push("doi", self.first(&["DO"]));
+        push("url", self.first(&["UR"]));
+        // SN holds either an ISBN or an ISSN, and only the former is kept
+        push(
+            "isbn",
+            self.first(&["SN"])
+                .filter(|sn| crate::models::normalize_isbn(sn).is_some()),
+        );
+        push("file", self.first(&["L1"]));
+        RawEntry {
+            line: self.line,
+            key: self.first(&["ID"]),
+            fields,
+        }
+    }
+}
+
+pub fn write(citations: &[Citation]) -> String {
+    let mut lines = Vec::new();
+    for citation in citations {
+        let pdf = &citation.pdf;
+        let mut tag = |tag: &str, value: &str| {
+            if !value.is_empty() {
+                lines.push(format!("{tag}  - {value}"));
+            }
+        };
+        let entry_type = match citation.work_type() {
+            WorkType::Article => "JOUR",
+            WorkType::Book => "BOOK",
+            WorkType::Preprint => "UNPB",
+            WorkType::Other => "GEN",
+        };
+        tag("TY", entry_type);
+        tag("ID", &citation.key);
+        tag("TI", &pdf.title);
+        for name in citation.author_names() {
+            match name.given_name {
+                Some(given) => tag("AU", &format!("{}, {given}", name.family_name)),
+                None => tag("AU", &name.family_name),
+            }
+        }
+        tag("PY", &pdf.year.to_string());
+        tag("T2", &pdf.journal);
+        tag("VL", &pdf.volume);
+        tag("IS", pdf.issue.as_deref().unwrap_or_default());
+        if let Some(pages) = &pdf.pages {
+            match pages.split_once('-') {
+                Some((start, end)) => {
+                    tag("SP", start.trim());
+                    tag("EP", end.trim());
+                }
+                None => tag("SP", pages),
+            }
+        }
+        tag("PB", pdf.publisher.as_deref().unwrap_or_default());
+        tag("DO", pdf.doi.as_deref().unwrap_or_default());
+        tag("UR", pdf.url.as_deref().unwrap_or_default());
+        tag("SN", pdf.isbn.as_deref().unwrap_or_default());
+        lines.push("ER  - ".to_string());
+        lines.push(String::new());
+    }
+    // The format is specified with CRLF line endings
+    lines.join("\r\n")
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_parse_ris() {
+        let text = "TY  - JOUR\nID  - curie1898\nTI  - Sur une substance nouvelle\nAU  - Curie, Pierre\nAU  - Curie, Marie\nPY  - 1898/07/18/\nJO  - Comptes rendus\nSP  - 175\nEP  - 178\nSN  - 0001-4036\nER  - \n\nTY  - JOUR\nTI  - Unterminated\n";
+        let entries = parse(text);
+        assert_eq!(entries.len(), 2);
+        let entry = entries[0].as_ref().unwrap();
+        assert_eq!(entry.key.as_deref(), Some("curie1898"));
+        assert!(
+            entry
+                .fields
+                .contains(&("authors", "Curie, Pierre; Curie, Marie".to_string()))
+        );
+        assert!(entry.fields.contains(&("pages", "175-178".to_string())));
+        assert!(!entry.fields.iter().any(|(column, _)| *column == "isbn"));
+        assert_eq!(entries[1].as_ref().unwrap_err().line, 13);
+    }
+}
@@ -0,0 +1,531 @@
+//! Commands run as `molmine <command> ...` instead of starting the web server. They work
+//! on `molmine.db` directly, without going through the API or its permission checks.
+
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+use futures::{Stream, StreamExt};
+
+use crate::db::{DATABASE_URL, establish, run_migrations};
+use crate::error::MolmineError;
+use crate::export::*;
+use crate::import::{CompoundFormat, ImportTarget, import_compounds};
+use crate::ingest::{self, IngestReport, IngestStatus, ingest_file, ingest_files, scan};
+use crate::jobs;
+use crate::labels::link_mentions;
+use crate::models::*;
+use crate::pdf::index_text;
+use crate::rdkit::SubstructureQuery;
+
+const USAGE: &str = "usage:
+  molmine migrate
+  molmine project list
+  molmine project create <name> --owner <username> [--fields <fields.json>]
+  molmine pdf import <project id> <file.pdf>...
+  molmine pdf index <project id>
+  molmine ingest <project id> <folder> [--watch] [--report <report.jsonl>]
+  molmine compound import <project id> <file> [--pdf <pdf id>] [--format sdf|smiles|csv] [--dry-run]
+  molmine compound export <project id> [--output <file>] [--format <format>]
+                          [--descriptors] [--fingerprints] [--images]
+      formats: sdf, csv, xlsx, parquet, arrow, cml, jsonld
+  molmine search --substructure <SMARTS> [--project <project id>]
+  molmine backup [<output.db>]
+  molmine worker [--workers <count>]";
+
+/// Options that are followed by a value
+const VALUE_OPTIONS: [&str; 9] = [
+    "--owner",
+    "--fields",
+    "--pdf",
+    "--format",
+    "--output",
+    "--substructure",
+    "--project",
+    "--report",
+    "--workers",
+];
+
+/// A command's positional arguments and `--options`
+#[derive(Debug, Default, PartialEq)]
+struct Args {
+    positional: Vec<String>,
+    options: Vec<(String, Option<String>)>,
+}
+
+impl Args {
+    fn parse(args: &[String]) -> Result<Self, MolmineError> {
+        let mut parsed = Args::default();
+        let mut args = args.iter();
+        while let Some(arg) = args.next() {
+            if !arg.starts_with("--") {
+                parsed.positional.push(arg.clone());
+            } else if let Some((name, value)) = arg.split_once('=') {
+                parsed
+                    .options
+                    .push((name.to_string(), Some(value.to_string())));
+            } else if VALUE_OPTIONS.contains(&arg.as_str()) {
+                let value = args.next().ok_or_else(|| {
+                    MolmineError::InvalidInput(format!("{arg} needs a value\n{USAGE}"))
+                })?;
+                parsed.options.push((arg.clone(), Some(value.clone())));
+            } else {
+                parsed.options.push((arg.clone(), None));
+            }
+        }
+        Ok(parsed)
+    }
+
+    fn value(&self, name: &str) -> Option<&str> {
+        self.options
+            .iter()
+            .find(|(option, _)| option == name)
+            .and_then(|(_, value)| value.as_deref())
+    }
+
+    fn flag(&self, name: &str) -> bool {
+        self.options.iter().any(|(option, _)| option == name)
+    }
+}
+
+fn usage() -> MolmineError {
+    MolmineError::InvalidInput(USAGE.to_string())
+}
+
+fn parse_id(text: &str, what: &str) -> Result<i32, MolmineError> {
+    text.parse()
+        .map_err(|_| MolmineError::InvalidInput(format!("Invalid {what} id: {text}")))
+}
+
+/// Runs the command given on the command line
+pub async fn run(args: &[String]) -> Result<(), MolmineError> {
+    let args = Args::parse(args)?;
+    let positional: Vec<&str> = args.positional.iter().map(String::as_str).collect();
+    match positional.as_slice() {
+        ["migrate"] => run_migrations(DATABASE_URL).await,
+        ["project", "list"] => list_projects().await,
+        ["project", "create", name] => {
+            let owner = args.value("--owner").ok_or_else(usage)?;
+            create_project(name, owner, args.value("--fields")).await
+        }
+        ["pdf", "import", project_id, files @ ..] if !files.is_empty() => {
+            import_pdfs(ProjectId(parse_id(project_id, "project")?), files).await
+        }
+        ["pdf", "index", project_id] => {
+            index_pdfs(ProjectId(parse_id(project_id, "project")?)).await
+        }
+        ["ingest", project_id, dir] => {
+            let project_id = ProjectId(parse_id(project_id, "project")?);
+            ingest_folder(
+                project_id,
+                dir,
+                args.value("--report"),
+                args.flag("--watch"),
+            )
+            .await
+        }
+        ["compound", "import", project_id, file] => {
+            let target = match args.value("--pdf") {
+                Some(pdf_id) => ImportTarget::Pdf(PdfId(parse_id(pdf_id, "PDF")?)),
+                None => ImportTarget::ExternalSource,
+            };
+            let format = match args.value("--format") {
+                Some(format) => Some(serde_json::from_value(format.into()).map_err(|_| {
+                    MolmineError::InvalidInput(format!("Unknown format: {format}"))
+                })?),
+                None => None,
+            };
+            let project_id = ProjectId(parse_id(project_id, "project")?);
+            import_compound_file(project_id, file, format, target, args.flag("--dry-run")).await
+        }
+        ["compound", "export", project_id] | ["export-sdf", project_id] => {
+            let options = TableOptions {
+                descriptors: args.flag("--descriptors"),
+                images: args.flag("--images"),
+                fingerprints: args.flag("--fingerprints"),
+            };
+            let project_id = ProjectId(parse_id(project_id, "project")?);
+            export_compounds(
+                project_id,
+                args.value("--output"),
+                args.value("--format"),
+                options,
+            )
+            .await
+        }
+        ["search"] => {
+            let smarts = args.value("--substructure").ok_or_else(usage)?;
+            let project_id = match args.value("--project") {
+                Some(project_id) => Some(ProjectId(parse_id(project_id, "project")?)),
+                None => None,
+            };
+            search_substructure(smarts, project_id).await
+        }
+        ["backup"] => backup(None).await,
+        ["backup", output] => backup(Some(output)).await,
+        ["worker"] => {
+            let count = match args.value("--workers") {
+                Some(count) => count.parse().map_err(|_| {
+                    MolmineError::InvalidInput(format!("Invalid worker count: {count}"))
+                })?,
+                None => jobs::worker_count(),
+            };
+            run_workers(count).await
+        }
+        _ => Err(usage()),
+    }
+}
+
+async fn list_projects() -> Result<(), MolmineError> {
+    let mut conn = establish().await?;
+    for project in Project::list_all(&mut conn).await? {
+        let created_at = project
+            .created_at
+            .map(|created_at| created_at.date().to_string())
+            .unwrap_or_default();
+        println!("{}\t{}\t{created_at}", project.id.0, project.name);
+    }
+    Ok(())
+}
+
+async fn create_project(
+    name: &str,
+    owner: &str,
+    fields_file: Option<&str>,
+) -> Result<(), MolmineError> {
+    let fields: Vec<ProjectField> = match fields_file {
+        Some(path) => serde_json::from_str(&std::fs::read_to_string(path)?)
+            .map_err(|e| MolmineError::InvalidInput(format!("Invalid fields in {path}: {e}")))?,
+        None => Vec::new(),
+    };
+    let name = name.trim();
+    if name.is_empty() {
+        return Err(MolmineError::InvalidInput(
+            "Project name is required".to_string(),
+        ));
+    }
+    let mut conn = establish().await?;
+    let owner = User::get_by_username(owner, &mut conn)
+        .await?
+        .ok_or(MolmineError::NotFound("User"))?;
+    let path = project_path(name)?;
+    if Project::get_by_path(&path, &mut conn).await?.is_some() {
+        return Err(MolmineError::InvalidInput(
+            "A project with this name already exists".to_string(),
+        ));
+    }
+    let project = NewProject {
+        name: name.to_string(),
+        path,
+        created_at: chrono::Utc::now().naive_utc(),
+        fields: serde_json::to_string(&fields)?,
+    }
+    .insert(owner.id, &mut conn)
+    .await?;
+    println!("{}\t{}", project.id.0, project.name);
+    Ok(())
+}
+
+/// Adds each PDF to the project with the details read from the file. Copies of papers
+/// already in the project are skipped, and a file that fails is reported while the rest
+/// are still imported.
+async fn import_pdfs(project_id: ProjectId, files: &[&str]) -> Result<(), MolmineError> {
+    let mut conn = establish().await?;
+    Project::get_by_id(project_id, &mut conn).await?;
+    Pdf::fill_content_hashes(project_id, &mut conn).await?;
+    let mut failed = 0;
+    for file in files {
+        let record = ingest_file(project_id, Path::new(file), &mut conn).await;
+        let reason = record.reason.as_deref().unwrap_or_default();
+        match record.status {
+            IngestStatus::Imported => println!(
+                "{}\t{}\t{file}",
+                record.pdf_id.map(|id| id.0).unwrap_or_default(),
+                record.title.as_deref().unwrap_or_default()
+            ),
+            IngestStatus::Duplicate => eprintln!("{file}: skipped, {reason}"),
+            IngestStatus::Failed => {
+                failed += 1;
+                eprintln!("{file}: {reason}");
+            }
+        }
+    }
+    if failed > 0 {
+        return Err(MolmineError::InvalidInput(format!(
+            "{failed} of {} files could not be imported",
+            files.len()
+        )));
+    }
+    Ok(())
+}
+
+/// Extracts the text of the project's papers that are not in the full-text index yet,
+/// such as those added before it existed or while PDFium was unavailable
+async fn index_pdfs(project_id: ProjectId) -> Result<(), MolmineError> {
+    let mut conn = establish().await?;
+    Project::get_by_id(project_id, &mut conn).await?;
+    Pdf::fill_content_hashes(project_id, &mut conn).await?;
+    let mut failed = 0;
+    for pdf_id in Pdf::list_unindexed(project_id, &mut conn).await? {
+        let pdf = Pdf::get_by_id(pdf_id, &mut conn).await?;
+        match index_text(pdf_id, pdf.data, &mut conn).await {
+            Ok(pages) => {
+                let mentions = link_mentions(pdf_id, &mut conn).await?;
+                println!(
+                    "{}\t{pages} pages\t{mentions} mentions\t{}",
+                    pdf_id.0, pdf.title
+                );
+            }
+            Err(e) => {
+                failed += 1;
+                eprintln!("{}\t{}: {e}", pdf_id.0, pdf.title);
+            }
+        }
+    }
+    if failed > 0 {
+        return Err(MolmineError::InvalidInput(format!(
+            "The text of {failed} papers could not be extracted"
+        )));
+    }
+    Ok(())
+}
+
+/// Imports every PDF in the folder, then with `watch` keeps importing new ones until
+/// stopped. Each file's outcome is written to the report.
+async fn ingest_folder(
+    project_id: ProjectId,
+    dir: &str,
+    report: Option<&str>,
+    watch: bool,
+) -> Result<(), MolmineError> {
+    let dir = Path::new(dir);
+    if !dir.is_dir() {
+        return Err(MolmineError::InvalidInput(format!(
+            "Not a folder: {}",
+            dir.display()
+        )));
+    }
+    let mut conn = establish().await?;
+    Project::get_by_id(project_id, &mut conn).await?;
+    let report_path = report.map_or_else(IngestReport::default_path, PathBuf::from);
+    let mut report = IngestReport::create(&report_path)?;
+    eprintln!("Writing the report to {}", report_path.display());
+
+    ingest_files(project_id, &scan(dir)?, &mut report, &mut conn).await?;
+    eprintln!("{}", report.summary());
+    if watch {
+        eprintln!(
+            "Watching {} for new PDFs, press Ctrl-C to stop",
+            dir.display()
+        );
+        ingest::watch(project_id, dir, &mut report, &mut conn).await?;
+    }
+    Ok(())
+}
+
+async fn import_compound_file(
+    project_id: ProjectId,
+    file: &str,
+    format: Option<CompoundFormat>,
+    target: ImportTarget,
+    dry_run: bool,
+) -> Result<(), MolmineError> {
+    let text = std::fs::read_to_string(file)?;
+    let format = format
+        .or_else(|| CompoundFormat::detect(Some(file), &text))
+        .ok_or_else(|| MolmineError::InvalidInput("The file is empty".to_string()))?;
+    let mut conn = establish().await?;
+    if let ImportTarget::Pdf(pdf_id) = target
+        && PdfInfo::get_by_id(pdf_id, &mut conn).await?.project_id != Some(project_id)
+    {
+        return Err(MolmineError::InvalidInput(
+            "The PDF belongs to another project".to_string(),
+        ));
+    }
+    let report = import_compounds(&text, format, project_id, target, dry_run, &mut conn).await?;
+    for failure in &report.failures {
+        eprintln!(
+            "record {} (line {}): {}",
+            failure.record, failure.line, failure.reason
+        );
+    }
+    if !report.unmapped_tags.is_empty() {
+        eprintln!(
+            "Not matching any field: {}",
+            report.unmapped_tags.join(", ")
+        );
+    }
+    let verb = if dry_run { "Would import" } else { "Imported" };
+    println!(
+        "{verb} {} compounds, {} failed",
+        report.imported.len(),
+        report.failures.len()
+    );
+    Ok(())
+}
+
+async fn write_stream<S, T>(stream: S, out: &mut dyn Write) -> Result<(), MolmineError>
+where
+    S: Stream<Item = Result<T, MolmineError>>,
+    T: AsRef<[u8]>,
+{
+    let mut stream = std::pin::pin!(stream);
+    while let Some(chunk) = stream.next().await {
+        out.write_all(chunk?.as_ref())?;
+    }
+    Ok(())
+}
+
+/// Writes the project's compounds to the file, or to standard output. The format is
+/// taken from the output's extension unless given.
+async fn export_compounds(
+    project_id: ProjectId,
+    output: Option<&str>,
+    format: Option<&str>,
+    options: TableOptions,
+) -> Result<(), MolmineError> {
+    let format = format
+        .or_else(|| output.and_then(|path| Path::new(path).extension()?.to_str()))
+        .unwrap_or("sdf")
+        .to_lowercase();
+    let mut out: Box<dyn Write> = match output {
+        Some(path) => Box::new(BufWriter::new(File::create(path)?)),
+        None => Box::new(BufWriter::new(std::io::stdout())),
+    };
+    let conn = establish().await?;
+    match format.as_str() {
+        "sdf" | "sd" => {
+            let export = SdfExport::new(project_id, conn).await?;
+            write_stream(export.into_stream(), &mut out).await?
+        }
+        "csv" => {
+            let export = CsvExport::new(project_id, options, conn).await?;
+            write_stream(export.into_stream(), &mut out).await?
+        }
+        "cml" => {
+            let export = CmlExport::new(project_id, conn).await?;
+            write_stream(export.into_stream(), &mut out).await?
+        }
+        "jsonld" => {
+            let export = JsonLdExport::new(project_id, conn).await?;
+            write_stream(export.into_stream(), &mut out).await?
+        }
+        "xlsx" => out.write_all(&write_xlsx(project_id, options, conn).await?)?,
+        "parquet" => out.write_all(
+            &write_columnar(project_id, ColumnarFormat::Parquet, options, conn).await?,
+        )?,
+        "arrow" | "feather" => {
+            out.write_all(&write_columnar(project_id, ColumnarFormat::Arrow, options, conn).await?)?
+        }
+        _ => {
+            return Err(MolmineError::InvalidInput(format!(
+                "Unknown format: {format}\n{USAGE}"
+            )));
+        }
+    }
+    out.flush()?;
+    Ok(())
+}
+
+/// Prints the compounds containing the SMARTS pattern, in one project or in all of them
+async fn search_substructure(
+    smarts: &str,
+    project_id: Option<ProjectId>,
+) -> Result<(), MolmineError> {
+    if SubstructureQuery::new(smarts).is_none() {
+        return Err(MolmineError::InvalidInput(format!(
+            "Invalid SMARTS pattern: {smarts}"
+        )));
+    }
+    let mut conn = establish().await?;
+    let projects = match project_id {
+        Some(project_id) => vec![Project::get_by_id(project_id, &mut conn).await?],
+        None => Project::list_all(&mut conn).await?,
+    };
+    for project in projects {
+        let mut batches = CompoundBatches::new(project.id);
+        while let Some(batch) = batches.next(&mut conn).await? {
+            let smarts = smarts.to_string();
+            // Matching is CPU-bound, so keep it off the async workers
+            let hits = tokio::task::spawn_blocking(move || {
+                let query = SubstructureQuery::new(&smarts)?;
+                Some(
+                    batch
+                        .into_iter()
+                        .filter(|(compound, _)| query.matches(&compound.smiles))
+                        .collect::<Vec<_>>(),
+                )
+            })
+            .await?
+            .unwrap_or_default();
+            for (compound, pdf) in hits {
+                println!(
+                    "{}\t{}\t{}\t{}\t{}",
+                    project.id.0, compound.id.0, compound.smiles, pdf.id.0, pdf.title
+                );
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Copies the database to the file, by default `molmine-backup-<timestamp>.db`
+async fn backup(output: Option<&str>) -> Result<(), MolmineError> {
+    let output = match output {
+        Some(output) => output.to_string(),
+        None => format!(
+            "molmine-backup-{}.db",
+            chrono::Local::now().format("%Y%m%d-%H%M%S")
+        ),
+    };
+    if Path::new(&output).exists() {
+        return Err(MolmineError::InvalidInput(format!(
+            "{output} already exists"
+        )));
+    }
+    let mut conn = establish().await?;
+    crate::db::backup(&output, &mut conn).await?;
+    println!("{output}");
+    Ok(())
+}
+
+/// Runs queued background jobs without the web server, until stopped
+async fn run_workers(count: usize) -> Result<(), MolmineError> {
+    if count == 0 {
+        return Err(MolmineError::InvalidInput(
+            "At least one worker is needed".to_string(),
+        ));
+    }
+    jobs::start_workers(count).await?;
+    eprintln!("Running {count} job workers, press Ctrl-C to stop");
+    tokio::signal::ctrl_c().await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_parse_args() {
+        let args: Vec<String> = [
+            "compound",
+            "export",
+            "3",
+            "--format",
+            "csv",
+            "--descriptors",
+        ]
+        .into_iter()
+        .map(String::from)
+        .collect();
+        let args = Args::parse(&args).unwrap();
+        assert_eq!(args.positional, ["compound", "export", "3"]);
+        assert_eq!(args.value("--format"), Some("csv"));
+        assert!(args.flag("--descriptors"));
+        assert!(!args.flag("--images"));
+        let args = Args::parse(&["--output=a.sdf".to_string()]).unwrap();
+        assert_eq!(args.value("--output"), Some("a.sdf"));
+        assert!(Args::parse(&["--pdf".to_string()]).is_err());
+    }
+}
@@ -21,7 +21,7 @@ pub async fn establish_async(db_url: &str) -> ConnectionResult<AsyncConn> {
     SyncConnectionWrapper::<SqliteConnection>::establish(db_url).await
 }
 
-const DATABASE_URL: &str = "sqlite://molmine.db";
+pub const DATABASE_URL: &str = "sqlite://molmine.db";
 pub async fn establish() -> Result<AsyncConn, MolmineError> {
     // Establish a synchronous connection to the database
     let conn = establish_async(DATABASE_URL).await?;
@@ -47,6 +47,18 @@ pub async fn run_migrations(db_url: &str) -> Result<(), MolmineError> {
     Ok(())
 }
 
+/// Writes a consistent copy of the database to `path`, which must not exist yet. The
+/// server can keep running while the copy is taken.
+pub async fn backup(path: &str, conn: &mut AsyncConn) -> Result<(), diesel::result::Error> {
+    use diesel::sql_types::Text;
+    use diesel_async::RunQueryDsl;
+    diesel::sql_query("VACUUM INTO ?")
+        .bind::<Text, _>(path)
+        .execute(conn)
+        .await?;
+    Ok(())
+}
+
 #[derive(QueryableByName)]
 struct LastId {
     #[diesel(sql_type = Integer)]
@@ -1,3 +1,6 @@
+use axum::Json;
+use axum::http::StatusCode;
+use axum::response::{IntoResponse, Response};
 use thiserror::Error;
 
 #[derive(Error, Debug)]
@@ -6,14 +9,78 @@ pub enum MolmineError {
     DieselConnectionError(#[from] diesel::ConnectionError),
     #[error("Error running database migrations: {0}")]
     DieselMigrationError(Box<dyn std::error::Error + Send + std::marker::Sync>),
-    // #[error("Failed to parse the PDF file")]
-    // PdfParseError(#[from] pdf::PdfError),
-    // #[error("Failed to parse the PDF file")]
-    // PdfiumError(#[from] pdfium_render::PdfiumError),
-    // #[error("Failed to parse the PDF file")]
-    // PdfiumRenderError(#[from] pdfium_render::PdfiumRenderError),
-    // #[error("Failed to parse the PDF file")]
-    // PdfiumPageError(#[from] pdfium_render::PdfiumPageError),
-    // #[error("Failed to parse the PDF file")]
-    // PdfiumBitmapError(#[from] pdfium_render::PdfiumBitmapError),
+    #[error("Database error: {0}")]
+    DieselError(#[from] diesel::result::Error),
+    #[error("Error (de)serializing JSON data: {0}")]
+    JsonError(#[from] serde_json::Error),
+    #[error("Error hashing password: {0}")]
+    PasswordHashError(String),
+    #[error("Authentication required")]
+    NotAuthenticated,
+    #[error("Invalid username or password")]
+    InvalidCredentials,
+    #[error("You do not have permission to do that")]
+    PermissionDenied,
+    #[error("{0} not found")]
+    NotFound(&'static str),
+    #[error("{0}")]
+    InvalidInput(String),
+    #[error("Failed to parse the PDF file: {0}")]
+    PdfParseError(#[from] lopdf::Error),
+    #[error("I/O error: {0}")]
+    IoError(#[from] std::io::Error),
+    #[error("Error writing CSV: {0}")]
+    CsvError(#[from] csv::Error),
+    #[error("Error writing the spreadsheet: {0}")]
+    XlsxError(#[from] rust_xlsxwriter::XlsxError),
+    #[error("Error writing Arrow data: {0}")]
+    ArrowError(#[from] arrow::error::ArrowError),
+    #[error("Error writing Parquet: {0}")]
+    ParquetError(#[from] parquet::errors::ParquetError),
+    #[error("Error reading or writing the archive: {0}")]
+    ArchiveError(#[from] zip::result::ZipError),
+    #[error("Error watching the folder: {0}")]
+    WatchError(#[from] notify::Error),
+    #[error("Background task failed: {0}")]
+    TaskError(#[from] tokio::task::JoinError),
+    #[error("Failed to read the PDF file with PDFium: {0}")]
+    PdfiumError(#[from] pdfium_render::prelude::PdfiumError),
+    #[error("Error encoding the image: {0}")]
+    ImageError(#[from] image::ImageError),
+    #[error("Structure recognition failed: {0}")]
+    OcsrError(String),
+}
+
+impl MolmineError {
+    pub fn status_code(&self) -> StatusCode {
+        match self {
+            MolmineError::NotAuthenticated | MolmineError::InvalidCredentials => {
+                StatusCode::UNAUTHORIZED
+            }
+            MolmineError::PermissionDenied => StatusCode::FORBIDDEN,
+            MolmineError::NotFound(_)
+            | MolmineError::DieselError(diesel::result::Error::NotFound) => StatusCode::NOT_FOUND,
+            MolmineError::InvalidInput(_)
+            | MolmineError::PdfParseError(_)
+            | MolmineError::ArchiveError(_) => StatusCode::BAD_REQUEST,
+            // The recognizer is a separate service or program that failed us
+            MolmineError::OcsrError(_) => StatusCode::BAD_GATEWAY,
+            _ => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+}
+
+/// API handlers return `MolmineError` directly; the frontend expects `{ "error": ... }` bodies
+impl IntoResponse for MolmineError {
+    fn into_response(self) -> Response {
+        let status = self.status_code();
+        if status.is_server_error() {
+            tracing::error!("{self}");
+        }
+        (
+            status,
+            Json(serde_json::json!({ "error": self.to_string() })),
+        )
+            .into_response()
+    }
 }
@@ -0,0 +1,123 @@
+//! Live updates for open pages: job progress, new compounds and records that failed
+//! validation. Events are broadcast within the server process and streamed to each
+//! browser by `/api/events` for its active project. Work done by another process,
+//! such as `molmine worker`, is not seen.
+
+use std::sync::LazyLock;
+
+use serde::Serialize;
+use tokio::sync::broadcast;
+
+use crate::models::*;
+
+/// Events kept for subscribers that fall behind before the oldest are dropped
+const EVENT_BUFFER: usize = 1024;
+
+static EVENTS: LazyLock<broadcast::Sender<ProjectEvent>> =
+    LazyLock::new(|| broadcast::channel(EVENT_BUFFER).0);
+
+#[derive(Clone, Debug, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ServerEvent {
+    /// A job changed status or reported progress
+    Job {
+        job_id: JobId,
+        kind: String,
+        status: JobStatus,
+        /// From 0 to 1, for jobs that report it
+        progress: Option<f64>,
+        message: Option<String>,
+    },
+    /// A compound was added to a paper
+    CompoundCreated {
+        compound_id: CompoundId,
+        pdf_id: PdfId,
+        smiles: String,
+    },
+    /// An imported record, file or region that could not be used
+    ValidationError { source: String, message: String },
+}
+
+impl ServerEvent {
+    /// The SSE event name, the same as the `type` in its data
+    pub fn name(&self) -> &'static str {
+        match self {
+            ServerEvent::Job { .. } => "job",
+            ServerEvent::CompoundCreated { .. } => "compound_created",
+            ServerEvent::ValidationError { .. } => "validation_error",
+        }
+    }
+
+    pub fn job(
+        job: &Job,
+        status: JobStatus,
+        progress: Option<f64>,
+        message: Option<String>,
+    ) -> Self {
+        ServerEvent::Job {
+            job_id: job.id,
+            kind: job.kind.clone(),
+            status,
+            progress,
+            message,
+        }
+    }
+}
+
+/// An event and the project it belongs to. Events outside any project are not sent.
+#[derive(Clone, Debug)]
+pub struct ProjectEvent {
+    pub project_id: Option<ProjectId>,
+    pub event: ServerEvent,
+}
+
+/// Sends the event to every page open on the project. Nobody listening is not an error.
+pub fn publish(project_id: Option<ProjectId>, event: ServerEvent) {
+    let _ = EVENTS.send(ProjectEvent { project_id, event });
+}
+
+pub fn subscribe() -> broadcast::Receiver<ProjectEvent> {
+    EVENTS.subscribe()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::time::Duration;
+    use tokio::sync::broadcast::error::RecvError;
+
+    #[tokio::test]
+    async fn test_server_event() {
+        let event = ServerEvent::CompoundCreated {
+            compound_id: CompoundId(4),
+            pdf_id: PdfId(2),
+            smiles: "CCO".to_string(),
+        };
+        assert_eq!(
+            serde_json::to_value(&event).unwrap(),
+            serde_json::json!({
+                "type": event.name(),
+                "compound_id": 4,
+                "pdf_id": 2,
+                "smiles": "CCO"
+            })
+        );
+
+        // Other tests publish on the same channel, so skip their events
+        let project_id = Some(ProjectId(-1));
+        let mut events = subscribe();
+        publish(project_id, event);
+        let received = tokio::time::timeout(Duration::from_secs(5), async {
+            loop {
+                match events.recv().await {
+                    Ok(received) if received.project_id == project_id => return received,
+                    Ok(_) | Err(RecvError::Lagged(_)) => continue,
+                    Err(RecvError::Closed) => panic!("The event channel closed"),
+                }
+            }
+        })
+        .await
+        .unwrap();
+        assert_eq!(received.event.name(), "compound_created");
+    }
+}
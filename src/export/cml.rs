@@ -0,0 +1,307 @@
+//! Chemical Markup Language export. Each compound becomes a `<molecule>` with its 2D
+//! atoms and bonds, identifiers, chemical data as properties and the source paper as
+//! Dublin Core metadata.
+
+use std::fmt::Write;
+
+use futures::Stream;
+use serde_json::Value;
+
+use super::{CompoundBatches, chemical_data, data_columns, value_text};
+use crate::bibliography::format_reference;
+use crate::db::AsyncConn;
+use crate::error::MolmineError;
+use crate::models::*;
+use crate::rdkit::smiles_to_molblock;
+
+const CML_HEADER: &str = "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n\
+    <cml xmlns=\"http://www.xml-cml.org/schema\" \
+    xmlns:dc=\"http://purl.org/dc/elements/1.1/\" \
+    xmlns:xsd=\"http://www.w3.org/2001/XMLSchema\" \
+    convention=\"convention:molecular\">\n";
+const CML_FOOTER: &str = "</cml>\n";
+
+/// Writes a project's compounds as one CML document, one batch of molecules at a time
+pub struct CmlExport {
+    conn: AsyncConn,
+    batches: CompoundBatches,
+    fields: Vec<ProjectField>,
+    header_written: bool,
+    footer_written: bool,
+}
+
+impl CmlExport {
+    pub async fn new(project_id: ProjectId, mut conn: AsyncConn) -> Result<Self, MolmineError> {
+        let fields = Project::get_by_id(project_id, &mut conn)
+            .await?
+            .field_defs()?;
+        Ok(CmlExport {
+            conn,
+            batches: CompoundBatches::new(project_id),
+            fields,
+            header_written: false,
+            footer_written: false,
+        })
+    }
+
+    /// The XML declaration, the molecules of each batch, the closing tag, then `None`
+    pub async fn next_chunk(&mut self) -> Result<Option<String>, MolmineError> {
+        if !self.header_written {
+            self.header_written = true;
+            return Ok(Some(CML_HEADER.to_string()));
+        }
+        let Some(batch) = self.batches.next(&mut self.conn).await? else {
+            if self.footer_written {
+                return Ok(None);
+            }
+            self.footer_written = true;
+            return Ok(Some(CML_FOOTER.to_string()));
+        };
+        let fields = self.fields.clone();
+        // Generating coordinates is CPU-bound, so keep it off the async workers
+        let chunk = tokio::task::spawn_blocking(move || {
+            let mut chunk = String::new();
+            for (compound, pdf) in &batch {
+                write_molecule(compound, pdf, &fields, &mut chunk);
+            }
+            chunk
+        })
+        .await?;
+        Ok(Some(chunk))
+    }
+
+    pub fn into_stream(self) -> impl Stream<Item = Result<String, MolmineError>> + Send {
+        futures::stream::try_unfold(self, |mut export| async move {
+            Ok(export.next_chunk().await?.map(|chunk| (chunk, export)))
+        })
+    }
+}
+
+/// Escapes text for use in XML content and attribute values, dropping the control
+/// characters XML 1.0 does not allow
+fn xml_escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&apos;"),
+            '\t' | '\n' | '\r' => escaped.push(c),
+            c if c.is_control() => {}
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+#[derive(Debug, PartialEq)]
+struct CmlAtom {
+    element: String,
+    x: f64,
+    y: f64,
+    charge: i32,
+}
+
+#[derive(Debug, PartialEq)]
+struct CmlBond {
+    from: usize,
+    to: usize,
+    /// CML bond order: 1, 2, 3 or A for aromatic
+    order: &'static str,
+}
+
+/// Reads the atoms and bonds of a V2000 molblock, or `None` if it is in another format
+fn read_molblock(molblock: &str) -> Option<(Vec<CmlAtom>, Vec<CmlBond>)> {
+    let field = |line: &str, start: usize, end: usize| -> Option<String> {
+        Some(line.get(start..end.min(line.len()))?.trim().to_string())
+    };
+    let lines: Vec<&str> = molblock.lines().collect();
+    let counts = lines.get(3)?;
+    if !counts.contains("V2000") {
+        return None;
+    }
+    let atom_count: usize = field(counts, 0, 3)?.parse().ok()?;
+    let bond_count: usize = field(counts, 3, 6)?.parse().ok()?;
+    let mut atoms = Vec::with_capacity(atom_count);
+    for line in lines.get(4..4 + atom_count)? {
+        // Charges in the atom block use codes, 1 to 3 for +3 to +1 and 5 to 7 for -1 to -3
+        let charge = match field(line, 36, 39).and_then(|code| code.parse::<i32>().ok()) {
+            Some(code @ 1..=3) => 4 - code,
+            Some(code @ 5..=7) => 4 - code,
+            _ => 0,
+        };
+        atoms.push(CmlAtom {
+            element: field(line, 31, 34)?,
+            x: field(line, 0, 10)?.parse().ok()?,
+            y: field(line, 10, 20)?.parse().ok()?,
+            charge,
+        });
+    }
+    let mut bonds = Vec::with_capacity(bond_count);
+    for line in lines.get(4 + atom_count..4 + atom_count + bond_count)? {
+        bonds.push(CmlBond {
+            from: field(line, 0, 3)?.parse().ok()?,
+            to: field(line, 3, 6)?.parse().ok()?,
+            order: match field(line, 6, 9)?.as_str() {
+                "2" => "2",
+                "3" => "3",
+                "4" => "A",
+                _ => "1",
+            },
+        });
+    }
+    // `M  CHG` lines replace every charge given in the atom block
+    let charge_lines: Vec<&&str> = lines[4 + atom_count + bond_count..]
+        .iter()
+        .filter(|line| line.starts_with("M  CHG"))
+        .collect();
+    if !charge_lines.is_empty() {
+        atoms.iter_mut().for_each(|atom| atom.charge = 0);
+    }
+    for line in charge_lines {
+        let values: Vec<&str> = line.split_whitespace().skip(3).collect();
+        for pair in values.chunks(2) {
+            if let [atom, charge] = pair
+                && let (Ok(atom), Ok(charge)) = (atom.parse::<usize>(), charge.parse())
+                && let Some(atom) = atoms.get_mut(atom.wrapping_sub(1))
+            {
+                atom.charge = charge;
+            }
+        }
+    }
+    Some((atoms, bonds))
+}
+
+/// Appends one `<molecule>` element for the compound
+pub fn write_molecule(
+    compound: &Compound,
+    pdf: &PdfInfo,
+    fields: &[ProjectField],
+    out: &mut String,
+) {
+    let _ = writeln!(
+        out,
+        "  <molecule id=\"m{}\" title=\"molmine-{}\">",
+        compound.id.0, compound.id.0
+    );
+    if !compound.smiles.is_empty() {
+        let _ = writeln!(
+            out,
+            "    <identifier convention=\"daylight:smiles\" value=\"{}\"/>",
+            xml_escape(&compound.smiles)
+        );
+    }
+    if !compound.inchi.is_empty() {
+        let _ = writeln!(
+            out,
+            "    <identifier convention=\"iupac:inchi\" value=\"{}\"/>",
+            xml_escape(&compound.inchi)
+        );
+    }
+
+    if let Some((atoms, bonds)) =
+        smiles_to_molblock(&compound.smiles).and_then(|molblock| read_molblock(&molblock))
+    {
+        out.push_str("    <atomArray>\n");
+        for (index, atom) in atoms.iter().enumerate() {
+            let _ = write!(
+                out,
+                "      <atom id=\"a{}\" elementType=\"{}\" x2=\"{:.4}\" y2=\"{:.4}\"",
+                index + 1,
+                xml_escape(&atom.element),
+                atom.x,
+                atom.y
+            );
+            if atom.charge != 0 {
+                let _ = write!(out, " formalCharge=\"{}\"", atom.charge);
+            }
+            out.push_str("/>\n");
+        }
+        out.push_str("    </atomArray>\n");
+        if !bonds.is_empty() {
+            out.push_str("    <bondArray>\n");
+            for bond in &bonds {
+                let _ = writeln!(
+                    out,
+                    "      <bond atomRefs2=\"a{} a{}\" order=\"{}\"/>",
+                    bond.from, bond.to, bond.order
+                );
+            }
+            out.push_str("    </bondArray>\n");
+        }
+    }
+
+    let data = chemical_data(compound);
+    let properties: Vec<(String, &Value)> = data_columns(fields, &data)
+        .into_iter()
+        .filter_map(|column| {
+            let value = data.get(&column)?;
+            value_text(value).is_some().then_some((column, value))
+        })
+        .collect();
+    if !properties.is_empty() {
+        out.push_str("    <propertyList>\n");
+        for (column, value) in properties {
+            let data_type = match value {
+                Value::Number(_) => "xsd:double",
+                _ => "xsd:string",
+            };
+            let _ = writeln!(
+                out,
+                "      <property title=\"{}\"><scalar dataType=\"{data_type}\">{}</scalar></property>",
+                xml_escape(&column),
+                xml_escape(&value_text(value).unwrap_or_default())
+            );
+        }
+        out.push_str("    </propertyList>\n");
+    }
+
+    out.push_str("    <metadataList>\n");
+    let _ = writeln!(
+        out,
+        "      <metadata name=\"dc:source\" content=\"{}\"/>",
+        xml_escape(&format_reference(pdf))
+    );
+    if let Some(doi) = &pdf.doi {
+        let _ = writeln!(
+            out,
+            "      <metadata name=\"dc:identifier\" content=\"https://doi.org/{}\"/>",
+            xml_escape(doi)
+        );
+    }
+    out.push_str("    </metadataList>\n");
+    out.push_str("  </molecule>\n");
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_read_molblock() {
+        let molblock = "\n     RDKit          2D\n\n  2  1  0  0  0  0  0  0  0  0999 V2000\n    \
+            0.0000    0.0000    0.0000 C   0  0  0  0  0  0  0  0  0  0  0  0\n    \
+            1.2990    0.7500    0.0000 O   0  5  0  0  0  0  0  0  0  0  0  0\n  \
+            1  2  1  0\nM  CHG  1   2  -1\nM  END\n";
+        let (atoms, bonds) = read_molblock(molblock).unwrap();
+        assert_eq!(atoms.len(), 2);
+        assert_eq!(atoms[1].element, "O");
+        assert_eq!(atoms[1].x, 1.299);
+        assert_eq!(atoms[1].charge, -1);
+        assert_eq!(
+            bonds,
+            vec![CmlBond {
+                from: 1,
+                to: 2,
+                order: "1"
+            }]
+        );
+        assert_eq!(
+            read_molblock("\n\n\n  0  0  0  0  0  0            999 V3000\n"),
+            None
+        );
+        assert_eq!(xml_escape("a<b & \"c\"\u{1}"), "a&lt;b &amp; &quot;c&quot;");
+    }
+}
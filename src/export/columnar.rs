@@ -0,0 +1,281 @@
+//! Parquet and Arrow IPC files of a project's compound table, for loading straight into
+//! pandas or polars. Columns are typed from the project's fields, so numbers arrive as
+//! float64 rather than text. Both formats end in a footer, so the file is built in memory.
+
+use std::sync::Arc;
+
+use arrow::array::{ArrayRef, BinaryArray, Float64Array, Int32Array, StringArray};
+use arrow::datatypes::{DataType, Field, Schema, SchemaRef};
+use arrow::error::ArrowError;
+use arrow::ipc::writer::FileWriter;
+use arrow::record_batch::RecordBatch;
+use parquet::arrow::ArrowWriter;
+use parquet::basic::Compression;
+use parquet::file::properties::WriterProperties;
+use serde_json::Value;
+
+use super::{CompoundBatches, TableLayout, TableOptions, chemical_data, value_text};
+use crate::db::AsyncConn;
+use crate::error::MolmineError;
+use crate::models::*;
+use crate::rdkit::{DESCRIPTORS, descriptors, fingerprint};
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum ColumnarFormat {
+    Parquet,
+    /// The Arrow IPC file format, also known as Feather v2
+    Arrow,
+}
+
+impl ColumnarFormat {
+    pub fn content_type(self) -> &'static str {
+        match self {
+            ColumnarFormat::Parquet => "application/vnd.apache.parquet",
+            ColumnarFormat::Arrow => "application/vnd.apache.arrow.file",
+        }
+    }
+
+    pub fn extension(self) -> &'static str {
+        match self {
+            ColumnarFormat::Parquet => "parquet",
+            ColumnarFormat::Arrow => "arrow",
+        }
+    }
+}
+
+/// A chemical data value as a number. Numeric fields may hold text when an import
+/// couldn't read the value, which is kept only if it parses.
+fn number_value(value: &Value) -> Option<f64> {
+    match value {
+        Value::Number(number) => number.as_f64(),
+        Value::String(text) => text.trim().parse().ok(),
+        _ => None,
+    }
+}
+
+/// Empty text is written as null, like the empty cells of the other table formats
+fn text_value(text: &str) -> Option<&str> {
+    (!text.is_empty()).then_some(text)
+}
+
+/// The typed columns of a project's compound table
+#[derive(Debug)]
+struct ColumnarLayout {
+    /// Each data column, with whether it is one of the project's number fields
+    data_columns: Vec<(String, bool)>,
+    descriptors: bool,
+    fingerprints: bool,
+}
+
+impl ColumnarLayout {
+    async fn load(
+        project_id: ProjectId,
+        options: TableOptions,
+        conn: &mut AsyncConn,
+    ) -> Result<Self, MolmineError> {
+        let fields = Project::get_by_id(project_id, conn).await?.field_defs()?;
+        let table = TableLayout::load(project_id, false, conn).await?;
+        let data_columns = table
+            .data_columns
+            .into_iter()
+            .map(|column| {
+                let number = fields
+                    .iter()
+                    .any(|field| field.name == column && field.field_type == FieldType::Number);
+                (column, number)
+            })
+            .collect();
+        Ok(ColumnarLayout {
+            data_columns,
+            descriptors: options.descriptors,
+            fingerprints: options.fingerprints,
+        })
+    }
+
+    /// Columns in the same order as the CSV export, with the fingerprint last
+    fn schema(&self) -> Schema {
+        let mut fields = vec![
+            Field::new("compound_id", DataType::Int32, false),
+            Field::new("SMILES", DataType::Utf8, true),
+            Field::new("InChI", DataType::Utf8, true),
+        ];
+        fields.extend(self.data_columns.iter().map(|(column, number)| {
+            let data_type = if *number {
+                DataType::Float64
+            } else {
+                DataType::Utf8
+            };
+            Field::new(column, data_type, true)
+        }));
+        if self.descriptors {
+            fields.extend(
+                DESCRIPTORS
+                    .iter()
+                    .map(|(column, _)| Field::new(*column, DataType::Float64, true)),
+            );
+        }
+        fields.extend([
+            Field::new("pdf_id", DataType::Int32, false),
+            Field::new("title", DataType::Utf8, true),
+            Field::new("authors", DataType::Utf8, true),
+            Field::new("year", DataType::Int32, false),
+            Field::new("journal", DataType::Utf8, true),
+            Field::new("doi", DataType::Utf8, true),
+        ]);
+        if self.fingerprints {
+            fields.push(Field::new("fingerprint", DataType::Binary, true));
+        }
+        Schema::new(fields)
+    }
+
+    fn record_batch(
+        &self,
+        schema: SchemaRef,
+        rows: &[(Compound, PdfInfo)],
+    ) -> Result<RecordBatch, ArrowError> {
+        let text = |value: fn(&(Compound, PdfInfo)) -> &str| -> ArrayRef {
+            Arc::new(
+                rows.iter()
+                    .map(|row| text_value(value(row)))
+                    .collect::<StringArray>(),
+            )
+        };
+        let mut columns: Vec<ArrayRef> = vec![
+            Arc::new(Int32Array::from_iter_values(
+                rows.iter().map(|(c, _)| c.id.0),
+            )),
+            text(|(c, _)| &c.smiles),
+            text(|(c, _)| &c.inchi),
+        ];
+
+        let data: Vec<_> = rows.iter().map(|(c, _)| chemical_data(c)).collect();
+        for (column, number) in &self.data_columns {
+            let values = data.iter().map(|data| data.get(column));
+            if *number {
+                let values = values.map(|value| value.and_then(number_value));
+                columns.push(Arc::new(values.collect::<Float64Array>()));
+            } else {
+                let values = values.map(|value| value.and_then(value_text));
+                columns.push(Arc::new(values.collect::<StringArray>()));
+            }
+        }
+
+        if self.descriptors {
+            let computed: Vec<_> = rows.iter().map(|(c, _)| descriptors(&c.smiles)).collect();
+            for index in 0..DESCRIPTORS.len() {
+                let values = computed
+                    .iter()
+                    .map(|values| values.as_ref().and_then(|values| values[index]));
+                columns.push(Arc::new(values.collect::<Float64Array>()));
+            }
+        }
+
+        columns.extend([
+            Arc::new(Int32Array::from_iter_values(
+                rows.iter().map(|(_, pdf)| pdf.id.0),
+            )) as ArrayRef,
+            text(|(_, pdf)| &pdf.title),
+            text(|(_, pdf)| &pdf.authors),
+            Arc::new(Int32Array::from_iter_values(
+                rows.iter().map(|(_, pdf)| pdf.year),
+            )),
+            text(|(_, pdf)| &pdf.journal),
+            text(|(_, pdf)| pdf.doi.as_deref().unwrap_or_default()),
+        ]);
+
+        if self.fingerprints {
+            let values = rows.iter().map(|(c, _)| fingerprint(&c.smiles));
+            columns.push(Arc::new(values.collect::<BinaryArray>()));
+        }
+        RecordBatch::try_new(schema, columns)
+    }
+}
+
+enum ColumnarWriter {
+    Parquet(ArrowWriter<Vec<u8>>),
+    Arrow(FileWriter<Vec<u8>>),
+}
+
+impl ColumnarWriter {
+    fn new(format: ColumnarFormat, schema: SchemaRef) -> Result<Self, MolmineError> {
+        Ok(match format {
+            ColumnarFormat::Parquet => {
+                let properties = WriterProperties::builder()
+                    .set_compression(Compression::SNAPPY)
+                    .build();
+                ColumnarWriter::Parquet(ArrowWriter::try_new(Vec::new(), schema, Some(properties))?)
+            }
+            ColumnarFormat::Arrow => {
+                ColumnarWriter::Arrow(FileWriter::try_new(Vec::new(), &schema)?)
+            }
+        })
+    }
+
+    fn write(&mut self, batch: &RecordBatch) -> Result<(), MolmineError> {
+        match self {
+            ColumnarWriter::Parquet(writer) => writer.write(batch)?,
+            ColumnarWriter::Arrow(writer) => writer.write(batch)?,
+        }
+        Ok(())
+    }
+
+    fn finish(self) -> Result<Vec<u8>, MolmineError> {
+        Ok(match self {
+            ColumnarWriter::Parquet(writer) => writer.into_inner()?,
+            ColumnarWriter::Arrow(mut writer) => {
+                writer.finish()?;
+                writer.into_inner()?
+            }
+        })
+    }
+}
+
+/// Builds a Parquet or Arrow file of the project's compound table, one record batch per
+/// batch of compounds
+pub async fn write_columnar(
+    project_id: ProjectId,
+    format: ColumnarFormat,
+    options: TableOptions,
+    mut conn: AsyncConn,
+) -> Result<Vec<u8>, MolmineError> {
+    let layout = Arc::new(ColumnarLayout::load(project_id, options, &mut conn).await?);
+    let schema = Arc::new(layout.schema());
+    let mut writer = ColumnarWriter::new(format, schema.clone())?;
+    let mut batches = CompoundBatches::new(project_id);
+    while let Some(batch) = batches.next(&mut conn).await? {
+        let layout = layout.clone();
+        let schema = schema.clone();
+        // Descriptors and fingerprints are CPU-bound, so keep them off the async workers
+        writer = tokio::task::spawn_blocking(move || {
+            writer.write(&layout.record_batch(schema, &batch)?)?;
+            Ok::<_, MolmineError>(writer)
+        })
+        .await??;
+    }
+    tokio::task::spawn_blocking(move || writer.finish()).await?
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_columnar_schema() {
+        let layout = ColumnarLayout {
+            data_columns: vec![("IC50".to_string(), true), ("note".to_string(), false)],
+            descriptors: true,
+            fingerprints: true,
+        };
+        let schema = layout.schema();
+        assert_eq!(schema.field(3).data_type(), &DataType::Float64);
+        assert_eq!(schema.field(4).data_type(), &DataType::Utf8);
+        assert_eq!(schema.fields().len(), 3 + 2 + DESCRIPTORS.len() + 6 + 1);
+        assert_eq!(
+            schema.fields().last().unwrap().data_type(),
+            &DataType::Binary
+        );
+        assert_eq!(number_value(&Value::from(2)), Some(2.0));
+        assert_eq!(number_value(&Value::from(" 1.5 ")), Some(1.5));
+        assert_eq!(number_value(&Value::from("n.d.")), None);
+    }
+}
@@ -0,0 +1,260 @@
+//! schema.org JSON-LD export for data repositories. The project is a `Dataset` citing
+//! its papers as `ScholarlyArticle`s, with each compound a Bioschemas `MolecularEntity`
+//! linked to the article it was extracted from.
+
+use futures::Stream;
+use serde_json::{Map, Value, json};
+
+use super::{CompoundBatches, chemical_data, data_columns, value_text};
+use crate::bibliography::{Citation, WorkType, load_citations};
+use crate::db::AsyncConn;
+use crate::error::MolmineError;
+use crate::models::*;
+
+const MOLECULAR_ENTITY_PROFILE: &str =
+    "https://bioschemas.org/profiles/MolecularEntity/0.5-RELEASE";
+
+/// Writes a project's compounds as one JSON-LD document, one batch of entities at a time
+pub struct JsonLdExport {
+    conn: AsyncConn,
+    batches: CompoundBatches,
+    fields: Vec<ProjectField>,
+    /// The dataset and its articles, written before the first compound
+    header: Option<String>,
+    first_entity: bool,
+    footer_written: bool,
+}
+
+impl JsonLdExport {
+    pub async fn new(project_id: ProjectId, mut conn: AsyncConn) -> Result<Self, MolmineError> {
+        let project = Project::get_by_id(project_id, &mut conn).await?;
+        let citations = load_citations(project_id, None, &mut conn).await?;
+        let mut dataset = json!({
+            "@context": {
+                "@vocab": "https://schema.org/",
+                "dct": "http://purl.org/dc/terms/",
+            },
+            "@type": "Dataset",
+            "name": project.name,
+            "citation": citations.iter().map(article).collect::<Vec<_>>(),
+        });
+        if let Some(created_at) = project.created_at {
+            dataset["dateCreated"] = json!(created_at.date().to_string());
+        }
+        // The compounds are streamed into `hasPart`, so the dataset object is left open
+        let dataset = serde_json::to_string_pretty(&dataset)?;
+        let header = format!(
+            "{},\n  \"hasPart\": [\n",
+            dataset.trim_end_matches('}').trim_end()
+        );
+        Ok(JsonLdExport {
+            conn,
+            batches: CompoundBatches::new(project_id),
+            fields: project.field_defs()?,
+            header: Some(header),
+            first_entity: true,
+            footer_written: false,
+        })
+    }
+
+    /// The dataset, the entities of each batch of compounds, the closing brackets, then
+    /// `None`
+    pub async fn next_chunk(&mut self) -> Result<Option<String>, MolmineError> {
+        if let Some(header) = self.header.take() {
+            return Ok(Some(header));
+        }
+        let Some(batch) = self.batches.next(&mut self.conn).await? else {
+            if self.footer_written {
+                return Ok(None);
+            }
+            self.footer_written = true;
+            return Ok(Some("\n  ]\n}\n".to_string()));
+        };
+        let mut chunk = String::new();
+        for (compound, pdf) in &batch {
+            if !self.first_entity {
+                chunk.push_str(",\n");
+            }
+            self.first_entity = false;
+            chunk.push_str("    ");
+            chunk.push_str(&serde_json::to_string(&molecular_entity(
+                compound,
+                pdf,
+                &self.fields,
+            ))?);
+        }
+        Ok(Some(chunk))
+    }
+
+    pub fn into_stream(self) -> impl Stream<Item = Result<String, MolmineError>> + Send {
+        futures::stream::try_unfold(self, |mut export| async move {
+            Ok(export.next_chunk().await?.map(|chunk| (chunk, export)))
+        })
+    }
+}
+
+/// The node ID of a paper: its DOI resolver link when it has one, so repositories can
+/// match it, or else a local reference
+fn article_id(pdf: &PdfInfo) -> String {
+    match &pdf.doi {
+        Some(doi) => format!("https://doi.org/{doi}"),
+        None => format!("#pdf-{}", pdf.id.0),
+    }
+}
+
+fn article(citation: &Citation) -> Value {
+    let pdf = &citation.pdf;
+    let authors: Vec<Value> = if citation.authors.is_empty() {
+        citation
+            .author_names()
+            .into_iter()
+            .map(|name| person(&name.family_name, name.given_name.as_deref(), None))
+            .collect()
+    } else {
+        citation
+            .authors
+            .iter()
+            .map(|author| {
+                person(
+                    &author.family_name,
+                    author.given_name.as_deref(),
+                    author.orcid.as_deref(),
+                )
+            })
+            .collect()
+    };
+    let mut identifiers = Vec::new();
+    for (property, value) in [
+        ("doi", &pdf.doi),
+        ("pmid", &pdf.pmid),
+        ("arxiv", &pdf.arxiv_id),
+        ("isbn", &pdf.isbn),
+    ] {
+        if let Some(value) = value {
+            identifiers.push(json!({
+                "@type": "PropertyValue",
+                "propertyID": property,
+                "value": value,
+            }));
+        }
+    }
+
+    let mut node = Map::new();
+    node.insert("@id".to_string(), json!(article_id(pdf)));
+    node.insert(
+        "@type".to_string(),
+        json!(match citation.work_type() {
+            WorkType::Article | WorkType::Preprint => "ScholarlyArticle",
+            WorkType::Book => "Book",
+            WorkType::Other => "CreativeWork",
+        }),
+    );
+    node.insert("name".to_string(), json!(pdf.title));
+    if !authors.is_empty() {
+        node.insert("author".to_string(), Value::Array(authors));
+    }
+    node.insert("datePublished".to_string(), json!(pdf.year.to_string()));
+    if !pdf.journal.is_empty() {
+        node.insert(
+            "isPartOf".to_string(),
+            json!({ "@type": "Periodical", "name": pdf.journal }),
+        );
+    }
+    if !pdf.volume.is_empty() {
+        node.insert("volumeNumber".to_string(), json!(pdf.volume));
+    }
+    for (property, value) in [
+        ("issueNumber", &pdf.issue),
+        ("pagination", &pdf.pages),
+        ("url", &pdf.url),
+    ] {
+        if let Some(value) = value {
+            node.insert(property.to_string(), json!(value));
+        }
+    }
+    if let Some(publisher) = &pdf.publisher {
+        node.insert(
+            "publisher".to_string(),
+            json!({ "@type": "Organization", "name": publisher }),
+        );
+    }
+    if !identifiers.is_empty() {
+        node.insert("identifier".to_string(), Value::Array(identifiers));
+    }
+    Value::Object(node)
+}
+
+fn person(family_name: &str, given_name: Option<&str>, orcid: Option<&str>) -> Value {
+    let mut node = json!({ "@type": "Person", "familyName": family_name });
+    if let Some(given_name) = given_name {
+        node["givenName"] = json!(given_name);
+    }
+    if let Some(orcid) = orcid {
+        node["@id"] = json!(format!("https://orcid.org/{orcid}"));
+    }
+    node
+}
+
+/// The compound as a Bioschemas `MolecularEntity`, its chemical data as property values
+pub fn molecular_entity(compound: &Compound, pdf: &PdfInfo, fields: &[ProjectField]) -> Value {
+    let name = format!("molmine-{}", compound.id.0);
+    let mut entity = json!({
+        "@id": format!("#compound-{}", compound.id.0),
+        "@type": "MolecularEntity",
+        "dct:conformsTo": { "@id": MOLECULAR_ENTITY_PROFILE },
+        "identifier": name,
+        "name": name,
+        "subjectOf": { "@id": article_id(pdf) },
+    });
+    if !compound.smiles.is_empty() {
+        entity["smiles"] = json!(compound.smiles);
+    }
+    if !compound.inchi.is_empty() {
+        entity["inChI"] = json!(compound.inchi);
+    }
+    let data = chemical_data(compound);
+    let properties: Vec<Value> = data_columns(fields, &data)
+        .into_iter()
+        .filter_map(|column| {
+            let value = data.get(&column)?;
+            let value = match value {
+                Value::Number(_) => value.clone(),
+                other => Value::String(value_text(other)?),
+            };
+            Some(json!({ "@type": "PropertyValue", "name": column, "value": value }))
+        })
+        .collect();
+    if !properties.is_empty() {
+        entity["additionalProperty"] = Value::Array(properties);
+    }
+    entity
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_molecular_entity() {
+        let compound = Compound {
+            id: CompoundId(7),
+            pdf_id: PdfId(2),
+            chemical_data: r#"{"IC50": 1.5, "note": ""}"#.to_string(),
+            ..fixtures::compound()
+        };
+        let pdf = PdfInfo {
+            id: PdfId(2),
+            doi: Some("10.1/x".to_string()),
+            ..fixtures::pdf_info()
+        };
+        let entity = molecular_entity(&compound, &pdf, &[]);
+        assert_eq!(entity["@type"], "MolecularEntity");
+        assert_eq!(entity["smiles"], "CCO");
+        assert!(entity.get("inChI").is_none());
+        assert_eq!(entity["subjectOf"]["@id"], "https://doi.org/10.1/x");
+        assert_eq!(
+            entity["additionalProperty"],
+            json!([{ "@type": "PropertyValue", "name": "IC50", "value": 1.5 }])
+        );
+    }
+}
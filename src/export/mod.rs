@@ -0,0 +1,101 @@
+//! Exports of a project's compound table.
+//!
+//! Exporters read compounds a batch at a time through [`CompoundBatches`]. The text
+//! formats emit each batch as soon as it is written, so a project never has to fit in
+//! memory.
+
+pub mod cml;
+pub mod columnar;
+pub mod jsonld;
+pub mod sdf;
+pub mod table;
+
+pub use cml::*;
+pub use columnar::*;
+pub use jsonld::*;
+pub use sdf::*;
+pub use table::*;
+
+use base64::Engine;
+use serde_json::{Map, Value};
+
+use crate::db::AsyncConn;
+use crate::models::*;
+
+/// How many compounds are read and written per batch
+pub const EXPORT_BATCH_SIZE: i64 = 500;
+
+/// Walks a project's compounds in id order, one batch at a time
+#[derive(Debug)]
+pub struct CompoundBatches {
+    project_id: ProjectId,
+    after: Option<CompoundId>,
+    done: bool,
+}
+
+impl CompoundBatches {
+    pub fn new(project_id: ProjectId) -> Self {
+        CompoundBatches {
+            project_id,
+            after: None,
+            done: false,
+        }
+    }
+
+    /// The next compounds with their papers, or `None` once all have been read
+    pub async fn next(
+        &mut self,
+        conn: &mut AsyncConn,
+    ) -> Result<Option<Vec<(Compound, PdfInfo)>>, diesel::result::Error> {
+        if self.done {
+            return Ok(None);
+        }
+        let batch =
+            Compound::list_page_for_project(self.project_id, self.after, EXPORT_BATCH_SIZE, conn)
+                .await?;
+        self.done = (batch.len() as i64) < EXPORT_BATCH_SIZE;
+        self.after = batch.last().map(|(compound, _)| compound.id).or(self.after);
+        Ok((!batch.is_empty()).then_some(batch))
+    }
+}
+
+/// The compound's chemical data as an object; anything unreadable counts as empty
+pub fn chemical_data(compound: &Compound) -> Map<String, Value> {
+    match serde_json::from_str(&compound.chemical_data) {
+        Ok(Value::Object(data)) => data,
+        _ => Map::new(),
+    }
+}
+
+/// The project's fields in their defined order, followed by any other keys found in the
+/// chemical data
+pub fn data_columns(fields: &[ProjectField], data: &Map<String, Value>) -> Vec<String> {
+    let mut columns: Vec<String> = fields.iter().map(|field| field.name.clone()).collect();
+    for key in data.keys() {
+        if !columns.contains(key) {
+            columns.push(key.clone());
+        }
+    }
+    columns
+}
+
+/// A chemical data value as plain text, or `None` when there is nothing to write
+pub fn value_text(value: &Value) -> Option<String> {
+    match value {
+        Value::Null => None,
+        Value::String(text) if text.trim().is_empty() => None,
+        Value::String(text) => Some(text.clone()),
+        Value::Number(number) => Some(number.to_string()),
+        Value::Bool(flag) => Some(flag.to_string()),
+        other => Some(other.to_string()),
+    }
+}
+
+/// The PNG bytes of the structure image captured for the compound, which the browser
+/// stores as a `data:image/png;base64,...` URL
+pub fn image_bytes(compound: &Compound) -> Option<Vec<u8>> {
+    let (_, encoded) = compound.image.split_once(";base64,")?;
+    base64::engine::general_purpose::STANDARD
+        .decode(encoded.trim())
+        .ok()
+}
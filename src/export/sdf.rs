@@ -0,0 +1,130 @@
+//! SD file export. Each compound is written as an RDKit molblock followed by its
+//! chemical data, SMILES, InChI and source paper as data items.
+
+use std::fmt::Write;
+
+use futures::Stream;
+
+use super::{CompoundBatches, chemical_data, data_columns, value_text};
+use crate::bibliography::format_reference;
+use crate::db::AsyncConn;
+use crate::error::MolmineError;
+use crate::models::*;
+use crate::rdkit::{EMPTY_MOLBLOCK, smiles_to_molblock};
+
+/// Writes a project's compounds as SDF, one batch of records at a time
+pub struct SdfExport {
+    conn: AsyncConn,
+    batches: CompoundBatches,
+    fields: Vec<ProjectField>,
+}
+
+impl SdfExport {
+    pub async fn new(project_id: ProjectId, mut conn: AsyncConn) -> Result<Self, MolmineError> {
+        let fields = Project::get_by_id(project_id, &mut conn)
+            .await?
+            .field_defs()?;
+        Ok(SdfExport {
+            conn,
+            batches: CompoundBatches::new(project_id),
+            fields,
+        })
+    }
+
+    /// The records of the next batch of compounds, or `None` once all have been written
+    pub async fn next_chunk(&mut self) -> Result<Option<String>, MolmineError> {
+        let Some(batch) = self.batches.next(&mut self.conn).await? else {
+            return Ok(None);
+        };
+        let fields = self.fields.clone();
+        // Generating coordinates is CPU-bound, so keep it off the async workers
+        let chunk = tokio::task::spawn_blocking(move || {
+            let mut chunk = String::new();
+            for (compound, pdf) in &batch {
+                write_record(compound, pdf, &fields, &mut chunk);
+            }
+            chunk
+        })
+        .await?;
+        Ok(Some(chunk))
+    }
+
+    pub fn into_stream(self) -> impl Stream<Item = Result<String, MolmineError>> + Send {
+        futures::stream::try_unfold(self, |mut export| async move {
+            Ok(export.next_chunk().await?.map(|chunk| (chunk, export)))
+        })
+    }
+}
+
+/// Data item names may not contain the angle brackets that delimit them
+fn tag_name(name: &str) -> String {
+    name.replace(['<', '>'], "_")
+}
+
+/// Data item values end at the first blank line, so multi-line text is folded onto one line
+fn tag_value(value: &str) -> String {
+    value.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+fn write_tag(name: &str, value: &str, out: &mut String) {
+    let value = tag_value(value);
+    if !value.is_empty() {
+        let _ = write!(out, ">  <{}>\n{value}\n\n", tag_name(name));
+    }
+}
+
+/// Appends one SDF record for the compound
+pub fn write_record(compound: &Compound, pdf: &PdfInfo, fields: &[ProjectField], out: &mut String) {
+    let molblock =
+        smiles_to_molblock(&compound.smiles).unwrap_or_else(|| EMPTY_MOLBLOCK.to_string());
+    // The first line of a molblock is the record's name
+    let body = molblock.split_once('\n').map_or("", |(_, body)| body);
+    let _ = writeln!(out, "molmine-{}", compound.id.0);
+    out.push_str(body);
+    if !out.ends_with('\n') {
+        out.push('\n');
+    }
+
+    let data = chemical_data(compound);
+    for column in data_columns(fields, &data) {
+        if let Some(value) = data.get(&column).and_then(value_text) {
+            write_tag(&column, &value, out);
+        }
+    }
+    write_tag("SMILES", &compound.smiles, out);
+    write_tag("InChI", &compound.inchi, out);
+    write_tag("molmine_compound_id", &compound.id.0.to_string(), out);
+    write_tag("molmine_pdf_id", &pdf.id.0.to_string(), out);
+    write_tag("Source", &format_reference(pdf), out);
+    if let Some(doi) = &pdf.doi {
+        write_tag("DOI", doi, out);
+    }
+    out.push_str("$$$$\n");
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_write_record() {
+        let compound = Compound {
+            id: CompoundId(7),
+            smiles: String::new(),
+            chemical_data: r#"{"IC50": 1.5, "note": "two\n\nlines", "empty": ""}"#.to_string(),
+            ..fixtures::compound()
+        };
+        let pdf = PdfInfo {
+            authors: "Smith, J".to_string(),
+            ..fixtures::pdf_info()
+        };
+        let mut out = String::new();
+        write_record(&compound, &pdf, &[], &mut out);
+        assert!(out.starts_with("molmine-7\n     molmine\n"));
+        assert!(out.contains(">  <IC50>\n1.5\n\n"));
+        assert!(out.contains(">  <note>\ntwo lines\n\n"));
+        assert!(!out.contains("<empty>"));
+        assert!(out.contains(">  <Source>\nSmith, J. Paper. 2019.\n\n"));
+        assert!(out.ends_with("$$$$\n"));
+    }
+}
@@ -0,0 +1,296 @@
+//! Compound tables joined to their papers, with one column per chemical data key. CSV
+//! is streamed like SDF; XLSX is a zip archive and is built in memory.
+
+use std::sync::Arc;
+
+use rust_xlsxwriter::{Format, Image, Workbook, XlsxError};
+use serde_json::Value;
+
+use super::{CompoundBatches, chemical_data, image_bytes, value_text};
+use crate::db::AsyncConn;
+use crate::error::MolmineError;
+use crate::models::*;
+use crate::rdkit::{DESCRIPTORS, descriptors};
+
+const COMPOUND_COLUMNS: [&str; 3] = ["compound_id", "SMILES", "InChI"];
+const PAPER_COLUMNS: [&str; 6] = ["pdf_id", "title", "authors", "year", "journal", "doi"];
+
+#[derive(Clone, Copy, Debug, Default)]
+pub struct TableOptions {
+    /// Add RDKit descriptor columns computed from each SMILES
+    pub descriptors: bool,
+    /// Embed each compound's captured structure image (XLSX only)
+    pub images: bool,
+    /// Add a binary column of RDKit fingerprints (Parquet and Arrow only)
+    pub fingerprints: bool,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Cell {
+    Text(String),
+    Number(f64),
+    Empty,
+}
+
+impl Cell {
+    fn text(value: &str) -> Cell {
+        if value.is_empty() {
+            Cell::Empty
+        } else {
+            Cell::Text(value.to_string())
+        }
+    }
+
+    fn into_text(self) -> String {
+        match self {
+            Cell::Text(text) => text,
+            Cell::Number(number) => number.to_string(),
+            Cell::Empty => String::new(),
+        }
+    }
+}
+
+/// The columns of a project's compound table
+#[derive(Debug)]
+pub struct TableLayout {
+    /// The project's fields in order, then any other chemical data keys in use
+    pub data_columns: Vec<String>,
+    pub descriptors: bool,
+}
+
+impl TableLayout {
+    pub async fn load(
+        project_id: ProjectId,
+        descriptors: bool,
+        conn: &mut AsyncConn,
+    ) -> Result<Self, MolmineError> {
+        let mut data_columns: Vec<String> = Project::get_by_id(project_id, conn)
+            .await?
+            .field_defs()?
+            .into_iter()
+            .map(|field| field.name)
+            .collect();
+        for key in Compound::chemical_data_keys(project_id, conn).await? {
+            if !data_columns.contains(&key) {
+                data_columns.push(key);
+            }
+        }
+        Ok(TableLayout {
+            data_columns,
+            descriptors,
+        })
+    }
+
+    pub fn header(&self) -> Vec<String> {
+        let mut header: Vec<String> = COMPOUND_COLUMNS.iter().map(|c| c.to_string()).collect();
+        header.extend(self.data_columns.iter().cloned());
+        if self.descriptors {
+            header.extend(DESCRIPTORS.iter().map(|(column, _)| column.to_string()));
+        }
+        header.extend(PAPER_COLUMNS.iter().map(|c| c.to_string()));
+        header
+    }
+
+    /// The cells of the compound's row, in [`header`](Self::header) order
+    pub fn row(&self, compound: &Compound, pdf: &PdfInfo) -> Vec<Cell> {
+        let mut row = vec![
+            Cell::Number(compound.id.0.into()),
+            Cell::text(&compound.smiles),
+            Cell::text(&compound.inchi),
+        ];
+        let data = chemical_data(compound);
+        row.extend(
+            self.data_columns
+                .iter()
+                .map(|column| match data.get(column) {
+                    Some(Value::Number(number)) => {
+                        number.as_f64().map_or(Cell::Empty, Cell::Number)
+                    }
+                    Some(value) => value_text(value).map_or(Cell::Empty, Cell::Text),
+                    None => Cell::Empty,
+                }),
+        );
+        if self.descriptors {
+            match descriptors(&compound.smiles) {
+                Some(values) => row.extend(
+                    values
+                        .into_iter()
+                        .map(|value| value.map_or(Cell::Empty, Cell::Number)),
+                ),
+                None => row.extend(DESCRIPTORS.iter().map(|_| Cell::Empty)),
+            }
+        }
+        row.extend([
+            Cell::Number(pdf.id.0.into()),
+            Cell::text(&pdf.title),
+            Cell::text(&pdf.authors),
+            Cell::Number(pdf.year.into()),
+            Cell::text(&pdf.journal),
+            Cell::text(pdf.doi.as_deref().unwrap_or_default()),
+        ]);
+        row
+    }
+}
+
+/// Writes a project's compound table as CSV, one batch of rows at a time
+pub struct CsvExport {
+    conn: AsyncConn,
+    batches: CompoundBatches,
+    layout: Arc<TableLayout>,
+    header_written: bool,
+}
+
+impl CsvExport {
+    pub async fn new(
+        project_id: ProjectId,
+        options: TableOptions,
+        mut conn: AsyncConn,
+    ) -> Result<Self, MolmineError> {
+        let layout = TableLayout::load(project_id, options.descriptors, &mut conn).await?;
+        Ok(CsvExport {
+            conn,
+            batches: CompoundBatches::new(project_id),
+            layout: Arc::new(layout),
+            header_written: false,
+        })
+    }
+
+    /// The header, then the rows of each batch of compounds, then `None`
+    pub async fn next_chunk(&mut self) -> Result<Option<Vec<u8>>, MolmineError> {
+        if !self.header_written {
+            self.header_written = true;
+            return write_csv(vec![self.layout.header()]).map(Some);
+        }
+        let Some(batch) = self.batches.next(&mut self.conn).await? else {
+            return Ok(None);
+        };
+        let layout = self.layout.clone();
+        // Descriptors are CPU-bound, so keep them off the async workers
+        let rows = tokio::task::spawn_blocking(move || {
+            batch
+                .iter()
+                .map(|(compound, pdf)| {
+                    layout
+                        .row(compound, pdf)
+                        .into_iter()
+                        .map(Cell::into_text)
+                        .collect()
+                })
+                .collect()
+        })
+        .await?;
+        write_csv(rows).map(Some)
+    }
+
+    pub fn into_stream(self) -> impl futures::Stream<Item = Result<Vec<u8>, MolmineError>> + Send {
+        futures::stream::try_unfold(self, |mut export| async move {
+            Ok(export.next_chunk().await?.map(|chunk| (chunk, export)))
+        })
+    }
+}
+
+fn write_csv(rows: Vec<Vec<String>>) -> Result<Vec<u8>, MolmineError> {
+    let mut data = Vec::new();
+    {
+        let mut writer = csv::Writer::from_writer(&mut data);
+        for row in rows {
+            writer.write_record(row)?;
+        }
+        writer.flush()?;
+    }
+    Ok(data)
+}
+
+/// Builds an XLSX workbook of the project's compound table
+pub async fn write_xlsx(
+    project_id: ProjectId,
+    options: TableOptions,
+    mut conn: AsyncConn,
+) -> Result<Vec<u8>, MolmineError> {
+    let layout = TableLayout::load(project_id, options.descriptors, &mut conn).await?;
+    let mut batches = CompoundBatches::new(project_id);
+    let mut rows = Vec::new();
+    while let Some(batch) = batches.next(&mut conn).await? {
+        rows.extend(batch);
+    }
+    let workbook =
+        tokio::task::spawn_blocking(move || build_workbook(&layout, &rows, options.images))
+            .await??;
+    Ok(workbook)
+}
+
+/// Height in points of rows holding a structure image
+const IMAGE_ROW_HEIGHT: f64 = 90.0;
+
+fn build_workbook(
+    layout: &TableLayout,
+    rows: &[(Compound, PdfInfo)],
+    images: bool,
+) -> Result<Vec<u8>, XlsxError> {
+    let mut workbook = Workbook::new();
+    let bold = Format::new().set_bold();
+    let worksheet = workbook.add_worksheet();
+    worksheet.set_name("Compounds")?;
+
+    // The image column comes first so structures sit next to their IDs
+    let first_column = u16::from(images);
+    if images {
+        worksheet.write_string_with_format(0, 0, "Structure", &bold)?;
+        worksheet.set_column_width(0, 24)?;
+    }
+    let header = layout.header();
+    for (index, name) in header.iter().enumerate() {
+        worksheet.write_string_with_format(0, first_column + index as u16, name, &bold)?;
+    }
+
+    for (index, (compound, pdf)) in rows.iter().enumerate() {
+        let row = index as u32 + 1;
+        if images
+            && let Some(png) = image_bytes(compound)
+            && let Ok(image) = Image::new_from_buffer(&png)
+        {
+            worksheet.set_row_height(row, IMAGE_ROW_HEIGHT)?;
+            worksheet.insert_image_fit_to_cell(row, 0, &image, true)?;
+        }
+        for (index, cell) in layout.row(compound, pdf).into_iter().enumerate() {
+            let column = first_column + index as u16;
+            match cell {
+                Cell::Text(text) => {
+                    worksheet.write_string(row, column, text)?;
+                }
+                Cell::Number(number) => {
+                    worksheet.write_number(row, column, number)?;
+                }
+                Cell::Empty => {}
+            }
+        }
+    }
+    worksheet.set_freeze_panes(1, 0)?;
+    let last_column = first_column + header.len() as u16 - 1;
+    worksheet.autofilter(0, 0, rows.len() as u32, last_column)?;
+    workbook.save_to_buffer()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_table_row() {
+        let layout = TableLayout {
+            data_columns: vec!["IC50".to_string(), "note".to_string()],
+            descriptors: false,
+        };
+        let compound = Compound {
+            id: CompoundId(3),
+            chemical_data: r#"{"IC50": 2.5, "note": "weak"}"#.to_string(),
+            ..fixtures::compound()
+        };
+        let pdf = fixtures::pdf_info();
+        let row = layout.row(&compound, &pdf);
+        assert_eq!(row.len(), layout.header().len());
+        assert_eq!(row[3], Cell::Number(2.5));
+        assert_eq!(row[4], Cell::Text("weak".to_string()));
+        assert_eq!(row[2], Cell::Empty);
+    }
+}
@@ -0,0 +1,279 @@
+//! Imports of compound lists from collaborators: SD files, SMILES files and CSV tables.
+//!
+//! The readers only split files into [`ParsedRecord`]s. Structures are then read with
+//! RDKit, and tags are matched to the project's fields in one place. A record that
+//! fails is reported and skipped; the rest are still imported.
+
+pub mod sdf;
+pub mod table;
+
+use std::collections::BTreeSet;
+
+use diesel_async::AsyncConnection;
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+
+use crate::db::AsyncConn;
+use crate::error::MolmineError;
+use crate::labels::normalize_label;
+use crate::models::*;
+use crate::rdkit::{canonical_smiles, molblock_to_smiles};
+
+/// A compound list file format
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum CompoundFormat {
+    Sdf,
+    Smiles,
+    Csv,
+}
+
+impl CompoundFormat {
+    /// Picks the format from the file extension, falling back to sniffing the contents
+    pub fn detect(filename: Option<&str>, text: &str) -> Option<CompoundFormat> {
+        let extension = filename
+            .and_then(|name| name.rsplit_once('.'))
+            .map(|(_, extension)| extension.to_lowercase());
+        match extension.as_deref() {
+            Some("sdf" | "sd" | "mol") => Some(CompoundFormat::Sdf),
+            Some("smi" | "smiles") => Some(CompoundFormat::Smiles),
+            Some("csv" | "tsv") => Some(CompoundFormat::Csv),
+            _ if text.contains("M  END") => Some(CompoundFormat::Sdf),
+            _ if text
+                .lines()
+                .next()
+                .is_some_and(|line| line.contains([',', '\t'])) =>
+            {
+                Some(CompoundFormat::Csv)
+            }
+            _ if !text.trim().is_empty() => Some(CompoundFormat::Smiles),
+            _ => None,
+        }
+    }
+
+    pub fn parse(self, text: &str) -> Result<Vec<Result<ParsedRecord, RecordFailure>>, String> {
+        match self {
+            CompoundFormat::Sdf => Ok(sdf::parse(text)),
+            CompoundFormat::Smiles => Ok(table::parse_smiles(text)),
+            CompoundFormat::Csv => table::parse_csv(text),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Structure {
+    Molblock(String),
+    Smiles(String),
+}
+
+/// A record as read from the file, before its structure is checked
+#[derive(Clone, Debug)]
+pub struct ParsedRecord {
+    /// Position of the record in the file, counting from 1
+    pub record: usize,
+    /// Line the record starts on
+    pub line: usize,
+    pub name: Option<String>,
+    pub structure: Structure,
+    /// SD data items or table columns, in file order
+    pub tags: Vec<(String, String)>,
+}
+
+/// A record that could not be imported
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct RecordFailure {
+    pub record: usize,
+    pub line: usize,
+    pub name: Option<String>,
+    pub reason: String,
+}
+
+/// Which paper the imported compounds are attached to
+#[derive(Copy, Clone, Debug)]
+pub enum ImportTarget {
+    Pdf(PdfId),
+    /// The project's placeholder paper for compounds that come from outside the literature
+    ExternalSource,
+}
+
+#[derive(Debug, Default, Serialize)]
+pub struct CompoundImportReport {
+    pub dry_run: bool,
+    /// The paper the compounds were attached to, absent on a dry run into the placeholder
+    pub pdf_id: Option<PdfId>,
+    pub imported: Vec<ImportedCompound>,
+    pub failures: Vec<RecordFailure>,
+    /// Tags that match none of the project's fields and were left out
+    pub unmapped_tags: Vec<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ImportedCompound {
+    pub record: usize,
+    pub name: Option<String>,
+    pub smiles: String,
+    /// The new compound, absent on a dry run
+    pub compound_id: Option<CompoundId>,
+}
+
+/// Reads the record's structure into canonical SMILES
+fn record_smiles(record: &ParsedRecord) -> Result<String, String> {
+    match &record.structure {
+        Structure::Molblock(molblock) => molblock_to_smiles(molblock)
+            .ok_or_else(|| "RDKit could not read the molblock".to_string()),
+        Structure::Smiles(smiles) => canonical_smiles(smiles)
+            .ok_or_else(|| format!("RDKit could not read the SMILES {smiles}")),
+    }
+}
+
+/// Turns a tag value into chemical data, as a number for numeric fields when it is one
+pub(crate) fn field_value(field: &ProjectField, value: &str) -> Value {
+    match field.field_type {
+        FieldType::Number => value
+            .parse::<f64>()
+            .ok()
+            .and_then(serde_json::Number::from_f64)
+            .map(Value::Number)
+            .unwrap_or_else(|| Value::String(value.to_string())),
+        FieldType::String => Value::String(value.to_string()),
+    }
+}
+
+/// The InChI and chemical data a record's tags hold, matching tags to fields by name
+/// regardless of case
+#[derive(Debug, Default)]
+struct MappedTags {
+    inchi: String,
+    chemical_data: Map<String, Value>,
+    /// Tags that matched no field
+    unmapped: Vec<String>,
+}
+
+fn map_tags(record: &ParsedRecord, fields: &[ProjectField]) -> MappedTags {
+    let mut mapped = MappedTags::default();
+    for (tag, value) in &record.tags {
+        let value = value.trim();
+        if tag.eq_ignore_ascii_case("inchi") {
+            mapped.inchi = value.to_string();
+            continue;
+        }
+        match fields
+            .iter()
+            .find(|field| field.name.trim().eq_ignore_ascii_case(tag.trim()))
+        {
+            Some(field) if !value.is_empty() => {
+                mapped
+                    .chemical_data
+                    .insert(field.name.clone(), field_value(field, value));
+            }
+            Some(_) => {}
+            None => mapped.unmapped.push(tag.clone()),
+        }
+    }
+    mapped
+}
+
+/// Creates a compound in the project for every record of the file that RDKit can read.
+/// All compounds are inserted in one transaction.
+pub async fn import_compounds(
+    text: &str,
+    format: CompoundFormat,
+    project_id: ProjectId,
+    target: ImportTarget,
+    dry_run: bool,
+    conn: &mut AsyncConn,
+) -> Result<CompoundImportReport, MolmineError> {
+    let parsed = format.parse(text).map_err(MolmineError::InvalidInput)?;
+    let fields = Project::get_by_id(project_id, conn).await?.field_defs()?;
+    // Reading structures is CPU-bound, so keep it off the async workers
+    let checked = tokio::task::spawn_blocking(move || {
+        parsed
+            .into_iter()
+            .map(|parsed| {
+                let record = parsed?;
+                match record_smiles(&record) {
+                    Ok(smiles) => Ok((record, smiles)),
+                    Err(reason) => Err(RecordFailure {
+                        record: record.record,
+                        line: record.line,
+                        name: record.name,
+                        reason,
+                    }),
+                }
+            })
+            .collect::<Vec<_>>()
+    })
+    .await?;
+
+    let mut report = CompoundImportReport {
+        dry_run,
+        ..Default::default()
+    };
+    let mut unmapped_tags = BTreeSet::new();
+    let mut planned = Vec::new();
+    for checked in checked {
+        match checked {
+            Ok((record, smiles)) => {
+                let mut mapped = map_tags(&record, &fields);
+                unmapped_tags.extend(std::mem::take(&mut mapped.unmapped));
+                planned.push((record, smiles, mapped));
+            }
+            Err(failure) => report.failures.push(failure),
+        }
+    }
+    report.unmapped_tags = unmapped_tags.into_iter().collect();
+
+    if dry_run {
+        if let ImportTarget::Pdf(pdf_id) = target {
+            report.pdf_id = Some(pdf_id);
+        }
+        report.imported = planned
+            .into_iter()
+            .map(|(record, smiles, _)| ImportedCompound {
+                record: record.record,
+                name: record.name,
+                smiles,
+                compound_id: None,
+            })
+            .collect();
+        return Ok(report);
+    }
+
+    let (pdf_id, imported) = conn
+        .transaction::<_, MolmineError, _>(|conn| {
+            Box::pin(async move {
+                let pdf_id = match target {
+                    ImportTarget::Pdf(pdf_id) => pdf_id,
+                    ImportTarget::ExternalSource => {
+                        PdfInfo::external_source(project_id, conn).await?.id
+                    }
+                };
+                let mut imported = Vec::new();
+                for (record, smiles, mapped) in planned {
+                    let inserted = NewCompound {
+                        inchi: mapped.inchi,
+                        chemical_data: Value::Object(mapped.chemical_data).to_string(),
+                        label: record
+                            .name
+                            .as_deref()
+                            .map(normalize_label)
+                            .unwrap_or_default(),
+                        ..NewCompound::new(pdf_id, smiles)
+                    }
+                    .insert(conn)
+                    .await?;
+                    imported.push(ImportedCompound {
+                        record: record.record,
+                        name: record.name,
+                        smiles: inserted.smiles,
+                        compound_id: Some(inserted.id),
+                    });
+                }
+                Ok((pdf_id, imported))
+            })
+        })
+        .await?;
+    report.pdf_id = Some(pdf_id);
+    report.imported = imported;
+    Ok(report)
+}
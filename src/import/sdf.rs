@@ -0,0 +1,97 @@
+//! SD file reader. Records end with `$$$$`; each is a molblock ending in `M  END`
+//! followed by `> <name>` data items whose values run until a blank line.
+
+use super::{ParsedRecord, RecordFailure, Structure};
+
+/// Splits an SD file into records without interpreting the molblocks
+pub fn parse(text: &str) -> Vec<Result<ParsedRecord, RecordFailure>> {
+    let mut records = Vec::new();
+    let mut lines: Vec<&str> = Vec::new();
+    let mut start_line = 1;
+    for (index, line) in text.lines().enumerate() {
+        if line.trim_end() == "$$$$" {
+            records.push(parse_record(records.len() + 1, start_line, &lines));
+            lines.clear();
+            start_line = index + 2;
+        } else {
+            lines.push(line);
+        }
+    }
+    // The terminator is often missing after the last record
+    if lines.iter().any(|line| !line.trim().is_empty()) {
+        records.push(parse_record(records.len() + 1, start_line, &lines));
+    }
+    records
+}
+
+fn parse_record(record: usize, line: usize, lines: &[&str]) -> Result<ParsedRecord, RecordFailure> {
+    let name = lines
+        .first()
+        .map(|name| name.trim().to_string())
+        .filter(|name| !name.is_empty());
+    let Some(end) = lines.iter().position(|line| line.trim_end() == "M  END") else {
+        return Err(RecordFailure {
+            record,
+            line,
+            name,
+            reason: "Molblock has no M  END line".to_string(),
+        });
+    };
+    let molblock = lines[..=end].join("\n") + "\n";
+
+    let mut tags = Vec::new();
+    let mut current: Option<(String, Vec<&str>)> = None;
+    for data_line in &lines[end + 1..] {
+        if data_line.starts_with('>')
+            && let Some((_, rest)) = data_line.split_once('<')
+            && let Some((tag, _)) = rest.split_once('>')
+        {
+            if let Some((tag, values)) = current.take() {
+                tags.push((tag, values.join("\n")));
+            }
+            current = Some((tag.trim().to_string(), Vec::new()));
+        } else if data_line.trim().is_empty() {
+            if let Some((tag, values)) = current.take() {
+                tags.push((tag, values.join("\n")));
+            }
+        } else if let Some((_, values)) = &mut current {
+            values.push(data_line.trim_end());
+        }
+    }
+    if let Some((tag, values)) = current {
+        tags.push((tag, values.join("\n")));
+    }
+    Ok(ParsedRecord {
+        record,
+        line,
+        name,
+        structure: Structure::Molblock(molblock),
+        tags,
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_parse_sdf() {
+        let text = "ethanol\n     RDKit          2D\n\n  3  2  0  0  0  0  0  0  0  0999 V2000\nM  END\n>  <IC50>  (1)\n12.5\n\n> <Notes>\nfirst\nsecond\n\n$$$$\nbroken\n\n\n$$$$\nlast\n\n\nM  END\n> <IC50>\n3\n";
+        let records = parse(text);
+        assert_eq!(records.len(), 3);
+        let first = records[0].as_ref().unwrap();
+        assert_eq!(first.name.as_deref(), Some("ethanol"));
+        assert_eq!(
+            first.tags,
+            vec![
+                ("IC50".to_string(), "12.5".to_string()),
+                ("Notes".to_string(), "first\nsecond".to_string()),
+            ]
+        );
+        let broken = records[1].as_ref().unwrap_err();
+        assert_eq!((broken.record, broken.line), (2, 14));
+        let last = records[2].as_ref().unwrap();
+        assert_eq!(last.line, 18);
+        assert_eq!(last.tags, vec![("IC50".to_string(), "3".to_string())]);
+    }
+}
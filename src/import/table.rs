@@ -0,0 +1,132 @@
+//! SMILES files (`SMILES name` per line) and CSV/TSV tables with a SMILES column.
+
+use super::{ParsedRecord, RecordFailure, Structure};
+
+/// Reads a `.smi` file: the SMILES, then optionally whitespace and a name. Blank lines,
+/// `#` comments and a `SMILES Name` header are skipped.
+pub fn parse_smiles(text: &str) -> Vec<Result<ParsedRecord, RecordFailure>> {
+    let mut records = Vec::new();
+    for (index, line) in text.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let (smiles, name) = match line.split_once(char::is_whitespace) {
+            Some((smiles, name)) => (smiles, Some(name.trim().to_string())),
+            None => (line, None),
+        };
+        if records.is_empty() && smiles.eq_ignore_ascii_case("smiles") {
+            continue;
+        }
+        records.push(Ok(ParsedRecord {
+            record: records.len() + 1,
+            line: index + 1,
+            name,
+            structure: Structure::Smiles(smiles.to_string()),
+            tags: Vec::new(),
+        }));
+    }
+    records
+}
+
+/// Column names taken as the structure and the record name
+const SMILES_COLUMNS: [&str; 3] = ["smiles", "canonical_smiles", "structure"];
+const NAME_COLUMNS: [&str; 4] = ["name", "id", "compound", "label"];
+
+/// Reads a CSV table, or a TSV one when the header contains tabs. Every column other than
+/// the SMILES becomes a tag.
+pub fn parse_csv(text: &str) -> Result<Vec<Result<ParsedRecord, RecordFailure>>, String> {
+    let header = text.lines().next().unwrap_or_default();
+    let delimiter = if header.contains('\t') { b'\t' } else { b',' };
+    let mut reader = csv::ReaderBuilder::new()
+        .delimiter(delimiter)
+        .flexible(true)
+        .trim(csv::Trim::All)
+        .from_reader(text.as_bytes());
+    let headers: Vec<String> = reader
+        .headers()
+        .map_err(|e| format!("Could not read the header row: {e}"))?
+        .iter()
+        .map(str::to_string)
+        .collect();
+    let find = |names: &[&str]| {
+        headers
+            .iter()
+            .position(|header| names.contains(&header.to_lowercase().as_str()))
+    };
+    let smiles_column = find(&SMILES_COLUMNS).ok_or_else(|| {
+        format!(
+            "No SMILES column; the header row is: {}",
+            headers.join(", ")
+        )
+    })?;
+    let name_column = find(&NAME_COLUMNS);
+
+    let mut records = Vec::new();
+    for (index, row) in reader.records().enumerate() {
+        let record = index + 1;
+        let row = match row {
+            Ok(row) => row,
+            Err(e) => {
+                records.push(Err(RecordFailure {
+                    record,
+                    line: 0,
+                    name: None,
+                    reason: e.to_string(),
+                }));
+                continue;
+            }
+        };
+        let line = row
+            .position()
+            .map_or(0, |position| position.line() as usize);
+        let name = name_column
+            .and_then(|column| row.get(column))
+            .filter(|name| !name.is_empty())
+            .map(str::to_string);
+        let smiles = row.get(smiles_column).unwrap_or_default();
+        if smiles.is_empty() {
+            records.push(Err(RecordFailure {
+                record,
+                line,
+                name,
+                reason: "Empty SMILES".to_string(),
+            }));
+            continue;
+        }
+        let tags = headers
+            .iter()
+            .zip(row.iter())
+            .enumerate()
+            .filter(|(column, _)| *column != smiles_column)
+            .map(|(_, (header, value))| (header.clone(), value.to_string()))
+            .collect();
+        records.push(Ok(ParsedRecord {
+            record,
+            line,
+            name,
+            structure: Structure::Smiles(smiles.to_string()),
+            tags,
+        }));
+    }
+    Ok(records)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_parse_smiles() {
+        let records = parse_smiles("SMILES Name\n# comment\nCCO ethanol\n\nc1ccccc1\n");
+        assert_eq!(records.len(), 2);
+        let ethanol = records[0].as_ref().unwrap();
+        assert_eq!(
+            (ethanol.record, ethanol.line, ethanol.name.as_deref()),
+            (1, 3, Some("ethanol"))
+        );
+        assert!(
+            matches!(&records[1].as_ref().unwrap().structure, Structure::Smiles(s) if s == "c1ccccc1")
+        );
+    }
+}
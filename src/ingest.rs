@@ -0,0 +1,363 @@
+//! Batch ingestion of a folder of PDFs into a project. Every file is recorded in a
+//! per-run report, one JSON line each, whether it was imported, skipped as a copy of a
+//! paper already in the project, or could not be read.
+
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+use chrono::{NaiveDateTime, Utc};
+use notify::{Event, EventKind, RecursiveMode, Watcher};
+use serde::Serialize;
+
+use crate::db::AsyncConn;
+use crate::error::MolmineError;
+use crate::models::*;
+use crate::pdf::{index_text, suggest_metadata};
+
+/// How long a file must go without changes before it is imported in watch mode, so
+/// files still being copied into the folder are not read half-written
+pub const WATCH_SETTLE_TIME: Duration = Duration::from_secs(2);
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum IngestStatus {
+    Imported,
+    Duplicate,
+    Failed,
+}
+
+impl IngestStatus {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            IngestStatus::Imported => "imported",
+            IngestStatus::Duplicate => "duplicate",
+            IngestStatus::Failed => "failed",
+        }
+    }
+}
+
+/// One line of the run report
+#[derive(Debug, Serialize)]
+pub struct IngestRecord {
+    pub time: NaiveDateTime,
+    pub file: PathBuf,
+    pub status: IngestStatus,
+    /// The new paper, or for a duplicate the paper already in the project
+    pub pdf_id: Option<PdfId>,
+    pub title: Option<String>,
+    pub reason: Option<String>,
+}
+
+impl IngestRecord {
+    fn new(file: &Path, status: IngestStatus) -> Self {
+        IngestRecord {
+            time: Utc::now().naive_utc(),
+            file: file.to_path_buf(),
+            status,
+            pdf_id: None,
+            title: None,
+            reason: None,
+        }
+    }
+
+    fn with_pdf(mut self, pdf_id: PdfId, title: &str) -> Self {
+        self.pdf_id = Some(pdf_id);
+        self.title = Some(title.to_string());
+        self
+    }
+}
+
+/// Writes the run report and keeps count of each outcome
+pub struct IngestReport {
+    writer: BufWriter<File>,
+    pub imported: usize,
+    pub duplicates: usize,
+    pub failed: usize,
+}
+
+impl IngestReport {
+    pub fn create(path: &Path) -> Result<Self, MolmineError> {
+        Ok(IngestReport {
+            writer: BufWriter::new(File::create(path)?),
+            imported: 0,
+            duplicates: 0,
+            failed: 0,
+        })
+    }
+
+    /// The report file name used when none is given, unique to the second
+    pub fn default_path() -> PathBuf {
+        PathBuf::from(format!(
+            "molmine-ingest-{}.jsonl",
+            chrono::Local::now().format("%Y%m%d-%H%M%S")
+        ))
+    }
+
+    /// Appends the record to the report and prints it. Each line is flushed straight
+    /// away, as watch mode only ends when the process is stopped.
+    pub fn record(&mut self, record: &IngestRecord) -> Result<(), MolmineError> {
+        match record.status {
+            IngestStatus::Imported => self.imported += 1,
+            IngestStatus::Duplicate => self.duplicates += 1,
+            IngestStatus::Failed => self.failed += 1,
+        }
+        serde_json::to_writer(&mut self.writer, record)?;
+        self.writer.write_all(b"\n")?;
+        self.writer.flush()?;
+
+        let file = record.file.display();
+        match record.status {
+            IngestStatus::Imported => println!(
+                "{}\t{}\t{}\t{file}",
+                record.status.as_str(),
+                record.pdf_id.map(|id| id.0).unwrap_or_default(),
+                record.title.as_deref().unwrap_or_default()
+            ),
+            IngestStatus::Duplicate | IngestStatus::Failed => eprintln!(
+                "{}\t{file}: {}",
+                record.status.as_str(),
+                record.reason.as_deref().unwrap_or_default()
+            ),
+        }
+        Ok(())
+    }
+
+    pub fn summary(&self) -> String {
+        format!(
+            "{} imported, {} duplicates skipped, {} failed",
+            self.imported, self.duplicates, self.failed
+        )
+    }
+}
+
+/// Whether the path names a PDF, going by its extension in any case
+pub fn is_pdf_path(path: &Path) -> bool {
+    path.extension()
+        .is_some_and(|extension| extension.eq_ignore_ascii_case("pdf"))
+}
+
+/// Every PDF in the folder and its subfolders, in path order. Hidden files and folders
+/// are left out, which also skips the temporary files some tools write while copying.
+pub fn scan(dir: &Path) -> Result<Vec<PathBuf>, MolmineError> {
+    let mut files = Vec::new();
+    let mut dirs = vec![dir.to_path_buf()];
+    while let Some(dir) = dirs.pop() {
+        for entry in std::fs::read_dir(&dir)? {
+            let entry = entry?;
+            let path = entry.path();
+            if entry.file_name().to_string_lossy().starts_with('.') {
+                continue;
+            }
+            let file_type = entry.file_type()?;
+            if file_type.is_dir() {
+                dirs.push(path);
+            } else if file_type.is_file() && is_pdf_path(&path) {
+                files.push(path);
+            }
+        }
+    }
+    files.sort();
+    Ok(files)
+}
+
+enum Ingested {
+    Imported(Pdf),
+    Duplicate(PdfInfo, String),
+}
+
+/// Adds one file to the project, never failing: problems with the file are recorded
+/// in the returned record instead
+pub async fn ingest_file(project_id: ProjectId, file: &Path, conn: &mut AsyncConn) -> IngestRecord {
+    match try_ingest_file(project_id, file, conn).await {
+        Ok(Ingested::Imported(pdf)) => {
+            IngestRecord::new(file, IngestStatus::Imported).with_pdf(pdf.id, &pdf.title)
+        }
+        Ok(Ingested::Duplicate(pdf, reason)) => IngestRecord {
+            reason: Some(reason),
+            ..IngestRecord::new(file, IngestStatus::Duplicate).with_pdf(pdf.id, &pdf.title)
+        },
+        Err(e) => IngestRecord {
+            reason: Some(e.to_string()),
+            ..IngestRecord::new(file, IngestStatus::Failed)
+        },
+    }
+}
+
+async fn try_ingest_file(
+    project_id: ProjectId,
+    file: &Path,
+    conn: &mut AsyncConn,
+) -> Result<Ingested, MolmineError> {
+    let data = tokio::fs::read(file).await?;
+    if !data.starts_with(b"%PDF-") {
+        return Err(MolmineError::InvalidInput("Not a PDF".to_string()));
+    }
+    let hash = content_hash(&data);
+    if let Some(existing) = Pdf::find_by_content_hash(project_id, &hash, conn).await? {
+        return Ok(Ingested::Duplicate(
+            existing,
+            "The same file is already in this project".to_string(),
+        ));
+    }
+
+    let (data, suggestion) = tokio::task::spawn_blocking(move || {
+        let suggestion = suggest_metadata(&data);
+        (data, suggestion)
+    })
+    .await?;
+    let suggestion = suggestion?;
+    let doi = suggestion.doi.as_deref().and_then(normalize_doi);
+    if let Some(doi) = &doi
+        && let Some(existing) =
+            PdfInfo::find_by_identifier(project_id, &PaperIdentifier::Doi(doi.clone()), conn)
+                .await?
+    {
+        return Ok(Ingested::Duplicate(
+            existing,
+            format!("A paper with DOI {doi} is already in this project"),
+        ));
+    }
+    // Papers without a readable title are named after their file, to be corrected later
+    let title = suggestion.title.unwrap_or_else(|| {
+        file.file_stem()
+            .map(|stem| stem.to_string_lossy().into_owned())
+            .unwrap_or_default()
+    });
+    let metadata = PdfMetadata {
+        title,
+        authors: suggestion.authors.unwrap_or_default(),
+        year: suggestion
+            .year
+            .unwrap_or_else(|| chrono::Datelike::year(&Utc::now())),
+        journal: suggestion.journal.unwrap_or_default(),
+        volume: suggestion.volume.unwrap_or_default(),
+        doi,
+        ..Default::default()
+    };
+    let mut pdf = metadata
+        .into_new_pdf(data, Some(project_id))
+        .insert(conn)
+        .await?;
+    // The paper is kept even if its text can't be read; the report only covers the import
+    if let Err(e) = index_text(pdf.id, std::mem::take(&mut pdf.data), conn).await {
+        tracing::warn!("{}: could not index the text: {e}", file.display());
+    }
+    Ok(Ingested::Imported(pdf))
+}
+
+/// Hashes the files of papers added before content hashes existed, so copies of them
+/// are recognised, then ingests the given files in order
+pub async fn ingest_files(
+    project_id: ProjectId,
+    files: &[PathBuf],
+    report: &mut IngestReport,
+    conn: &mut AsyncConn,
+) -> Result<(), MolmineError> {
+    Pdf::fill_content_hashes(project_id, conn).await?;
+    for file in files {
+        report.record(&ingest_file(project_id, file, conn).await)?;
+    }
+    Ok(())
+}
+
+/// Files seen changing in watch mode, waiting for their writes to settle
+#[derive(Debug, Default)]
+pub struct PendingFiles {
+    last_changed: HashMap<PathBuf, Instant>,
+}
+
+impl PendingFiles {
+    pub fn touch(&mut self, path: PathBuf, now: Instant) {
+        self.last_changed.insert(path, now);
+    }
+
+    /// Removes and returns the files unchanged for [`WATCH_SETTLE_TIME`], in path order
+    pub fn settled(&mut self, now: Instant) -> Vec<PathBuf> {
+        let mut settled: Vec<PathBuf> = self
+            .last_changed
+            .iter()
+            .filter(|(_, changed)| now.duration_since(**changed) >= WATCH_SETTLE_TIME)
+            .map(|(path, _)| path.clone())
+            .collect();
+        for path in &settled {
+            self.last_changed.remove(path);
+        }
+        settled.sort();
+        settled
+    }
+}
+
+/// Keeps ingesting PDFs as they are created in or copied into the folder, until the
+/// process is stopped
+pub async fn watch(
+    project_id: ProjectId,
+    dir: &Path,
+    report: &mut IngestReport,
+    conn: &mut AsyncConn,
+) -> Result<(), MolmineError> {
+    let (sender, mut events) = tokio::sync::mpsc::unbounded_channel();
+    // The watcher calls back from its own thread, so events are handed over by channel
+    let mut watcher = notify::recommended_watcher(move |event: notify::Result<Event>| {
+        let _ = sender.send(event);
+    })?;
+    watcher.watch(dir, RecursiveMode::Recursive)?;
+
+    let mut pending = PendingFiles::default();
+    let mut ticks = tokio::time::interval(Duration::from_millis(500));
+    loop {
+        tokio::select! {
+            event = events.recv() => match event {
+                Some(Ok(event)) => {
+                    if matches!(event.kind, EventKind::Create(_) | EventKind::Modify(_)) {
+                        for path in event.paths.into_iter().filter(|path| is_pdf_path(path)) {
+                            pending.touch(path, Instant::now());
+                        }
+                    }
+                }
+                Some(Err(e)) => tracing::warn!("Error watching {}: {e}", dir.display()),
+                None => return Ok(()),
+            },
+            _ = ticks.tick() => {
+                for file in pending.settled(Instant::now()) {
+                    // Files moved away or deleted before settling are not reported
+                    if file.is_file() {
+                        report.record(&ingest_file(project_id, &file, conn).await)?;
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_pending_files() {
+        assert!(is_pdf_path(Path::new("papers/Smith 2019.PDF")));
+        assert!(!is_pdf_path(Path::new("papers/notes.txt")));
+        assert!(!is_pdf_path(Path::new("papers/pdf")));
+
+        let start = Instant::now();
+        let mut pending = PendingFiles::default();
+        pending.touch(PathBuf::from("b.pdf"), start);
+        pending.touch(PathBuf::from("a.pdf"), start);
+        pending.touch(PathBuf::from("c.pdf"), start + Duration::from_secs(1));
+        assert!(pending.settled(start + Duration::from_secs(1)).is_empty());
+        // c.pdf changed again, so it waits for another full settle time
+        pending.touch(PathBuf::from("c.pdf"), start + Duration::from_secs(2));
+        assert_eq!(
+            pending.settled(start + WATCH_SETTLE_TIME),
+            vec![PathBuf::from("a.pdf"), PathBuf::from("b.pdf")]
+        );
+        assert!(pending.settled(start + Duration::from_secs(3)).is_empty());
+        assert_eq!(
+            pending.settled(start + Duration::from_secs(4)),
+            vec![PathBuf::from("c.pdf")]
+        );
+    }
+}
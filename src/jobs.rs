@@ -0,0 +1,416 @@
+//! Background jobs for work too slow to finish within a request: text extraction,
+//! structure detection and recognition, and large exports. Folders on the server are
+//! ingested with the `ingest` CLI command instead, as jobs are queued over HTTP.
+//!
+//! Jobs are rows of the `jobs` table, so queued work survives a restart. Worker tasks
+//! on the server's runtime claim them one at a time, retry failures after a growing
+//! delay and stop a running job as soon as it is cancelled. Status changes and
+//! progress are published as [`ServerEvent`]s.
+
+use std::path::PathBuf;
+use std::sync::LazyLock;
+use std::time::Duration;
+
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
+use serde_json::{Value, json};
+use tokio::sync::{Mutex, Notify};
+
+use crate::db::{AsyncConn, establish};
+use crate::error::MolmineError;
+use crate::events::{ServerEvent, publish};
+use crate::export::{ColumnarFormat, TableOptions, write_columnar, write_xlsx};
+use crate::labels::link_mentions;
+use crate::models::*;
+use crate::ocsr::{recognize_with_server_backend, render_region};
+use crate::pdf::index_text;
+use crate::segment::propose_regions;
+
+pub const DEFAULT_WORKERS: usize = 2;
+/// How many times a job is tried before it is failed
+pub const MAX_ATTEMPTS: i32 = 3;
+pub const EXPORT_DIR: &str = "cache/exports";
+/// How often idle workers look for jobs queued by other processes, such as the CLI
+const POLL_INTERVAL: Duration = Duration::from_secs(5);
+/// How often a running job checks whether it has been cancelled
+const CANCEL_CHECK_INTERVAL: Duration = Duration::from_secs(1);
+/// The wait before the first retry, doubled for each one after
+const RETRY_DELAY: Duration = Duration::from_secs(10);
+const MAX_RETRY_DELAY: Duration = Duration::from_secs(60 * 60);
+
+/// Wakes an idle worker when a job is queued
+static JOB_QUEUED: LazyLock<Notify> = LazyLock::new(Notify::new);
+/// Workers claim jobs one at a time, so they don't compete for the database lock
+static CLAIM_LOCK: Mutex<()> = Mutex::const_new(());
+
+/// The work a job does, stored as JSON in `jobs.task`
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum JobTask {
+    /// Extract the paper's text into the full-text index
+    IndexText { pdf_id: PdfId },
+    /// Propose structure regions on every page of the paper
+    DetectRegions { pdf_id: PdfId },
+    /// Recognize the structure drawn in a region
+    RecognizeRegion { region_id: RegionId },
+    /// Recognize the structures in every region of the paper that wasn't rejected
+    RecognizePdf { pdf_id: PdfId },
+    /// Write every compound of the job's project to a file for download
+    Export {
+        format: ExportFileFormat,
+        #[serde(default)]
+        descriptors: bool,
+        #[serde(default)]
+        images: bool,
+        #[serde(default)]
+        fingerprints: bool,
+    },
+}
+
+impl JobTask {
+    pub fn kind(&self) -> &'static str {
+        match self {
+            JobTask::IndexText { .. } => "index_text",
+            JobTask::DetectRegions { .. } => "detect_regions",
+            JobTask::RecognizeRegion { .. } => "recognize_region",
+            JobTask::RecognizePdf { .. } => "recognize_pdf",
+            JobTask::Export { .. } => "export",
+        }
+    }
+}
+
+/// Export formats built in memory, which are the slow ones; the others are streamed
+/// straight from the export endpoint
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ExportFileFormat {
+    Xlsx,
+    Parquet,
+    Arrow,
+}
+
+impl ExportFileFormat {
+    pub fn content_type(self) -> &'static str {
+        match self {
+            ExportFileFormat::Xlsx => {
+                "application/vnd.openxmlformats-officedocument.spreadsheetml.sheet"
+            }
+            ExportFileFormat::Parquet => ColumnarFormat::Parquet.content_type(),
+            ExportFileFormat::Arrow => ColumnarFormat::Arrow.content_type(),
+        }
+    }
+
+    pub fn extension(self) -> &'static str {
+        match self {
+            ExportFileFormat::Xlsx => "xlsx",
+            ExportFileFormat::Parquet => ColumnarFormat::Parquet.extension(),
+            ExportFileFormat::Arrow => ColumnarFormat::Arrow.extension(),
+        }
+    }
+}
+
+/// Where the file written by an export job is kept
+pub fn export_path(job_id: JobId, format: ExportFileFormat) -> PathBuf {
+    PathBuf::from(EXPORT_DIR).join(format!("job-{}.{}", job_id.0, format.extension()))
+}
+
+/// Adds a job to the queue and wakes a worker for it
+pub async fn enqueue(
+    task: &JobTask,
+    project_id: Option<ProjectId>,
+    created_by: Option<UserId>,
+    conn: &mut AsyncConn,
+) -> Result<Job, MolmineError> {
+    let job = NewJob {
+        project_id,
+        created_by,
+        kind: task.kind().to_string(),
+        task: serde_json::to_string(task)?,
+        max_attempts: MAX_ATTEMPTS,
+        run_after: Utc::now().naive_utc(),
+    }
+    .insert(conn)
+    .await?;
+    JOB_QUEUED.notify_one();
+    Ok(job)
+}
+
+/// How long to wait before trying a job again after its `attempts`th attempt failed
+pub fn retry_delay(attempts: i32) -> Duration {
+    let doublings = attempts.clamp(1, 16) as u32 - 1;
+    (RETRY_DELAY * 2u32.pow(doublings)).min(MAX_RETRY_DELAY)
+}
+
+/// The number of workers to run, from `MOLMINE_JOB_WORKERS`
+pub fn worker_count() -> usize {
+    std::env::var("MOLMINE_JOB_WORKERS")
+        .ok()
+        .and_then(|count| count.parse().ok())
+        .unwrap_or(DEFAULT_WORKERS)
+}
+
+/// Returns jobs interrupted by the last shutdown to the queue and starts `count`
+/// workers on the current runtime
+pub async fn start_workers(count: usize) -> Result<(), MolmineError> {
+    let mut conn = establish().await?;
+    let requeued = Job::requeue_interrupted(&mut conn).await?;
+    if requeued > 0 {
+        tracing::info!("Requeued {requeued} jobs interrupted by a restart");
+    }
+    for worker in 0..count {
+        tokio::spawn(work(worker));
+    }
+    Ok(())
+}
+
+async fn work(worker: usize) {
+    loop {
+        match next_job().await {
+            Ok(Some(job)) => run(job).await,
+            Ok(None) => {
+                let _ = tokio::time::timeout(POLL_INTERVAL, JOB_QUEUED.notified()).await;
+            }
+            Err(e) => {
+                tracing::error!("Job worker {worker} could not claim a job: {e}");
+                tokio::time::sleep(POLL_INTERVAL).await;
+            }
+        }
+    }
+}
+
+async fn next_job() -> Result<Option<Job>, MolmineError> {
+    let _claiming = CLAIM_LOCK.lock().await;
+    let mut conn = establish().await?;
+    Ok(Job::claim_next(Utc::now().naive_utc(), &mut conn).await?)
+}
+
+/// Runs a claimed job to the end, or until it is cancelled, and records the outcome
+async fn run(job: Job) {
+    let outcome = if job.attempts > job.max_attempts {
+        // Interrupted by restarts on every attempt
+        Some(Err(MolmineError::InvalidInput(
+            "The job was interrupted too many times".to_string(),
+        )))
+    } else {
+        publish(
+            job.project_id,
+            ServerEvent::job(&job, JobStatus::Running, None, None),
+        );
+        tokio::select! {
+            outcome = perform(&job) => Some(outcome),
+            _ = cancelled(job.id) => None,
+        }
+    };
+    if let Err(e) = record(&job, outcome).await {
+        tracing::error!("Could not record the outcome of job {}: {e}", job.id.0);
+    }
+}
+
+/// Resolves once the job has been cancelled
+async fn cancelled(job_id: JobId) {
+    let Ok(mut conn) = establish().await else {
+        return std::future::pending().await;
+    };
+    loop {
+        tokio::time::sleep(CANCEL_CHECK_INTERVAL).await;
+        if Job::is_cancel_requested(job_id, &mut conn)
+            .await
+            .unwrap_or(false)
+        {
+            return;
+        }
+    }
+}
+
+async fn record(
+    job: &Job,
+    outcome: Option<Result<Value, MolmineError>>,
+) -> Result<(), MolmineError> {
+    let mut conn = establish().await?;
+    let event = match outcome {
+        None => {
+            Job::finish(job.id, JobStatus::Cancelled, None, None, &mut conn).await?;
+            ServerEvent::job(job, JobStatus::Cancelled, None, None)
+        }
+        Some(Ok(result)) => {
+            let result = serde_json::to_string(&result)?;
+            Job::finish(job.id, JobStatus::Succeeded, Some(result), None, &mut conn).await?;
+            ServerEvent::job(job, JobStatus::Succeeded, Some(1.0), None)
+        }
+        // Bad input and missing papers fail the same way every time
+        Some(Err(e)) if e.status_code().is_client_error() || job.attempts >= job.max_attempts => {
+            tracing::warn!("Job {} ({}) failed: {e}", job.id.0, job.kind);
+            Job::finish(
+                job.id,
+                JobStatus::Failed,
+                None,
+                Some(e.to_string()),
+                &mut conn,
+            )
+            .await?;
+            ServerEvent::job(job, JobStatus::Failed, None, Some(e.to_string()))
+        }
+        Some(Err(e)) => {
+            let retry_at = Utc::now().naive_utc()
+                + chrono::Duration::from_std(retry_delay(job.attempts)).unwrap_or_default();
+            Job::retry_later(job.id, e.to_string(), retry_at, &mut conn).await?;
+            ServerEvent::job(job, JobStatus::Queued, None, Some(e.to_string()))
+        }
+    };
+    publish(job.project_id, event);
+    Ok(())
+}
+
+/// Records how far a running job has got and tells the pages open on its project
+async fn report_progress(
+    job: &Job,
+    done: usize,
+    total: usize,
+    message: String,
+    conn: &mut AsyncConn,
+) {
+    let progress = if total == 0 {
+        1.0
+    } else {
+        done as f64 / total as f64
+    };
+    if let Err(e) = Job::set_progress(job.id, progress, conn).await {
+        tracing::warn!("Could not record the progress of job {}: {e}", job.id.0);
+    }
+    publish(
+        job.project_id,
+        ServerEvent::job(job, JobStatus::Running, Some(progress), Some(message)),
+    );
+}
+
+/// Does the job's work, returning a JSON summary to store as its result
+async fn perform(job: &Job) -> Result<Value, MolmineError> {
+    let task: JobTask = serde_json::from_str(&job.task)?;
+    let mut conn = establish().await?;
+    match task {
+        JobTask::IndexText { pdf_id } => {
+            let pdf = Pdf::get_by_id(pdf_id, &mut conn).await?;
+            if pdf.data.is_empty() {
+                return Err(MolmineError::NotFound("PDF file"));
+            }
+            let pages = index_text(pdf_id, pdf.data, &mut conn).await?;
+            let mentions = link_mentions(pdf_id, &mut conn).await?;
+            Ok(json!({ "pages": pages, "mentions": mentions }))
+        }
+        JobTask::DetectRegions { pdf_id } => {
+            let pdf = Pdf::get_by_id(pdf_id, &mut conn).await?;
+            if pdf.data.is_empty() {
+                return Err(MolmineError::NotFound("PDF file"));
+            }
+            let regions = propose_regions(pdf_id, pdf.data, &mut conn).await?;
+            Ok(json!({ "regions": regions.len() }))
+        }
+        JobTask::RecognizeRegion { region_id } => {
+            let project_id = job.project_id.ok_or_else(|| {
+                MolmineError::InvalidInput("Recognizing needs a project".to_string())
+            })?;
+            let region = StructureRegion::get_by_id(region_id, &mut conn).await?;
+            let png = render_region(&region, &mut conn).await?;
+            Ok(serde_json::to_value(
+                recognize_with_server_backend(&png, project_id, Some(region.id), &mut conn).await?,
+            )?)
+        }
+        JobTask::RecognizePdf { pdf_id } => {
+            let project_id = job.project_id.ok_or_else(|| {
+                MolmineError::InvalidInput("Recognizing needs a project".to_string())
+            })?;
+            let regions: Vec<StructureRegion> =
+                StructureRegion::list_for_pdf(pdf_id, None, &mut conn)
+                    .await?
+                    .into_iter()
+                    .filter(|region| region.status != RegionStatus::Rejected)
+                    .collect();
+            let mut results = Vec::with_capacity(regions.len());
+            for (index, region) in regions.iter().enumerate() {
+                let recognized = async {
+                    let png = render_region(region, &mut conn).await?;
+                    recognize_with_server_backend(&png, project_id, Some(region.id), &mut conn)
+                        .await
+                }
+                .await;
+                let name = format!("Region {} on page {}", region.id.0, region.page_number);
+                // One unreadable drawing doesn't stop the rest of the paper
+                match recognized {
+                    Ok(report) => {
+                        results.push(json!({ "region_id": region.id, "recognition": report }))
+                    }
+                    Err(e) => {
+                        publish(
+                            job.project_id,
+                            ServerEvent::ValidationError {
+                                source: name.clone(),
+                                message: e.to_string(),
+                            },
+                        );
+                        results.push(json!({ "region_id": region.id, "error": e.to_string() }))
+                    }
+                }
+                report_progress(job, index + 1, regions.len(), name, &mut conn).await;
+            }
+            Ok(json!({ "regions": results }))
+        }
+        JobTask::Export {
+            format,
+            descriptors,
+            images,
+            fingerprints,
+        } => {
+            let project_id = job
+                .project_id
+                .ok_or_else(|| MolmineError::InvalidInput("Exports need a project".to_string()))?;
+            let options = TableOptions {
+                descriptors,
+                images,
+                fingerprints,
+            };
+            let data = match format {
+                ExportFileFormat::Xlsx => write_xlsx(project_id, options, conn).await?,
+                ExportFileFormat::Parquet => {
+                    write_columnar(project_id, ColumnarFormat::Parquet, options, conn).await?
+                }
+                ExportFileFormat::Arrow => {
+                    write_columnar(project_id, ColumnarFormat::Arrow, options, conn).await?
+                }
+            };
+            tokio::fs::create_dir_all(EXPORT_DIR).await?;
+            tokio::fs::write(export_path(job.id, format), &data).await?;
+            Ok(json!({ "size": data.len() }))
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_job_task() {
+        let task: JobTask =
+            serde_json::from_str(r#"{"kind": "export", "format": "parquet"}"#).unwrap();
+        assert_eq!(
+            task,
+            JobTask::Export {
+                format: ExportFileFormat::Parquet,
+                descriptors: false,
+                images: false,
+                fingerprints: false,
+            }
+        );
+        let task = JobTask::RecognizeRegion {
+            region_id: RegionId(7),
+        };
+        assert_eq!(
+            serde_json::to_value(&task).unwrap(),
+            json!({ "kind": task.kind(), "region_id": 7 })
+        );
+
+        assert_eq!(retry_delay(1), RETRY_DELAY);
+        assert_eq!(retry_delay(3), RETRY_DELAY * 4);
+        assert_eq!(retry_delay(40), MAX_RETRY_DELAY);
+    }
+}
@@ -0,0 +1,274 @@
+//! Compound labels, the short names such as **7a** or **12** that papers use to refer to
+//! the compounds they draw, tabulate and discuss, and finding where a paper's text
+//! mentions them.
+//!
+//! Labels with letters, like `7a`, are recognized wherever they appear as a word. Bare
+//! numbers are far too common for that, so a label like `12` only counts when it is in
+//! brackets or follows a word such as "compound", possibly through a list like
+//! "compounds 7, 8 and 12".
+
+use std::collections::BTreeSet;
+
+use crate::db::AsyncConn;
+use crate::error::MolmineError;
+use crate::models::*;
+
+/// Words that introduce compound numbers in running text
+const CUE_WORDS: [&str; 16] = [
+    "compound",
+    "compounds",
+    "compd",
+    "cmpd",
+    "analog",
+    "analogs",
+    "analogue",
+    "analogues",
+    "derivative",
+    "derivatives",
+    "inhibitor",
+    "inhibitors",
+    "ligand",
+    "ligands",
+    "product",
+    "products",
+];
+/// Abbreviations whose full stop does not end a sentence
+const ABBREVIATIONS: [&str; 14] = [
+    "fig", "figs", "al", "e.g", "i.e", "ca", "cf", "vs", "ref", "refs", "eq", "no", "approx",
+    "resp",
+];
+/// Sentences longer than this, in characters, are cut down around the mention
+const MAX_SENTENCE_CHARS: usize = 600;
+
+/// The label as written, without the brackets, bold markers, trailing punctuation or
+/// "compound" prefix around it, and with typographic primes written as `'`
+pub fn normalize_label(text: &str) -> String {
+    let mut label = text.trim().replace(['′', '’'], "'");
+    loop {
+        let trimmed = label
+            .trim()
+            .trim_start_matches(['(', '[', '*'])
+            .trim_end_matches([')', ']', '*', ',', ';', ':', '.'])
+            .trim();
+        let lowercase = trimmed.to_lowercase();
+        let trimmed = ["compound ", "compd ", "cmpd "]
+            .iter()
+            .find(|prefix| lowercase.starts_with(*prefix))
+            .map_or(trimmed, |prefix| trimmed[prefix.len()..].trim());
+        if trimmed == label {
+            return label;
+        }
+        label = trimmed.to_string();
+    }
+}
+
+/// Whether the text looks like a compound label once normalized: a number of up to three
+/// digits, optionally after a capital letter as in `S4`, and optionally
+/// followed by up to two lowercase letters and primes, as in `7a` or `12b'`
+pub fn is_compound_label(text: &str) -> bool {
+    let label = normalize_label(text);
+    let rest = label.trim_start_matches(|c: char| c.is_ascii_uppercase());
+    if label.len() - rest.len() > 1 {
+        return false;
+    }
+    let suffix = rest.trim_start_matches(|c: char| c.is_ascii_digit());
+    let digits = rest.len() - suffix.len();
+    if !(1..=3).contains(&digits) || rest.starts_with('0') {
+        return false;
+    }
+    let primes = suffix.trim_start_matches(|c: char| c.is_ascii_lowercase());
+    suffix.len() - primes.len() <= 2 && primes.len() <= 2 && primes.chars().all(|c| c == '\'')
+}
+
+/// Splits text into sentences, joining lines broken within a sentence
+pub fn split_sentences(text: &str) -> Vec<String> {
+    let words: Vec<&str> = text.split_whitespace().collect();
+    let mut sentences = Vec::new();
+    let mut sentence: Vec<&str> = Vec::new();
+    for (index, word) in words.iter().enumerate() {
+        sentence.push(word);
+        let stripped = word.trim_end_matches(['"', '\'', '\u{201d}', ')', ']']);
+        let Some(body) = stripped.strip_suffix(['.', '!', '?']) else {
+            continue;
+        };
+        let next_starts = words
+            .get(index + 1)
+            .and_then(|next| {
+                next.trim_start_matches(['"', '\u{201c}', '(', '['])
+                    .chars()
+                    .next()
+            })
+            .is_none_or(|c| c.is_uppercase() || c.is_ascii_digit());
+        let body = body.trim_start_matches(['"', '\u{201c}', '(', '[']);
+        let abbreviation = ABBREVIATIONS.contains(&body.to_lowercase().as_str())
+            || (body.len() == 1 && body.chars().all(char::is_uppercase));
+        if next_starts && !abbreviation {
+            sentences.push(sentence.join(" "));
+            sentence.clear();
+        }
+    }
+    if !sentence.is_empty() {
+        sentences.push(sentence.join(" "));
+    }
+    sentences
+}
+
+/// A sentence that mentions a label
+#[derive(Clone, Debug, PartialEq)]
+pub struct LabelMention {
+    pub label: String,
+    pub sentence: String,
+}
+
+/// Whether a label made only of digits is introduced as a compound: in brackets, or
+/// after a cue word with nothing but other labels and conjunctions in between
+fn is_cued(words: &[&str], index: usize, part: &str) -> bool {
+    if part.trim_start().starts_with(['(', '[']) {
+        return true;
+    }
+    for word in words[..index].iter().rev() {
+        let bare = word
+            .trim_matches(|c: char| !c.is_alphanumeric())
+            .to_lowercase();
+        if CUE_WORDS.contains(&bare.as_str()) {
+            return true;
+        }
+        if !(is_compound_label(word) || matches!(bare.as_str(), "and" | "or" | "")) {
+            return false;
+        }
+    }
+    false
+}
+
+/// The sentence, or when it is very long the part of it around the word at `start..end`
+fn context(sentence: &str, start: usize, end: usize) -> String {
+    if sentence.chars().count() <= MAX_SENTENCE_CHARS {
+        return sentence.to_string();
+    }
+    let half = MAX_SENTENCE_CHARS / 2;
+    let mut from = start.saturating_sub(half);
+    while !sentence.is_char_boundary(from) {
+        from -= 1;
+    }
+    let mut to = (end + half).min(sentence.len());
+    while !sentence.is_char_boundary(to) {
+        to += 1;
+    }
+    let mut excerpt = sentence[from..to].trim().to_string();
+    if from > 0 {
+        excerpt.insert(0, '\u{2026}');
+    }
+    if to < sentence.len() {
+        excerpt.push('\u{2026}');
+    }
+    excerpt
+}
+
+/// Every sentence of the text that mentions one of the labels, once per label and
+/// sentence, in reading order
+pub fn find_mentions(text: &str, labels: &[&str]) -> Vec<LabelMention> {
+    let mut mentions = Vec::new();
+    for sentence in split_sentences(text) {
+        let words: Vec<&str> = sentence.split(' ').collect();
+        let mut found = BTreeSet::new();
+        let mut offset = 0;
+        for (index, word) in words.iter().enumerate() {
+            for part in word.split([',', ';', '/']) {
+                let label = normalize_label(part);
+                let numeric = label.chars().all(|c| c.is_ascii_digit());
+                if labels.contains(&label.as_str())
+                    && (!numeric || is_cued(&words, index, part))
+                    && found.insert(label.clone())
+                {
+                    mentions.push(LabelMention {
+                        label,
+                        sentence: context(&sentence, offset, offset + word.len()),
+                    });
+                }
+            }
+            offset += word.len() + 1;
+        }
+    }
+    mentions
+}
+
+/// Finds the mentions of the paper's labelled compounds in its extracted text and
+/// stores them in place of those found before, returning how many there are
+pub async fn link_mentions(pdf_id: PdfId, conn: &mut AsyncConn) -> Result<usize, MolmineError> {
+    let compounds = Compound::list_for_pdf(pdf_id, conn).await?;
+    let labels: BTreeSet<&str> = compounds
+        .iter()
+        .map(|compound| compound.label.as_str())
+        .filter(|label| !label.is_empty())
+        .collect();
+    let labels: Vec<&str> = labels.into_iter().collect();
+    let mut mentions = Vec::new();
+    if !labels.is_empty() {
+        for (page_number, text) in Pdf::page_texts(pdf_id, conn).await? {
+            for found in find_mentions(&text, &labels) {
+                for compound in compounds.iter().filter(|c| c.label == found.label) {
+                    mentions.push(NewCompoundMention {
+                        compound_id: compound.id,
+                        pdf_id,
+                        page_number,
+                        sentence: found.sentence.clone(),
+                    });
+                }
+            }
+        }
+    }
+    CompoundMention::replace_for_pdf(pdf_id, &mentions, conn).await?;
+    Ok(mentions.len())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_compound_label() {
+        assert_eq!(normalize_label(" (**7a**), "), "7a");
+        assert_eq!(normalize_label("Compound 12b′"), "12b'");
+        assert!(is_compound_label("7a"));
+        assert!(is_compound_label("(12)"));
+        assert!(is_compound_label("S4"));
+        assert!(is_compound_label("3b'"));
+        assert!(!is_compound_label("2019"));
+        assert!(!is_compound_label("07"));
+        assert!(!is_compound_label("IC50"));
+        assert!(!is_compound_label("nM"));
+        assert!(!is_compound_label(""));
+    }
+    #[test]
+    fn test_find_mentions() {
+        let text = "Potent inhibitors were found (Fig. 2). Compounds 7, 8 and 12 were\n\
+                    made in 3 steps. The methyl ester 7a was inactive, but 7a' and\n\
+                    (12) were not. After 12 h, 7b crystallized.";
+        let sentences = split_sentences(text);
+        assert_eq!(sentences[0], "Potent inhibitors were found (Fig. 2).");
+        let labels = ["7", "7a", "7a'", "12", "7b"];
+        let mentions: Vec<(String, usize)> = find_mentions(text, &labels)
+            .into_iter()
+            .map(|mention| {
+                let sentence = sentences.iter().position(|s| *s == mention.sentence);
+                (mention.label, sentence.unwrap())
+            })
+            .collect();
+        assert_eq!(
+            mentions,
+            vec![
+                ("7".to_string(), 1),
+                ("12".to_string(), 1),
+                ("7a".to_string(), 2),
+                ("7a'".to_string(), 2),
+                ("12".to_string(), 2),
+                ("7b".to_string(), 3),
+            ]
+        );
+
+        let long = format!("{} 7a {}", "word ".repeat(200), "word ".repeat(200));
+        let excerpt = &find_mentions(&long, &["7a"])[0].sentence;
+        assert!(excerpt.starts_with('\u{2026}') && excerpt.ends_with('\u{2026}'));
+        assert!(excerpt.contains(" 7a "));
+    }
+}
@@ -7,6 +7,25 @@ async fn main() {
     use leptos_axum::{LeptosRoutes, generate_route_list};
     use molmine::app::*;
 
+    // Warnings from the library go to stderr, for the server and the commands alike
+    tracing_subscriber::fmt()
+        .with_env_filter(
+            tracing_subscriber::EnvFilter::try_from_default_env()
+                .unwrap_or_else(|_| tracing_subscriber::EnvFilter::new("info")),
+        )
+        .with_writer(std::io::stderr)
+        .init();
+
+    // Any arguments name a headless command rather than starting the server
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    if !args.is_empty() {
+        if let Err(e) = molmine::cli::run(&args).await {
+            eprintln!("{e}");
+            std::process::exit(1);
+        }
+        return;
+    }
+
     let conf = get_configuration(None).unwrap();
     let addr = conf.leptos_options.site_addr;
     let leptos_options = conf.leptos_options;
@@ -14,6 +33,7 @@ async fn main() {
     let routes = generate_route_list(App);
 
     let app = Router::new()
+        .merge(molmine::api::router())
         .leptos_routes(&leptos_options, routes, {
             let leptos_options = leptos_options.clone();
             move || shell(leptos_options.clone())
@@ -21,6 +41,11 @@ async fn main() {
         .fallback(leptos_axum::file_and_error_handler(shell))
         .with_state(leptos_options);
 
+    // Background jobs run on the server's runtime alongside the requests
+    if let Err(e) = molmine::jobs::start_workers(molmine::jobs::worker_count()).await {
+        log!("Background jobs are not running: {e}");
+    }
+
     // run our app with hyper
     // `axum::Server` is a re-export of `hyper::Server`
     log!("listening on http://{}", &addr);
@@ -0,0 +1,510 @@
+//! Markush structures: one core drawn with attachment points such as R1 and R2, and an
+//! R-group table giving the substituent at each point for every compound of a series.
+//!
+//! Enumeration joins a row's substituents to the core by turning every attachment point
+//! into a ring-closure bond shared with its substituent, so `c1ccccc1[R1]` with
+//! `R1 = OMe` becomes `c1ccccc%(500)1.O%(500)C`. RDKit then reads that SMILES, which
+//! checks the valences, and writes the compound canonically.
+
+use std::collections::BTreeMap;
+
+use diesel_async::AsyncConnection;
+use serde::{Deserialize, Serialize};
+
+use crate::db::AsyncConn;
+use crate::error::MolmineError;
+use crate::labels::normalize_label;
+use crate::models::*;
+use crate::rdkit::canonical_smiles;
+
+/// Substituents that R-group tables give by name, as SMILES bonded through `*`. Names are
+/// looked up before reading a substituent as SMILES, so `CN` is a nitrile rather than a
+/// methylamino group.
+const ABBREVIATIONS: &[(&str, &str)] = &[
+    ("H", "*[H]"),
+    ("Me", "*C"),
+    ("Et", "*CC"),
+    ("Pr", "*CCC"),
+    ("nPr", "*CCC"),
+    ("n-Pr", "*CCC"),
+    ("iPr", "*C(C)C"),
+    ("i-Pr", "*C(C)C"),
+    ("cPr", "*C1CC1"),
+    ("Bu", "*CCCC"),
+    ("nBu", "*CCCC"),
+    ("n-Bu", "*CCCC"),
+    ("tBu", "*C(C)(C)C"),
+    ("t-Bu", "*C(C)(C)C"),
+    ("Ph", "*c1ccccc1"),
+    ("Bn", "*Cc1ccccc1"),
+    ("Ac", "*C(C)=O"),
+    ("OH", "*O"),
+    ("OMe", "*OC"),
+    ("OEt", "*OCC"),
+    ("OPh", "*Oc1ccccc1"),
+    ("SH", "*S"),
+    ("SMe", "*SC"),
+    ("NH2", "*N"),
+    ("NHMe", "*NC"),
+    ("NMe2", "*N(C)C"),
+    ("NHAc", "*NC(C)=O"),
+    ("CN", "*C#N"),
+    ("NO2", "*[N+](=O)[O-]"),
+    ("CF3", "*C(F)(F)F"),
+    ("OCF3", "*OC(F)(F)F"),
+    ("CHO", "*C=O"),
+    ("CO2H", "*C(=O)O"),
+    ("COOH", "*C(=O)O"),
+    ("CO2Me", "*C(=O)OC"),
+    ("COOMe", "*C(=O)OC"),
+    ("CONH2", "*C(N)=O"),
+];
+/// Ring-closure numbers for attachments start here, well above those people write
+const FIRST_RING_BOND: usize = 500;
+
+/// A piece of a SMILES string
+#[derive(Clone, Debug, PartialEq)]
+enum Token {
+    Atom(String),
+    Bond(char),
+    Open,
+    Close,
+    RingBond(String),
+    Dot,
+}
+
+fn tokenize(smiles: &str) -> Result<Vec<Token>, String> {
+    let chars: Vec<char> = smiles.trim().chars().collect();
+    let mut tokens = Vec::new();
+    let mut i = 0;
+    while i < chars.len() {
+        let c = chars[i];
+        let token = match c {
+            '[' => {
+                let end = chars[i..]
+                    .iter()
+                    .position(|&c| c == ']')
+                    .ok_or_else(|| format!("Unclosed [ in {smiles}"))?;
+                let atom = chars[i..=i + end].iter().collect();
+                i += end;
+                Token::Atom(atom)
+            }
+            'C' if chars.get(i + 1) == Some(&'l') => {
+                i += 1;
+                Token::Atom("Cl".to_string())
+            }
+            'B' if chars.get(i + 1) == Some(&'r') => {
+                i += 1;
+                Token::Atom("Br".to_string())
+            }
+            'B' | 'C' | 'N' | 'O' | 'P' | 'S' | 'F' | 'I' | 'b' | 'c' | 'n' | 'o' | 'p' | 's'
+            | '*' => Token::Atom(c.to_string()),
+            '-' | '=' | '#' | '$' | ':' | '/' | '\\' => Token::Bond(c),
+            '(' => Token::Open,
+            ')' => Token::Close,
+            '0'..='9' => Token::RingBond(c.to_string()),
+            '%' => {
+                let length = match chars.get(i + 1) {
+                    Some('(') => chars[i..].iter().position(|&c| c == ')').map(|end| end + 1),
+                    _ => Some(3),
+                };
+                let number: String = length
+                    .and_then(|length| chars.get(i..i + length))
+                    .map(|number| number.iter().collect())
+                    .filter(|number: &String| number.chars().any(|c| c.is_ascii_digit()))
+                    .ok_or_else(|| format!("Invalid ring bond number in {smiles}"))?;
+                i += number.len() - 1;
+                Token::RingBond(number)
+            }
+            '.' => Token::Dot,
+            _ => return Err(format!("Unexpected {c:?} in {smiles}")),
+        };
+        tokens.push(token);
+        i += 1;
+    }
+    Ok(tokens)
+}
+
+fn write(tokens: &[Token]) -> String {
+    let mut smiles = String::new();
+    for token in tokens {
+        match token {
+            Token::Atom(text) | Token::RingBond(text) => smiles.push_str(text),
+            Token::Bond(bond) => smiles.push(*bond),
+            Token::Open => smiles.push('('),
+            Token::Close => smiles.push(')'),
+            Token::Dot => smiles.push('.'),
+        }
+    }
+    smiles
+}
+
+/// The name of the attachment point the atom is: `R1` for `[R1]`, `[*:1]` or `[1*]`, an
+/// empty name for a bare `*`, and `None` for real atoms
+fn attachment_name(token: &Token) -> Option<String> {
+    let Token::Atom(atom) = token else {
+        return None;
+    };
+    let numbered = |digits: &str| {
+        (!digits.is_empty() && digits.chars().all(|c| c.is_ascii_digit()))
+            .then(|| format!("R{digits}"))
+    };
+    if atom == "*" {
+        return Some(String::new());
+    }
+    let inner = atom.strip_prefix('[')?.strip_suffix(']')?;
+    if inner == "*" {
+        Some(String::new())
+    } else if let Some(map) = inner.strip_prefix("*:") {
+        numbered(map)
+    } else if let Some(isotope) = inner.strip_suffix('*') {
+        numbered(isotope)
+    } else {
+        // `[R1']` is an R-group, but `[Rb]` and `[Ru]` are elements
+        let rest = inner.strip_prefix('R')?;
+        rest.chars()
+            .all(|c| c.is_ascii_digit() || c == '\'')
+            .then(|| inner.to_string())
+    }
+}
+
+/// Replaces the attachment point at `index` with the ring bond on the atom it is bonded
+/// to, keeping the bond's order
+fn splice(tokens: &mut Vec<Token>, index: usize, ring: &str) -> Result<(), String> {
+    let one_bond = "must be bonded to exactly one atom".to_string();
+    let ring_bond = |bond: Option<char>| {
+        bond.map(Token::Bond)
+            .into_iter()
+            .chain([Token::RingBond(ring.to_string())])
+    };
+    let before = index.checked_sub(1).map(|i| &tokens[i]);
+    if matches!(before, None | Some(Token::Dot)) {
+        // Leading, as in `[R1]c1ccccc1`: bonded to the atom after it
+        let (bond, atom) = match (tokens.get(index + 1), tokens.get(index + 2)) {
+            (Some(Token::Bond(bond)), Some(Token::Atom(_))) => (Some(*bond), index + 2),
+            (Some(Token::Atom(_)), _) => (None, index + 1),
+            _ => return Err(one_bond),
+        };
+        tokens.splice(atom + 1..atom + 1, ring_bond(bond));
+        tokens.drain(index..atom);
+        return Ok(());
+    }
+    if !matches!(
+        tokens.get(index + 1),
+        None | Some(Token::Dot | Token::Close)
+    ) {
+        return Err(one_bond);
+    }
+    let bond = match before {
+        Some(Token::Bond(bond)) => Some(*bond),
+        _ => None,
+    };
+    let start = if bond.is_some() { index - 1 } else { index };
+    // The atom the chain or branch holding the attachment point continues from
+    let mut depth = 0;
+    let mut neighbour = None;
+    for i in (0..start).rev() {
+        match &tokens[i] {
+            Token::Close => depth += 1,
+            Token::Open if depth > 0 => depth -= 1,
+            Token::Atom(_) if depth == 0 => {
+                neighbour = Some(i);
+                break;
+            }
+            Token::Dot => break,
+            _ => {}
+        }
+    }
+    let neighbour = neighbour.ok_or(one_bond)?;
+    // A branch holding only the attachment point is dropped with it
+    if start > 0 && tokens[start - 1] == Token::Open && tokens.get(index + 1) == Some(&Token::Close)
+    {
+        tokens.drain(start - 1..=index + 1);
+    } else {
+        tokens.drain(start..=index);
+    }
+    tokens.splice(neighbour + 1..neighbour + 1, ring_bond(bond));
+    Ok(())
+}
+
+/// A core with its attachment points turned into numbered ring bonds
+#[derive(Debug, PartialEq)]
+struct PreparedCore {
+    smiles: String,
+    /// The name of each attachment point with its ring bond, in the order they appear
+    attachments: Vec<(String, String)>,
+}
+
+fn prepare_core(core: &str) -> Result<PreparedCore, String> {
+    let mut tokens = tokenize(core)?;
+    let mut attachments = Vec::new();
+    while let Some((index, name)) = tokens
+        .iter()
+        .enumerate()
+        .find_map(|(index, token)| Some((index, attachment_name(token)?)))
+    {
+        if name.is_empty() {
+            return Err(
+                "The core's attachment points need names, such as [R1] or [*:1]".to_string(),
+            );
+        }
+        let ring = format!("%({})", FIRST_RING_BOND + attachments.len());
+        splice(&mut tokens, index, &ring)
+            .map_err(|e| format!("The attachment point {name} of the core {e}"))?;
+        attachments.push((name, ring));
+    }
+    if attachments.is_empty() {
+        return Err("The core has no attachment points, such as [R1]".to_string());
+    }
+    Ok(PreparedCore {
+        smiles: write(&tokens),
+        attachments,
+    })
+}
+
+/// The substituent as SMILES bonded through the ring bond. Substituents without an
+/// attachment point are bonded through their first atom.
+fn prepare_substituent(value: &str, ring: &str) -> Result<String, String> {
+    let value = value.trim();
+    if value.is_empty() {
+        return Err("The substituent is empty".to_string());
+    }
+    let smiles = ABBREVIATIONS
+        .iter()
+        .find(|(name, _)| *name == value)
+        .map_or(value, |(_, smiles)| smiles);
+    let mut tokens = tokenize(smiles)?;
+    if tokens.contains(&Token::Dot) {
+        return Err(format!("The substituent {value} must be a single fragment"));
+    }
+    let points: Vec<usize> = tokens
+        .iter()
+        .enumerate()
+        .filter(|(_, token)| attachment_name(token).is_some())
+        .map(|(index, _)| index)
+        .collect();
+    let index = match points.as_slice() {
+        [] => {
+            tokens.insert(0, Token::Atom("*".to_string()));
+            0
+        }
+        [index] => *index,
+        _ => {
+            return Err(format!(
+                "The substituent {value} has more than one attachment point"
+            ));
+        }
+    };
+    splice(&mut tokens, index, ring)
+        .map_err(|e| format!("The attachment point of the substituent {value} {e}"))?;
+    Ok(write(&tokens))
+}
+
+/// A row of an R-group table: the compound's label and the substituent at each of the
+/// core's attachment points, by name. Substituents are SMILES, optionally with one `*`
+/// where they attach, or common abbreviations such as `OMe`.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct RGroupRow {
+    pub label: String,
+    pub substituents: BTreeMap<String, String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct EnumeratedCompound {
+    /// Position of the row in the table, counting from 1
+    pub row: usize,
+    pub label: String,
+    pub smiles: String,
+    pub assignments: BTreeMap<String, String>,
+    /// The new compound, absent on a dry run
+    pub compound_id: Option<CompoundId>,
+}
+
+/// A row that could not be enumerated
+#[derive(Debug, Serialize)]
+pub struct EnumerationFailure {
+    pub row: usize,
+    pub label: String,
+    pub reason: String,
+}
+
+/// Joins the row's substituents to the core and has RDKit read the result
+fn enumerate_row(core: &PreparedCore, row: &RGroupRow) -> Result<String, String> {
+    let substituents: BTreeMap<&str, &str> = row
+        .substituents
+        .iter()
+        .map(|(name, value)| (name.trim(), value.as_str()))
+        .collect();
+    if let Some(name) = substituents
+        .keys()
+        .find(|name| !core.attachments.iter().any(|(point, _)| point == *name))
+    {
+        return Err(format!("The core has no attachment point {name}"));
+    }
+    let mut parts = vec![core.smiles.clone()];
+    for (name, ring) in &core.attachments {
+        let value = substituents
+            .get(name.as_str())
+            .ok_or_else(|| format!("No substituent for {name}"))?;
+        parts.push(prepare_substituent(value, ring)?);
+    }
+    let smiles = parts.join(".");
+    canonical_smiles(&smiles)
+        .ok_or_else(|| format!("RDKit could not read the enumerated structure {smiles}"))
+}
+
+/// Every compound of the series, and the rows that could not be enumerated. Only an
+/// unusable core fails the whole table.
+pub fn enumerate(
+    core: &str,
+    rows: &[RGroupRow],
+) -> Result<(Vec<EnumeratedCompound>, Vec<EnumerationFailure>), MolmineError> {
+    let core = prepare_core(core).map_err(MolmineError::InvalidInput)?;
+    let mut enumerated = Vec::new();
+    let mut failures = Vec::new();
+    for (index, row) in rows.iter().enumerate() {
+        match enumerate_row(&core, row) {
+            Ok(smiles) => enumerated.push(EnumeratedCompound {
+                row: index + 1,
+                label: normalize_label(&row.label),
+                smiles,
+                assignments: row
+                    .substituents
+                    .iter()
+                    .map(|(name, value)| (name.trim().to_string(), value.trim().to_string()))
+                    .collect(),
+                compound_id: None,
+            }),
+            Err(reason) => failures.push(EnumerationFailure {
+                row: index + 1,
+                label: row.label.clone(),
+                reason,
+            }),
+        }
+    }
+    Ok((enumerated, failures))
+}
+
+#[derive(Debug, Serialize)]
+pub struct MarkushReport {
+    pub dry_run: bool,
+    /// The stored generic structure, absent on a dry run or when no row could be enumerated
+    pub source: Option<MarkushSource>,
+    pub enumerated: Vec<EnumeratedCompound>,
+    pub failures: Vec<EnumerationFailure>,
+}
+
+/// Enumerates the generic structure and creates a compound on the paper for every row
+/// that RDKit can read, tagged with the stored structure and the row's substituents.
+/// The structure and its compounds are inserted in one transaction.
+pub async fn add_markush(
+    pdf_id: PdfId,
+    name: &str,
+    core: &str,
+    rows: Vec<RGroupRow>,
+    dry_run: bool,
+    conn: &mut AsyncConn,
+) -> Result<MarkushReport, MolmineError> {
+    if rows.is_empty() {
+        return Err(MolmineError::InvalidInput(
+            "The R-group table has no rows".to_string(),
+        ));
+    }
+    let new_source = NewMarkushSource {
+        pdf_id,
+        name: name.trim().to_string(),
+        core: core.trim().to_string(),
+        rgroups: serde_json::to_string(&rows)?,
+    };
+    // Reading structures is CPU-bound, so keep it off the async workers
+    let core = new_source.core.clone();
+    let (mut enumerated, failures) =
+        tokio::task::spawn_blocking(move || enumerate(&core, &rows)).await??;
+    if dry_run || enumerated.is_empty() {
+        return Ok(MarkushReport {
+            dry_run,
+            source: None,
+            enumerated,
+            failures,
+        });
+    }
+
+    let (source, enumerated) = conn
+        .transaction::<_, MolmineError, _>(|conn| {
+            Box::pin(async move {
+                let source = new_source.insert(conn).await?;
+                for compound in &mut enumerated {
+                    let inserted = NewCompound {
+                        label: compound.label.clone(),
+                        markush_source_id: Some(source.id),
+                        rgroup_assignments: Some(serde_json::to_string(&compound.assignments)?),
+                        ..NewCompound::new(pdf_id, compound.smiles.clone())
+                    }
+                    .insert(conn)
+                    .await?;
+                    compound.compound_id = Some(inserted.id);
+                }
+                Ok((source, enumerated))
+            })
+        })
+        .await?;
+    Ok(MarkushReport {
+        dry_run,
+        source: Some(source),
+        enumerated,
+        failures,
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_prepare_markush() {
+        let core = prepare_core("O=C([*:1])c1ccc(cc1)[R2]").unwrap();
+        assert_eq!(core.smiles, "O=C%(500)c1ccc%(501)(cc1)");
+        assert_eq!(
+            core.attachments,
+            vec![
+                ("R1".to_string(), "%(500)".to_string()),
+                ("R2".to_string(), "%(501)".to_string())
+            ]
+        );
+        assert_eq!(
+            prepare_core("[R1]=Cc1ccccc1").unwrap().smiles,
+            "C=%(500)c1ccccc1"
+        );
+        assert!(prepare_core("C[R1]C").is_err());
+        assert!(prepare_core("c1ccccc1*").is_err());
+        assert!(prepare_core("c1ccccc1[Rb]").is_err());
+
+        assert_eq!(prepare_substituent("OMe", "%(500)").unwrap(), "O%(500)C");
+        assert_eq!(prepare_substituent("H", "%(500)").unwrap(), "[H]%(500)");
+        assert_eq!(prepare_substituent("Cl", "%(500)").unwrap(), "Cl%(500)");
+        assert_eq!(
+            prepare_substituent("CC(C)[*]", "%(500)").unwrap(),
+            "CC%(500)(C)"
+        );
+        assert!(prepare_substituent("[*]CC[*]", "%(500)").is_err());
+
+        let row = |label: &str, substituents: &[(&str, &str)]| RGroupRow {
+            label: label.to_string(),
+            substituents: substituents
+                .iter()
+                .map(|(name, value)| (name.to_string(), value.to_string()))
+                .collect(),
+        };
+        let rows = [
+            row("7a", &[("R1", "OMe"), ("R2", "F")]),
+            row("7b", &[("R1", "NH2")]),
+            row("7c", &[("R1", "H"), ("R2", "H"), ("R3", "Me")]),
+        ];
+        let (enumerated, failures) = enumerate("O=C([*:1])c1ccc(cc1)[R2]", &rows).unwrap();
+        assert_eq!(enumerated.len(), 1);
+        assert_eq!(enumerated[0].label, "7a");
+        assert_eq!(enumerated[0].assignments["R1"], "OMe");
+        assert_eq!(failures[0].reason, "No substituent for R2");
+        assert_eq!(failures[1].reason, "The core has no attachment point R3");
+    }
+}
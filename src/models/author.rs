@@ -0,0 +1,382 @@
+use super::{AuthorId, Pdf, PdfId, PdfInfo, ProjectId};
+use crate::db::{AsyncConn, get_last_rowid};
+use crate::schema::*;
+use diesel::prelude::*;
+use diesel_async::AsyncConnection;
+use diesel_async::RunQueryDsl;
+use serde::{Deserialize, Serialize};
+
+/// A person listed as an author on one or more papers of a project
+#[derive(Queryable, Selectable, Identifiable, Debug, Serialize, Deserialize)]
+#[diesel(table_name = authors)]
+#[diesel(check_for_backend(diesel::sqlite::Sqlite))]
+pub struct Author {
+    pub id: AuthorId,
+    pub project_id: ProjectId,
+    pub family_name: String,
+    pub given_name: Option<String>,
+    pub orcid: Option<String>,
+}
+
+impl Author {
+    pub async fn get_by_id(
+        author_id: AuthorId,
+        conn: &mut AsyncConn,
+    ) -> Result<Author, diesel::result::Error> {
+        use crate::schema::authors::dsl::*;
+        authors.find(author_id).first(conn).await
+    }
+
+    pub async fn get_by_orcid(
+        project: ProjectId,
+        orcid_id: &str,
+        conn: &mut AsyncConn,
+    ) -> Result<Option<Author>, diesel::result::Error> {
+        use crate::schema::authors::dsl::*;
+        authors
+            .filter(project_id.eq(project))
+            .filter(orcid.eq(orcid_id))
+            .first(conn)
+            .await
+            .optional()
+    }
+
+    /// Finds the project's oldest author with exactly this name, creating one if there
+    /// is none. Projects never share authors, so people who share a name in different
+    /// projects are kept apart.
+    pub async fn find_or_insert(
+        project: ProjectId,
+        name: &NewAuthor,
+        conn: &mut AsyncConn,
+    ) -> Result<Author, diesel::result::Error> {
+        use crate::schema::authors::dsl::*;
+        let query = authors
+            .filter(project_id.eq(project))
+            .filter(family_name.eq(&name.family_name))
+            .order(id.asc())
+            .into_boxed();
+        let query = match &name.given_name {
+            Some(given) => query.filter(given_name.eq(given)),
+            None => query.filter(given_name.is_null()),
+        };
+        match query.first(conn).await.optional()? {
+            Some(author) => Ok(author),
+            None => name.insert(project, conn).await,
+        }
+    }
+
+    /// The authors of a paper in the order they are listed
+    pub async fn list_for_pdf(
+        pdf: PdfId,
+        conn: &mut AsyncConn,
+    ) -> Result<Vec<Author>, diesel::result::Error> {
+        authors::table
+            .inner_join(pdf_authors::table)
+            .filter(pdf_authors::pdf_id.eq(pdf))
+            .order(pdf_authors::position.asc())
+            .select(Author::as_select())
+            .load(conn)
+            .await
+    }
+
+    /// Authors of papers in the project whose name contains every word of `query`
+    pub async fn search(
+        project: ProjectId,
+        query: &str,
+        conn: &mut AsyncConn,
+    ) -> Result<Vec<Author>, diesel::result::Error> {
+        let mut statement = authors::table
+            .inner_join(pdf_authors::table.inner_join(pdfs::table))
+            .filter(pdfs::project_id.eq(project))
+            .select(Author::as_select())
+            .distinct()
+            .order((authors::family_name.asc(), authors::given_name.asc()))
+            .into_boxed();
+        for word in query.split_whitespace() {
+            let pattern = format!("%{word}%");
+            statement = statement.filter(
+                authors::family_name
+                    .like(pattern.clone())
+                    .or(authors::given_name.like(pattern))
+                    .or(authors::orcid.eq(word.to_string())),
+            );
+        }
+        statement.load(conn).await
+    }
+
+    pub async fn set_orcid(
+        author_id: AuthorId,
+        new_orcid: Option<&str>,
+        conn: &mut AsyncConn,
+    ) -> Result<Author, diesel::result::Error> {
+        use crate::schema::authors::dsl::*;
+        diesel::update(authors.find(author_id))
+            .set(orcid.eq(new_orcid))
+            .execute(conn)
+            .await?;
+        Author::get_by_id(author_id, conn).await
+    }
+
+    /// The name as it would appear in a byline, e.g. "Marie Curie"
+    pub fn display_name(&self) -> String {
+        match &self.given_name {
+            Some(given) => format!("{given} {}", self.family_name),
+            None => self.family_name.clone(),
+        }
+    }
+}
+
+/// Used for inserting a new author
+#[derive(Insertable, Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[diesel(table_name = authors)]
+pub struct NewAuthor {
+    pub family_name: String,
+    pub given_name: Option<String>,
+}
+
+impl NewAuthor {
+    pub async fn insert(
+        &self,
+        project: ProjectId,
+        conn: &mut AsyncConn,
+    ) -> Result<Author, diesel::result::Error> {
+        use crate::schema::authors::dsl::*;
+        let author = conn
+            .transaction::<_, diesel::result::Error, _>(|conn| {
+                Box::pin(async move {
+                    diesel::insert_into(authors)
+                        .values((self, project_id.eq(project)))
+                        .execute(conn)
+                        .await?;
+                    let author_id = get_last_rowid(conn).await?;
+                    Author::get_by_id(AuthorId(author_id), conn).await
+                })
+            })
+            .await?;
+        Ok(author)
+    }
+
+    /// Parses a single name written as "Family, Given", "Given Family" or "Family GA"
+    pub fn parse(name: &str) -> Option<NewAuthor> {
+        let name = name.trim();
+        let (family, given) = match name.split_once(',') {
+            Some((family, given)) => (family.trim(), given.trim()),
+            None => match name.rsplit_once(' ') {
+                None => (name, ""),
+                Some((head, last)) if is_initials(last) => (head.trim(), last),
+                Some((head, last)) => (last, head.trim()),
+            },
+        };
+        if family.is_empty() {
+            return None;
+        }
+        Some(NewAuthor {
+            family_name: family.to_string(),
+            given_name: (!given.is_empty()).then(|| given.to_string()),
+        })
+    }
+}
+
+/// Undotted initials as in PubMed-style names ("Smith JA")
+fn is_initials(word: &str) -> bool {
+    (1..=3).contains(&word.len()) && word.chars().all(|c| c.is_ascii_uppercase())
+}
+
+/// Splits a free-text author list into names.
+///
+/// Names are separated by "and", "&" or ";". A piece containing commas is a list of
+/// names itself unless it reads as a single "Family, Given". The authors migration
+/// applies the same rules in SQL, so keep the two in step.
+pub fn parse_author_list(list: &str) -> Vec<NewAuthor> {
+    let normalized = list
+        .trim()
+        .replace(", and ", ";")
+        .replace(" and ", ";")
+        .replace(" & ", ";");
+    normalized
+        .split(';')
+        .map(|piece| piece.trim().trim_end_matches(',').trim())
+        .filter(|piece| !piece.is_empty())
+        .flat_map(|piece| {
+            let is_list = piece.matches(',').count() > 1
+                || piece
+                    .split_once(',')
+                    .is_some_and(|(head, _)| head.trim().contains(' '));
+            if is_list {
+                piece.split(',').collect()
+            } else {
+                vec![piece]
+            }
+        })
+        .filter_map(NewAuthor::parse)
+        .collect()
+}
+
+/// Validates an ORCID iD, given bare or as an orcid.org URL, and returns it in
+/// the canonical `0000-0000-0000-000X` form
+pub fn normalize_orcid(input: &str) -> Option<String> {
+    let input = input.trim();
+    let input = ["https://orcid.org/", "http://orcid.org/", "orcid.org/"]
+        .iter()
+        .find_map(|prefix| input.strip_prefix(prefix))
+        .unwrap_or(input);
+    let chars: Vec<char> = input
+        .chars()
+        .filter(|c| *c != '-')
+        .map(|c| c.to_ascii_uppercase())
+        .collect();
+    if chars.len() != 16 || !chars[..15].iter().all(char::is_ascii_digit) {
+        return None;
+    }
+    // ISO 7064 MOD 11-2 check digit
+    let total = chars[..15]
+        .iter()
+        .filter_map(|c| c.to_digit(10))
+        .fold(0, |total, digit| (total + digit) * 2);
+    let check = match (12 - total % 11) % 11 {
+        10 => 'X',
+        digit => char::from_digit(digit, 10)?,
+    };
+    if chars[15] != check {
+        return None;
+    }
+    let groups: Vec<String> = chars.chunks(4).map(|c| c.iter().collect()).collect();
+    Some(groups.join("-"))
+}
+
+impl PdfInfo {
+    /// Papers in the project that list the author
+    pub async fn list_for_author(
+        author: AuthorId,
+        project: ProjectId,
+        conn: &mut AsyncConn,
+    ) -> Result<Vec<PdfInfo>, diesel::result::Error> {
+        pdfs::table
+            .inner_join(pdf_authors::table)
+            .filter(pdf_authors::author_id.eq(author))
+            .filter(pdfs::project_id.eq(project))
+            .order(pdfs::year.desc())
+            .select(PdfInfo::as_select())
+            .load(conn)
+            .await
+    }
+}
+
+impl Pdf {
+    /// Replaces the paper's author links with `names`, in order. Authors belong to the
+    /// paper's project, so a paper without one is left without authors.
+    pub async fn set_authors(
+        pdf: PdfId,
+        names: &[NewAuthor],
+        conn: &mut AsyncConn,
+    ) -> Result<(), diesel::result::Error> {
+        diesel::delete(pdf_authors::table.filter(pdf_authors::pdf_id.eq(pdf)))
+            .execute(conn)
+            .await?;
+        let project: Option<ProjectId> = pdfs::table
+            .find(pdf)
+            .select(pdfs::project_id)
+            .first(conn)
+            .await?;
+        let Some(project) = project else {
+            return Ok(());
+        };
+        for (position, name) in (0..).zip(names) {
+            let author = Author::find_or_insert(project, name, conn).await?;
+            diesel::insert_into(pdf_authors::table)
+                .values((
+                    pdf_authors::pdf_id.eq(pdf),
+                    pdf_authors::author_id.eq(author.id),
+                    pdf_authors::position.eq(position),
+                ))
+                .execute(conn)
+                .await?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn name(family: &str, given: Option<&str>) -> NewAuthor {
+        NewAuthor {
+            family_name: family.to_string(),
+            given_name: given.map(str::to_string),
+        }
+    }
+
+    #[test]
+    fn test_parse_author_list() {
+        let expected = vec![name("Smith", Some("John")), name("Doe", Some("Jane"))];
+        assert_eq!(parse_author_list("Smith, John and Doe, Jane"), expected);
+        assert_eq!(parse_author_list("John Smith, Jane Doe"), expected);
+        assert_eq!(parse_author_list("John Smith & Jane Doe"), expected);
+        assert_eq!(
+            parse_author_list("Smith J, Doe JA, and Curie"),
+            vec![
+                name("Smith", Some("J")),
+                name("Doe", Some("JA")),
+                name("Curie", None)
+            ]
+        );
+        assert_eq!(parse_author_list("Smith, John"), vec![expected[0].clone()]);
+        assert!(parse_author_list("  ").is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_authors_per_project() {
+        use crate::db::establish_test;
+        use crate::models::{PdfMetadata, fixtures};
+
+        let mut conn = establish_test().await.unwrap();
+        let mut authors = Vec::new();
+        for _ in 0..2 {
+            let project = fixtures::insert_project(&mut conn).await;
+            let pdf = PdfMetadata {
+                title: "Paper".to_string(),
+                authors: "Curie, Marie".to_string(),
+                ..Default::default()
+            }
+            .into_new_pdf(Vec::new(), Some(project.id))
+            .insert(&mut conn)
+            .await
+            .unwrap();
+            authors.extend(Author::list_for_pdf(pdf.id, &mut conn).await.unwrap());
+        }
+        assert_eq!(authors.len(), 2);
+        assert_ne!(authors[0].id, authors[1].id);
+
+        // The same ORCID iD may be given to the namesake in another project
+        let orcid = "0000-0002-1825-0097";
+        for author in &authors {
+            Author::set_orcid(author.id, Some(orcid), &mut conn)
+                .await
+                .unwrap();
+        }
+        let first = Author::get_by_orcid(authors[0].project_id, orcid, &mut conn)
+            .await
+            .unwrap();
+        assert_eq!(first.map(|author| author.id), Some(authors[0].id));
+
+        fixtures::delete_project(authors[0].project_id, &mut conn).await;
+        assert!(Author::get_by_id(authors[0].id, &mut conn).await.is_err());
+        assert!(Author::get_by_id(authors[1].id, &mut conn).await.is_ok());
+        fixtures::delete_project(authors[1].project_id, &mut conn).await;
+    }
+
+    #[test]
+    fn test_normalize_orcid() {
+        assert_eq!(
+            normalize_orcid("https://orcid.org/0000-0002-1825-0097").as_deref(),
+            Some("0000-0002-1825-0097")
+        );
+        assert_eq!(
+            normalize_orcid("000000021694233x").as_deref(),
+            Some("0000-0002-1694-233X")
+        );
+        assert_eq!(normalize_orcid("0000-0002-1825-0098"), None);
+        assert_eq!(normalize_orcid("1234"), None);
+    }
+}
@@ -0,0 +1,125 @@
+//! Records for tests, built in memory or inserted into the test database
+
+use super::{
+    Compound, CompoundId, NewCompound, NewProject, NewUser, Pdf, PdfId, PdfInfo, PdfMetadata,
+    Project, ProjectId, ProjectMember, UserId,
+};
+use crate::db::AsyncConn;
+use crate::schema::{api_token_projects, api_tokens, sessions, users};
+use diesel::prelude::*;
+use diesel_async::RunQueryDsl;
+
+/// Not a PHC string, so no password verifies against it and test accounts can't sign in
+const UNUSABLE_PASSWORD_HASH: &str = "!";
+
+/// A new project with a new owner, both uniquely named so that tests can share the database
+pub async fn insert_project(conn: &mut AsyncConn) -> Project {
+    let suffix = chrono::Utc::now().timestamp_nanos_opt().unwrap();
+    let owner = NewUser {
+        username: format!("test-user-{suffix}"),
+        password_hash: UNUSABLE_PASSWORD_HASH.to_string(),
+    }
+    .insert(conn)
+    .await
+    .unwrap();
+    NewProject {
+        name: format!("Test project {suffix}"),
+        path: format!("projects/test-project-{suffix}"),
+        created_at: chrono::Utc::now().naive_utc(),
+        fields: "[]".to_string(),
+    }
+    .insert(owner.id, conn)
+    .await
+    .unwrap()
+}
+
+/// Deletes a project made by [`insert_project`] with everything in it and its members'
+/// accounts, sessions and API tokens, leaving the test database as it was
+pub async fn delete_project(project: ProjectId, conn: &mut AsyncConn) {
+    let members: Vec<UserId> = ProjectMember::list_for_project(project, conn)
+        .await
+        .unwrap()
+        .into_iter()
+        .map(|(_, user)| user.id)
+        .collect();
+    Project::delete(project, conn).await.unwrap();
+    let tokens = api_tokens::table
+        .filter(api_tokens::user_id.eq_any(&members))
+        .select(api_tokens::id);
+    diesel::delete(api_token_projects::table.filter(api_token_projects::token_id.eq_any(tokens)))
+        .execute(conn)
+        .await
+        .unwrap();
+    diesel::delete(api_tokens::table.filter(api_tokens::user_id.eq_any(&members)))
+        .execute(conn)
+        .await
+        .unwrap();
+    diesel::delete(sessions::table.filter(sessions::user_id.eq_any(&members)))
+        .execute(conn)
+        .await
+        .unwrap();
+    diesel::delete(users::table.filter(users::id.eq_any(members)))
+        .execute(conn)
+        .await
+        .unwrap();
+}
+
+/// A paper without a file in the project
+pub async fn insert_pdf(project: ProjectId, conn: &mut AsyncConn) -> Pdf {
+    PdfMetadata {
+        title: "Test paper".to_string(),
+        ..Default::default()
+    }
+    .into_new_pdf(Vec::new(), Some(project))
+    .insert(conn)
+    .await
+    .unwrap()
+}
+
+/// Ethanol from the paper, as drawn by hand
+pub fn new_compound(pdf_id: PdfId) -> NewCompound {
+    NewCompound {
+        inchi: "InChI=1S/C2H6O/c1-2-3/h3H,2H2,1H3".to_string(),
+        ..NewCompound::new(pdf_id, "CCO".to_string())
+    }
+}
+
+/// Compound 1, ethanol from paper 1, without an InChI, image, data or label
+pub fn compound() -> Compound {
+    Compound {
+        id: CompoundId(1),
+        pdf_id: PdfId(1),
+        smiles: "CCO".to_string(),
+        inchi: String::new(),
+        image: String::new(),
+        chemical_data: "{}".to_string(),
+        recognition_backend: None,
+        recognized_smiles: None,
+        recognized_inchi: None,
+        recognition_confidence: None,
+        label: String::new(),
+        markush_source_id: None,
+        rgroup_assignments: None,
+    }
+}
+
+/// Paper 1, titled "Paper" from 2019, in no project and with no other details
+pub fn pdf_info() -> PdfInfo {
+    PdfInfo {
+        id: PdfId(1),
+        title: "Paper".to_string(),
+        authors: String::new(),
+        year: 2019,
+        journal: String::new(),
+        volume: String::new(),
+        project_id: None,
+        doi: None,
+        pmid: None,
+        arxiv_id: None,
+        isbn: None,
+        url: None,
+        pages: None,
+        issue: None,
+        publisher: None,
+    }
+}
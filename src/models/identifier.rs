@@ -0,0 +1,250 @@
+use super::{PdfId, PdfInfo, ProjectId};
+use crate::db::AsyncConn;
+use crate::error::MolmineError;
+use diesel::prelude::*;
+use diesel_async::RunQueryDsl;
+
+/// A persistent identifier that a paper can be looked up by
+#[derive(Clone, Debug, Hash, PartialEq, Eq)]
+pub enum PaperIdentifier {
+    Doi(String),
+    Pmid(String),
+    ArxivId(String),
+    Isbn(String),
+}
+
+impl PaperIdentifier {
+    /// Normalizes `value` as an identifier of the given kind ("doi", "pmid", "arxiv" or "isbn")
+    pub fn parse(kind: &str, value: &str) -> Option<PaperIdentifier> {
+        match kind {
+            "doi" => normalize_doi(value).map(PaperIdentifier::Doi),
+            "pmid" => normalize_pmid(value).map(PaperIdentifier::Pmid),
+            "arxiv" | "arxiv_id" => normalize_arxiv_id(value).map(PaperIdentifier::ArxivId),
+            "isbn" => normalize_isbn(value).map(PaperIdentifier::Isbn),
+            _ => None,
+        }
+    }
+}
+
+fn strip_prefixes<'a>(value: &'a str, prefixes: &[&str]) -> &'a str {
+    let value = value.trim();
+    prefixes
+        .iter()
+        .find_map(|prefix| {
+            value
+                .get(..prefix.len())
+                .filter(|head| head.eq_ignore_ascii_case(prefix))
+                .map(|_| value[prefix.len()..].trim_start())
+        })
+        .unwrap_or(value)
+}
+
+/// Validates a DOI, given bare, as a `doi:` reference or as a doi.org link, and returns
+/// it lowercased. DOIs are case-insensitive, so this is the form they are compared in.
+pub fn normalize_doi(input: &str) -> Option<String> {
+    let doi = strip_prefixes(
+        input,
+        &[
+            "https://doi.org/",
+            "http://doi.org/",
+            "https://dx.doi.org/",
+            "http://dx.doi.org/",
+            "doi.org/",
+            "doi:",
+        ],
+    );
+    let (prefix, suffix) = doi.split_once('/')?;
+    let registrant = prefix.strip_prefix("10.")?;
+    let valid = (4..=9).contains(&registrant.split('.').next()?.len())
+        && registrant.chars().all(|c| c.is_ascii_digit() || c == '.')
+        && !suffix.is_empty()
+        && !suffix.chars().any(char::is_whitespace);
+    valid.then(|| doi.to_lowercase())
+}
+
+/// A PubMed identifier: a positive integer, optionally written as "PMID: 123"
+pub fn normalize_pmid(input: &str) -> Option<String> {
+    let pmid = strip_prefixes(input, &["pmid:", "pmid"]);
+    let valid = (1..=9).contains(&pmid.len())
+        && pmid.chars().all(|c| c.is_ascii_digit())
+        && !pmid.starts_with('0');
+    valid.then(|| pmid.to_string())
+}
+
+/// An arXiv identifier in the current `2101.01234v2` or the pre-2007 `hep-th/9901001`
+/// scheme, given bare, as `arXiv:` or as an arxiv.org link
+pub fn normalize_arxiv_id(input: &str) -> Option<String> {
+    let id = strip_prefixes(
+        input,
+        &[
+            "https://arxiv.org/abs/",
+            "http://arxiv.org/abs/",
+            "https://arxiv.org/pdf/",
+            "arxiv.org/abs/",
+            "arxiv:",
+        ],
+    );
+    let id = id.strip_suffix(".pdf").unwrap_or(id);
+    let (base, version) = match id.rsplit_once('v') {
+        Some((base, version))
+            if !version.is_empty() && version.chars().all(|c| c.is_ascii_digit()) =>
+        {
+            (base, Some(version))
+        }
+        _ => (id, None),
+    };
+    let is_digits = |s: &str, lengths: std::ops::RangeInclusive<usize>| {
+        lengths.contains(&s.len()) && s.chars().all(|c| c.is_ascii_digit())
+    };
+    let valid = match base.split_once('/') {
+        Some((archive, number)) => {
+            !archive.is_empty()
+                && archive
+                    .chars()
+                    .all(|c| c.is_ascii_lowercase() || c == '-' || c == '.')
+                && is_digits(number, 7..=7)
+        }
+        None => base
+            .split_once('.')
+            .is_some_and(|(month, number)| is_digits(month, 4..=4) && is_digits(number, 4..=5)),
+    };
+    valid.then(|| match version {
+        Some(version) => format!("{base}v{version}"),
+        None => base.to_string(),
+    })
+}
+
+/// Validates an ISBN-10 or ISBN-13 check digit and returns it without separators
+pub fn normalize_isbn(input: &str) -> Option<String> {
+    let isbn: String = strip_prefixes(input, &["isbn-13:", "isbn-10:", "isbn:", "isbn"])
+        .chars()
+        .filter(|c| !matches!(c, '-' | ' '))
+        .map(|c| c.to_ascii_uppercase())
+        .collect();
+    // Only the last character of an ISBN-10 may be X, standing for 10
+    let digits: Option<Vec<u32>> = isbn
+        .chars()
+        .enumerate()
+        .map(|(i, c)| match c {
+            'X' if isbn.len() == 10 && i == 9 => Some(10),
+            c => c.to_digit(10),
+        })
+        .collect();
+    let valid = match digits {
+        Some(digits) if digits.len() == 10 => {
+            (1..=10).rev().zip(&digits).map(|(w, d)| w * d).sum::<u32>() % 11 == 0
+        }
+        Some(digits) if digits.len() == 13 => {
+            [1, 3]
+                .iter()
+                .cycle()
+                .zip(&digits)
+                .map(|(w, d)| w * d)
+                .sum::<u32>()
+                % 10
+                == 0
+        }
+        _ => false,
+    };
+    valid.then_some(isbn)
+}
+
+/// Accepts absolute http(s) links only
+pub fn normalize_url(input: &str) -> Option<String> {
+    let url = input.trim();
+    let valid = (url.starts_with("https://") || url.starts_with("http://"))
+        && !url.chars().any(char::is_whitespace);
+    valid.then(|| url.to_string())
+}
+
+impl PdfInfo {
+    /// Finds the paper in the project carrying the identifier
+    pub async fn find_by_identifier(
+        project: ProjectId,
+        identifier: &PaperIdentifier,
+        conn: &mut AsyncConn,
+    ) -> Result<Option<PdfInfo>, diesel::result::Error> {
+        use crate::schema::pdfs::dsl::*;
+        let query = pdfs
+            .filter(project_id.eq(project))
+            .select(PdfInfo::as_select())
+            .into_boxed();
+        let query = match identifier {
+            PaperIdentifier::Doi(value) => query.filter(doi.eq(value)),
+            PaperIdentifier::Pmid(value) => query.filter(pmid.eq(value)),
+            PaperIdentifier::ArxivId(value) => query.filter(arxiv_id.eq(value)),
+            PaperIdentifier::Isbn(value) => query.filter(isbn.eq(value)),
+        };
+        query.first(conn).await.optional()
+    }
+
+    /// Rejects a DOI that another paper in the project already has. Every way of adding
+    /// or editing papers goes through this, so they all report duplicates alike.
+    pub async fn ensure_unique_doi(
+        project: ProjectId,
+        doi: &str,
+        except: Option<PdfId>,
+        conn: &mut AsyncConn,
+    ) -> Result<(), MolmineError> {
+        let existing =
+            PdfInfo::find_by_identifier(project, &PaperIdentifier::Doi(doi.to_string()), conn)
+                .await?;
+        match existing {
+            Some(existing) if Some(existing.id) != except => {
+                Err(MolmineError::InvalidInput(format!(
+                    "A paper with DOI {doi} already exists in this project: {}",
+                    existing.title
+                )))
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_normalize_doi() {
+        let expected = Some("10.1021/acs.jmedchem.9b01234".to_string());
+        assert_eq!(normalize_doi("10.1021/acs.jmedchem.9b01234"), expected);
+        assert_eq!(
+            normalize_doi("https://doi.org/10.1021/ACS.JMedChem.9b01234"),
+            expected
+        );
+        assert_eq!(
+            normalize_doi(" DOI: 10.1021/acs.jmedchem.9b01234"),
+            expected
+        );
+        assert_eq!(normalize_doi("10.12/short"), None);
+        assert_eq!(normalize_doi("not a doi"), None);
+    }
+
+    #[test]
+    fn test_normalize_other_identifiers() {
+        assert_eq!(
+            normalize_pmid("PMID: 31234567").as_deref(),
+            Some("31234567")
+        );
+        assert_eq!(normalize_pmid("12ab"), None);
+        assert_eq!(
+            normalize_arxiv_id("arXiv:2101.01234v2").as_deref(),
+            Some("2101.01234v2")
+        );
+        assert_eq!(
+            normalize_arxiv_id("https://arxiv.org/abs/hep-th/9901001").as_deref(),
+            Some("hep-th/9901001")
+        );
+        assert_eq!(normalize_arxiv_id("21.0123"), None);
+        assert_eq!(
+            normalize_isbn("ISBN 978-0-306-40615-7").as_deref(),
+            Some("9780306406157")
+        );
+        assert_eq!(
+            normalize_isbn("0-306-40615-2").as_deref(),
+            Some("0306406152")
+        );
+        assert_eq!(normalize_isbn("978-0-306-40615-8"), None);
+    }
+}
@@ -0,0 +1,253 @@
+use super::{JobId, ProjectId, UserId};
+use crate::db::{AsyncConn, get_last_rowid};
+use crate::schema::*;
+use chrono::NaiveDateTime;
+use diesel::prelude::*;
+use diesel_async::AsyncConnection;
+use diesel_async::RunQueryDsl;
+use diesel_derive_enum::DbEnum;
+use serde::{Deserialize, Serialize};
+
+/// Where a background job is in its life
+#[derive(DbEnum, Copy, Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum JobStatus {
+    /// Waiting for a worker, including between retries
+    Queued,
+    Running,
+    Succeeded,
+    /// Gave up after an error that retrying won't fix or after its last attempt
+    Failed,
+    Cancelled,
+}
+
+impl JobStatus {
+    /// Whether the job has ended and will not run again
+    pub fn is_finished(self) -> bool {
+        matches!(
+            self,
+            JobStatus::Succeeded | JobStatus::Failed | JobStatus::Cancelled
+        )
+    }
+}
+
+/// A unit of background work, with `task` and `result` stored as JSON
+#[derive(Queryable, Selectable, Identifiable, Debug, Serialize, Deserialize)]
+#[diesel(table_name = jobs)]
+#[diesel(check_for_backend(diesel::sqlite::Sqlite))]
+pub struct Job {
+    pub id: JobId,
+    pub project_id: Option<ProjectId>,
+    pub created_by: Option<UserId>,
+    pub kind: String,
+    pub task: String,
+    pub status: JobStatus,
+    pub attempts: i32,
+    pub max_attempts: i32,
+    /// Set when a running job is cancelled, for its worker to notice
+    pub cancel_requested: bool,
+    pub result: Option<String>,
+    /// The last error, kept while the job waits to be retried
+    pub error: Option<String>,
+    /// A queued job is not started before this time
+    pub run_after: NaiveDateTime,
+    pub created_at: NaiveDateTime,
+    pub started_at: Option<NaiveDateTime>,
+    pub finished_at: Option<NaiveDateTime>,
+    /// How far the current attempt has got, from 0 to 1, for jobs that report it
+    pub progress: Option<f64>,
+}
+
+impl Job {
+    pub async fn get_by_id(
+        job_id: JobId,
+        conn: &mut AsyncConn,
+    ) -> Result<Job, diesel::result::Error> {
+        use crate::schema::jobs::dsl::*;
+        jobs.find(job_id).first(conn).await
+    }
+
+    /// The project's most recent jobs first, optionally only those with one status
+    pub async fn list_for_project(
+        project: ProjectId,
+        with_status: Option<JobStatus>,
+        limit: i64,
+        conn: &mut AsyncConn,
+    ) -> Result<Vec<Job>, diesel::result::Error> {
+        use crate::schema::jobs::dsl::*;
+        let mut query = jobs.filter(project_id.eq(project)).into_boxed();
+        if let Some(with_status) = with_status {
+            query = query.filter(status.eq(with_status));
+        }
+        query.order(id.desc()).limit(limit).load(conn).await
+    }
+
+    /// Marks the oldest queued job that is due as running and returns it. A job is
+    /// only claimed if it is still queued when updated, so two workers never get the
+    /// same one.
+    pub async fn claim_next(
+        now: NaiveDateTime,
+        conn: &mut AsyncConn,
+    ) -> Result<Option<Job>, diesel::result::Error> {
+        conn.transaction::<_, diesel::result::Error, _>(|conn| {
+            Box::pin(async move {
+                use crate::schema::jobs::dsl::*;
+                let Some(job_id) = jobs
+                    .filter(status.eq(JobStatus::Queued))
+                    .filter(run_after.le(now))
+                    .order((run_after.asc(), id.asc()))
+                    .select(id)
+                    .first::<JobId>(conn)
+                    .await
+                    .optional()?
+                else {
+                    return Ok(None);
+                };
+                let claimed =
+                    diesel::update(jobs.find(job_id).filter(status.eq(JobStatus::Queued)))
+                        .set((
+                            status.eq(JobStatus::Running),
+                            attempts.eq(attempts + 1),
+                            started_at.eq(now),
+                            progress.eq(None::<f64>),
+                        ))
+                        .execute(conn)
+                        .await?;
+                if claimed == 0 {
+                    return Ok(None);
+                }
+                Job::get_by_id(job_id, conn).await.map(Some)
+            })
+        })
+        .await
+    }
+
+    /// Ends the job with the given status
+    pub async fn finish(
+        job_id: JobId,
+        new_status: JobStatus,
+        new_result: Option<String>,
+        new_error: Option<String>,
+        conn: &mut AsyncConn,
+    ) -> Result<(), diesel::result::Error> {
+        use crate::schema::jobs::dsl::*;
+        diesel::update(jobs.find(job_id))
+            .set((
+                status.eq(new_status),
+                result.eq(new_result),
+                error.eq(new_error),
+                finished_at.eq(chrono::Utc::now().naive_utc()),
+            ))
+            .execute(conn)
+            .await?;
+        Ok(())
+    }
+
+    pub async fn set_progress(
+        job_id: JobId,
+        new_progress: f64,
+        conn: &mut AsyncConn,
+    ) -> Result<(), diesel::result::Error> {
+        use crate::schema::jobs::dsl::*;
+        diesel::update(jobs.find(job_id))
+            .set(progress.eq(new_progress))
+            .execute(conn)
+            .await?;
+        Ok(())
+    }
+
+    /// Puts a failed job back in the queue to run again at `retry_at`
+    pub async fn retry_later(
+        job_id: JobId,
+        new_error: String,
+        retry_at: NaiveDateTime,
+        conn: &mut AsyncConn,
+    ) -> Result<(), diesel::result::Error> {
+        use crate::schema::jobs::dsl::*;
+        diesel::update(jobs.find(job_id))
+            .set((
+                status.eq(JobStatus::Queued),
+                error.eq(new_error),
+                run_after.eq(retry_at),
+            ))
+            .execute(conn)
+            .await?;
+        Ok(())
+    }
+
+    /// Cancels a queued job straight away, or asks the worker running it to stop.
+    /// Jobs that have already ended are left as they are.
+    pub async fn cancel(job_id: JobId, conn: &mut AsyncConn) -> Result<Job, diesel::result::Error> {
+        use crate::schema::jobs::dsl::*;
+        diesel::update(jobs.find(job_id).filter(status.eq(JobStatus::Queued)))
+            .set((
+                status.eq(JobStatus::Cancelled),
+                finished_at.eq(chrono::Utc::now().naive_utc()),
+            ))
+            .execute(conn)
+            .await?;
+        diesel::update(jobs.find(job_id).filter(status.eq(JobStatus::Running)))
+            .set(cancel_requested.eq(true))
+            .execute(conn)
+            .await?;
+        Job::get_by_id(job_id, conn).await
+    }
+
+    pub async fn is_cancel_requested(
+        job_id: JobId,
+        conn: &mut AsyncConn,
+    ) -> Result<bool, diesel::result::Error> {
+        use crate::schema::jobs::dsl::*;
+        jobs.find(job_id).select(cancel_requested).first(conn).await
+    }
+
+    /// Returns jobs left running by a server that stopped to the queue, or cancels
+    /// them if that was asked for. Their interrupted attempt still counts, so a job
+    /// that brings the server down is not retried forever.
+    pub async fn requeue_interrupted(conn: &mut AsyncConn) -> Result<usize, diesel::result::Error> {
+        use crate::schema::jobs::dsl::*;
+        diesel::update(
+            jobs.filter(status.eq(JobStatus::Running))
+                .filter(cancel_requested.eq(true)),
+        )
+        .set((
+            status.eq(JobStatus::Cancelled),
+            finished_at.eq(chrono::Utc::now().naive_utc()),
+        ))
+        .execute(conn)
+        .await?;
+        // Jobs out of attempts are failed by the worker that claims them next
+        diesel::update(jobs.filter(status.eq(JobStatus::Running)))
+            .set(status.eq(JobStatus::Queued))
+            .execute(conn)
+            .await
+    }
+}
+
+/// Used for queueing a new job
+#[derive(Insertable, Debug)]
+#[diesel(table_name = jobs)]
+pub struct NewJob {
+    pub project_id: Option<ProjectId>,
+    pub created_by: Option<UserId>,
+    pub kind: String,
+    pub task: String,
+    pub max_attempts: i32,
+    pub run_after: NaiveDateTime,
+}
+
+impl NewJob {
+    pub async fn insert(&self, conn: &mut AsyncConn) -> Result<Job, diesel::result::Error> {
+        conn.transaction::<_, diesel::result::Error, _>(|conn| {
+            Box::pin(async move {
+                diesel::insert_into(jobs::table)
+                    .values(self)
+                    .execute(conn)
+                    .await?;
+                let job_id = JobId(get_last_rowid(conn).await?);
+                Job::get_by_id(job_id, conn).await
+            })
+        })
+        .await
+    }
+}
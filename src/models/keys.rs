@@ -10,3 +10,27 @@ pub struct PdfId(pub i32);
 
 #[derive(DieselNewType, Copy, Clone, Debug, From, Hash, PartialEq, Eq, Serialize, Deserialize)]
 pub struct ProjectId(pub i32);
+
+#[derive(DieselNewType, Copy, Clone, Debug, From, Hash, PartialEq, Eq, Serialize, Deserialize)]
+pub struct UserId(pub i32);
+
+#[derive(DieselNewType, Copy, Clone, Debug, From, Hash, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ApiTokenId(pub i32);
+
+#[derive(DieselNewType, Copy, Clone, Debug, From, Hash, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AuthorId(pub i32);
+
+#[derive(DieselNewType, Copy, Clone, Debug, From, Hash, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RegionId(pub i32);
+
+#[derive(DieselNewType, Copy, Clone, Debug, From, Hash, PartialEq, Eq, Serialize, Deserialize)]
+pub struct JobId(pub i32);
+
+#[derive(DieselNewType, Copy, Clone, Debug, From, Hash, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MentionId(pub i32);
+
+#[derive(DieselNewType, Copy, Clone, Debug, From, Hash, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MarkushSourceId(pub i32);
+
+#[derive(DieselNewType, Copy, Clone, Debug, From, Hash, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RecognitionId(pub i32);
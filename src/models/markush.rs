@@ -0,0 +1,79 @@
+use super::{Compound, MarkushSourceId, PdfId};
+use crate::db::{AsyncConn, get_last_rowid};
+use crate::schema::*;
+use diesel::prelude::*;
+use diesel_async::RunQueryDsl;
+use serde::{Deserialize, Serialize};
+
+/// A generic structure from a paper: a core SMILES with named attachment points and the
+/// R-group table, as JSON, that its compounds were enumerated from
+#[derive(Queryable, Selectable, Identifiable, Debug, Serialize, Deserialize)]
+#[diesel(table_name = markush_sources)]
+#[diesel(check_for_backend(diesel::sqlite::Sqlite))]
+pub struct MarkushSource {
+    pub id: MarkushSourceId,
+    pub pdf_id: PdfId,
+    /// Where the paper shows it, such as "Table 2"
+    pub name: String,
+    pub core: String,
+    pub rgroups: String,
+}
+
+impl MarkushSource {
+    pub async fn get_by_id(
+        source_id: MarkushSourceId,
+        conn: &mut AsyncConn,
+    ) -> Result<MarkushSource, diesel::result::Error> {
+        use crate::schema::markush_sources::dsl::*;
+        markush_sources.find(source_id).first(conn).await
+    }
+
+    pub async fn list_for_pdf(
+        pdf: PdfId,
+        conn: &mut AsyncConn,
+    ) -> Result<Vec<MarkushSource>, diesel::result::Error> {
+        use crate::schema::markush_sources::dsl::*;
+        markush_sources
+            .filter(pdf_id.eq(pdf))
+            .order(id.asc())
+            .load(conn)
+            .await
+    }
+
+    /// The compounds enumerated from the source that still exist
+    pub async fn compounds(
+        source_id: MarkushSourceId,
+        conn: &mut AsyncConn,
+    ) -> Result<Vec<Compound>, diesel::result::Error> {
+        compounds::table
+            .filter(compounds::markush_source_id.eq(source_id))
+            .order(compounds::id.asc())
+            .load(conn)
+            .await
+    }
+}
+
+/// Used for inserting a generic structure before enumerating it
+#[derive(Insertable, Debug)]
+#[diesel(table_name = markush_sources)]
+pub struct NewMarkushSource {
+    pub pdf_id: PdfId,
+    pub name: String,
+    pub core: String,
+    pub rgroups: String,
+}
+
+impl NewMarkushSource {
+    pub async fn insert(
+        &self,
+        conn: &mut AsyncConn,
+    ) -> Result<MarkushSource, diesel::result::Error> {
+        use crate::schema::markush_sources::dsl::*;
+        diesel::insert_into(markush_sources)
+            .values(self)
+            .execute(conn)
+            .await?;
+        let source_id = MarkushSourceId(get_last_rowid(conn).await?);
+        MarkushSource::get_by_id(source_id, conn).await
+    }
+}
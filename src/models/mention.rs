@@ -0,0 +1,81 @@
+use super::{CompoundId, MentionId, PdfId};
+use crate::db::AsyncConn;
+use crate::schema::*;
+use diesel::prelude::*;
+use diesel_async::AsyncConnection;
+use diesel_async::RunQueryDsl;
+use serde::{Deserialize, Serialize};
+
+/// A sentence on a page of a paper that refers to a compound by its label
+#[derive(Queryable, Selectable, Identifiable, Debug, Serialize, Deserialize)]
+#[diesel(table_name = compound_mentions)]
+#[diesel(check_for_backend(diesel::sqlite::Sqlite))]
+pub struct CompoundMention {
+    pub id: MentionId,
+    pub compound_id: CompoundId,
+    pub pdf_id: PdfId,
+    pub page_number: i32,
+    pub sentence: String,
+}
+
+impl CompoundMention {
+    /// The compound's mentions in reading order
+    pub async fn list_for_compound(
+        compound: CompoundId,
+        conn: &mut AsyncConn,
+    ) -> Result<Vec<CompoundMention>, diesel::result::Error> {
+        use crate::schema::compound_mentions::dsl::*;
+        compound_mentions
+            .filter(compound_id.eq(compound))
+            .order((page_number.asc(), id.asc()))
+            .load(conn)
+            .await
+    }
+
+    /// Mentions of all the paper's compounds in reading order
+    pub async fn list_for_pdf(
+        pdf: PdfId,
+        conn: &mut AsyncConn,
+    ) -> Result<Vec<CompoundMention>, diesel::result::Error> {
+        use crate::schema::compound_mentions::dsl::*;
+        compound_mentions
+            .filter(pdf_id.eq(pdf))
+            .order((page_number.asc(), id.asc()))
+            .load(conn)
+            .await
+    }
+
+    /// Replaces every mention found in the paper with a new set
+    pub async fn replace_for_pdf(
+        pdf: PdfId,
+        mentions: &[NewCompoundMention],
+        conn: &mut AsyncConn,
+    ) -> Result<(), diesel::result::Error> {
+        conn.transaction::<_, diesel::result::Error, _>(|conn| {
+            Box::pin(async move {
+                use crate::schema::compound_mentions::dsl::*;
+                diesel::delete(compound_mentions.filter(pdf_id.eq(pdf)))
+                    .execute(conn)
+                    .await?;
+                for mention in mentions {
+                    diesel::insert_into(compound_mentions)
+                        .values(mention)
+                        .execute(conn)
+                        .await?;
+                }
+                Ok(())
+            })
+        })
+        .await
+    }
+}
+
+/// Used for inserting a found mention
+#[derive(Insertable, Debug, PartialEq)]
+#[diesel(table_name = compound_mentions)]
+pub struct NewCompoundMention {
+    pub compound_id: CompoundId,
+    pub pdf_id: PdfId,
+    pub page_number: i32,
+    pub sentence: String,
+}
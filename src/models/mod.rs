@@ -1,13 +1,37 @@
+pub mod author;
+#[cfg(test)]
+pub(crate) mod fixtures;
+pub mod identifier;
+pub mod job;
 pub mod keys;
+pub mod markush;
+pub mod mention;
+pub mod recognition;
+pub mod region;
+pub mod text;
+pub mod token;
+pub mod user;
+pub use author::*;
+pub use identifier::*;
+pub use job::*;
 pub use keys::*;
+pub use markush::*;
+pub use mention::*;
+pub use recognition::*;
+pub use region::*;
+pub use text::*;
+pub use token::*;
+pub use user::*;
 
 use crate::db::{AsyncConn, get_last_rowid};
+use crate::error::MolmineError;
 use crate::schema::*;
 use chrono::NaiveDateTime;
 use diesel::prelude::*;
 use diesel_async::AsyncConnection;
 use diesel_async::RunQueryDsl;
 use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
 
 /// Represents a compound in the database
 #[derive(Queryable, Selectable, Identifiable, Debug, Serialize, Deserialize)]
@@ -20,6 +44,144 @@ pub struct Compound {
     pub inchi: String,
     pub image: String,
     pub chemical_data: String,
+    /// The recognizer that read the structure from its drawing, if one did. `smiles`
+    /// holds the structure as curated, which may differ from what was recognized.
+    pub recognition_backend: Option<String>,
+    pub recognized_smiles: Option<String>,
+    pub recognized_inchi: Option<String>,
+    pub recognition_confidence: Option<f64>,
+    /// What the paper calls the compound, such as `7a`, or empty if unknown
+    pub label: String,
+    /// The generic structure the compound was enumerated from, if it was
+    pub markush_source_id: Option<MarkushSourceId>,
+    /// The substituent at each of that structure's attachment points, as a JSON object
+    pub rgroup_assignments: Option<String>,
+}
+
+impl Compound {
+    pub async fn get_by_id(
+        compound_id: CompoundId,
+        conn: &mut AsyncConn,
+    ) -> Result<Compound, diesel::result::Error> {
+        use crate::schema::compounds::dsl::*;
+        compounds.find(compound_id).first(conn).await
+    }
+
+    pub async fn list_for_pdf(
+        pdf: PdfId,
+        conn: &mut AsyncConn,
+    ) -> Result<Vec<Compound>, diesel::result::Error> {
+        use crate::schema::compounds::dsl::*;
+        compounds
+            .filter(pdf_id.eq(pdf))
+            .order(id.asc())
+            .load(conn)
+            .await
+    }
+
+    /// One page of a project's compounds with their papers, in id order after `after`.
+    /// Exports walk large projects with this instead of loading every row at once.
+    pub async fn list_page_for_project(
+        project: ProjectId,
+        after: Option<CompoundId>,
+        limit: i64,
+        conn: &mut AsyncConn,
+    ) -> Result<Vec<(Compound, PdfInfo)>, diesel::result::Error> {
+        let mut query = compounds::table
+            .inner_join(pdfs::table)
+            .filter(pdfs::project_id.eq(project))
+            .into_boxed();
+        if let Some(after) = after {
+            query = query.filter(compounds::id.gt(after));
+        }
+        query
+            .order(compounds::id.asc())
+            .limit(limit)
+            .select((Compound::as_select(), PdfInfo::as_select()))
+            .load(conn)
+            .await
+    }
+
+    /// The project's compounds that were read by a structure recognizer
+    pub async fn list_recognized_for_project(
+        project: ProjectId,
+        conn: &mut AsyncConn,
+    ) -> Result<Vec<Compound>, diesel::result::Error> {
+        compounds::table
+            .inner_join(pdfs::table)
+            .filter(pdfs::project_id.eq(project))
+            .filter(compounds::recognition_backend.is_not_null())
+            .order(compounds::id.asc())
+            .select(Compound::as_select())
+            .load(conn)
+            .await
+    }
+
+    /// Every key used in the chemical data of the project's compounds, sorted
+    pub async fn chemical_data_keys(
+        project: ProjectId,
+        conn: &mut AsyncConn,
+    ) -> Result<Vec<String>, diesel::result::Error> {
+        #[derive(QueryableByName)]
+        struct DataKey {
+            #[diesel(sql_type = diesel::sql_types::Text)]
+            key: String,
+        }
+        // Rows that aren't JSON objects are treated as empty rather than failing the query
+        let keys: Vec<DataKey> = diesel::sql_query(
+            "SELECT DISTINCT data.key AS key \
+             FROM compounds JOIN pdfs ON pdfs.id = compounds.pdf_id, \
+             json_each(CASE WHEN json_valid(compounds.chemical_data) \
+                 AND json_type(compounds.chemical_data) = 'object' \
+                 THEN compounds.chemical_data ELSE '{}' END) AS data \
+             WHERE pdfs.project_id = ? ORDER BY data.key",
+        )
+        .bind::<diesel::sql_types::Integer, _>(project)
+        .load(conn)
+        .await?;
+        Ok(keys.into_iter().map(|data_key| data_key.key).collect())
+    }
+
+    pub async fn update(
+        compound_id: CompoundId,
+        changes: &CompoundChanges,
+        conn: &mut AsyncConn,
+    ) -> Result<Compound, diesel::result::Error> {
+        use crate::schema::compounds::dsl::*;
+        diesel::update(compounds.find(compound_id))
+            .set(changes)
+            .execute(conn)
+            .await?;
+        Compound::get_by_id(compound_id, conn).await
+    }
+
+    pub async fn set_chemical_data(
+        compound_id: CompoundId,
+        new_data: &str,
+        conn: &mut AsyncConn,
+    ) -> Result<(), diesel::result::Error> {
+        use crate::schema::compounds::dsl::*;
+        diesel::update(compounds.find(compound_id))
+            .set(chemical_data.eq(new_data))
+            .execute(conn)
+            .await?;
+        Ok(())
+    }
+
+    pub async fn delete(
+        compound_id: CompoundId,
+        conn: &mut AsyncConn,
+    ) -> Result<(), diesel::result::Error> {
+        diesel::delete(
+            compound_mentions::table.filter(compound_mentions::compound_id.eq(compound_id)),
+        )
+        .execute(conn)
+        .await?;
+        diesel::delete(compounds::table.find(compound_id))
+            .execute(conn)
+            .await?;
+        Ok(())
+    }
 }
 
 /// Used for inserting a new compound
@@ -31,6 +193,66 @@ pub struct NewCompound {
     pub inchi: String,
     pub image: String,
     pub chemical_data: String,
+    pub recognition_backend: Option<String>,
+    pub recognized_smiles: Option<String>,
+    pub recognized_inchi: Option<String>,
+    pub recognition_confidence: Option<f64>,
+    pub label: String,
+    pub markush_source_id: Option<MarkushSourceId>,
+    pub rgroup_assignments: Option<String>,
+}
+
+/// Used for updating a compound. Fields left as `None` keep their stored value.
+#[derive(AsChangeset, Debug)]
+#[diesel(table_name = compounds)]
+pub struct CompoundChanges {
+    pub pdf_id: PdfId,
+    pub smiles: String,
+    pub inchi: Option<String>,
+    pub image: Option<String>,
+    pub chemical_data: Option<String>,
+    pub recognition_backend: Option<String>,
+    pub recognized_smiles: Option<String>,
+    pub recognized_inchi: Option<String>,
+    pub recognition_confidence: Option<f64>,
+    pub label: Option<String>,
+}
+
+impl NewCompound {
+    /// A compound with only its structure, as imported or enumerated rather than drawn
+    pub fn new(pdf_id: PdfId, smiles: String) -> NewCompound {
+        NewCompound {
+            pdf_id,
+            smiles,
+            inchi: String::new(),
+            image: String::new(),
+            chemical_data: "{}".to_string(),
+            recognition_backend: None,
+            recognized_smiles: None,
+            recognized_inchi: None,
+            recognition_confidence: None,
+            label: String::new(),
+            markush_source_id: None,
+            rgroup_assignments: None,
+        }
+    }
+
+    pub async fn insert(&self, conn: &mut AsyncConn) -> Result<Compound, diesel::result::Error> {
+        use crate::schema::compounds::dsl::*;
+        let compound = conn
+            .transaction::<_, diesel::result::Error, _>(|conn| {
+                Box::pin(async move {
+                    diesel::insert_into(compounds)
+                        .values(self)
+                        .execute(conn)
+                        .await?;
+                    let compound_id = get_last_rowid(conn).await?;
+                    Compound::get_by_id(CompoundId(compound_id), conn).await
+                })
+            })
+            .await?;
+        Ok(compound)
+    }
 }
 
 /// Represents a PDF document in the database
@@ -45,6 +267,19 @@ pub struct Pdf {
     pub journal: String,
     pub volume: String,
     pub data: Vec<u8>,
+    pub project_id: Option<ProjectId>,
+    pub doi: Option<String>,
+    pub pmid: Option<String>,
+    pub arxiv_id: Option<String>,
+    pub isbn: Option<String>,
+    pub url: Option<String>,
+    pub pages: Option<String>,
+    pub issue: Option<String>,
+    pub publisher: Option<String>,
+    /// SHA-256 of `data` in hex, absent until the file is stored or hashed
+    pub content_hash: Option<String>,
+    /// Number of pages in the full-text index, absent until the text is extracted
+    pub page_count: Option<i32>,
 }
 
 impl Pdf {
@@ -55,6 +290,281 @@ impl Pdf {
         use crate::schema::pdfs::dsl::*;
         pdfs.find(pdf_id).first(conn).await
     }
+
+    /// Updates the bibliographic fields and re-links the paper's authors
+    pub async fn update_metadata(
+        pdf_id: PdfId,
+        metadata: &PdfMetadata,
+        conn: &mut AsyncConn,
+    ) -> Result<PdfInfo, MolmineError> {
+        use crate::schema::pdfs::dsl::*;
+        conn.transaction::<_, MolmineError, _>(|conn| {
+            Box::pin(async move {
+                let pdf = PdfInfo::get_by_id(pdf_id, conn).await?;
+                if let (Some(project), Some(new_doi)) = (pdf.project_id, &metadata.doi) {
+                    PdfInfo::ensure_unique_doi(project, new_doi, Some(pdf_id), conn).await?;
+                }
+                diesel::update(pdfs.find(pdf_id))
+                    .set(metadata)
+                    .execute(conn)
+                    .await?;
+                Pdf::set_authors(pdf_id, &parse_author_list(&metadata.authors), conn).await?;
+                Ok(PdfInfo::get_by_id(pdf_id, conn).await?)
+            })
+        })
+        .await
+    }
+
+    /// Stores the file for a paper that was created without one, or replaces it
+    pub async fn attach_file(
+        pdf_id: PdfId,
+        file: Vec<u8>,
+        conn: &mut AsyncConn,
+    ) -> Result<PdfInfo, diesel::result::Error> {
+        use crate::schema::pdfs::dsl::*;
+        let hash = self::content_hash(&file);
+        diesel::update(pdfs.find(pdf_id))
+            .set((data.eq(file), content_hash.eq(hash)))
+            .execute(conn)
+            .await?;
+        PdfInfo::get_by_id(pdf_id, conn).await
+    }
+
+    /// The paper in the project whose file has the given content hash, if any
+    pub async fn find_by_content_hash(
+        project: ProjectId,
+        hash: &str,
+        conn: &mut AsyncConn,
+    ) -> Result<Option<PdfInfo>, diesel::result::Error> {
+        use crate::schema::pdfs::dsl::*;
+        pdfs.filter(project_id.eq(project))
+            .filter(content_hash.eq(hash))
+            .select(PdfInfo::as_select())
+            .first(conn)
+            .await
+            .optional()
+    }
+
+    /// Hashes the stored files of the project's papers that predate content hashes,
+    /// returning how many were hashed
+    pub async fn fill_content_hashes(
+        project: ProjectId,
+        conn: &mut AsyncConn,
+    ) -> Result<usize, diesel::result::Error> {
+        use crate::schema::pdfs::dsl::*;
+        let missing: Vec<PdfId> = pdfs
+            .filter(project_id.eq(project))
+            .filter(content_hash.is_null())
+            .select(id)
+            .load(conn)
+            .await?;
+        let mut hashed = 0;
+        // One file at a time, so only a single paper is held in memory
+        for pdf_id in missing {
+            let file: Vec<u8> = pdfs.find(pdf_id).select(data).first(conn).await?;
+            if file.is_empty() {
+                continue;
+            }
+            diesel::update(pdfs.find(pdf_id))
+                .set(content_hash.eq(self::content_hash(&file)))
+                .execute(conn)
+                .await?;
+            hashed += 1;
+        }
+        Ok(hashed)
+    }
+
+    /// Deletes the PDF along with every compound extracted from it, its author links, its
+    /// structure regions and its generic structures. Recognitions read from its regions are
+    /// kept without the region.
+    pub async fn delete(pdf_id: PdfId, conn: &mut AsyncConn) -> Result<(), diesel::result::Error> {
+        conn.transaction::<_, diesel::result::Error, _>(|conn| {
+            Box::pin(async move {
+                diesel::delete(
+                    compound_mentions::table.filter(compound_mentions::pdf_id.eq(pdf_id)),
+                )
+                .execute(conn)
+                .await?;
+                diesel::delete(compounds::table.filter(compounds::pdf_id.eq(pdf_id)))
+                    .execute(conn)
+                    .await?;
+                diesel::delete(markush_sources::table.filter(markush_sources::pdf_id.eq(pdf_id)))
+                    .execute(conn)
+                    .await?;
+                diesel::delete(pdf_authors::table.filter(pdf_authors::pdf_id.eq(pdf_id)))
+                    .execute(conn)
+                    .await?;
+                let region_ids = structure_regions::table
+                    .filter(structure_regions::pdf_id.eq(pdf_id))
+                    .select(structure_regions::id.nullable());
+                diesel::update(
+                    recognitions::table.filter(recognitions::region_id.eq_any(region_ids)),
+                )
+                .set(recognitions::region_id.eq(None::<RegionId>))
+                .execute(conn)
+                .await?;
+                diesel::delete(
+                    structure_regions::table.filter(structure_regions::pdf_id.eq(pdf_id)),
+                )
+                .execute(conn)
+                .await?;
+                diesel::delete(pdfs::table.find(pdf_id))
+                    .execute(conn)
+                    .await?;
+                Ok(())
+            })
+        })
+        .await
+    }
+}
+
+/// A PDF's bibliographic information without the file contents
+#[derive(Queryable, Selectable, Debug, Serialize, Deserialize)]
+#[diesel(table_name = pdfs)]
+#[diesel(check_for_backend(diesel::sqlite::Sqlite))]
+pub struct PdfInfo {
+    pub id: PdfId,
+    pub title: String,
+    pub authors: String,
+    pub year: i32,
+    pub journal: String,
+    pub volume: String,
+    pub project_id: Option<ProjectId>,
+    pub doi: Option<String>,
+    pub pmid: Option<String>,
+    pub arxiv_id: Option<String>,
+    pub isbn: Option<String>,
+    pub url: Option<String>,
+    pub pages: Option<String>,
+    pub issue: Option<String>,
+    pub publisher: Option<String>,
+}
+
+impl PdfInfo {
+    pub async fn get_by_id(
+        pdf_id: PdfId,
+        conn: &mut AsyncConn,
+    ) -> Result<PdfInfo, diesel::result::Error> {
+        use crate::schema::pdfs::dsl::*;
+        pdfs.find(pdf_id)
+            .select(PdfInfo::as_select())
+            .first(conn)
+            .await
+    }
+
+    pub async fn list_for_project(
+        project: ProjectId,
+        conn: &mut AsyncConn,
+    ) -> Result<Vec<PdfInfo>, diesel::result::Error> {
+        use crate::schema::pdfs::dsl::*;
+        pdfs.filter(project_id.eq(project))
+            .order(id.asc())
+            .select(PdfInfo::as_select())
+            .load(conn)
+            .await
+    }
+
+    /// The project's placeholder paper for compounds imported from outside the
+    /// literature, created on first use
+    pub async fn external_source(
+        project: ProjectId,
+        conn: &mut AsyncConn,
+    ) -> Result<PdfInfo, MolmineError> {
+        let existing = {
+            use crate::schema::pdfs::dsl::*;
+            pdfs.filter(project_id.eq(project))
+                .filter(title.eq(EXTERNAL_SOURCE_TITLE))
+                .order(id.asc())
+                .select(PdfInfo::as_select())
+                .first(conn)
+                .await
+                .optional()?
+        };
+        if let Some(existing) = existing {
+            return Ok(existing);
+        }
+        let pdf = PdfMetadata {
+            title: EXTERNAL_SOURCE_TITLE.to_string(),
+            year: chrono::Datelike::year(&chrono::Utc::now()),
+            ..Default::default()
+        }
+        .into_new_pdf(Vec::new(), Some(project))
+        .insert(conn)
+        .await?;
+        Ok(PdfInfo::get_by_id(pdf.id, conn).await?)
+    }
+
+    /// Papers of the project that any of the compounds were extracted from
+    pub async fn list_for_compounds(
+        project: ProjectId,
+        compound_ids: &[CompoundId],
+        conn: &mut AsyncConn,
+    ) -> Result<Vec<PdfInfo>, diesel::result::Error> {
+        use crate::schema::{compounds, pdfs};
+        pdfs::table
+            .filter(pdfs::project_id.eq(project))
+            .filter(
+                pdfs::id.eq_any(
+                    compounds::table
+                        .filter(compounds::id.eq_any(compound_ids))
+                        .select(compounds::pdf_id),
+                ),
+            )
+            .order(pdfs::id.asc())
+            .select(PdfInfo::as_select())
+            .load(conn)
+            .await
+    }
+}
+
+/// Title of the placeholder paper that holds compounds from outside the literature
+pub const EXTERNAL_SOURCE_TITLE: &str = "External source";
+
+/// Editable bibliographic fields of a PDF
+#[derive(AsChangeset, Clone, Debug, Default, Serialize, Deserialize)]
+#[diesel(table_name = pdfs)]
+#[diesel(treat_none_as_null = true)]
+pub struct PdfMetadata {
+    pub title: String,
+    pub authors: String,
+    pub year: i32,
+    pub journal: String,
+    pub volume: String,
+    pub doi: Option<String>,
+    pub pmid: Option<String>,
+    pub arxiv_id: Option<String>,
+    pub isbn: Option<String>,
+    pub url: Option<String>,
+    pub pages: Option<String>,
+    pub issue: Option<String>,
+    pub publisher: Option<String>,
+}
+
+impl PdfMetadata {
+    pub fn into_new_pdf(self, data: Vec<u8>, project_id: Option<ProjectId>) -> NewPdf {
+        NewPdf {
+            title: self.title,
+            authors: self.authors,
+            year: self.year,
+            journal: self.journal,
+            volume: self.volume,
+            data,
+            project_id,
+            doi: self.doi,
+            pmid: self.pmid,
+            arxiv_id: self.arxiv_id,
+            isbn: self.isbn,
+            url: self.url,
+            pages: self.pages,
+            issue: self.issue,
+            publisher: self.publisher,
+        }
+    }
+}
+
+/// The hex SHA-256 of a PDF file, used to recognise a file that was already added
+pub fn content_hash(file: &[u8]) -> String {
+    hex::encode(Sha256::digest(file))
 }
 
 /// Used for inserting a new PDF
@@ -67,17 +577,36 @@ pub struct NewPdf {
     pub journal: String,
     pub volume: String,
     pub data: Vec<u8>,
+    pub project_id: Option<ProjectId>,
+    pub doi: Option<String>,
+    pub pmid: Option<String>,
+    pub arxiv_id: Option<String>,
+    pub isbn: Option<String>,
+    pub url: Option<String>,
+    pub pages: Option<String>,
+    pub issue: Option<String>,
+    pub publisher: Option<String>,
 }
 
 impl NewPdf {
-    pub async fn insert(&self, conn: &mut AsyncConn) -> Result<Pdf, diesel::result::Error> {
+    pub async fn insert(&self, conn: &mut AsyncConn) -> Result<Pdf, MolmineError> {
         use crate::schema::pdfs::dsl::*;
         let pdf = conn
-            .transaction::<_, diesel::result::Error, _>(|conn| {
+            .transaction::<_, MolmineError, _>(|conn| {
                 Box::pin(async move {
+                    if let (Some(project), Some(new_doi)) = (self.project_id, &self.doi) {
+                        PdfInfo::ensure_unique_doi(project, new_doi, None, conn).await?;
+                    }
                     diesel::insert_into(pdfs).values(self).execute(conn).await?;
-                    let pdf_id = get_last_rowid(conn).await?;
-                    Pdf::get_by_id(PdfId(pdf_id), conn).await
+                    let pdf_id = PdfId(get_last_rowid(conn).await?);
+                    if !self.data.is_empty() {
+                        diesel::update(pdfs.find(pdf_id))
+                            .set(content_hash.eq(self::content_hash(&self.data)))
+                            .execute(conn)
+                            .await?;
+                    }
+                    Pdf::set_authors(pdf_id, &parse_author_list(&self.authors), conn).await?;
+                    Ok(Pdf::get_by_id(pdf_id, conn).await?)
                 })
             })
             .await?;
@@ -105,14 +634,184 @@ pub struct NewProjectData {
 /// Represents a project in the database
 #[derive(Queryable, Selectable, Identifiable, Debug, Serialize, Deserialize)]
 #[diesel(table_name = projects)]
+#[diesel(check_for_backend(diesel::sqlite::Sqlite))]
 pub struct Project {
     pub id: ProjectId,
     pub name: String,
     pub path: String,
-    pub created_at: NaiveDateTime,
+    pub created_at: Option<NaiveDateTime>,
     pub fields: String,
 }
 
+/// Turns a project name into the directory name used for its files
+pub fn slugify(name: &str) -> String {
+    let slug: String = name
+        .trim()
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() {
+                c.to_ascii_lowercase()
+            } else {
+                '-'
+            }
+        })
+        .collect();
+    slug.split('-')
+        .filter(|part| !part.is_empty())
+        .collect::<Vec<_>>()
+        .join("-")
+}
+
+/// The unique `path` of a project with this name. Names without a letter or digit would
+/// all share the same path, so they are rejected.
+pub fn project_path(name: &str) -> Result<String, MolmineError> {
+    let slug = slugify(name);
+    if slug.is_empty() {
+        return Err(MolmineError::InvalidInput(
+            "Project names need at least one letter or digit".to_string(),
+        ));
+    }
+    Ok(format!("projects/{slug}"))
+}
+
+impl Project {
+    pub async fn get_by_path(
+        project_path: &str,
+        conn: &mut AsyncConn,
+    ) -> Result<Option<Project>, diesel::result::Error> {
+        use crate::schema::projects::dsl::*;
+        projects
+            .filter(path.eq(project_path))
+            .first(conn)
+            .await
+            .optional()
+    }
+
+    pub async fn get_by_id(
+        project_id: ProjectId,
+        conn: &mut AsyncConn,
+    ) -> Result<Project, diesel::result::Error> {
+        use crate::schema::projects::dsl::*;
+        projects.find(project_id).first(conn).await
+    }
+
+    pub async fn list_all(conn: &mut AsyncConn) -> Result<Vec<Project>, diesel::result::Error> {
+        use crate::schema::projects::dsl::*;
+        projects.order(id.asc()).load(conn).await
+    }
+
+    /// Lists the projects a user belongs to, together with their role on each
+    pub async fn list_for_user(
+        user: UserId,
+        conn: &mut AsyncConn,
+    ) -> Result<Vec<(Project, ProjectRole)>, diesel::result::Error> {
+        projects::table
+            .inner_join(project_members::table)
+            .filter(project_members::user_id.eq(user))
+            .order(projects::name.asc())
+            .select((Project::as_select(), project_members::role))
+            .load(conn)
+            .await
+    }
+
+    /// Parses the JSON-encoded chemical data field definitions
+    pub fn field_defs(&self) -> Result<Vec<ProjectField>, serde_json::Error> {
+        serde_json::from_str(&self.fields)
+    }
+
+    pub async fn update(
+        project_id: ProjectId,
+        new_name: &str,
+        new_fields: &str,
+        conn: &mut AsyncConn,
+    ) -> Result<Project, diesel::result::Error> {
+        use crate::schema::projects::dsl::*;
+        diesel::update(projects.find(project_id))
+            .set((name.eq(new_name), fields.eq(new_fields)))
+            .execute(conn)
+            .await?;
+        Project::get_by_id(project_id, conn).await
+    }
+
+    /// Deletes the project with its papers, authors, compounds, recognitions, jobs and
+    /// memberships
+    pub async fn delete(
+        project_id: ProjectId,
+        conn: &mut AsyncConn,
+    ) -> Result<(), diesel::result::Error> {
+        conn.transaction::<_, diesel::result::Error, _>(|conn| {
+            Box::pin(async move {
+                let pdf_ids = pdfs::table
+                    .filter(pdfs::project_id.eq(project_id))
+                    .select(pdfs::id);
+                diesel::delete(
+                    compound_mentions::table.filter(compound_mentions::pdf_id.eq_any(pdf_ids)),
+                )
+                .execute(conn)
+                .await?;
+                diesel::delete(compounds::table.filter(compounds::pdf_id.eq_any(pdf_ids)))
+                    .execute(conn)
+                    .await?;
+                diesel::delete(
+                    markush_sources::table.filter(markush_sources::pdf_id.eq_any(pdf_ids)),
+                )
+                .execute(conn)
+                .await?;
+                diesel::delete(pdf_authors::table.filter(pdf_authors::pdf_id.eq_any(pdf_ids)))
+                    .execute(conn)
+                    .await?;
+                diesel::delete(
+                    structure_regions::table.filter(structure_regions::pdf_id.eq_any(pdf_ids)),
+                )
+                .execute(conn)
+                .await?;
+                diesel::delete(pdfs::table.filter(pdfs::project_id.eq(project_id)))
+                    .execute(conn)
+                    .await?;
+                diesel::delete(authors::table.filter(authors::project_id.eq(project_id)))
+                    .execute(conn)
+                    .await?;
+                diesel::delete(recognitions::table.filter(recognitions::project_id.eq(project_id)))
+                    .execute(conn)
+                    .await?;
+                diesel::delete(jobs::table.filter(jobs::project_id.eq(project_id)))
+                    .execute(conn)
+                    .await?;
+                diesel::update(sessions::table.filter(sessions::active_project_id.eq(project_id)))
+                    .set(sessions::active_project_id.eq(None::<ProjectId>))
+                    .execute(conn)
+                    .await?;
+                diesel::delete(
+                    project_members::table.filter(project_members::project_id.eq(project_id)),
+                )
+                .execute(conn)
+                .await?;
+                diesel::delete(projects::table.find(project_id))
+                    .execute(conn)
+                    .await?;
+                Ok(())
+            })
+        })
+        .await
+    }
+}
+
+/// The kind of value a project field holds
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum FieldType {
+    Number,
+    String,
+}
+
+/// A chemical data field defined on a project, stored as JSON in `projects.fields`
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct ProjectField {
+    pub name: String,
+    #[serde(rename = "type")]
+    pub field_type: FieldType,
+}
+
 /// Used for inserting a new project
 #[derive(Insertable, Debug)]
 #[diesel(table_name = projects)]
@@ -123,6 +822,37 @@ pub struct NewProject {
     pub fields: String,
 }
 
+impl NewProject {
+    /// Inserts the project and makes `owner` its first member
+    pub async fn insert(
+        &self,
+        owner: UserId,
+        conn: &mut AsyncConn,
+    ) -> Result<Project, diesel::result::Error> {
+        use crate::schema::projects::dsl::*;
+        let project = conn
+            .transaction::<_, diesel::result::Error, _>(|conn| {
+                Box::pin(async move {
+                    diesel::insert_into(projects)
+                        .values(self)
+                        .execute(conn)
+                        .await?;
+                    let project_id = ProjectId(get_last_rowid(conn).await?);
+                    ProjectMember {
+                        project_id,
+                        user_id: owner,
+                        role: ProjectRole::Owner,
+                    }
+                    .upsert(conn)
+                    .await?;
+                    Project::get_by_id(project_id, conn).await
+                })
+            })
+            .await?;
+        Ok(project)
+    }
+}
+
 // Association between compounds and PDFs
 #[derive(Associations, Debug)]
 #[diesel(belongs_to(Pdf))]
@@ -140,15 +870,64 @@ mod test {
     #[tokio::test]
     async fn test_insert_pdf() {
         let mut conn = establish_test().await.unwrap();
-        let new_pdf = NewPdf {
+        let new_pdf = PdfMetadata {
             title: "Test PDF".to_string(),
             authors: "Author".to_string(),
             year: 2023,
             journal: "Journal".to_string(),
             volume: "1".to_string(),
-            data: vec![1, 2, 3],
-        };
+            ..Default::default()
+        }
+        .into_new_pdf(vec![1, 2, 3], None);
         let pdf = new_pdf.insert(&mut conn).await.unwrap();
         assert_eq!(pdf.title, "Test PDF");
     }
+
+    #[tokio::test]
+    async fn test_duplicate_doi() {
+        let mut conn = establish_test().await.unwrap();
+        let project = fixtures::insert_project(&mut conn).await;
+        let metadata = PdfMetadata {
+            title: "Paper".to_string(),
+            doi: Some("10.1000/duplicate".to_string()),
+            ..Default::default()
+        };
+        let first = metadata
+            .clone()
+            .into_new_pdf(Vec::new(), Some(project.id))
+            .insert(&mut conn)
+            .await
+            .unwrap();
+        let second = metadata
+            .clone()
+            .into_new_pdf(Vec::new(), Some(project.id))
+            .insert(&mut conn)
+            .await;
+        assert!(matches!(second, Err(MolmineError::InvalidInput(_))));
+
+        let other = PdfMetadata {
+            title: "Other paper".to_string(),
+            ..Default::default()
+        }
+        .into_new_pdf(Vec::new(), Some(project.id))
+        .insert(&mut conn)
+        .await
+        .unwrap();
+        let updated = Pdf::update_metadata(other.id, &metadata, &mut conn).await;
+        assert!(matches!(updated, Err(MolmineError::InvalidInput(_))));
+        // A paper keeps its own DOI when it is edited
+        Pdf::update_metadata(first.id, &metadata, &mut conn)
+            .await
+            .unwrap();
+        fixtures::delete_project(project.id, &mut conn).await;
+    }
+
+    #[test]
+    fn test_project_path() {
+        assert_eq!(
+            project_path(" My Project! ").unwrap(),
+            "projects/my-project"
+        );
+        assert!(project_path("!!!").is_err());
+    }
 }
@@ -0,0 +1,67 @@
+use super::{ProjectId, RecognitionId, RegionId};
+use crate::db::{AsyncConn, get_last_rowid};
+use crate::schema::*;
+use chrono::NaiveDateTime;
+use diesel::prelude::*;
+use diesel_async::AsyncConnection;
+use diesel_async::RunQueryDsl;
+use serde::{Deserialize, Serialize};
+
+/// A structure the server's recognizer read from an image. Compounds refer to it by ID, so
+/// what was recognized is never taken from the browser.
+#[derive(Queryable, Selectable, Identifiable, Debug, Serialize, Deserialize)]
+#[diesel(table_name = recognitions)]
+#[diesel(check_for_backend(diesel::sqlite::Sqlite))]
+pub struct RecognitionRecord {
+    pub id: RecognitionId,
+    pub project_id: ProjectId,
+    /// The region the image was rendered from, if it came from a paper
+    pub region_id: Option<RegionId>,
+    pub backend: String,
+    /// Canonical SMILES of the recognized structure
+    pub smiles: String,
+    pub inchi: Option<String>,
+    pub confidence: Option<f64>,
+    pub created_at: NaiveDateTime,
+}
+
+impl RecognitionRecord {
+    pub async fn get_by_id(
+        recognition_id: RecognitionId,
+        conn: &mut AsyncConn,
+    ) -> Result<RecognitionRecord, diesel::result::Error> {
+        use crate::schema::recognitions::dsl::*;
+        recognitions.find(recognition_id).first(conn).await
+    }
+}
+
+/// Used for recording a recognition
+#[derive(Insertable, Debug)]
+#[diesel(table_name = recognitions)]
+pub struct NewRecognitionRecord {
+    pub project_id: ProjectId,
+    pub region_id: Option<RegionId>,
+    pub backend: String,
+    pub smiles: String,
+    pub inchi: Option<String>,
+    pub confidence: Option<f64>,
+}
+
+impl NewRecognitionRecord {
+    pub async fn insert(
+        &self,
+        conn: &mut AsyncConn,
+    ) -> Result<RecognitionRecord, diesel::result::Error> {
+        conn.transaction::<_, diesel::result::Error, _>(|conn| {
+            Box::pin(async move {
+                diesel::insert_into(recognitions::table)
+                    .values(self)
+                    .execute(conn)
+                    .await?;
+                let recognition_id = RecognitionId(get_last_rowid(conn).await?);
+                RecognitionRecord::get_by_id(recognition_id, conn).await
+            })
+        })
+        .await
+    }
+}
@@ -0,0 +1,131 @@
+use super::{PdfId, RegionId};
+use crate::db::{AsyncConn, get_last_rowid};
+use crate::schema::*;
+use chrono::NaiveDateTime;
+use diesel::prelude::*;
+use diesel_async::AsyncConnection;
+use diesel_async::RunQueryDsl;
+use diesel_derive_enum::DbEnum;
+use serde::{Deserialize, Serialize};
+
+/// Where a proposed structure region is in review
+#[derive(DbEnum, Copy, Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum RegionStatus {
+    /// Proposed by detection and waiting for the user
+    Pending,
+    /// Confirmed as a structure to extract
+    Accepted,
+    /// Not a structure; detection won't propose it again
+    Rejected,
+}
+
+/// A box around a chemical structure drawing on a page of a paper, in PDF points from
+/// the top left of the page
+#[derive(Queryable, Selectable, Identifiable, Debug, Serialize, Deserialize)]
+#[diesel(table_name = structure_regions)]
+#[diesel(check_for_backend(diesel::sqlite::Sqlite))]
+pub struct StructureRegion {
+    pub id: RegionId,
+    pub pdf_id: PdfId,
+    pub page_number: i32,
+    pub x: f64,
+    pub y: f64,
+    pub width: f64,
+    pub height: f64,
+    /// How much the region looks like a structure drawing, from 0 to 1
+    pub score: f64,
+    pub status: RegionStatus,
+    pub created_at: NaiveDateTime,
+}
+
+impl StructureRegion {
+    pub async fn get_by_id(
+        region_id: RegionId,
+        conn: &mut AsyncConn,
+    ) -> Result<StructureRegion, diesel::result::Error> {
+        use crate::schema::structure_regions::dsl::*;
+        structure_regions.find(region_id).first(conn).await
+    }
+
+    /// The paper's regions in reading order, optionally only those with one status
+    pub async fn list_for_pdf(
+        pdf: PdfId,
+        with_status: Option<RegionStatus>,
+        conn: &mut AsyncConn,
+    ) -> Result<Vec<StructureRegion>, diesel::result::Error> {
+        use crate::schema::structure_regions::dsl::*;
+        let mut query = structure_regions.filter(pdf_id.eq(pdf)).into_boxed();
+        if let Some(with_status) = with_status {
+            query = query.filter(status.eq(with_status));
+        }
+        query
+            .order((page_number.asc(), y.asc(), x.asc()))
+            .load(conn)
+            .await
+    }
+
+    pub async fn set_status(
+        region_id: RegionId,
+        new_status: RegionStatus,
+        conn: &mut AsyncConn,
+    ) -> Result<StructureRegion, diesel::result::Error> {
+        use crate::schema::structure_regions::dsl::*;
+        diesel::update(structure_regions.find(region_id))
+            .set(status.eq(new_status))
+            .execute(conn)
+            .await?;
+        StructureRegion::get_by_id(region_id, conn).await
+    }
+
+    /// Replaces the paper's pending regions with a new set of proposals, leaving the
+    /// ones the user has already reviewed alone
+    pub async fn replace_pending(
+        pdf: PdfId,
+        regions: &[NewStructureRegion],
+        conn: &mut AsyncConn,
+    ) -> Result<Vec<StructureRegion>, diesel::result::Error> {
+        conn.transaction::<_, diesel::result::Error, _>(|conn| {
+            Box::pin(async move {
+                use crate::schema::structure_regions::dsl::*;
+                let pending = structure_regions
+                    .filter(pdf_id.eq(pdf))
+                    .filter(status.eq(RegionStatus::Pending));
+                diesel::update(
+                    recognitions::table.filter(
+                        recognitions::region_id.eq_any(pending.clone().select(id.nullable())),
+                    ),
+                )
+                .set(recognitions::region_id.eq(None::<RegionId>))
+                .execute(conn)
+                .await?;
+                diesel::delete(pending).execute(conn).await?;
+                let mut inserted = Vec::with_capacity(regions.len());
+                for region in regions {
+                    diesel::insert_into(structure_regions)
+                        .values(region)
+                        .execute(conn)
+                        .await?;
+                    let region_id = RegionId(get_last_rowid(conn).await?);
+                    inserted.push(StructureRegion::get_by_id(region_id, conn).await?);
+                }
+                Ok(inserted)
+            })
+        })
+        .await
+    }
+}
+
+/// Used for inserting a proposed region
+#[derive(Insertable, Debug)]
+#[diesel(table_name = structure_regions)]
+pub struct NewStructureRegion {
+    pub pdf_id: PdfId,
+    pub page_number: i32,
+    pub x: f64,
+    pub y: f64,
+    pub width: f64,
+    pub height: f64,
+    pub score: f64,
+    pub status: RegionStatus,
+}
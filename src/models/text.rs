@@ -0,0 +1,240 @@
+use super::{Pdf, PdfId, PdfInfo, ProjectId};
+use crate::db::AsyncConn;
+use crate::schema::*;
+use diesel::prelude::*;
+use diesel::sql_types::{Integer, Text};
+use diesel_async::AsyncConnection;
+use diesel_async::RunQueryDsl;
+use serde::{Deserialize, Serialize};
+
+/// Marks the start and end of a matched term in snippets from the index
+const MATCH_START: char = '\u{2}';
+const MATCH_END: char = '\u{3}';
+
+/// A page of a paper that matches a full-text search
+#[derive(Debug, Serialize, Deserialize)]
+pub struct PageMatch {
+    pub page_number: i32,
+    /// The matching passage as HTML, the search terms wrapped in `<mark>`
+    pub snippet: String,
+}
+
+/// A paper that matches a full-text search, best match first
+#[derive(Debug, Serialize, Deserialize)]
+pub struct TextSearchResult {
+    pub pdf: PdfInfo,
+    /// Highlighted title, authors or journal when the paper's details match
+    pub details: Option<String>,
+    pub pages: Vec<PageMatch>,
+}
+
+impl Pdf {
+    /// Replaces the paper's pages in the full-text index, one entry per page in order
+    pub async fn set_page_texts(
+        pdf_id: PdfId,
+        texts: &[String],
+        conn: &mut AsyncConn,
+    ) -> Result<(), diesel::result::Error> {
+        conn.transaction::<_, diesel::result::Error, _>(|conn| {
+            Box::pin(async move {
+                diesel::sql_query("DELETE FROM pdf_text WHERE pdf_id = ? AND page_number > 0")
+                    .bind::<Integer, _>(pdf_id)
+                    .execute(conn)
+                    .await?;
+                for (index, text) in texts.iter().enumerate() {
+                    diesel::sql_query(
+                        "INSERT INTO pdf_text (title, authors, journal, text, pdf_id, page_number) \
+                         VALUES ('', '', '', ?, ?, ?)",
+                    )
+                    .bind::<Text, _>(text)
+                    .bind::<Integer, _>(pdf_id)
+                    .bind::<Integer, _>(index as i32 + 1)
+                    .execute(conn)
+                    .await?;
+                }
+                diesel::update(pdfs::table.find(pdf_id))
+                    .set(pdfs::page_count.eq(texts.len() as i32))
+                    .execute(conn)
+                    .await?;
+                Ok(())
+            })
+        })
+        .await
+    }
+
+    /// The extracted text of each of the paper's pages as `(page_number, text)`, empty
+    /// until the file is indexed
+    pub async fn page_texts(
+        pdf_id: PdfId,
+        conn: &mut AsyncConn,
+    ) -> Result<Vec<(i32, String)>, diesel::result::Error> {
+        #[derive(QueryableByName)]
+        struct PageText {
+            #[diesel(sql_type = Integer)]
+            page_number: i32,
+            #[diesel(sql_type = Text)]
+            text: String,
+        }
+        let pages: Vec<PageText> = diesel::sql_query(
+            "SELECT page_number, text FROM pdf_text \
+             WHERE pdf_id = ? AND page_number > 0 ORDER BY page_number",
+        )
+        .bind::<Integer, _>(pdf_id)
+        .load(conn)
+        .await?;
+        Ok(pages
+            .into_iter()
+            .map(|page| (page.page_number, page.text))
+            .collect())
+    }
+
+    /// Papers in the project with a file whose text has not been extracted yet
+    pub async fn list_unindexed(
+        project: ProjectId,
+        conn: &mut AsyncConn,
+    ) -> Result<Vec<PdfId>, diesel::result::Error> {
+        use crate::schema::pdfs::dsl::*;
+        pdfs.filter(project_id.eq(project))
+            .filter(page_count.is_null())
+            .filter(content_hash.is_not_null())
+            .order(id.asc())
+            .select(id)
+            .load(conn)
+            .await
+    }
+}
+
+/// Turns what the user typed into an FTS5 query that finds papers containing every
+/// word. Double-quoted text is kept together as a phrase and a trailing `*` matches
+/// any word starting with what precedes it; everything else is taken literally, so
+/// input can never be an invalid query.
+pub fn fts_query(input: &str) -> Option<String> {
+    let mut terms = Vec::new();
+    for (index, part) in input.split('"').enumerate() {
+        // Odd parts were inside quotes
+        let words: Vec<&str> = if index % 2 == 1 {
+            vec![part]
+        } else {
+            part.split_whitespace().collect()
+        };
+        for word in words {
+            let (word, prefix) = match word.strip_suffix('*') {
+                Some(word) if index % 2 == 0 => (word, true),
+                _ => (word, false),
+            };
+            if word.trim().is_empty() {
+                continue;
+            }
+            let term = format!("\"{}\"", word.trim());
+            terms.push(if prefix { term + "*" } else { term });
+        }
+    }
+    (!terms.is_empty()).then(|| terms.join(" "))
+}
+
+/// Escapes a snippet from the index for HTML and turns its match markers into `<mark>`
+fn highlight(snippet: &str) -> String {
+    let mut html = String::with_capacity(snippet.len() + 16);
+    for c in snippet.chars() {
+        match c {
+            MATCH_START => html.push_str("<mark>"),
+            MATCH_END => html.push_str("</mark>"),
+            '&' => html.push_str("&amp;"),
+            '<' => html.push_str("&lt;"),
+            '>' => html.push_str("&gt;"),
+            '"' => html.push_str("&quot;"),
+            c => html.push(c),
+        }
+    }
+    html
+}
+
+/// Searches the text and details of the project's papers, returning up to `limit`
+/// matching pages grouped by paper
+pub async fn search_text(
+    project: ProjectId,
+    query: &str,
+    limit: i64,
+    conn: &mut AsyncConn,
+) -> Result<Vec<TextSearchResult>, diesel::result::Error> {
+    #[derive(QueryableByName)]
+    struct Hit {
+        #[diesel(sql_type = Integer)]
+        pdf_id: PdfId,
+        #[diesel(sql_type = Integer)]
+        page_number: i32,
+        #[diesel(sql_type = Text)]
+        snippet: String,
+    }
+    let Some(query) = fts_query(query) else {
+        return Ok(Vec::new());
+    };
+    // Matches in the title count for most, then authors and journal, then page text
+    let hits: Vec<Hit> = diesel::sql_query(format!(
+        "SELECT pdf_text.pdf_id AS pdf_id, pdf_text.page_number AS page_number, \
+         snippet(pdf_text, -1, '{MATCH_START}', '{MATCH_END}', '…', 16) AS snippet \
+         FROM pdf_text JOIN pdfs ON pdfs.id = pdf_text.pdf_id \
+         WHERE pdf_text MATCH ? AND pdfs.project_id = ? \
+         ORDER BY bm25(pdf_text, 10.0, 5.0, 2.0, 1.0) LIMIT ?"
+    ))
+    .bind::<Text, _>(query)
+    .bind::<Integer, _>(project)
+    .bind::<diesel::sql_types::BigInt, _>(limit)
+    .load(conn)
+    .await?;
+
+    let mut results: Vec<TextSearchResult> = Vec::new();
+    for hit in hits {
+        let index = match results
+            .iter()
+            .position(|result| result.pdf.id == hit.pdf_id)
+        {
+            Some(index) => index,
+            None => {
+                results.push(TextSearchResult {
+                    pdf: PdfInfo::get_by_id(hit.pdf_id, conn).await?,
+                    details: None,
+                    pages: Vec::new(),
+                });
+                results.len() - 1
+            }
+        };
+        let snippet = highlight(&hit.snippet);
+        if hit.page_number == 0 {
+            results[index].details = Some(snippet);
+        } else {
+            results[index].pages.push(PageMatch {
+                page_number: hit.page_number,
+                snippet,
+            });
+        }
+    }
+    Ok(results)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_fts_query() {
+        assert_eq!(
+            fts_query("kinase  inhibitor"),
+            Some("\"kinase\" \"inhibitor\"".to_string())
+        );
+        assert_eq!(
+            fts_query("\"binding affinity\" IC50 pyrid*"),
+            Some("\"binding affinity\" \"IC50\" \"pyrid\"*".to_string())
+        );
+        // Operators and stray quotes are searched for as text
+        assert_eq!(
+            fts_query("NOT (a OR \"b"),
+            Some("\"NOT\" \"(a\" \"OR\" \"b\"".to_string())
+        );
+        assert_eq!(fts_query("  * \"\" "), None);
+        assert_eq!(
+            highlight("a \u{2}<b>\u{3} & c"),
+            "a <mark>&lt;b&gt;</mark> &amp; c"
+        );
+    }
+}
@@ -0,0 +1,145 @@
+use super::{ApiTokenId, ProjectId, User, UserId};
+use crate::db::{AsyncConn, get_last_rowid};
+use crate::schema::*;
+use chrono::NaiveDateTime;
+use diesel::prelude::*;
+use diesel_async::AsyncConnection;
+use diesel_async::RunQueryDsl;
+use serde::{Deserialize, Serialize};
+
+/// A personal API token for scripted access. Only the SHA-256 of the secret is stored.
+#[derive(Queryable, Selectable, Identifiable, Debug, Serialize, Deserialize)]
+#[diesel(table_name = api_tokens)]
+#[diesel(check_for_backend(diesel::sqlite::Sqlite))]
+pub struct ApiToken {
+    pub id: ApiTokenId,
+    pub user_id: UserId,
+    pub name: String,
+    #[serde(skip)]
+    pub token_hash: String,
+    pub can_write: bool,
+    pub created_at: NaiveDateTime,
+    pub expires_at: Option<NaiveDateTime>,
+    pub last_used_at: Option<NaiveDateTime>,
+    pub revoked_at: Option<NaiveDateTime>,
+}
+
+impl ApiToken {
+    pub async fn get_by_id(
+        token_id: ApiTokenId,
+        conn: &mut AsyncConn,
+    ) -> Result<ApiToken, diesel::result::Error> {
+        use crate::schema::api_tokens::dsl::*;
+        api_tokens.find(token_id).first(conn).await
+    }
+
+    /// Looks up an unrevoked, unexpired token together with its owner
+    pub async fn find_valid(
+        hash: &str,
+        conn: &mut AsyncConn,
+    ) -> Result<Option<(ApiToken, User)>, diesel::result::Error> {
+        let now = chrono::Utc::now().naive_utc();
+        api_tokens::table
+            .inner_join(users::table)
+            .filter(api_tokens::token_hash.eq(hash))
+            .filter(api_tokens::revoked_at.is_null())
+            .filter(
+                api_tokens::expires_at
+                    .is_null()
+                    .or(api_tokens::expires_at.gt(now)),
+            )
+            .select((ApiToken::as_select(), User::as_select()))
+            .first(conn)
+            .await
+            .optional()
+    }
+
+    pub async fn list_for_user(
+        user: UserId,
+        conn: &mut AsyncConn,
+    ) -> Result<Vec<ApiToken>, diesel::result::Error> {
+        use crate::schema::api_tokens::dsl::*;
+        api_tokens
+            .filter(user_id.eq(user))
+            .order(created_at.desc())
+            .load(conn)
+            .await
+    }
+
+    /// The projects this token may access
+    pub async fn projects(
+        &self,
+        conn: &mut AsyncConn,
+    ) -> Result<Vec<ProjectId>, diesel::result::Error> {
+        use crate::schema::api_token_projects::dsl::*;
+        api_token_projects
+            .filter(token_id.eq(self.id))
+            .select(project_id)
+            .load(conn)
+            .await
+    }
+
+    pub async fn touch(&self, conn: &mut AsyncConn) -> Result<(), diesel::result::Error> {
+        use crate::schema::api_tokens::dsl::*;
+        diesel::update(api_tokens.find(self.id))
+            .set(last_used_at.eq(chrono::Utc::now().naive_utc()))
+            .execute(conn)
+            .await?;
+        Ok(())
+    }
+
+    pub async fn revoke(
+        token_id: ApiTokenId,
+        conn: &mut AsyncConn,
+    ) -> Result<ApiToken, diesel::result::Error> {
+        use crate::schema::api_tokens::dsl::*;
+        diesel::update(api_tokens.find(token_id).filter(revoked_at.is_null()))
+            .set(revoked_at.eq(chrono::Utc::now().naive_utc()))
+            .execute(conn)
+            .await?;
+        ApiToken::get_by_id(token_id, conn).await
+    }
+}
+
+/// Used for inserting a new API token
+#[derive(Insertable, Debug)]
+#[diesel(table_name = api_tokens)]
+pub struct NewApiToken {
+    pub user_id: UserId,
+    pub name: String,
+    pub token_hash: String,
+    pub can_write: bool,
+    pub expires_at: Option<NaiveDateTime>,
+}
+
+impl NewApiToken {
+    /// Inserts the token and the projects it is scoped to
+    pub async fn insert(
+        &self,
+        scope: &[ProjectId],
+        conn: &mut AsyncConn,
+    ) -> Result<ApiToken, diesel::result::Error> {
+        let token = conn
+            .transaction::<_, diesel::result::Error, _>(|conn| {
+                Box::pin(async move {
+                    diesel::insert_into(api_tokens::table)
+                        .values(self)
+                        .execute(conn)
+                        .await?;
+                    let token_id = ApiTokenId(get_last_rowid(conn).await?);
+                    for project in scope {
+                        diesel::insert_into(api_token_projects::table)
+                            .values((
+                                api_token_projects::token_id.eq(token_id),
+                                api_token_projects::project_id.eq(*project),
+                            ))
+                            .execute(conn)
+                            .await?;
+                    }
+                    ApiToken::get_by_id(token_id, conn).await
+                })
+            })
+            .await?;
+        Ok(token)
+    }
+}
@@ -0,0 +1,268 @@
+use super::{ProjectId, UserId};
+use crate::db::{AsyncConn, get_last_rowid};
+use crate::schema::*;
+use chrono::NaiveDateTime;
+use diesel::prelude::*;
+use diesel_async::AsyncConnection;
+use diesel_async::RunQueryDsl;
+use diesel_derive_enum::DbEnum;
+use serde::{Deserialize, Serialize};
+
+/// Represents a local user account
+#[derive(Queryable, Selectable, Identifiable, Debug, Serialize, Deserialize)]
+#[diesel(table_name = users)]
+#[diesel(check_for_backend(diesel::sqlite::Sqlite))]
+pub struct User {
+    pub id: UserId,
+    pub username: String,
+    #[serde(skip)]
+    pub password_hash: String,
+    pub created_at: NaiveDateTime,
+}
+
+impl User {
+    pub async fn get_by_id(
+        user_id: UserId,
+        conn: &mut AsyncConn,
+    ) -> Result<User, diesel::result::Error> {
+        use crate::schema::users::dsl::*;
+        users.find(user_id).first(conn).await
+    }
+
+    pub async fn get_by_username(
+        name: &str,
+        conn: &mut AsyncConn,
+    ) -> Result<Option<User>, diesel::result::Error> {
+        use crate::schema::users::dsl::*;
+        users.filter(username.eq(name)).first(conn).await.optional()
+    }
+
+    pub async fn count(conn: &mut AsyncConn) -> Result<i64, diesel::result::Error> {
+        use crate::schema::users::dsl::*;
+        users.count().get_result(conn).await
+    }
+}
+
+/// Used for inserting a new user
+#[derive(Insertable, Debug)]
+#[diesel(table_name = users)]
+pub struct NewUser {
+    pub username: String,
+    pub password_hash: String,
+}
+
+impl NewUser {
+    pub async fn insert(&self, conn: &mut AsyncConn) -> Result<User, diesel::result::Error> {
+        use crate::schema::users::dsl::*;
+        let user = conn
+            .transaction::<_, diesel::result::Error, _>(|conn| {
+                Box::pin(async move {
+                    diesel::insert_into(users)
+                        .values(self)
+                        .execute(conn)
+                        .await?;
+                    let user_id = get_last_rowid(conn).await?;
+                    User::get_by_id(UserId(user_id), conn).await
+                })
+            })
+            .await?;
+        Ok(user)
+    }
+}
+
+/// A signed-in browser session. Only the SHA-256 of the cookie token is stored.
+#[derive(Queryable, Selectable, Identifiable, Debug)]
+#[diesel(table_name = sessions)]
+#[diesel(primary_key(token_hash))]
+#[diesel(check_for_backend(diesel::sqlite::Sqlite))]
+pub struct Session {
+    pub token_hash: String,
+    pub user_id: UserId,
+    pub active_project_id: Option<ProjectId>,
+    pub created_at: NaiveDateTime,
+    pub expires_at: NaiveDateTime,
+}
+
+impl Session {
+    /// Looks up an unexpired session together with its user
+    pub async fn find_valid(
+        hash: &str,
+        conn: &mut AsyncConn,
+    ) -> Result<Option<(Session, User)>, diesel::result::Error> {
+        let now = chrono::Utc::now().naive_utc();
+        sessions::table
+            .inner_join(users::table)
+            .filter(sessions::token_hash.eq(hash))
+            .filter(sessions::expires_at.gt(now))
+            .select((Session::as_select(), User::as_select()))
+            .first(conn)
+            .await
+            .optional()
+    }
+
+    pub async fn set_active_project(
+        &self,
+        project: Option<ProjectId>,
+        conn: &mut AsyncConn,
+    ) -> Result<(), diesel::result::Error> {
+        use crate::schema::sessions::dsl::*;
+        diesel::update(sessions.find(&self.token_hash))
+            .set(active_project_id.eq(project))
+            .execute(conn)
+            .await?;
+        Ok(())
+    }
+
+    pub async fn delete(hash: &str, conn: &mut AsyncConn) -> Result<(), diesel::result::Error> {
+        use crate::schema::sessions::dsl::*;
+        diesel::delete(sessions.find(hash)).execute(conn).await?;
+        Ok(())
+    }
+
+    pub async fn delete_expired(conn: &mut AsyncConn) -> Result<usize, diesel::result::Error> {
+        use crate::schema::sessions::dsl::*;
+        let now = chrono::Utc::now().naive_utc();
+        diesel::delete(sessions.filter(expires_at.le(now)))
+            .execute(conn)
+            .await
+    }
+}
+
+/// Used for inserting a new session
+#[derive(Insertable, Debug)]
+#[diesel(table_name = sessions)]
+pub struct NewSession {
+    pub token_hash: String,
+    pub user_id: UserId,
+    pub expires_at: NaiveDateTime,
+}
+
+impl NewSession {
+    pub async fn insert(&self, conn: &mut AsyncConn) -> Result<(), diesel::result::Error> {
+        use crate::schema::sessions::dsl::*;
+        diesel::insert_into(sessions)
+            .values(self)
+            .execute(conn)
+            .await?;
+        Ok(())
+    }
+}
+
+/// A user's role on a project. Variants are ordered from least to most privileged.
+#[derive(DbEnum, Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ProjectRole {
+    Viewer,
+    Editor,
+    Owner,
+}
+
+impl ProjectRole {
+    /// Whether this role grants everything `required` does
+    pub fn allows(self, required: ProjectRole) -> bool {
+        self >= required
+    }
+}
+
+/// Membership of a user in a project
+#[derive(Queryable, Selectable, Insertable, Debug, Serialize, Deserialize)]
+#[diesel(table_name = project_members)]
+#[diesel(check_for_backend(diesel::sqlite::Sqlite))]
+pub struct ProjectMember {
+    pub project_id: ProjectId,
+    pub user_id: UserId,
+    pub role: ProjectRole,
+}
+
+impl ProjectMember {
+    pub async fn role_of(
+        project: ProjectId,
+        user: UserId,
+        conn: &mut AsyncConn,
+    ) -> Result<Option<ProjectRole>, diesel::result::Error> {
+        use crate::schema::project_members::dsl::*;
+        project_members
+            .find((project, user))
+            .select(role)
+            .first(conn)
+            .await
+            .optional()
+    }
+
+    /// Adds the member, or changes their role if they already belong to the project
+    pub async fn upsert(&self, conn: &mut AsyncConn) -> Result<(), diesel::result::Error> {
+        use crate::schema::project_members::dsl::*;
+        diesel::insert_into(project_members)
+            .values(self)
+            .on_conflict((project_id, user_id))
+            .do_update()
+            .set(role.eq(self.role))
+            .execute(conn)
+            .await?;
+        Ok(())
+    }
+
+    /// Makes the user the owner of every project without members, such as those from
+    /// before projects had members
+    pub async fn adopt_memberless(
+        user: UserId,
+        conn: &mut AsyncConn,
+    ) -> Result<usize, diesel::result::Error> {
+        let memberless: Vec<ProjectId> = projects::table
+            .filter(diesel::dsl::not(diesel::dsl::exists(
+                project_members::table.filter(project_members::project_id.eq(projects::id)),
+            )))
+            .select(projects::id)
+            .load(conn)
+            .await?;
+        for &project_id in &memberless {
+            diesel::insert_into(project_members::table)
+                .values(ProjectMember {
+                    project_id,
+                    user_id: user,
+                    role: ProjectRole::Owner,
+                })
+                .execute(conn)
+                .await?;
+        }
+        Ok(memberless.len())
+    }
+
+    pub async fn remove(
+        project: ProjectId,
+        user: UserId,
+        conn: &mut AsyncConn,
+    ) -> Result<(), diesel::result::Error> {
+        use crate::schema::project_members::dsl::*;
+        diesel::delete(project_members.find((project, user)))
+            .execute(conn)
+            .await?;
+        Ok(())
+    }
+
+    pub async fn list_for_project(
+        project: ProjectId,
+        conn: &mut AsyncConn,
+    ) -> Result<Vec<(ProjectMember, User)>, diesel::result::Error> {
+        project_members::table
+            .inner_join(users::table)
+            .filter(project_members::project_id.eq(project))
+            .order(users::username.asc())
+            .select((ProjectMember::as_select(), User::as_select()))
+            .load(conn)
+            .await
+    }
+
+    pub async fn count_owners(
+        project: ProjectId,
+        conn: &mut AsyncConn,
+    ) -> Result<i64, diesel::result::Error> {
+        use crate::schema::project_members::dsl::*;
+        project_members
+            .filter(project_id.eq(project))
+            .filter(role.eq(ProjectRole::Owner))
+            .count()
+            .get_result(conn)
+            .await
+    }
+}
@@ -0,0 +1,523 @@
+//! Optical chemical structure recognition, turning a picture of a structure drawing
+//! into SMILES.
+//!
+//! Recognizers are backends behind [`OcsrBackend`], chosen when the server starts with
+//! `MOLMINE_OCSR_BACKEND`:
+//!
+//! - `http` (the default) posts the image to a recognition service at
+//!   `MOLMINE_OCSR_URL`
+//! - `command` runs a local tool such as OSRA, given as `MOLMINE_OCSR_COMMAND` with
+//!   `{image}` standing for the path of the image file
+//! - `mock` answers every image with `MOLMINE_OCSR_MOCK_SMILES`, for tests and demos
+
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+use std::sync::OnceLock;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::Duration;
+
+use axum::async_trait;
+use base64::Engine;
+use serde::{Deserialize, Serialize};
+use serde_json::{Value, json};
+
+use crate::db::AsyncConn;
+use crate::error::MolmineError;
+use crate::models::*;
+use crate::pdf::{Region, RenderRequest, render_cached};
+use crate::rdkit::{canonical_smiles, molblock_to_smiles, smiles_to_molblock};
+
+pub const DEFAULT_OCSR_URL: &str = "http://localhost:5000/api/recognize-structure";
+/// OSRA printing canonical SMILES followed by its confidence estimate
+pub const DEFAULT_OCSR_COMMAND: &str = "osra -f can -p {image}";
+pub const DEFAULT_MOCK_SMILES: &str = "c1ccccc1";
+/// How long a recognizer may spend on one image
+const OCSR_TIMEOUT: Duration = Duration::from_secs(120);
+/// Recognizers read small labels and bond details better from sharper images
+pub const RECOGNITION_DPI: u32 = 300;
+
+/// What a recognizer made of an image
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct Recognition {
+    pub smiles: String,
+    #[serde(default)]
+    pub molblock: Option<String>,
+    #[serde(default)]
+    pub inchi: Option<String>,
+    /// From 0 to 1, for recognizers that estimate one
+    #[serde(default)]
+    pub confidence: Option<f64>,
+}
+
+/// A structure recognizer, taking a PNG of a single structure drawing
+#[async_trait]
+pub trait OcsrBackend: Send + Sync {
+    /// Short name reported alongside results
+    fn name(&self) -> &'static str;
+
+    async fn recognize(&self, png: &[u8]) -> Result<Recognition, MolmineError>;
+}
+
+fn ocsr_error(err: impl std::fmt::Display) -> MolmineError {
+    MolmineError::OcsrError(err.to_string())
+}
+
+/// A recognition service taking `{ "image": "data:image/png;base64,..." }` and answering
+/// with the fields of a [`Recognition`], or `{ "error": ... }`
+pub struct HttpBackend {
+    url: String,
+    client: reqwest::Client,
+}
+
+impl HttpBackend {
+    pub fn new(url: &str) -> Result<Self, MolmineError> {
+        let client = reqwest::Client::builder()
+            .timeout(OCSR_TIMEOUT)
+            .build()
+            .map_err(ocsr_error)?;
+        Ok(HttpBackend {
+            url: url.to_string(),
+            client,
+        })
+    }
+}
+
+#[async_trait]
+impl OcsrBackend for HttpBackend {
+    fn name(&self) -> &'static str {
+        "http"
+    }
+
+    async fn recognize(&self, png: &[u8]) -> Result<Recognition, MolmineError> {
+        let image = format!(
+            "data:image/png;base64,{}",
+            base64::engine::general_purpose::STANDARD.encode(png)
+        );
+        let response = self
+            .client
+            .post(&self.url)
+            .json(&json!({ "image": image }))
+            .send()
+            .await
+            .map_err(ocsr_error)?;
+        let status = response.status();
+        let body: Value = response.json().await.map_err(ocsr_error)?;
+        if !status.is_success() {
+            return Err(MolmineError::OcsrError(
+                body["error"]
+                    .as_str()
+                    .map_or_else(|| status.to_string(), str::to_string),
+            ));
+        }
+        serde_json::from_value(body)
+            .map_err(|e| MolmineError::OcsrError(format!("Unexpected response: {e}")))
+    }
+}
+
+/// A command-line recognizer that prints the SMILES it found, optionally followed by a
+/// confidence, on its first line of output
+pub struct CommandBackend {
+    program: String,
+    args: Vec<String>,
+}
+
+impl CommandBackend {
+    /// Reads a command line such as `osra -f can {image}`. Without an `{image}`
+    /// argument the image path is passed last.
+    pub fn parse(command: &str) -> Result<Self, MolmineError> {
+        let mut words = command.split_whitespace().map(str::to_string);
+        let program = words
+            .next()
+            .ok_or_else(|| MolmineError::InvalidInput("The OCSR command is empty".to_string()))?;
+        let mut args: Vec<String> = words.collect();
+        if !args.iter().any(|arg| arg.contains("{image}")) {
+            args.push("{image}".to_string());
+        }
+        Ok(CommandBackend { program, args })
+    }
+}
+
+/// The first structure in a recognizer's output, with the confidence printed after it
+/// if there is one
+pub fn parse_command_output(output: &str) -> Option<Recognition> {
+    let line = output.lines().find(|line| !line.trim().is_empty())?;
+    let mut words = line.split_whitespace();
+    let smiles = words.next()?.to_string();
+    let confidence = words
+        .next()
+        .and_then(|word| word.parse::<f64>().ok())
+        .filter(|confidence| confidence.is_finite());
+    Some(Recognition {
+        smiles,
+        confidence,
+        ..Default::default()
+    })
+}
+
+#[async_trait]
+impl OcsrBackend for CommandBackend {
+    fn name(&self) -> &'static str {
+        "command"
+    }
+
+    async fn recognize(&self, png: &[u8]) -> Result<Recognition, MolmineError> {
+        static NEXT_FILE: AtomicUsize = AtomicUsize::new(0);
+        let path: PathBuf = std::env::temp_dir().join(format!(
+            "molmine-ocsr-{}-{}.png",
+            std::process::id(),
+            NEXT_FILE.fetch_add(1, Ordering::Relaxed)
+        ));
+        tokio::fs::write(&path, png).await?;
+        let args: Vec<String> = self
+            .args
+            .iter()
+            .map(|arg| arg.replace("{image}", &path.to_string_lossy()))
+            .collect();
+        let output = tokio::time::timeout(
+            OCSR_TIMEOUT,
+            tokio::process::Command::new(&self.program)
+                .args(&args)
+                .kill_on_drop(true)
+                .output(),
+        )
+        .await;
+        let _ = tokio::fs::remove_file(&path).await;
+
+        let output = output
+            .map_err(|_| MolmineError::OcsrError(format!("{} timed out", self.program)))?
+            .map_err(|e| MolmineError::OcsrError(format!("Could not run {}: {e}", self.program)))?;
+        if !output.status.success() {
+            return Err(MolmineError::OcsrError(format!(
+                "{} failed with {}: {}",
+                self.program,
+                output.status,
+                String::from_utf8_lossy(&output.stderr).trim()
+            )));
+        }
+        parse_command_output(&String::from_utf8_lossy(&output.stdout))
+            .ok_or_else(|| MolmineError::OcsrError("No structure was recognized".to_string()))
+    }
+}
+
+/// Answers every image with the same structure and counts the calls
+pub struct MockBackend {
+    recognition: Recognition,
+    calls: AtomicUsize,
+}
+
+impl MockBackend {
+    pub fn new(smiles: &str, confidence: Option<f64>) -> Self {
+        MockBackend {
+            recognition: Recognition {
+                smiles: smiles.to_string(),
+                confidence,
+                ..Default::default()
+            },
+            calls: AtomicUsize::new(0),
+        }
+    }
+
+    pub fn calls(&self) -> usize {
+        self.calls.load(Ordering::Relaxed)
+    }
+}
+
+#[async_trait]
+impl OcsrBackend for MockBackend {
+    fn name(&self) -> &'static str {
+        "mock"
+    }
+
+    async fn recognize(&self, png: &[u8]) -> Result<Recognition, MolmineError> {
+        self.calls.fetch_add(1, Ordering::Relaxed);
+        if png.is_empty() {
+            return Err(MolmineError::OcsrError("The image is empty".to_string()));
+        }
+        Ok(self.recognition.clone())
+    }
+}
+
+/// The backend configured by the `MOLMINE_OCSR_*` environment variables
+pub fn backend_from_env() -> Result<Box<dyn OcsrBackend>, MolmineError> {
+    let setting = |name: &str, default: &str| std::env::var(name).unwrap_or(default.to_string());
+    match setting("MOLMINE_OCSR_BACKEND", "http").as_str() {
+        "http" => Ok(Box::new(HttpBackend::new(&setting(
+            "MOLMINE_OCSR_URL",
+            DEFAULT_OCSR_URL,
+        ))?)),
+        "command" => Ok(Box::new(CommandBackend::parse(&setting(
+            "MOLMINE_OCSR_COMMAND",
+            DEFAULT_OCSR_COMMAND,
+        ))?)),
+        "mock" => Ok(Box::new(MockBackend::new(
+            &setting("MOLMINE_OCSR_MOCK_SMILES", DEFAULT_MOCK_SMILES),
+            None,
+        ))),
+        other => Err(MolmineError::InvalidInput(format!(
+            "Unknown OCSR backend {other}; use http, command or mock"
+        ))),
+    }
+}
+
+/// The server's recognizer, set up from the environment on first use
+pub fn backend() -> Result<&'static dyn OcsrBackend, MolmineError> {
+    static BACKEND: OnceLock<Box<dyn OcsrBackend>> = OnceLock::new();
+    if let Some(backend) = BACKEND.get() {
+        return Ok(backend.as_ref());
+    }
+    let backend = backend_from_env()?;
+    Ok(BACKEND.get_or_init(|| backend).as_ref())
+}
+
+/// Runs the recognizer and checks its answer, returning canonical SMILES with a
+/// molblock for the structure editor
+pub async fn recognize(backend: &dyn OcsrBackend, png: &[u8]) -> Result<Recognition, MolmineError> {
+    let mut recognition = backend.recognize(png).await?;
+    // Some recognizers only return a molblock
+    let smiles = match (recognition.smiles.trim(), &recognition.molblock) {
+        ("", Some(molblock)) => molblock_to_smiles(molblock).unwrap_or_default(),
+        (smiles, _) => smiles.to_string(),
+    };
+    recognition.smiles = canonical_smiles(&smiles)
+        .ok_or_else(|| MolmineError::OcsrError(format!("Not a valid structure: {smiles:?}")))?;
+    if recognition.molblock.is_none() {
+        recognition.molblock = smiles_to_molblock(&recognition.smiles);
+    }
+    recognition.confidence = recognition
+        .confidence
+        .map(|confidence| confidence.clamp(0.0, 1.0));
+    Ok(recognition)
+}
+
+/// A checked recognition together with the backend that made it
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct RecognitionReport {
+    #[serde(flatten)]
+    pub recognition: Recognition,
+    pub backend: String,
+    /// The stored record, which compounds drawn from this recognition refer to
+    #[serde(default)]
+    pub recognition_id: Option<RecognitionId>,
+}
+
+/// Recognizes the structure in the PNG with the server's backend and records what it
+/// read, so compounds can cite the recognition rather than repeat it
+pub async fn recognize_with_server_backend(
+    png: &[u8],
+    project: ProjectId,
+    region: Option<RegionId>,
+    conn: &mut AsyncConn,
+) -> Result<RecognitionReport, MolmineError> {
+    let backend = backend()?;
+    let recognition = recognize(backend, png).await?;
+    let record = NewRecognitionRecord {
+        project_id: project,
+        region_id: region,
+        backend: backend.name().to_string(),
+        smiles: recognition.smiles.clone(),
+        inchi: recognition.inchi.clone(),
+        confidence: recognition.confidence,
+    }
+    .insert(conn)
+    .await?;
+    Ok(RecognitionReport {
+        recognition,
+        backend: record.backend,
+        recognition_id: Some(record.id),
+    })
+}
+
+/// Renders a structure region of a paper for recognition
+pub async fn render_region(
+    region: &StructureRegion,
+    conn: &mut AsyncConn,
+) -> Result<Vec<u8>, MolmineError> {
+    let pdf = Pdf::get_by_id(region.pdf_id, conn).await?;
+    if pdf.data.is_empty() {
+        return Err(MolmineError::NotFound("PDF file"));
+    }
+    let request = RenderRequest {
+        page_number: region.page_number as u16,
+        dpi: RECOGNITION_DPI,
+        region: Some(Region {
+            x: region.x as f32,
+            y: region.y as f32,
+            width: region.width as f32,
+            height: region.height as f32,
+        }),
+    };
+    let (_, png) = render_cached(pdf, request).await?;
+    Ok(png)
+}
+
+/// How well one recognizer's structures held up once curators had checked them
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct BackendAccuracy {
+    pub backend: String,
+    pub compounds: usize,
+    /// Compounds whose curated structure is the one recognized
+    pub exact_matches: usize,
+    pub exact_match_rate: f64,
+    /// Mean characters changed between the recognized and curated canonical SMILES
+    pub mean_edit_distance: f64,
+    /// Mean of the confidences the recognizer reported, if it reported any
+    pub mean_confidence: Option<f64>,
+}
+
+/// Whether curators kept the recognized structure, judged by the canonical SMILES RDKit
+/// computes here for both structures. These stand in for InChIKeys, which the rdkit crate
+/// can't compute. Stored InChIs aren't compared, as the curated one comes from the browser.
+pub fn is_exact_match(compound: &Compound) -> bool {
+    compound
+        .recognized_smiles
+        .as_deref()
+        .is_some_and(|recognized| {
+            comparable_smiles(recognized) == comparable_smiles(&compound.smiles)
+        })
+}
+
+fn comparable_smiles(smiles: &str) -> String {
+    canonical_smiles(smiles).unwrap_or_else(|| smiles.trim().to_string())
+}
+
+/// The Levenshtein distance between two strings, counted in characters
+pub fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut previous: Vec<usize> = (0..=b.len()).collect();
+    for (i, a_char) in a.chars().enumerate() {
+        let mut current = vec![i + 1; b.len() + 1];
+        for (j, b_char) in b.iter().enumerate() {
+            let substitution = previous[j] + usize::from(a_char != *b_char);
+            current[j + 1] = substitution.min(previous[j + 1] + 1).min(current[j] + 1);
+        }
+        previous = current;
+    }
+    previous[b.len()]
+}
+
+/// Accuracy of each recognizer over the compounds it read, sorted by backend name
+pub fn backend_accuracy(compounds: &[Compound]) -> Vec<BackendAccuracy> {
+    let mut by_backend: BTreeMap<&str, Vec<&Compound>> = BTreeMap::new();
+    for compound in compounds {
+        if let Some(backend) = &compound.recognition_backend {
+            by_backend.entry(backend).or_default().push(compound);
+        }
+    }
+    by_backend
+        .into_iter()
+        .map(|(backend, compounds)| {
+            let count = compounds.len();
+            let exact_matches = compounds.iter().filter(|c| is_exact_match(c)).count();
+            let total_distance: usize = compounds
+                .iter()
+                .map(|c| {
+                    let recognized = c.recognized_smiles.as_deref().unwrap_or_default();
+                    edit_distance(
+                        &comparable_smiles(recognized),
+                        &comparable_smiles(&c.smiles),
+                    )
+                })
+                .sum();
+            let confidences: Vec<f64> = compounds
+                .iter()
+                .filter_map(|c| c.recognition_confidence)
+                .collect();
+            BackendAccuracy {
+                backend: backend.to_string(),
+                compounds: count,
+                exact_matches,
+                exact_match_rate: exact_matches as f64 / count as f64,
+                mean_edit_distance: total_distance as f64 / count as f64,
+                mean_confidence: (!confidences.is_empty())
+                    .then(|| confidences.iter().sum::<f64>() / confidences.len() as f64),
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_command_backend() {
+        let backend = CommandBackend::parse("osra -f can").unwrap();
+        assert_eq!(backend.program, "osra");
+        assert_eq!(backend.args, vec!["-f", "can", "{image}"]);
+        assert!(CommandBackend::parse("  ").is_err());
+
+        assert_eq!(
+            parse_command_output("\nCC(=O)O 0.82\nc1ccccc1\n"),
+            Some(Recognition {
+                smiles: "CC(=O)O".to_string(),
+                confidence: Some(0.82),
+                ..Default::default()
+            })
+        );
+        assert_eq!(
+            parse_command_output("CCO").map(|r| r.confidence),
+            Some(None)
+        );
+        assert_eq!(parse_command_output(" \n"), None);
+    }
+
+    #[tokio::test]
+    async fn test_recognize_with_mock() {
+        let backend = MockBackend::new("c1ccccc1", Some(1.5));
+        let recognition = recognize(&backend, b"png").await.unwrap();
+        assert_eq!(recognition.smiles, "c1ccccc1");
+        assert!(recognition.molblock.is_some());
+        assert_eq!(recognition.confidence, Some(1.0));
+        assert_eq!(backend.calls(), 1);
+
+        assert!(recognize(&backend, b"").await.is_err());
+        assert_eq!(backend.calls(), 2);
+
+        let invalid = MockBackend::new("", None);
+        assert!(matches!(
+            recognize(&invalid, b"png").await,
+            Err(MolmineError::OcsrError(_))
+        ));
+        assert_eq!(invalid.calls(), 1);
+    }
+
+    fn recognized(backend: &str, recognized: &str, curated: &str) -> Compound {
+        Compound {
+            smiles: curated.to_string(),
+            recognition_backend: Some(backend.to_string()),
+            recognized_smiles: Some(recognized.to_string()),
+            recognition_confidence: Some(0.5),
+            ..fixtures::compound()
+        }
+    }
+
+    #[test]
+    fn test_backend_accuracy() {
+        assert_eq!(edit_distance("kitten", "sitting"), 3);
+        assert_eq!(edit_distance("", "CCO"), 3);
+        assert_eq!(edit_distance("CCO", "CCO"), 0);
+
+        let compounds = vec![
+            recognized("osra", "CCO", "CCO"),
+            recognized("osra", "CCO", "CCN"),
+            recognized("mock", "c1ccccc1", "c1ccccc1"),
+        ];
+        let accuracy = backend_accuracy(&compounds);
+        assert_eq!(accuracy.len(), 2);
+        assert_eq!(accuracy[0].backend, "mock");
+        assert_eq!(accuracy[1].backend, "osra");
+        assert_eq!(accuracy[1].compounds, 2);
+        assert_eq!(accuracy[1].exact_matches, 1);
+        assert_eq!(accuracy[1].exact_match_rate, 0.5);
+        assert_eq!(accuracy[1].mean_edit_distance, 0.5);
+        assert_eq!(accuracy[1].mean_confidence, Some(0.5));
+
+        // Stored InChIs don't decide, whatever they claim
+        let kept = Compound {
+            recognized_inchi: Some("InChI=1S/C2H6O/c1-2-3/h3H,2H2,1H3".to_string()),
+            inchi: "InChI=1S/C2H7N/c1-2-3/h2-3H2,1H3".to_string(),
+            ..recognized("osra", "CCO", "CCO")
+        };
+        assert!(is_exact_match(&kept));
+    }
+}
@@ -0,0 +1,94 @@
+use leptos::prelude::*;
+
+/// Signs in with a username and password and sets the session cookie
+#[server]
+pub async fn login(username: String, password: String) -> Result<(), ServerFnError> {
+    use crate::auth;
+    use crate::db::establish;
+
+    let mut conn = establish().await?;
+    let (_, token) = auth::login(&username, &password, &mut conn).await?;
+    set_cookie(auth::session_cookie(token))?;
+    leptos_axum::redirect("/");
+    Ok(())
+}
+
+/// Creates a local account and signs in as it
+#[server]
+pub async fn register(username: String, password: String) -> Result<(), ServerFnError> {
+    use crate::auth;
+    use crate::db::establish;
+
+    let mut conn = establish().await?;
+    let (_, token) = auth::register(&username, &password, &mut conn).await?;
+    set_cookie(auth::session_cookie(token))?;
+    leptos_axum::redirect("/");
+    Ok(())
+}
+
+#[server]
+pub async fn logout() -> Result<(), ServerFnError> {
+    use crate::auth;
+    use crate::db::establish;
+    use crate::models::Session;
+
+    let current = auth::require_user().await?;
+    let mut conn = establish().await?;
+    Session::delete(&current.require_session()?.token_hash, &mut conn).await?;
+    set_cookie(auth::removal_cookie())?;
+    leptos_axum::redirect("/login");
+    Ok(())
+}
+
+/// Returns the signed-in username, if any
+#[server]
+pub async fn current_username() -> Result<Option<String>, ServerFnError> {
+    Ok(crate::auth::require_user()
+        .await
+        .ok()
+        .map(|current| current.user.username))
+}
+
+#[cfg(feature = "ssr")]
+fn set_cookie(cookie: axum_extra::extract::cookie::Cookie<'static>) -> Result<(), ServerFnError> {
+    use axum::http::HeaderValue;
+    use axum::http::header::SET_COOKIE;
+
+    let response = expect_context::<leptos_axum::ResponseOptions>();
+    response.insert_header(SET_COOKIE, HeaderValue::from_str(&cookie.to_string())?);
+    Ok(())
+}
+
+#[component]
+pub fn LoginPage() -> impl IntoView {
+    let login = ServerAction::<Login>::new();
+    let register = ServerAction::<Register>::new();
+    let error = move || {
+        login
+            .value()
+            .get()
+            .or_else(|| register.value().get())
+            .and_then(|result| result.err())
+            .map(|err| err.to_string())
+    };
+
+    view! {
+        <div class="container" style="max-width: 28rem">
+            <Show when=move || error().is_some()>
+                <div class="alert alert-danger">{error}</div>
+            </Show>
+            <h4>"Sign in"</h4>
+            <ActionForm action=login>
+                <input class="form-control mb-2" type="text" name="username" placeholder="Username" required/>
+                <input class="form-control mb-2" type="password" name="password" placeholder="Password" required/>
+                <button class="btn btn-primary" type="submit">"Sign in"</button>
+            </ActionForm>
+            <h4 class="mt-4">"Create an account"</h4>
+            <ActionForm action=register>
+                <input class="form-control mb-2" type="text" name="username" placeholder="Username" required/>
+                <input class="form-control mb-2" type="password" name="password" placeholder="Password" minlength="8" required/>
+                <button class="btn btn-outline-primary" type="submit">"Register"</button>
+            </ActionForm>
+        </div>
+    }
+}
@@ -1,5 +1,7 @@
 pub mod about;
+pub mod login;
 pub mod navbar;
 
 pub use about::*;
+pub use login::*;
 pub use navbar::*;
@@ -0,0 +1,396 @@
+//! Suggests bibliographic fields for an uploaded PDF from its document information
+//! dictionary, its XMP metadata packet and the text of its first page.
+//!
+//! Sources are consulted from most to least reliable, and a later source only fills
+//! fields that an earlier one left empty.
+
+use lopdf::{Document, Object, decode_text_string};
+use serde::{Deserialize, Serialize};
+
+use crate::error::MolmineError;
+
+/// Bibliographic fields detected in a PDF, offered to the user to accept or edit
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct PaperSuggestion {
+    pub title: Option<String>,
+    pub authors: Option<String>,
+    pub year: Option<i32>,
+    pub journal: Option<String>,
+    pub volume: Option<String>,
+    pub doi: Option<String>,
+}
+
+impl PaperSuggestion {
+    /// Fills the fields that are still empty from `other`
+    pub fn fill_from(&mut self, other: PaperSuggestion) {
+        self.title = self.title.take().or(other.title);
+        self.authors = self.authors.take().or(other.authors);
+        self.year = self.year.or(other.year);
+        self.journal = self.journal.take().or(other.journal);
+        self.volume = self.volume.take().or(other.volume);
+        self.doi = self.doi.take().or(other.doi);
+    }
+}
+
+pub fn suggest_metadata(data: &[u8]) -> Result<PaperSuggestion, MolmineError> {
+    let doc = Document::load_mem(data)?;
+    let info = document_info(&doc);
+
+    let mut suggestion = xmp_packet(&doc)
+        .map(|xml| parse_xmp(&xml))
+        .unwrap_or_default();
+    suggestion.fill_from(parse_info(&info));
+    if let Some(text) = first_page_text(&doc) {
+        suggestion.fill_from(parse_first_page(&text));
+    }
+    // The file's creation date is only a rough stand-in for the publication year
+    suggestion.year = suggestion
+        .year
+        .or_else(|| info.creation_date.as_deref().and_then(find_year));
+    Ok(suggestion)
+}
+
+/// The text entries of the trailer's `/Info` dictionary that we care about
+#[derive(Debug, Default)]
+struct DocumentInfo {
+    title: Option<String>,
+    author: Option<String>,
+    subject: Option<String>,
+    keywords: Option<String>,
+    creation_date: Option<String>,
+}
+
+fn document_info(doc: &Document) -> DocumentInfo {
+    let Some(dict) = doc
+        .trailer
+        .get(b"Info")
+        .and_then(|info| doc.dereference(info))
+        .and_then(|(_, info)| info.as_dict())
+        .ok()
+    else {
+        return DocumentInfo::default();
+    };
+    let text = |key: &[u8]| {
+        dict.get(key)
+            .and_then(|value| doc.dereference(value))
+            .and_then(|(_, value)| decode_text_string(value))
+            .ok()
+            .map(|value| value.trim().to_string())
+            .filter(|value| !value.is_empty())
+    };
+    DocumentInfo {
+        title: text(b"Title"),
+        author: text(b"Author"),
+        subject: text(b"Subject"),
+        keywords: text(b"Keywords"),
+        creation_date: text(b"CreationDate"),
+    }
+}
+
+fn xmp_packet(doc: &Document) -> Option<String> {
+    let metadata = doc.catalog().ok()?.get(b"Metadata").ok()?;
+    let (_, metadata) = doc.dereference(metadata).ok()?;
+    let stream = match metadata {
+        Object::Stream(stream) => stream,
+        _ => return None,
+    };
+    let content = stream
+        .decompressed_content()
+        .unwrap_or_else(|_| stream.content.clone());
+    Some(String::from_utf8_lossy(&content).into_owned())
+}
+
+fn first_page_text(doc: &Document) -> Option<String> {
+    let first_page = *doc.get_pages().keys().next()?;
+    doc.extract_text(&[first_page]).ok()
+}
+
+fn parse_info(info: &DocumentInfo) -> PaperSuggestion {
+    let mut suggestion = PaperSuggestion {
+        title: info
+            .title
+            .clone()
+            .filter(|title| !is_placeholder_title(title)),
+        authors: info.author.clone(),
+        ..Default::default()
+    };
+    // Publishers commonly put "Journal Name, 12 (2019) 345-350. doi:..." in the subject
+    if let Some(subject) = &info.subject {
+        if let Some((journal, rest)) = subject.split_once(',') {
+            let volume: String = rest
+                .trim_start()
+                .chars()
+                .take_while(|c| c.is_ascii_digit())
+                .collect();
+            if !volume.is_empty() {
+                suggestion.journal = Some(journal.trim().to_string());
+                suggestion.volume = Some(volume);
+            }
+        }
+        suggestion.doi = find_doi(subject);
+    }
+    suggestion.doi = suggestion
+        .doi
+        .or_else(|| info.keywords.as_deref().and_then(find_doi));
+    suggestion
+}
+
+/// Titles that authoring tools fill in on their own rather than the paper's title
+fn is_placeholder_title(title: &str) -> bool {
+    let lower = title.to_lowercase();
+    title.chars().count() < 4
+        || lower.starts_with("microsoft word")
+        || lower.starts_with("untitled")
+        || [".pdf", ".doc", ".docx", ".tex", ".dvi"]
+            .iter()
+            .any(|extension| lower.ends_with(extension))
+        || title.chars().all(|c| !c.is_alphabetic())
+}
+
+pub fn parse_xmp(xml: &str) -> PaperSuggestion {
+    let authors = xmp_items(xml, "dc:creator");
+    PaperSuggestion {
+        title: xmp_items(xml, "dc:title")
+            .into_iter()
+            .next()
+            .filter(|title| !is_placeholder_title(title)),
+        authors: (!authors.is_empty()).then(|| authors.join("; ")),
+        year: ["prism:coverDate", "prism:publicationDate", "dc:date"]
+            .iter()
+            .find_map(|tag| xmp_items(xml, tag).first().and_then(|date| find_year(date))),
+        journal: xmp_items(xml, "prism:publicationName").into_iter().next(),
+        volume: xmp_items(xml, "prism:volume").into_iter().next(),
+        doi: xmp_items(xml, "prism:doi")
+            .into_iter()
+            .chain(xmp_items(xml, "dc:identifier"))
+            .find_map(|identifier| find_doi(&identifier)),
+    }
+}
+
+/// Reads a property written either as an element (possibly holding an `rdf:Seq`,
+/// `rdf:Bag` or `rdf:Alt` list) or as an attribute of `rdf:Description`
+fn xmp_items(xml: &str, tag: &str) -> Vec<String> {
+    if let Some(inner) = xml_element(xml, tag) {
+        let items: Vec<String> = xml_elements(inner, "rdf:li")
+            .into_iter()
+            .map(decode_entities)
+            .filter(|item| !item.is_empty())
+            .collect();
+        if !items.is_empty() {
+            return items;
+        }
+        if !inner.contains('<') {
+            let value = decode_entities(inner);
+            if !value.is_empty() {
+                return vec![value];
+            }
+        }
+    }
+    xml_attribute(xml, tag)
+        .map(decode_entities)
+        .filter(|value| !value.is_empty())
+        .into_iter()
+        .collect()
+}
+
+fn xml_element<'a>(xml: &'a str, tag: &str) -> Option<&'a str> {
+    xml_elements(xml, tag).into_iter().next()
+}
+
+/// The contents of every `<tag ...>...</tag>` element, ignoring nesting of the same tag
+fn xml_elements<'a>(xml: &'a str, tag: &str) -> Vec<&'a str> {
+    let open = format!("<{tag}");
+    let close = format!("</{tag}>");
+    let mut elements = Vec::new();
+    let mut rest = xml;
+    while let Some(start) = rest.find(&open) {
+        let after_name = &rest[start + open.len()..];
+        // Make sure we matched the whole tag name and not a prefix of a longer one
+        if !after_name.starts_with(['>', ' ', '\t', '\r', '\n', '/']) {
+            rest = after_name;
+            continue;
+        }
+        let Some(tag_end) = after_name.find('>') else {
+            break;
+        };
+        if after_name[..tag_end].ends_with('/') {
+            rest = &after_name[tag_end + 1..];
+            continue;
+        }
+        let body = &after_name[tag_end + 1..];
+        let Some(end) = body.find(&close) else {
+            break;
+        };
+        elements.push(&body[..end]);
+        rest = &body[end + close.len()..];
+    }
+    elements
+}
+
+fn xml_attribute<'a>(xml: &'a str, name: &str) -> Option<&'a str> {
+    let mut rest = xml;
+    while let Some(start) = rest.find(name) {
+        let after_name = &rest[start + name.len()..];
+        let preceded_by_space = rest[..start].ends_with(char::is_whitespace);
+        let value = after_name
+            .trim_start()
+            .strip_prefix('=')
+            .map(str::trim_start);
+        if let (true, Some(value)) = (preceded_by_space, value)
+            && let Some(quote) = value.chars().next().filter(|c| *c == '"' || *c == '\'')
+        {
+            let value = &value[1..];
+            return value.find(quote).map(|end| &value[..end]);
+        }
+        rest = after_name;
+    }
+    None
+}
+
+fn decode_entities(text: &str) -> String {
+    text.trim()
+        .replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&apos;", "'")
+        .replace("&#39;", "'")
+        .replace("&amp;", "&")
+}
+
+pub fn parse_first_page(text: &str) -> PaperSuggestion {
+    PaperSuggestion {
+        title: guess_title(text),
+        year: copyright_year(text),
+        doi: find_doi(text),
+        ..Default::default()
+    }
+}
+
+/// The first line that reads like a sentence-length heading and not like a page header
+fn guess_title(text: &str) -> Option<String> {
+    const HEADER_WORDS: [&str; 10] = [
+        "doi",
+        "http",
+        "www.",
+        "©",
+        "copyright",
+        "journal",
+        "vol.",
+        "volume",
+        "received",
+        "article",
+    ];
+    text.lines()
+        .map(|line| line.split_whitespace().collect::<Vec<_>>().join(" "))
+        .find(|line| {
+            let lower = line.to_lowercase();
+            let words = line.split(' ').count();
+            (4..=40).contains(&words)
+                && (20..=300).contains(&line.chars().count())
+                && !HEADER_WORDS.iter().any(|word| lower.contains(word))
+                && !line.ends_with('.')
+        })
+}
+
+/// A year that follows a copyright or publication notice
+fn copyright_year(text: &str) -> Option<i32> {
+    let lower = text.to_lowercase();
+    ["©", "copyright", "published", "received"]
+        .iter()
+        .filter_map(|marker| lower.find(marker).map(|at| at + marker.len()))
+        .filter_map(|at| {
+            let window: String = lower[at..].chars().take(60).collect();
+            find_year(&window)
+        })
+        .next()
+}
+
+/// The first plausible publication year (1900 up to next year) in the text
+pub fn find_year(text: &str) -> Option<i32> {
+    let latest = chrono::Datelike::year(&chrono::Utc::now()) + 1;
+    let bytes = text.as_bytes();
+    (0..bytes.len().saturating_sub(3)).find_map(|start| {
+        let candidate = &bytes[start..start + 4];
+        let bounded = (start == 0 || !bytes[start - 1].is_ascii_digit())
+            && bytes
+                .get(start + 4)
+                .is_none_or(|next| !next.is_ascii_digit());
+        if !bounded || !candidate.iter().all(u8::is_ascii_digit) {
+            return None;
+        }
+        let year: i32 = std::str::from_utf8(candidate).ok()?.parse().ok()?;
+        (1900..=latest).contains(&year).then_some(year)
+    })
+}
+
+/// Finds the first DOI (`10.<registrant>/<suffix>`) in free text
+pub fn find_doi(text: &str) -> Option<String> {
+    let mut rest = text;
+    while let Some(start) = rest.find("10.") {
+        let candidate = &rest[start..];
+        let preceded_by_digit = rest[..start]
+            .chars()
+            .next_back()
+            .is_some_and(|c| c.is_ascii_digit());
+        let registrant: String = candidate[3..]
+            .chars()
+            .take_while(|c| c.is_ascii_digit() || *c == '.')
+            .collect();
+        let after_registrant = &candidate[3 + registrant.len()..];
+        if !preceded_by_digit && registrant.len() >= 4 && after_registrant.starts_with('/') {
+            let suffix: String = after_registrant[1..]
+                .chars()
+                .take_while(|c| !c.is_whitespace() && !matches!(c, '"' | '<' | '>'))
+                .collect();
+            // Sentence punctuation and closing brackets are rarely part of the DOI
+            let suffix = suffix.trim_end_matches(['.', ',', ';', ':', ')', ']', '}']);
+            if !suffix.is_empty() {
+                return Some(format!("10.{registrant}/{suffix}"));
+            }
+        }
+        rest = &candidate[3..];
+    }
+    None
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_find_doi() {
+        assert_eq!(
+            find_doi("Available at https://doi.org/10.1021/acs.jmedchem.9b01234."),
+            Some("10.1021/acs.jmedchem.9b01234".to_string())
+        );
+        assert_eq!(
+            find_doi("(doi:10.1016/j.ejmech.2019.111.005)"),
+            Some("10.1016/j.ejmech.2019.111.005".to_string())
+        );
+        assert_eq!(find_doi("pages 110.5-120, 10.12/short"), None);
+    }
+
+    #[test]
+    fn test_parse_xmp() {
+        let xml = r#"<x:xmpmeta><rdf:RDF>
+            <rdf:Description rdf:about="" prism:volume="62">
+              <dc:title><rdf:Alt><rdf:li xml:lang="x-default">Potent &amp; selective inhibitors</rdf:li></rdf:Alt></dc:title>
+              <dc:creator><rdf:Seq><rdf:li>Jane Smith</rdf:li><rdf:li>Wei Zhang</rdf:li></rdf:Seq></dc:creator>
+              <prism:publicationName>Journal of Medicinal Chemistry</prism:publicationName>
+              <prism:coverDate>2019-03-14</prism:coverDate>
+              <dc:identifier>doi:10.1021/acs.jmedchem.8b01234</dc:identifier>
+            </rdf:Description>
+        </rdf:RDF></x:xmpmeta>"#;
+        assert_eq!(
+            parse_xmp(xml),
+            PaperSuggestion {
+                title: Some("Potent & selective inhibitors".to_string()),
+                authors: Some("Jane Smith; Wei Zhang".to_string()),
+                year: Some(2019),
+                journal: Some("Journal of Medicinal Chemistry".to_string()),
+                volume: Some("62".to_string()),
+                doi: Some("10.1021/acs.jmedchem.8b01234".to_string()),
+            }
+        );
+    }
+}
@@ -0,0 +1,26 @@
+//! Server-side processing of uploaded PDF files
+
+pub mod metadata;
+pub mod render;
+pub mod table;
+pub mod text;
+
+pub use metadata::*;
+pub use render::*;
+pub use table::*;
+pub use text::*;
+
+use pdfium_render::prelude::*;
+
+use crate::error::MolmineError;
+
+/// Loads the PDFium library, from the folder named by `PDFIUM_DYNAMIC_LIB_PATH` when it
+/// is set, otherwise from the working directory or else the system library path
+pub fn pdfium() -> Result<Pdfium, MolmineError> {
+    let bindings = match std::env::var("PDFIUM_DYNAMIC_LIB_PATH") {
+        Ok(dir) => Pdfium::bind_to_library(Pdfium::pdfium_platform_library_name_at_path(&dir)),
+        Err(_) => Pdfium::bind_to_library(Pdfium::pdfium_platform_library_name_at_path("./"))
+            .or_else(|_| Pdfium::bind_to_system_library()),
+    }?;
+    Ok(Pdfium::new(bindings))
+}
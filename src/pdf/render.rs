@@ -0,0 +1,215 @@
+//! Renders PDF pages, or a region of a page, to PNG with PDFium. Rendered images are
+//! cached on disk under the file's content hash, so a replaced file never serves stale
+//! images and the cache can be deleted at any time.
+
+use std::io::Cursor;
+use std::path::PathBuf;
+
+use image::ImageFormat;
+use pdfium_render::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::error::MolmineError;
+use crate::models::*;
+
+pub const RENDER_CACHE_DIR: &str = "cache/renders";
+pub const DEFAULT_DPI: u32 = 150;
+pub const MIN_DPI: u32 = 36;
+pub const MAX_DPI: u32 = 600;
+/// Largest image rendered for a page before cropping, about a 2 m² poster at 150 DPI
+const MAX_PIXELS: f32 = 100_000_000.0;
+/// PDF user space has 72 points to the inch
+const POINTS_PER_INCH: f32 = 72.0;
+
+/// A rectangle on a page in PDF points, measured from the top left corner like the
+/// coordinates of selections made in the viewer at 100% zoom
+#[derive(Copy, Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Region {
+    pub x: f32,
+    pub y: f32,
+    pub width: f32,
+    pub height: f32,
+}
+
+/// Which page of a paper to render, at what resolution and optionally cropped
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct RenderRequest {
+    /// Counted from 1, as in the viewer
+    pub page_number: u16,
+    pub dpi: u32,
+    pub region: Option<Region>,
+}
+
+impl RenderRequest {
+    pub fn validate(&self) -> Result<(), MolmineError> {
+        if self.page_number == 0 {
+            return Err(MolmineError::InvalidInput(
+                "Page numbers start at 1".to_string(),
+            ));
+        }
+        if !(MIN_DPI..=MAX_DPI).contains(&self.dpi) {
+            return Err(MolmineError::InvalidInput(format!(
+                "DPI must be between {MIN_DPI} and {MAX_DPI}"
+            )));
+        }
+        if let Some(region) = self.region {
+            let values = [region.x, region.y, region.width, region.height];
+            if values.iter().any(|value| !value.is_finite())
+                || region.x < 0.0
+                || region.y < 0.0
+                || region.width <= 0.0
+                || region.height <= 0.0
+            {
+                return Err(MolmineError::InvalidInput(
+                    "The region must have a positive size and lie on the page".to_string(),
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    /// Names the rendered image of this request for the file with the given content
+    /// hash, used both as the cache file name and the HTTP entity tag
+    pub fn cache_key(&self, content_hash: &str) -> String {
+        let mut key = format!("{content_hash}-p{}-{}dpi", self.page_number, self.dpi);
+        if let Some(region) = self.region {
+            key.push_str(&format!(
+                "-{:.1}_{:.1}_{:.1}_{:.1}",
+                region.x, region.y, region.width, region.height
+            ));
+        }
+        key
+    }
+}
+
+/// The pixels of a rendered page covered by the region, clipped to the page, as
+/// `(x, y, width, height)`; `None` if the region lies entirely off the page
+fn pixel_rect(region: Region, scale: f32, width: u32, height: u32) -> Option<(u32, u32, u32, u32)> {
+    let left = (region.x * scale).floor().max(0.0) as u32;
+    let top = (region.y * scale).floor().max(0.0) as u32;
+    let right = (((region.x + region.width) * scale).ceil() as u32).min(width);
+    let bottom = (((region.y + region.height) * scale).ceil() as u32).min(height);
+    (left < right && top < bottom).then(|| (left, top, right - left, bottom - top))
+}
+
+/// Renders the requested page of the PDF to a PNG
+pub fn render_page(data: &[u8], request: &RenderRequest) -> Result<Vec<u8>, MolmineError> {
+    let pdfium = super::pdfium()?;
+    let document = pdfium.load_pdf_from_byte_slice(data, None)?;
+    let pages = document.pages();
+    if request.page_number > pages.len() {
+        return Err(MolmineError::InvalidInput(format!(
+            "Page {} is past the end of the {}-page PDF",
+            request.page_number,
+            pages.len()
+        )));
+    }
+    let page = pages.get(request.page_number - 1)?;
+    let scale = request.dpi as f32 / POINTS_PER_INCH;
+    if page.width().value * scale * page.height().value * scale > MAX_PIXELS {
+        return Err(MolmineError::InvalidInput(format!(
+            "The page is too large to render at {} DPI",
+            request.dpi
+        )));
+    }
+    let config = PdfRenderConfig::new().scale_page_by_factor(scale);
+    let mut image = page.render_with_config(&config)?.as_image();
+    if let Some(region) = request.region {
+        let (x, y, width, height) = pixel_rect(region, scale, image.width(), image.height())
+            .ok_or_else(|| {
+                MolmineError::InvalidInput("The region lies outside the page".to_string())
+            })?;
+        image = image.crop_imm(x, y, width, height);
+    }
+    let mut png = Cursor::new(Vec::new());
+    image.write_to(&mut png, ImageFormat::Png)?;
+    Ok(png.into_inner())
+}
+
+/// A PNG of the requested page of the paper with its cache key, rendered on the first
+/// request and read from the cache after that
+pub async fn render_cached(
+    pdf: Pdf,
+    request: RenderRequest,
+) -> Result<(String, Vec<u8>), MolmineError> {
+    request.validate()?;
+    // Papers added before content hashes were stored are hashed here
+    let hash = match &pdf.content_hash {
+        Some(hash) => hash.clone(),
+        None => content_hash(&pdf.data),
+    };
+    let key = request.cache_key(&hash);
+    let path = PathBuf::from(RENDER_CACHE_DIR).join(format!("{key}.png"));
+    if let Ok(png) = tokio::fs::read(&path).await {
+        return Ok((key, png));
+    }
+
+    // Rendering is CPU-bound, so keep it off the async workers
+    let png = tokio::task::spawn_blocking(move || render_page(&pdf.data, &request)).await??;
+    // Written under a temporary name and renamed, so a concurrent request never reads
+    // a partly written image. Failing to cache is not worth failing the request for.
+    let partial = path.with_extension(format!("{}.partial", std::process::id()));
+    let cached = async {
+        tokio::fs::create_dir_all(RENDER_CACHE_DIR).await?;
+        tokio::fs::write(&partial, &png).await?;
+        tokio::fs::rename(&partial, &path).await
+    };
+    if let Err(e) = cached.await {
+        tracing::warn!("Could not cache {}: {e}", path.display());
+    }
+    Ok((key, png))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_render_region() {
+        let region = Region {
+            x: 72.0,
+            y: 36.0,
+            width: 144.0,
+            height: 1000.0,
+        };
+        // At 144 DPI a point is two pixels, and the region is clipped to the page
+        assert_eq!(
+            pixel_rect(region, 2.0, 1224, 1584),
+            Some((144, 72, 288, 1512))
+        );
+        assert_eq!(
+            pixel_rect(Region { x: 700.0, ..region }, 2.0, 1224, 1584),
+            None
+        );
+
+        let request = RenderRequest {
+            page_number: 3,
+            dpi: 300,
+            region: Some(region),
+        };
+        assert!(request.validate().is_ok());
+        assert_eq!(
+            request.cache_key("ab12"),
+            "ab12-p3-300dpi-72.0_36.0_144.0_1000.0"
+        );
+        for invalid in [
+            RenderRequest {
+                page_number: 0,
+                ..request
+            },
+            RenderRequest {
+                dpi: 1200,
+                ..request
+            },
+            RenderRequest {
+                region: Some(Region {
+                    width: -5.0,
+                    ..region
+                }),
+                ..request
+            },
+        ] {
+            assert!(invalid.validate().is_err());
+        }
+    }
+}
@@ -0,0 +1,492 @@
+//! Reads a table of values, such as the activities of a paper's compounds, from a region
+//! of a PDF page into rows keyed by compound label, and fills the values into the
+//! chemical data of the compounds with those labels.
+//!
+//! PDFs keep no table structure, only placed glyphs. Characters are joined into words,
+//! words at the same height into lines and nearby words into cells. Columns are the
+//! bands the cells of typical lines cover. Rows start at lines with a compound label in
+//! the first column: lines above the first row make up the header, lines with an empty
+//! first column continue the row above, and lines holding a single cell across several
+//! columns, such as captions and footnotes, are left out.
+
+use std::collections::BTreeMap;
+
+use diesel_async::AsyncConnection;
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+
+use super::Region;
+use crate::db::AsyncConn;
+use crate::error::MolmineError;
+use crate::import::field_value;
+use crate::labels::{is_compound_label, normalize_label};
+use crate::models::*;
+
+/// Characters further apart than this, in ems, are in separate words
+const WORD_GAP: f32 = 0.3;
+/// Words further apart than this, in ems, are in separate cells
+const CELL_GAP: f32 = 1.0;
+
+/// A word on the page in PDF points from the top left, like [`Region`]
+#[derive(Clone, Debug, PartialEq)]
+pub struct TextBox {
+    pub text: String,
+    pub x: f32,
+    pub y: f32,
+    pub width: f32,
+    pub height: f32,
+}
+
+impl TextBox {
+    fn center_y(&self) -> f32 {
+        self.y + self.height / 2.0
+    }
+}
+
+/// A table read from a page, with one cell per column in every row
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct ExtractedTable {
+    /// Column headings, empty for columns without one
+    pub columns: Vec<String>,
+    pub rows: Vec<TableRow>,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct TableRow {
+    /// The first cell as a compound label, see [`normalize_label`]
+    pub label: String,
+    pub cells: Vec<String>,
+}
+
+/// The words of the page that lie within the region, in the order PDFium reads them
+pub fn extract_words(
+    data: &[u8],
+    page_number: u16,
+    region: Region,
+) -> Result<Vec<TextBox>, MolmineError> {
+    let pdfium = super::pdfium()?;
+    let document = pdfium.load_pdf_from_byte_slice(data, None)?;
+    let pages = document.pages();
+    if page_number == 0 || page_number > pages.len() {
+        return Err(MolmineError::InvalidInput(format!(
+            "The PDF has no page {page_number}"
+        )));
+    }
+    let page = pages.get(page_number - 1)?;
+    let page_height = page.height().value;
+    let text = page.text()?;
+    let mut words = Vec::new();
+    let mut word: Option<TextBox> = None;
+    for char in text.chars().iter() {
+        let (Some(c), Ok(bounds)) = (char.unicode_char(), char.loose_bounds()) else {
+            continue;
+        };
+        let glyph = TextBox {
+            text: match c {
+                '\u{2212}' => '-',
+                c => c,
+            }
+            .to_string(),
+            x: bounds.left().value,
+            y: page_height - bounds.top().value,
+            width: bounds.width().value,
+            height: bounds.height().value,
+        };
+        let inside = (region.x..=region.x + region.width).contains(&(glyph.x + glyph.width / 2.0))
+            && (region.y..=region.y + region.height).contains(&glyph.center_y());
+        if c.is_whitespace() || c.is_control() || !inside {
+            words.extend(word.take());
+            continue;
+        }
+        match &mut word {
+            Some(current)
+                if (glyph.x - (current.x + current.width)).abs() < WORD_GAP * glyph.height
+                    && (glyph.center_y() - current.center_y()).abs() < glyph.height / 2.0 =>
+            {
+                current.text.push_str(&glyph.text);
+                let right = (glyph.x + glyph.width).max(current.x + current.width);
+                let bottom = (glyph.y + glyph.height).max(current.y + current.height);
+                current.y = current.y.min(glyph.y);
+                current.width = right - current.x;
+                current.height = bottom - current.y;
+            }
+            _ => words.extend(word.replace(glyph)),
+        }
+    }
+    words.extend(word);
+    Ok(words)
+}
+
+/// Words of one line close enough together to be one cell
+#[derive(Debug)]
+struct Cell {
+    text: String,
+    left: f32,
+    right: f32,
+}
+
+/// Groups words into lines from top to bottom, each sorted left to right
+fn group_lines(words: &[TextBox]) -> Vec<Vec<&TextBox>> {
+    let mut sorted: Vec<&TextBox> = words.iter().collect();
+    sorted.sort_by(|a, b| a.center_y().total_cmp(&b.center_y()));
+    let mut lines: Vec<Vec<&TextBox>> = Vec::new();
+    for word in sorted {
+        match lines.last_mut() {
+            Some(line)
+                if (word.center_y() - line[0].center_y()).abs()
+                    < word.height.max(line[0].height) / 2.0 =>
+            {
+                line.push(word)
+            }
+            _ => lines.push(vec![word]),
+        }
+    }
+    for line in &mut lines {
+        line.sort_by(|a, b| a.x.total_cmp(&b.x));
+    }
+    lines
+}
+
+fn line_cells(line: &[&TextBox]) -> Vec<Cell> {
+    let mut cells: Vec<Cell> = Vec::new();
+    for word in line {
+        match cells.last_mut() {
+            Some(cell) if word.x - cell.right < CELL_GAP * word.height => {
+                cell.text.push(' ');
+                cell.text.push_str(&word.text);
+                cell.right = cell.right.max(word.x + word.width);
+            }
+            _ => cells.push(Cell {
+                text: word.text.clone(),
+                left: word.x,
+                right: word.x + word.width,
+            }),
+        }
+    }
+    cells
+}
+
+/// The horizontal bands of the columns, from the lines with the most common number of
+/// cells so that headings and notes spanning columns don't join them
+fn column_bands(lines: &[Vec<Cell>]) -> Vec<(f32, f32)> {
+    let mut counts: BTreeMap<usize, usize> = BTreeMap::new();
+    for line in lines.iter().filter(|line| line.len() > 1) {
+        *counts.entry(line.len()).or_default() += 1;
+    }
+    let typical = counts
+        .into_iter()
+        .max_by_key(|&(cells, lines)| (lines, cells))
+        .map_or(1, |(cells, _)| cells);
+    let mut intervals: Vec<(f32, f32)> = lines
+        .iter()
+        .filter(|line| line.len() == typical)
+        .flatten()
+        .map(|cell| (cell.left, cell.right))
+        .collect();
+    intervals.sort_by(|a, b| a.0.total_cmp(&b.0));
+    let mut bands: Vec<(f32, f32)> = Vec::new();
+    for (left, right) in intervals {
+        match bands.last_mut() {
+            Some(band) if left <= band.1 => band.1 = band.1.max(right),
+            _ => bands.push((left, right)),
+        }
+    }
+    bands
+}
+
+/// How far the cell is from the band, negative by how much they overlap
+fn separation(cell: &Cell, band: (f32, f32)) -> f32 {
+    cell.left.max(band.0) - cell.right.min(band.1)
+}
+
+fn append(text: &mut String, more: &str) {
+    if more.is_empty() {
+        return;
+    }
+    if !text.is_empty() {
+        text.push(' ');
+    }
+    text.push_str(more);
+}
+
+/// Lays the words of a table out into a header and rows keyed by compound label
+pub fn layout_table(words: &[TextBox]) -> ExtractedTable {
+    let lines: Vec<Vec<Cell>> = group_lines(words)
+        .iter()
+        .map(|line| line_cells(line))
+        .collect();
+    let bands = column_bands(&lines);
+    let mut table = ExtractedTable {
+        columns: vec![String::new(); bands.len()],
+        rows: Vec::new(),
+    };
+    for line in &lines {
+        let spanned = bands
+            .iter()
+            .filter(|band| separation(&line[0], **band) < 0.0)
+            .count();
+        if line.len() == 1 && spanned > 1 {
+            continue;
+        }
+        let mut cells = vec![String::new(); bands.len()];
+        for cell in line {
+            let column = (0..bands.len())
+                .min_by(|&a, &b| separation(cell, bands[a]).total_cmp(&separation(cell, bands[b])))
+                .unwrap_or_default();
+            append(&mut cells[column], &cell.text);
+        }
+        if table.rows.is_empty() && !is_compound_label(&cells[0]) {
+            for (heading, cell) in table.columns.iter_mut().zip(&cells) {
+                append(heading, cell);
+            }
+        } else if cells[0].is_empty() {
+            if let Some(row) = table.rows.last_mut() {
+                for (row_cell, cell) in row.cells.iter_mut().zip(&cells) {
+                    append(row_cell, cell);
+                }
+            }
+        } else {
+            table.rows.push(TableRow {
+                label: normalize_label(&cells[0]),
+                cells,
+            });
+        }
+    }
+    table
+}
+
+/// Reads the table in a region of a page of the PDF
+pub fn extract_table(
+    data: &[u8],
+    page_number: u16,
+    region: Region,
+) -> Result<ExtractedTable, MolmineError> {
+    Ok(layout_table(&extract_words(data, page_number, region)?))
+}
+
+/// A heading or field name without case, units in brackets or punctuation, so that
+/// `IC50 (nM)` is taken for the field `IC50`
+fn comparable_name(name: &str) -> String {
+    name.split(['(', '['])
+        .next()
+        .unwrap_or_default()
+        .chars()
+        .filter(|c| c.is_alphanumeric())
+        .flat_map(char::to_lowercase)
+        .collect()
+}
+
+/// The project field each column seems to hold, matched on its heading
+pub fn suggest_mapping(columns: &[String], fields: &[ProjectField]) -> Vec<Option<String>> {
+    columns
+        .iter()
+        .map(|heading| {
+            let heading = comparable_name(heading);
+            fields
+                .iter()
+                .find(|field| !heading.is_empty() && comparable_name(&field.name) == heading)
+                .map(|field| field.name.clone())
+        })
+        .collect()
+}
+
+#[derive(Debug, Default, Serialize)]
+pub struct TableFillReport {
+    pub dry_run: bool,
+    pub filled: Vec<FilledCompound>,
+    /// Row labels that match none of the paper's compounds
+    pub unmatched_labels: Vec<String>,
+    /// Values left out because the compound already had one and overwriting was off
+    pub kept_values: usize,
+}
+
+#[derive(Debug, Serialize)]
+pub struct FilledCompound {
+    pub compound_id: CompoundId,
+    pub label: String,
+    /// The fields given values
+    pub fields: Vec<String>,
+}
+
+/// Merges the table's values into the chemical data of the compounds whose labels
+/// match its rows, returning the changed compounds with their new data
+pub fn fill_chemical_data(
+    table: &ExtractedTable,
+    mapping: &[Option<String>],
+    fields: &[ProjectField],
+    compounds: &[Compound],
+    overwrite: bool,
+    report: &mut TableFillReport,
+) -> Vec<(CompoundId, String)> {
+    let mut changed = Vec::new();
+    for row in &table.rows {
+        let label = normalize_label(&row.label);
+        let matching: Vec<&Compound> = compounds
+            .iter()
+            .filter(|compound| !label.is_empty() && compound.label == label)
+            .collect();
+        if matching.is_empty() {
+            report.unmatched_labels.push(row.label.clone());
+            continue;
+        }
+        for compound in matching {
+            let mut data: Map<String, Value> =
+                serde_json::from_str(&compound.chemical_data).unwrap_or_default();
+            let mut filled = Vec::new();
+            for (cell, field_name) in row.cells.iter().zip(mapping) {
+                let value = cell.trim();
+                let Some(field) = field_name
+                    .as_ref()
+                    .and_then(|name| fields.iter().find(|field| &field.name == name))
+                else {
+                    continue;
+                };
+                if value.is_empty() {
+                    continue;
+                }
+                let has_value = match data.get(&field.name) {
+                    None | Some(Value::Null) => false,
+                    Some(Value::String(existing)) => !existing.is_empty(),
+                    Some(_) => true,
+                };
+                if has_value && !overwrite {
+                    report.kept_values += 1;
+                    continue;
+                }
+                data.insert(field.name.clone(), field_value(field, value));
+                filled.push(field.name.clone());
+            }
+            if !filled.is_empty() {
+                changed.push((compound.id, Value::Object(data).to_string()));
+                report.filled.push(FilledCompound {
+                    compound_id: compound.id,
+                    label: compound.label.clone(),
+                    fields: filled,
+                });
+            }
+        }
+    }
+    changed
+}
+
+/// Fills the table's values into the chemical data of the paper's compounds with
+/// matching labels, mapping columns to the fields of the paper's project. All changes
+/// are made in one transaction.
+pub async fn fill_from_table(
+    pdf_id: PdfId,
+    table: &ExtractedTable,
+    mapping: &[Option<String>],
+    overwrite: bool,
+    dry_run: bool,
+    conn: &mut AsyncConn,
+) -> Result<TableFillReport, MolmineError> {
+    let pdf = PdfInfo::get_by_id(pdf_id, conn).await?;
+    let project_id = pdf.project_id.ok_or_else(|| {
+        MolmineError::InvalidInput("The paper belongs to no project with fields".to_string())
+    })?;
+    let fields = Project::get_by_id(project_id, conn).await?.field_defs()?;
+    if let Some(unknown) = mapping
+        .iter()
+        .flatten()
+        .find(|name| !fields.iter().any(|field| &field.name == *name))
+    {
+        return Err(MolmineError::InvalidInput(format!(
+            "The project has no field {unknown}"
+        )));
+    }
+    let compounds = Compound::list_for_pdf(pdf_id, conn).await?;
+    let mut report = TableFillReport {
+        dry_run,
+        ..Default::default()
+    };
+    let changed = fill_chemical_data(table, mapping, &fields, &compounds, overwrite, &mut report);
+    if !dry_run {
+        conn.transaction::<_, diesel::result::Error, _>(|conn| {
+            Box::pin(async move {
+                for (compound_id, data) in &changed {
+                    Compound::set_chemical_data(*compound_id, data, conn).await?;
+                }
+                Ok(())
+            })
+        })
+        .await?;
+    }
+    Ok(report)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn word(text: &str, x: f32, y: f32) -> TextBox {
+        TextBox {
+            text: text.to_string(),
+            x,
+            y,
+            width: 6.0 * text.len() as f32,
+            height: 10.0,
+        }
+    }
+
+    #[test]
+    fn test_layout_table() {
+        let words = vec![
+            word("Table", 50.0, 10.0),
+            word("1.", 84.0, 10.0),
+            word("Kinase", 98.0, 10.0),
+            word("inhibition", 138.0, 10.0),
+            word("of", 200.0, 10.0),
+            word("analogues", 215.0, 10.0),
+            word("compd", 50.0, 30.0),
+            word("R", 120.0, 30.0),
+            word("IC50", 200.0, 30.0),
+            word("(nM)", 200.0, 42.0),
+            word("7a", 50.0, 60.0),
+            word("Me", 120.0, 60.0),
+            word("12", 200.0, 60.0),
+            word("7b", 50.0, 75.0),
+            word("Et", 120.0, 75.0),
+            word("n.d.", 200.0, 75.0),
+            word("i-Pr", 120.0, 87.0),
+            word("(8)", 50.0, 102.0),
+            word("Ph", 120.0, 102.0),
+            word("0.5", 200.0, 102.0),
+        ];
+        let table = layout_table(&words);
+        assert_eq!(table.columns, vec!["compd", "R", "IC50 (nM)"]);
+        assert_eq!(table.rows.len(), 3);
+        assert_eq!(table.rows[0].label, "7a");
+        assert_eq!(table.rows[0].cells, vec!["7a", "Me", "12"]);
+        assert_eq!(table.rows[1].cells, vec!["7b", "Et i-Pr", "n.d."]);
+        assert_eq!(table.rows[2].label, "8");
+
+        let fields = vec![
+            ProjectField {
+                name: "IC50".to_string(),
+                field_type: FieldType::Number,
+            },
+            ProjectField {
+                name: "note".to_string(),
+                field_type: FieldType::String,
+            },
+        ];
+        let mapping = suggest_mapping(&table.columns, &fields);
+        assert_eq!(mapping, vec![None, None, Some("IC50".to_string())]);
+
+        let compound = |id, label: &str, data: &str| Compound {
+            id: CompoundId(id),
+            chemical_data: data.to_string(),
+            label: label.to_string(),
+            ..fixtures::compound()
+        };
+        let compounds = vec![compound(1, "7a", "{}"), compound(2, "8", r#"{"IC50": 3}"#)];
+        let mut report = TableFillReport::default();
+        let changed = fill_chemical_data(&table, &mapping, &fields, &compounds, false, &mut report);
+        assert_eq!(
+            changed,
+            vec![(CompoundId(1), r#"{"IC50":12.0}"#.to_string())]
+        );
+        assert_eq!(report.unmatched_labels, vec!["7b"]);
+        assert_eq!(report.kept_values, 1);
+    }
+}
@@ -0,0 +1,68 @@
+//! Extracts the text of each page of a PDF into the full-text index, so papers can be
+//! searched by their contents.
+
+use crate::db::AsyncConn;
+use crate::error::MolmineError;
+use crate::models::*;
+
+/// The text of each page in order, tidied by [`clean_page_text`]
+pub fn extract_page_texts(data: &[u8]) -> Result<Vec<String>, MolmineError> {
+    let pdfium = super::pdfium()?;
+    let document = pdfium.load_pdf_from_byte_slice(data, None)?;
+    let mut texts = Vec::new();
+    for page in document.pages().iter() {
+        texts.push(clean_page_text(&page.text()?.all()));
+    }
+    Ok(texts)
+}
+
+/// Normalizes line endings and drops the control characters PDFium uses for soft
+/// hyphens and unknown glyphs, rejoining words it hyphenated across lines
+pub fn clean_page_text(text: &str) -> String {
+    let mut cleaned = String::with_capacity(text.len());
+    let mut chars = text.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            // A soft hyphen at a line break joins the two halves of a word
+            '\u{2}' | '\u{ad}' => {
+                while chars.next_if(|next| matches!(next, '\r' | '\n')).is_some() {}
+            }
+            '\r' => {
+                if chars.peek() != Some(&'\n') {
+                    cleaned.push('\n');
+                }
+            }
+            '\n' | '\t' => cleaned.push(c),
+            '\u{fffe}' | '\u{fffd}' => {}
+            c if c.is_control() => {}
+            c => cleaned.push(c),
+        }
+    }
+    cleaned.trim().to_string()
+}
+
+/// Extracts the text of the paper's file and stores it in the full-text index,
+/// returning the number of pages
+pub async fn index_text(
+    pdf_id: PdfId,
+    data: Vec<u8>,
+    conn: &mut AsyncConn,
+) -> Result<usize, MolmineError> {
+    // Text extraction is CPU-bound, so keep it off the async workers
+    let texts = tokio::task::spawn_blocking(move || extract_page_texts(&data)).await??;
+    Pdf::set_page_texts(pdf_id, &texts, conn).await?;
+    Ok(texts.len())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_clean_page_text() {
+        assert_eq!(
+            clean_page_text("  Potent inhi\u{2}\r\nbitors\r\nof kinase\u{1}\rB\u{fffe}  "),
+            "Potent inhibitors\nof kinase\nB"
+        );
+    }
+}
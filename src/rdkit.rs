@@ -1,3 +1,94 @@
+/// Placeholder molblock for compounds whose SMILES RDKit cannot read, so their data is
+/// still exported
+pub const EMPTY_MOLBLOCK: &str =
+    "\n     molmine\n\n  0  0  0  0  0  0  0  0  0  0999 V2000\nM  END\n";
+
+/// A V2000 molblock with 2D coordinates for the SMILES, or `None` if RDKit can't parse it
+pub fn smiles_to_molblock(smiles: &str) -> Option<String> {
+    if smiles.trim().is_empty() {
+        return None;
+    }
+    let mol = ::rdkit::ROMol::from_smiles(smiles).ok()?;
+    Some(mol.to_molblock())
+}
+
+/// RDKit's canonical form of the SMILES, or `None` if it can't be parsed
+pub fn canonical_smiles(smiles: &str) -> Option<String> {
+    if smiles.trim().is_empty() {
+        return None;
+    }
+    let mol = ::rdkit::ROMol::from_smiles(smiles.trim()).ok()?;
+    Some(mol.as_smiles())
+}
+
+/// Canonical SMILES for a V2000/V3000 molblock, or `None` if RDKit can't read it
+pub fn molblock_to_smiles(molblock: &str) -> Option<String> {
+    let mol = ::rdkit::RWMol::from_mol_block(molblock, true, true, false)?;
+    Some(mol.to_ro_mol().as_smiles())
+}
+
+/// Descriptor columns offered by the table exports, with RDKit's property names
+pub const DESCRIPTORS: [(&str, &str); 10] = [
+    ("MW", "amw"),
+    ("Exact mass", "exactmw"),
+    ("cLogP", "CrippenClogP"),
+    ("TPSA", "tpsa"),
+    ("HBD", "NumHBD"),
+    ("HBA", "NumHBA"),
+    ("Rotatable bonds", "NumRotatableBonds"),
+    ("Heavy atoms", "NumHeavyAtoms"),
+    ("Aromatic rings", "NumAromaticRings"),
+    ("Fraction Csp3", "FractionCSP3"),
+];
+
+/// The [`DESCRIPTORS`] of the molecule in order, or `None` if RDKit can't parse the SMILES
+pub fn descriptors(smiles: &str) -> Option<Vec<Option<f64>>> {
+    if smiles.trim().is_empty() {
+        return None;
+    }
+    let mol = ::rdkit::ROMol::from_smiles(smiles.trim()).ok()?;
+    let properties = ::rdkit::Properties::new().compute_properties(&mol);
+    Some(
+        DESCRIPTORS
+            .iter()
+            .map(|(_, name)| properties.get(*name).copied())
+            .collect(),
+    )
+}
+
+/// RDKit's topological fingerprint of the molecule as packed bits, the first bit in the
+/// lowest bit of the first byte, or `None` if RDKit can't parse the SMILES
+pub fn fingerprint(smiles: &str) -> Option<Vec<u8>> {
+    if smiles.trim().is_empty() {
+        return None;
+    }
+    let mol = ::rdkit::ROMol::from_smiles(smiles.trim()).ok()?;
+    Some(mol.fingerprint().0.into_vec())
+}
+
+/// A SMARTS substructure query, parsed once to be matched against many molecules
+pub struct SubstructureQuery(::rdkit::ROMol);
+
+impl SubstructureQuery {
+    /// Parses the SMARTS pattern, or returns `None` if RDKit can't read it
+    pub fn new(smarts: &str) -> Option<Self> {
+        if smarts.trim().is_empty() {
+            return None;
+        }
+        let query = ::rdkit::RWMol::from_smarts(smarts.trim()).ok()?;
+        Some(SubstructureQuery(query.to_ro_mol()))
+    }
+
+    /// Whether the molecule contains the substructure; unreadable SMILES never match
+    pub fn matches(&self, smiles: &str) -> bool {
+        let Ok(mol) = ::rdkit::ROMol::from_smiles(smiles.trim()) else {
+            return false;
+        };
+        let params = ::rdkit::SubstructMatchParameters::default();
+        !::rdkit::substruct_match(&mol, &self.0, &params).is_empty()
+    }
+}
+
 #[cfg(test)]
 mod test {
     #[test]
@@ -1,5 +1,46 @@
 // @generated automatically by Diesel CLI.
 
+diesel::table! {
+    api_token_projects (token_id, project_id) {
+        token_id -> Integer,
+        project_id -> Integer,
+    }
+}
+
+diesel::table! {
+    api_tokens (id) {
+        id -> Integer,
+        user_id -> Integer,
+        name -> Text,
+        token_hash -> Text,
+        can_write -> Bool,
+        created_at -> Timestamp,
+        expires_at -> Nullable<Timestamp>,
+        last_used_at -> Nullable<Timestamp>,
+        revoked_at -> Nullable<Timestamp>,
+    }
+}
+
+diesel::table! {
+    authors (id) {
+        id -> Integer,
+        project_id -> Integer,
+        family_name -> Text,
+        given_name -> Nullable<Text>,
+        orcid -> Nullable<Text>,
+    }
+}
+
+diesel::table! {
+    compound_mentions (id) {
+        id -> Integer,
+        compound_id -> Integer,
+        pdf_id -> Integer,
+        page_number -> Integer,
+        sentence -> Text,
+    }
+}
+
 diesel::table! {
     compounds (id) {
         id -> Integer,
@@ -8,6 +49,52 @@ diesel::table! {
         inchi -> Text,
         image -> Text,
         chemical_data -> Text,
+        recognition_backend -> Nullable<Text>,
+        recognized_smiles -> Nullable<Text>,
+        recognized_inchi -> Nullable<Text>,
+        recognition_confidence -> Nullable<Double>,
+        label -> Text,
+        markush_source_id -> Nullable<Integer>,
+        rgroup_assignments -> Nullable<Text>,
+    }
+}
+
+diesel::table! {
+    jobs (id) {
+        id -> Integer,
+        project_id -> Nullable<Integer>,
+        created_by -> Nullable<Integer>,
+        kind -> Text,
+        task -> Text,
+        status -> Text,
+        attempts -> Integer,
+        max_attempts -> Integer,
+        cancel_requested -> Bool,
+        result -> Nullable<Text>,
+        error -> Nullable<Text>,
+        run_after -> Timestamp,
+        created_at -> Timestamp,
+        started_at -> Nullable<Timestamp>,
+        finished_at -> Nullable<Timestamp>,
+        progress -> Nullable<Double>,
+    }
+}
+
+diesel::table! {
+    markush_sources (id) {
+        id -> Integer,
+        pdf_id -> Integer,
+        name -> Text,
+        core -> Text,
+        rgroups -> Text,
+    }
+}
+
+diesel::table! {
+    pdf_authors (pdf_id, position) {
+        pdf_id -> Integer,
+        author_id -> Integer,
+        position -> Integer,
     }
 }
 
@@ -20,6 +107,17 @@ diesel::table! {
         journal -> Text,
         volume -> Text,
         data -> Binary,
+        project_id -> Nullable<Integer>,
+        doi -> Nullable<Text>,
+        pmid -> Nullable<Text>,
+        arxiv_id -> Nullable<Text>,
+        isbn -> Nullable<Text>,
+        url -> Nullable<Text>,
+        pages -> Nullable<Text>,
+        issue -> Nullable<Text>,
+        publisher -> Nullable<Text>,
+        content_hash -> Nullable<Text>,
+        page_count -> Nullable<Integer>,
     }
 }
 
@@ -30,6 +128,14 @@ diesel::table! {
     }
 }
 
+diesel::table! {
+    project_members (project_id, user_id) {
+        project_id -> Integer,
+        user_id -> Integer,
+        role -> Text,
+    }
+}
+
 diesel::table! {
     projects (id) {
         id -> Integer,
@@ -40,11 +146,90 @@ diesel::table! {
     }
 }
 
+diesel::table! {
+    recognitions (id) {
+        id -> Integer,
+        project_id -> Integer,
+        region_id -> Nullable<Integer>,
+        backend -> Text,
+        smiles -> Text,
+        inchi -> Nullable<Text>,
+        confidence -> Nullable<Double>,
+        created_at -> Timestamp,
+    }
+}
+
+diesel::table! {
+    sessions (token_hash) {
+        token_hash -> Text,
+        user_id -> Integer,
+        active_project_id -> Nullable<Integer>,
+        created_at -> Timestamp,
+        expires_at -> Timestamp,
+    }
+}
+
+diesel::table! {
+    structure_regions (id) {
+        id -> Integer,
+        pdf_id -> Integer,
+        page_number -> Integer,
+        x -> Double,
+        y -> Double,
+        width -> Double,
+        height -> Double,
+        score -> Double,
+        status -> Text,
+        created_at -> Timestamp,
+    }
+}
+
+diesel::table! {
+    users (id) {
+        id -> Integer,
+        username -> Text,
+        password_hash -> Text,
+        created_at -> Timestamp,
+    }
+}
+
+diesel::joinable!(api_token_projects -> api_tokens (token_id));
+diesel::joinable!(api_token_projects -> projects (project_id));
+diesel::joinable!(api_tokens -> users (user_id));
+diesel::joinable!(authors -> projects (project_id));
+diesel::joinable!(compound_mentions -> compounds (compound_id));
+diesel::joinable!(compound_mentions -> pdfs (pdf_id));
+diesel::joinable!(compounds -> markush_sources (markush_source_id));
 diesel::joinable!(compounds -> pdfs (pdf_id));
+diesel::joinable!(jobs -> projects (project_id));
+diesel::joinable!(jobs -> users (created_by));
+diesel::joinable!(markush_sources -> pdfs (pdf_id));
+diesel::joinable!(pdf_authors -> authors (author_id));
+diesel::joinable!(pdf_authors -> pdfs (pdf_id));
+diesel::joinable!(pdfs -> projects (project_id));
+diesel::joinable!(project_members -> projects (project_id));
+diesel::joinable!(project_members -> users (user_id));
+diesel::joinable!(recognitions -> projects (project_id));
+diesel::joinable!(recognitions -> structure_regions (region_id));
+diesel::joinable!(sessions -> projects (active_project_id));
+diesel::joinable!(sessions -> users (user_id));
+diesel::joinable!(structure_regions -> pdfs (pdf_id));
 
 diesel::allow_tables_to_appear_in_same_query!(
+    api_token_projects,
+    api_tokens,
+    authors,
+    compound_mentions,
     compounds,
+    jobs,
+    markush_sources,
+    pdf_authors,
     pdfs,
     project_data,
+    project_members,
     projects,
+    recognitions,
+    sessions,
+    structure_regions,
+    users,
 );
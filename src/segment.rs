@@ -0,0 +1,329 @@
+//! Finds chemical structure drawings on the pages of a paper and proposes boxes around
+//! them for the user to review.
+//!
+//! Pages are rendered in grayscale and their ink is smeared by a few pixels, so the
+//! bonds, atom labels and charges of one drawing merge into a single blob. Blobs are
+//! then scored from the connected components of the unsmeared ink: a structure's
+//! bonds form one large connected skeleton spanning the drawing, while text is many
+//! small glyphs and photos are solid.
+
+use pdfium_render::prelude::*;
+
+use crate::db::AsyncConn;
+use crate::error::MolmineError;
+use crate::models::*;
+
+/// Resolution pages are scanned at, enough to keep bond lines apart
+pub const SEGMENT_DPI: u32 = 100;
+/// Pixels darker than this count as ink
+const INK_THRESHOLD: u8 = 160;
+/// How far ink is smeared to join a drawing's pieces, in inches
+const MERGE_DISTANCE: f64 = 0.05;
+/// Smallest width and height of a drawing, in inches
+const MIN_SIZE: f64 = 0.4;
+/// Largest share of the page a single drawing may cover
+const MAX_PAGE_SHARE: f64 = 0.6;
+/// Margin added around each proposed box, in inches
+const PADDING: f64 = 0.05;
+/// Lowest score a blob needs to be proposed
+const MIN_SCORE: f64 = 0.35;
+/// Proposals overlapping a reviewed region at least this much are dropped
+const REVIEWED_OVERLAP: f64 = 0.5;
+
+/// A proposed box in pixels of the scanned page
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Candidate {
+    pub x: usize,
+    pub y: usize,
+    pub width: usize,
+    pub height: usize,
+    pub score: f64,
+}
+
+/// The bounding box and size of a connected group of pixels
+#[derive(Copy, Clone, Debug)]
+struct Component {
+    min_x: usize,
+    min_y: usize,
+    max_x: usize,
+    max_y: usize,
+    pixels: usize,
+    /// Index of one of its pixels
+    first: usize,
+}
+
+impl Component {
+    fn width(&self) -> usize {
+        self.max_x - self.min_x + 1
+    }
+
+    fn height(&self) -> usize {
+        self.max_y - self.min_y + 1
+    }
+}
+
+/// Sets every pixel within `radius` of a set pixel, as two passes of a moving window
+fn dilate(mask: &[bool], width: usize, height: usize, radius: usize) -> Vec<bool> {
+    let mut rows = vec![false; mask.len()];
+    for y in 0..height {
+        let row = &mask[y * width..(y + 1) * width];
+        let mut prefix = vec![0usize; width + 1];
+        for x in 0..width {
+            prefix[x + 1] = prefix[x] + row[x] as usize;
+        }
+        for x in 0..width {
+            let (start, end) = (x.saturating_sub(radius), (x + radius + 1).min(width));
+            rows[y * width + x] = prefix[end] > prefix[start];
+        }
+    }
+    let mut dilated = vec![false; mask.len()];
+    let mut prefix = vec![0usize; height + 1];
+    for x in 0..width {
+        for y in 0..height {
+            prefix[y + 1] = prefix[y] + rows[y * width + x] as usize;
+        }
+        for y in 0..height {
+            let (start, end) = (y.saturating_sub(radius), (y + radius + 1).min(height));
+            dilated[y * width + x] = prefix[end] > prefix[start];
+        }
+    }
+    dilated
+}
+
+/// Labels the 8-connected groups of set pixels, returning each pixel's component
+/// index plus one (zero for unset pixels) and the components
+fn connected_components(mask: &[bool], width: usize, height: usize) -> (Vec<u32>, Vec<Component>) {
+    let mut labels = vec![0u32; mask.len()];
+    let mut components = Vec::new();
+    let mut stack = Vec::new();
+    for start in 0..mask.len() {
+        if !mask[start] || labels[start] != 0 {
+            continue;
+        }
+        let label = components.len() as u32 + 1;
+        let mut component = Component {
+            min_x: start % width,
+            min_y: start / width,
+            max_x: start % width,
+            max_y: start / width,
+            pixels: 0,
+            first: start,
+        };
+        labels[start] = label;
+        stack.push(start);
+        while let Some(index) = stack.pop() {
+            let (x, y) = (index % width, index / width);
+            component.pixels += 1;
+            component.min_x = component.min_x.min(x);
+            component.max_x = component.max_x.max(x);
+            component.min_y = component.min_y.min(y);
+            component.max_y = component.max_y.max(y);
+            for ny in y.saturating_sub(1)..=(y + 1).min(height - 1) {
+                for nx in x.saturating_sub(1)..=(x + 1).min(width - 1) {
+                    let neighbour = ny * width + nx;
+                    if mask[neighbour] && labels[neighbour] == 0 {
+                        labels[neighbour] = label;
+                        stack.push(neighbour);
+                    }
+                }
+            }
+        }
+        components.push(component);
+    }
+    (labels, components)
+}
+
+/// Proposes boxes around structure drawings on a grayscale page scanned at `dpi`, in
+/// reading order
+pub fn detect_candidates(pixels: &[u8], width: usize, height: usize, dpi: u32) -> Vec<Candidate> {
+    if width == 0 || height == 0 {
+        return Vec::new();
+    }
+    let inches = |value: f64| (value * dpi as f64).round() as usize;
+    let ink: Vec<bool> = pixels.iter().map(|&value| value < INK_THRESHOLD).collect();
+    let merged = dilate(&ink, width, height, inches(MERGE_DISTANCE).max(1));
+    let (blob_labels, blobs) = connected_components(&merged, width, height);
+    let (_, strokes) = connected_components(&ink, width, height);
+
+    // Each stroke lies within exactly one blob; find each blob's ink and largest stroke
+    let mut ink_pixels = vec![0usize; blobs.len()];
+    let mut largest: Vec<Option<Component>> = vec![None; blobs.len()];
+    for stroke in strokes {
+        let blob = blob_labels[stroke.first] as usize - 1;
+        ink_pixels[blob] += stroke.pixels;
+        if largest[blob].is_none_or(|largest| stroke.pixels > largest.pixels) {
+            largest[blob] = Some(stroke);
+        }
+    }
+
+    let min_size = inches(MIN_SIZE);
+    let padding = inches(PADDING);
+    let mut candidates = Vec::new();
+    for (index, blob) in blobs.iter().enumerate() {
+        let Some(skeleton) = largest[index] else {
+            continue;
+        };
+        let area = (blob.width() * blob.height()) as f64;
+        if blob.width() < min_size
+            || blob.height() < min_size
+            || area > MAX_PAGE_SHARE * (width * height) as f64
+            || blob.width() > 5 * blob.height()
+            || blob.height() > 5 * blob.width()
+        {
+            continue;
+        }
+        // Line drawings leave most of their box blank; filled shapes and photos don't
+        let density = ink_pixels[index] as f64 / area;
+        let skeleton_area = (skeleton.width() * skeleton.height()) as f64;
+        if !(0.01..=0.3).contains(&density) || skeleton.pixels as f64 / skeleton_area > 0.5 {
+            continue;
+        }
+        // How much of the ink is one connected drawing, and how much of the blob it spans
+        let share = skeleton.pixels as f64 / ink_pixels[index] as f64;
+        let span = (skeleton.width() as f64 / blob.width() as f64)
+            .min(skeleton.height() as f64 / blob.height() as f64);
+        let score = ((share + span) / 2.0).min(1.0);
+        if score < MIN_SCORE {
+            continue;
+        }
+        let x = blob.min_x.saturating_sub(padding);
+        let y = blob.min_y.saturating_sub(padding);
+        candidates.push(Candidate {
+            x,
+            y,
+            width: (blob.max_x + padding + 1).min(width) - x,
+            height: (blob.max_y + padding + 1).min(height) - y,
+            score,
+        });
+    }
+    candidates.sort_by_key(|candidate| (candidate.y, candidate.x));
+    candidates
+}
+
+/// The share of the smaller box covered by the other, so a proposal nested in a
+/// reviewed region also counts as overlapping
+fn overlap(a: (f64, f64, f64, f64), b: (f64, f64, f64, f64)) -> f64 {
+    let width = (a.0 + a.2).min(b.0 + b.2) - a.0.max(b.0);
+    let height = (a.1 + a.3).min(b.1 + b.3) - a.1.max(b.1);
+    if width <= 0.0 || height <= 0.0 {
+        return 0.0;
+    }
+    width * height / (a.2 * a.3).min(b.2 * b.3)
+}
+
+/// Scans every page of the paper's file, returning the proposed boxes in PDF points
+pub fn detect_structures(
+    pdf_id: PdfId,
+    data: &[u8],
+) -> Result<Vec<NewStructureRegion>, MolmineError> {
+    let pdfium = crate::pdf::pdfium()?;
+    let document = pdfium.load_pdf_from_byte_slice(data, None)?;
+    let scale = SEGMENT_DPI as f64 / 72.0;
+    let config = PdfRenderConfig::new().scale_page_by_factor(scale as f32);
+    let mut regions = Vec::new();
+    for (index, page) in document.pages().iter().enumerate() {
+        let image = page.render_with_config(&config)?.as_image().to_luma8();
+        let (width, height) = (image.width() as usize, image.height() as usize);
+        for candidate in detect_candidates(image.as_raw(), width, height, SEGMENT_DPI) {
+            regions.push(NewStructureRegion {
+                pdf_id,
+                page_number: index as i32 + 1,
+                x: candidate.x as f64 / scale,
+                y: candidate.y as f64 / scale,
+                width: candidate.width as f64 / scale,
+                height: candidate.height as f64 / scale,
+                score: candidate.score,
+                status: RegionStatus::Pending,
+            });
+        }
+    }
+    Ok(regions)
+}
+
+/// Detects the structures in the paper's file and stores them as its pending regions,
+/// replacing earlier proposals. Boxes the user has already accepted or rejected are not
+/// proposed again.
+pub async fn propose_regions(
+    pdf_id: PdfId,
+    data: Vec<u8>,
+    conn: &mut AsyncConn,
+) -> Result<Vec<StructureRegion>, MolmineError> {
+    // Rendering and scanning are CPU-bound, so keep them off the async workers
+    let proposals = tokio::task::spawn_blocking(move || detect_structures(pdf_id, &data)).await??;
+    let reviewed: Vec<StructureRegion> = StructureRegion::list_for_pdf(pdf_id, None, conn)
+        .await?
+        .into_iter()
+        .filter(|region| region.status != RegionStatus::Pending)
+        .collect();
+    let proposals: Vec<NewStructureRegion> = proposals
+        .into_iter()
+        .filter(|proposal| {
+            !reviewed.iter().any(|region| {
+                region.page_number == proposal.page_number
+                    && overlap(
+                        (region.x, region.y, region.width, region.height),
+                        (proposal.x, proposal.y, proposal.width, proposal.height),
+                    ) >= REVIEWED_OVERLAP
+            })
+        })
+        .collect();
+    Ok(StructureRegion::replace_pending(pdf_id, &proposals, conn).await?)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    /// Draws a line two pixels thick onto a white page
+    fn draw_line(page: &mut [u8], width: usize, from: (f64, f64), to: (f64, f64)) {
+        let steps = (to.0 - from.0).abs().max((to.1 - from.1).abs()).ceil() as usize;
+        for step in 0..=steps {
+            let t = step as f64 / steps.max(1) as f64;
+            let x = (from.0 + (to.0 - from.0) * t).round() as usize;
+            let y = (from.1 + (to.1 - from.1) * t).round() as usize;
+            for (dx, dy) in [(0, 0), (1, 0), (0, 1), (1, 1)] {
+                page[(y + dy) * width + x + dx] = 0;
+            }
+        }
+    }
+
+    #[test]
+    fn test_detect_candidates() {
+        let (width, height) = (850, 1100);
+        let mut page = vec![255u8; width * height];
+        // A paragraph: rows of small solid glyphs
+        for row in 0..12 {
+            for glyph in 0..60 {
+                let (x0, y0) = (60 + glyph * 9, 80 + row * 14);
+                for y in y0..y0 + 8 {
+                    for x in x0..x0 + 6 {
+                        page[y * width + x] = 0;
+                    }
+                }
+            }
+        }
+        // A benzene ring with a substituent, 1.2 inches across
+        let (cx, cy, r) = (425.0, 600.0, 60.0);
+        let corner = |i: usize| {
+            let angle = std::f64::consts::PI / 3.0 * i as f64;
+            (cx + r * angle.cos(), cy + r * angle.sin())
+        };
+        for i in 0..6 {
+            draw_line(&mut page, width, corner(i), corner(i + 1));
+        }
+        draw_line(&mut page, width, corner(0), (cx + r + 50.0, cy));
+
+        let candidates = detect_candidates(&page, width, height, 100);
+        assert_eq!(candidates.len(), 1);
+        let candidate = candidates[0];
+        assert!(candidate.x < 365 && candidate.x + candidate.width > 535);
+        assert!(candidate.y < 548 && candidate.y + candidate.height > 652);
+        assert!(candidate.score > 0.9);
+
+        assert_eq!(overlap((0.0, 0.0, 10.0, 10.0), (5.0, 5.0, 2.0, 2.0)), 1.0);
+        assert_eq!(
+            overlap((0.0, 0.0, 10.0, 10.0), (20.0, 0.0, 10.0, 10.0)),
+            0.0
+        );
+    }
+}